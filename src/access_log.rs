@@ -0,0 +1,46 @@
+use std::{fmt, net::SocketAddr, time::Duration};
+
+use crate::http_server::{HTTPMethod, HTTPVersion};
+
+/// called once per answered request. Runs on the worker thread, after the response was sent
+pub type AccessLogger = fn(&AccessLogEntry);
+
+/// what happened to a single request
+pub struct AccessLogEntry<'a> {
+    pub method: &'a HTTPMethod,
+    /// the request target as sent, including the query
+    pub target: &'a str,
+    pub version: HTTPVersion,
+    pub status: u16,
+    /// bytes of body sent, not counting headers or chunk framing
+    pub body_size: u64,
+    /// from reading the request line until the response was written
+    pub duration: Duration,
+    pub peer_addr: Option<SocketAddr>,
+}
+
+/// an `AccessLogger` printing every entry to stdout
+pub fn print(entry: &AccessLogEntry) {
+    println!("{}", entry);
+}
+
+impl fmt::Display for AccessLogEntry<'_> {
+    /// `<peer> "<method> <target> <version>" <status> <size> <duration>`, with `-` for a
+    /// missing peer
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.peer_addr {
+            Some(addr) => write!(f, "{}", addr)?,
+            None => write!(f, "-")?,
+        }
+        write!(
+            f,
+            " \"{} {} {}\" {} {} {:.3}ms",
+            self.method,
+            self.target,
+            self.version,
+            self.status,
+            self.body_size,
+            self.duration.as_secs_f64() * 1000.0
+        )
+    }
+}
@@ -0,0 +1,87 @@
+use crate::{
+    base64,
+    http_server::{HTTPRequest, HTTPResponse},
+    middleware::Next,
+};
+
+/// the name of a user authenticated by `basic`, in the request's extensions
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BasicUser(pub String);
+
+/// middleware requiring HTTP Basic authentication. `verify` is given the user name, password
+/// and passthrough. Accepted users are put into the request's extensions as `BasicUser`, and
+/// everyone else is answered with 401 and a challenge for `realm`
+pub fn basic<T>(
+    realm: &str,
+    verify: impl Fn(&str, &str, &T) -> bool + Send + Sync + 'static,
+) -> impl Fn(&mut HTTPRequest, &T, Next<T>) -> HTTPResponse + Send + Sync + 'static {
+    let challenge = format!(
+        "Basic realm=\"{}\", charset=\"UTF-8\"",
+        realm.replace(['\\', '"'], "")
+    );
+    move |request: &mut HTTPRequest, passthrough: &T, next: Next<T>| {
+        let credentials = basic_credentials(request);
+        match credentials {
+            Some((user, password)) if verify(&user, &password, passthrough) => {
+                request.extensions.insert(BasicUser(user));
+                next.run(request, passthrough)
+            }
+            _ => unauthorized(&challenge),
+        }
+    }
+}
+
+/// middleware requiring a bearer token. `verify` turns the token into whatever it stands for,
+/// like a user or its claims, which is put into the request's extensions for the listener.
+/// Missing and rejected tokens are answered with 401
+pub fn bearer<T, C: Send + Sync + 'static>(
+    verify: impl Fn(&str, &T) -> Option<C> + Send + Sync + 'static,
+) -> impl Fn(&mut HTTPRequest, &T, Next<T>) -> HTTPResponse + Send + Sync + 'static {
+    move |request: &mut HTTPRequest, passthrough: &T, next: Next<T>| {
+        let Some(token) = bearer_token(request) else {
+            return unauthorized("Bearer");
+        };
+        match verify(token, passthrough) {
+            Some(verified) => {
+                request.extensions.insert(verified);
+                next.run(request, passthrough)
+            }
+            None => unauthorized("Bearer error=\"invalid_token\""),
+        }
+    }
+}
+
+/// user name and password of a `Authorization: Basic` header
+pub fn basic_credentials(request: &HTTPRequest) -> Option<(String, String)> {
+    let encoded = scheme_value(request, "Basic")?;
+    let decoded = String::from_utf8(base64::decode(encoded, base64::STANDARD)?).ok()?;
+    let (user, password) = decoded.split_once(':')?;
+    Some((String::from(user), String::from(password)))
+}
+
+/// the token of a `Authorization: Bearer` header
+pub fn bearer_token(request: &HTTPRequest) -> Option<&str> {
+    scheme_value(request, "Bearer").filter(|token| !token.is_empty())
+}
+
+/// compare secrets in time independent of where they differ, so it can't be measured how much
+/// of a guess was right
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// the credentials of the Authorization header if it uses `scheme`, which is case insensitive
+fn scheme_value<'a>(request: &'a HTTPRequest, scheme: &str) -> Option<&'a str> {
+    let (name, value) = request.header("Authorization")?.trim().split_once(' ')?;
+    name.eq_ignore_ascii_case(scheme).then(|| value.trim())
+}
+
+fn unauthorized(challenge: &str) -> HTTPResponse {
+    HTTPResponse::builder()
+        .status(401)
+        .header("WWW-Authenticate", challenge)
+        .text("Unauthorized")
+}
@@ -0,0 +1,62 @@
+/// the alphabet of RFC 4648 base64
+pub(crate) const STANDARD: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// the url and filename safe alphabet, used without padding by JWTs
+#[cfg(any(feature = "jwt", feature = "http2"))]
+pub(crate) const URL_SAFE: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+/// decode `input` written with `alphabet`, padding being optional. `None` if it isn't valid,
+/// including when the bits left over at the end aren't zero, so every input has one encoding
+pub(crate) fn decode(input: &str, alphabet: &[u8; 64]) -> Option<Vec<u8>> {
+    let input = input.trim_end_matches('=').as_bytes();
+    if input.len() % 4 == 1 {
+        return None;
+    }
+    let mut out = Vec::with_capacity(input.len() * 3 / 4);
+    for chunk in input.chunks(4) {
+        let mut group = 0u32;
+        for (i, byte) in chunk.iter().enumerate() {
+            let value = alphabet.iter().position(|c| c == byte)? as u32;
+            group |= value << (18 - 6 * i);
+        }
+        let bytes = group.to_be_bytes();
+        if bytes[chunk.len()..].iter().any(|byte| *byte != 0) {
+            return None;
+        }
+        out.extend_from_slice(&bytes[1..chunk.len()]);
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rfc_4648_vectors() {
+        for (decoded, encoded) in [
+            ("", ""),
+            ("f", "Zg=="),
+            ("fo", "Zm8="),
+            ("foo", "Zm9v"),
+            ("foob", "Zm9vYg=="),
+            ("fooba", "Zm9vYmE="),
+            ("foobar", "Zm9vYmFy"),
+        ] {
+            assert_eq!(decode(encoded, STANDARD).unwrap(), decoded.as_bytes());
+            assert_eq!(
+                decode(encoded.trim_end_matches('='), STANDARD).unwrap(),
+                decoded.as_bytes()
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_input() {
+        for invalid in ["Z", "Zm9vY", "Zm9v!", "Zh==", "Zm9=", "Zm-v"] {
+            assert_eq!(decode(invalid, STANDARD), None, "{}", invalid);
+        }
+    }
+}
@@ -0,0 +1,376 @@
+use std::{
+    fmt,
+    io::{self, BufRead, ErrorKind, Read},
+    sync::{Arc, Mutex, OnceLock},
+};
+
+use crate::{
+    compression::{decode_body, DecodeError},
+    headers::HeaderMap,
+    http_server::{
+        get_400_default_response, get_408_default_response, get_413_default_response,
+        read_chunked_body, ChunkedDecoder, HTTPResponse,
+    },
+};
+
+/// the body of a request. It's left on the connection until a listener or middleware first
+/// asks for it, so requests whose body isn't needed don't wait for it. Reading it is only
+/// possible until the response is sent, unread bodies are discarded then. Since its decoded
+/// length isn't known upfront, requests with a Content-Encoding lose both that header and
+/// Content-Length, which `sent_length` still tells
+pub struct RequestBody {
+    source: Arc<Mutex<Source>>,
+    bytes: OnceLock<Result<Vec<u8>, BodyError>>,
+    trailers: OnceLock<HeaderMap>,
+    /// how the body came in, kept for after it's read
+    sent: Framing,
+}
+
+/// why a body couldn't be read
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BodyError {
+    /// larger than `max_body_size` as sent, or `max_decompressed_body_size` once decoded
+    TooLarge,
+    /// the client didn't send it within the read timeout
+    Timeout,
+    /// malformed chunks or Content-Encoding, or the connection ended early
+    Invalid,
+    /// a Content-Encoding other than gzip or deflate
+    UnsupportedEncoding,
+}
+
+/// a body read off the connection as it arrives, made by `RequestBody::reader`. Its errors
+/// carry the `BodyError` they stand for
+pub struct BodyReader<'a> {
+    body: &'a RequestBody,
+    from: Origin<'a>,
+}
+
+enum Origin<'a> {
+    Memory(&'a [u8]),
+    Connection {
+        input: Arc<Mutex<dyn BufRead + Send>>,
+        framing: Streaming,
+    },
+}
+
+enum Streaming {
+    /// the bytes left
+    Length(usize),
+    Chunked(ChunkedDecoder),
+}
+
+/// the connection's side of a body being read lazily
+pub(crate) struct PendingBody(Arc<Mutex<Source>>);
+
+pub(crate) enum Source {
+    Unread(BodySource),
+    /// read, or never there
+    Done,
+    /// reading stopped halfway, leaving the connection in an unknown state
+    Failed,
+}
+
+/// where an unread body comes from and how it's framed
+pub(crate) struct BodySource {
+    pub(crate) input: Arc<Mutex<dyn BufRead + Send>>,
+    pub(crate) framing: Framing,
+    pub(crate) max_body_size: usize,
+    /// the Content-Encodings to undo, comma separated
+    pub(crate) encoding: Option<String>,
+    pub(crate) max_decompressed_body_size: usize,
+}
+
+#[derive(Clone, Copy)]
+pub(crate) enum Framing {
+    Length(usize),
+    Chunked,
+}
+
+impl RequestBody {
+    /// a body left on the connection, and the connection's handle to take it back
+    pub(crate) fn pending(source: BodySource) -> (RequestBody, PendingBody) {
+        let sent = source.framing;
+        let source = Arc::new(Mutex::new(Source::Unread(source)));
+        let body = RequestBody {
+            source: Arc::clone(&source),
+            bytes: OnceLock::new(),
+            trailers: OnceLock::new(),
+            sent,
+        };
+        (body, PendingBody(source))
+    }
+
+    /// the body after undoing any Content-Encoding, read on the first call
+    pub fn bytes(&self) -> Result<&[u8], BodyError> {
+        match self.bytes.get_or_init(|| self.read()) {
+            Ok(bytes) => Ok(bytes),
+            Err(error) => Err(*error),
+        }
+    }
+
+    /// the body as it arrives, for bodies too large to keep in memory such as uploads. It
+    /// can be taken once, `bytes` is empty afterwards. Bodies already read or with a
+    /// Content-Encoding are read into memory first
+    pub fn reader(&self) -> Result<BodyReader<'_>, BodyError> {
+        let memory = |bytes| BodyReader {
+            body: self,
+            from: Origin::Memory(bytes),
+        };
+        if self.bytes.get().is_some() {
+            return self.bytes().map(memory);
+        }
+        let mut source = self.source.lock().unwrap();
+        let unread = match std::mem::replace(&mut *source, Source::Failed) {
+            Source::Unread(unread) if unread.encoding.is_none() => unread,
+            other => {
+                *source = other;
+                drop(source);
+                return self.bytes().map(memory);
+            }
+        };
+        let framing = match unread.framing {
+            Framing::Length(length) if length > unread.max_body_size => {
+                let _ = self.bytes.set(Err(BodyError::TooLarge));
+                return Err(BodyError::TooLarge);
+            }
+            Framing::Length(length) => Streaming::Length(length),
+            Framing::Chunked => Streaming::Chunked(ChunkedDecoder::new(unread.max_body_size)),
+        };
+        let _ = self.bytes.set(Ok(Vec::new()));
+        Ok(BodyReader {
+            body: self,
+            from: Origin::Connection {
+                input: unread.input,
+                framing,
+            },
+        })
+    }
+
+    /// the body as text, empty if it isn't valid utf8
+    pub fn text(&self) -> Result<&str, BodyError> {
+        self.bytes()
+            .map(|bytes| std::str::from_utf8(bytes).unwrap_or_default())
+    }
+
+    /// whether the request has a body, however it's framed or encoded
+    pub fn is_present(&self) -> bool {
+        match self.sent {
+            Framing::Length(length) => length > 0,
+            Framing::Chunked => true,
+        }
+    }
+
+    /// the length of the body as sent, before undoing any Content-Encoding. It's known even
+    /// once Content-Length is removed from encoded requests, `None` for chunked bodies
+    pub fn sent_length(&self) -> Option<u64> {
+        match self.sent {
+            Framing::Length(length) => Some(length as u64),
+            Framing::Chunked => None,
+        }
+    }
+
+    /// the fields sent after a chunked body, empty for other bodies. Reads the body if it
+    /// wasn't yet, as the trailers follow it
+    pub fn trailers(&self) -> Result<&HeaderMap, BodyError> {
+        self.bytes()?;
+        Ok(self.trailers.get_or_init(HeaderMap::new))
+    }
+
+    #[cfg(feature = "http2")]
+    /// the body with `trailers` that arrived apart from it, as on HTTP/2 streams
+    pub(crate) fn with_trailers(self, trailers: HeaderMap) -> RequestBody {
+        let _ = self.trailers.set(trailers);
+        self
+    }
+
+    /// lower the most bytes the body may have, both as sent and once decoded, if it's still
+    /// unread
+    pub(crate) fn limit(&self, max_body_size: usize) {
+        if let Source::Unread(unread) = &mut *self.source.lock().unwrap() {
+            unread.max_body_size = unread.max_body_size.min(max_body_size);
+            unread.max_decompressed_body_size =
+                unread.max_decompressed_body_size.min(max_body_size);
+        }
+    }
+
+    fn read(&self) -> Result<Vec<u8>, BodyError> {
+        let mut source = self.source.lock().unwrap();
+        let Source::Unread(mut unread) = std::mem::replace(&mut *source, Source::Failed) else {
+            *source = Source::Done;
+            return Ok(Vec::new());
+        };
+        let encoding = unread.encoding.take();
+        let limit = unread.max_decompressed_body_size;
+        let (body, trailers) = unread.read()?;
+        let _ = self.trailers.set(trailers);
+        // the body has left the connection, even if decoding it fails
+        *source = Source::Done;
+        drop(source);
+
+        match encoding {
+            Some(encoding) => decode_body(&encoding, body, limit).map_err(|error| {
+                println!("failed decoding {} request body: {:?}", encoding, error);
+                BodyError::from(error)
+            }),
+            None => Ok(body),
+        }
+    }
+}
+
+impl Default for RequestBody {
+    fn default() -> RequestBody {
+        RequestBody::from(Vec::new())
+    }
+}
+
+impl From<Vec<u8>> for RequestBody {
+    /// a body that is already read, like one built for a test
+    fn from(bytes: Vec<u8>) -> RequestBody {
+        RequestBody {
+            source: Arc::new(Mutex::new(Source::Done)),
+            sent: Framing::Length(bytes.len()),
+            bytes: OnceLock::from(Ok(bytes)),
+            trailers: OnceLock::new(),
+        }
+    }
+}
+
+impl PendingBody {
+    /// end the listener's access to the body, returning what's left of it
+    pub(crate) fn reclaim(&self) -> Source {
+        std::mem::replace(&mut *self.0.lock().unwrap(), Source::Done)
+    }
+}
+
+impl BodySource {
+    /// the body as sent, and its trailers
+    fn read(self) -> Result<(Vec<u8>, HeaderMap), BodyError> {
+        let mut guard = self.input.lock().unwrap();
+        let mut input: &mut (dyn BufRead + Send) = &mut *guard;
+        let body = match self.framing {
+            Framing::Length(length) if length > self.max_body_size => {
+                Err(io::Error::new(ErrorKind::FileTooLarge, "body too large"))
+            }
+            Framing::Length(length) => {
+                let mut body = vec![0; length];
+                input
+                    .read_exact(&mut body)
+                    .map(|_| (body, HeaderMap::new()))
+            }
+            Framing::Chunked => read_chunked_body(&mut input, self.max_body_size),
+        };
+        body.map_err(|error| BodyError::from_io(&error))
+    }
+
+    /// skip the body if it's at most `limit` bytes as sent. Returns whether the connection can
+    /// be used for another request
+    pub(crate) fn discard(self, limit: usize) -> bool {
+        let mut guard = self.input.lock().unwrap();
+        let mut input: &mut (dyn BufRead + Send) = &mut *guard;
+        match self.framing {
+            Framing::Length(length) if length <= limit => {
+                let length = length as u64;
+                let copied = io::copy(&mut input.take(length), &mut io::sink());
+                matches!(copied, Ok(copied) if copied == length)
+            }
+            Framing::Length(_) => false,
+            Framing::Chunked => read_chunked_body(&mut input, limit).is_ok(),
+        }
+    }
+}
+
+impl Read for BodyReader<'_> {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        let (input, framing) = match &mut self.from {
+            Origin::Memory(bytes) => return bytes.read(buffer),
+            Origin::Connection { input, framing } => (input, framing),
+        };
+        let mut guard = input.lock().unwrap();
+        let mut input: &mut (dyn BufRead + Send) = &mut *guard;
+        let read = match framing {
+            Streaming::Length(0) => Ok(0),
+            Streaming::Length(left) => {
+                let wanted = buffer.len().min(*left);
+                match input.read(&mut buffer[..wanted]) {
+                    Ok(0) if wanted > 0 => Err(io::Error::new(
+                        ErrorKind::UnexpectedEof,
+                        "stream ended within the body",
+                    )),
+                    Ok(read) => {
+                        *left -= read;
+                        Ok(read)
+                    }
+                    Err(error) => Err(error),
+                }
+            }
+            Streaming::Chunked(decoder) => decoder.read(&mut input, buffer),
+        };
+        let read =
+            read.map_err(|error| io::Error::new(error.kind(), BodyError::from_io(&error)))?;
+        if read == 0 && !buffer.is_empty() {
+            // the body has left the connection, so it can take the next request
+            if let Streaming::Chunked(decoder) = framing {
+                let _ = self
+                    .body
+                    .trailers
+                    .set(decoder.trailers.take().unwrap_or_default());
+            }
+            *self.body.source.lock().unwrap() = Source::Done;
+        }
+        Ok(read)
+    }
+}
+
+impl BodyError {
+    /// what failing to read the body off the connection means
+    fn from_io(error: &io::Error) -> BodyError {
+        match error.kind() {
+            ErrorKind::FileTooLarge => BodyError::TooLarge,
+            ErrorKind::WouldBlock | ErrorKind::TimedOut => BodyError::Timeout,
+            _ => {
+                println!("failed reading request body: {}", error);
+                BodyError::Invalid
+            }
+        }
+    }
+
+    /// the response a server sends for the error, 413, 408, 400 or 415
+    pub fn response(&self) -> HTTPResponse {
+        match self {
+            BodyError::TooLarge => get_413_default_response(),
+            BodyError::Timeout => get_408_default_response(),
+            BodyError::Invalid => get_400_default_response(),
+            BodyError::UnsupportedEncoding => DecodeError::Unsupported.response(),
+        }
+    }
+}
+
+impl From<BodyError> for HTTPResponse {
+    fn from(error: BodyError) -> HTTPResponse {
+        error.response()
+    }
+}
+
+impl From<DecodeError> for BodyError {
+    fn from(error: DecodeError) -> BodyError {
+        match error {
+            DecodeError::Unsupported => BodyError::UnsupportedEncoding,
+            DecodeError::Invalid => BodyError::Invalid,
+            DecodeError::TooLarge => BodyError::TooLarge,
+        }
+    }
+}
+
+impl fmt::Display for BodyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BodyError::TooLarge => write!(f, "request body too large"),
+            BodyError::Timeout => write!(f, "timed out reading request body"),
+            BodyError::Invalid => write!(f, "malformed request body"),
+            BodyError::UnsupportedEncoding => write!(f, "unsupported Content-Encoding"),
+        }
+    }
+}
+
+impl std::error::Error for BodyError {}
@@ -0,0 +1,73 @@
+use std::{
+    ops::{Deref, DerefMut},
+    sync::Mutex,
+};
+
+/// byte buffers shared by all workers. Connections take one for reading request heads and
+/// responses one for writing, and hand it back when done so its allocation is reused
+pub(crate) struct BufferPool {
+    buffers: Mutex<Vec<Vec<u8>>>,
+    buffer_size: usize,
+    capacity: usize,
+}
+
+/// a buffer taken from a pool, returned to it when dropped
+pub(crate) struct PooledBuffer<'a> {
+    buffer: Vec<u8>,
+    pool: &'a BufferPool,
+}
+
+impl BufferPool {
+    /// a pool keeping at most `capacity` idle buffers of `buffer_size` bytes
+    pub(crate) fn new(buffer_size: usize, capacity: usize) -> BufferPool {
+        BufferPool {
+            buffers: Mutex::new(Vec::new()),
+            // reading into an empty buffer would look like the end of the stream
+            buffer_size: buffer_size.max(1),
+            capacity,
+        }
+    }
+
+    pub(crate) fn buffer_size(&self) -> usize {
+        self.buffer_size
+    }
+
+    /// an empty buffer with room for at least `buffer_size` bytes, allocated if none is idle
+    pub(crate) fn take(&self) -> PooledBuffer<'_> {
+        let buffer = self.buffers.lock().unwrap().pop();
+        PooledBuffer {
+            buffer: buffer.unwrap_or_else(|| Vec::with_capacity(self.buffer_size)),
+            pool: self,
+        }
+    }
+
+    fn give_back(&self, mut buffer: Vec<u8>) {
+        // a buffer grown for a large request goes back to the usual size
+        buffer.clear();
+        buffer.shrink_to(self.buffer_size);
+        let mut buffers = self.buffers.lock().unwrap();
+        if buffers.len() < self.capacity {
+            buffers.push(buffer);
+        }
+    }
+}
+
+impl Deref for PooledBuffer<'_> {
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Vec<u8> {
+        &self.buffer
+    }
+}
+
+impl DerefMut for PooledBuffer<'_> {
+    fn deref_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.buffer
+    }
+}
+
+impl Drop for PooledBuffer<'_> {
+    fn drop(&mut self) {
+        self.pool.give_back(std::mem::take(&mut self.buffer));
+    }
+}
@@ -0,0 +1,563 @@
+use std::{collections::HashMap, net::SocketAddr, sync::Arc, time::Duration};
+
+use crate::{
+    access_log::AccessLogger,
+    error_pages::ErrorPage,
+    forwarded::TrustedProxies,
+    http_server::{
+        into_listener, ConnectionPriority, Dispatch, HTTPListener, HTTPMethod, HTTPRequest,
+        HTTPResponse, HTTPServer, Route, TrailingSlash,
+    },
+    lifecycle::{ConnectionEvent, ConnectionHook, StartHook},
+    metrics::Metrics,
+    middleware::{Middleware, Next},
+    response::IntoResponse,
+    router::Router,
+    scope::Scope,
+    shutdown::{ConnectionGauge, ShutdownHook},
+    thread_pool::Priority,
+    tunnel::Tunnel,
+};
+
+#[cfg(feature = "openapi")]
+use crate::openapi::OpenApi;
+
+/// fluent configuration of a `HTTPServer`, so routes can be registered without touching `Arc` or `Route`
+pub struct HTTPServerBuilder<T: std::marker::Sync + std::marker::Send + 'static> {
+    address: String,
+    port: u64,
+    router: Router<T>,
+    hosts: HashMap<String, Router<T>>,
+    default_404_listener: Option<HTTPListener<T>>,
+    default_405_listener: Option<HTTPListener<T>>,
+    default_500_listener: Option<HTTPListener<T>>,
+    error_pages: HashMap<u16, ErrorPage>,
+    threads: usize,
+    max_threads: usize,
+    thread_idle_timeout: Duration,
+    max_queued_connections: Option<usize>,
+    dispatch: Dispatch,
+    connection_priority: Option<ConnectionPriority>,
+    overload_retry_after: Duration,
+    max_connections: Option<usize>,
+    shed_connections: bool,
+    unix_socket_mode: Option<u32>,
+    passthrough: T,
+    keep_alive_timeout: Option<Duration>,
+    middleware: Vec<Middleware<T>>,
+    server_header: Option<String>,
+    drain_timeout: Duration,
+    shutdown_on_signals: bool,
+    shutdown_hooks: Vec<ShutdownHook>,
+    shutdown_begin_hooks: Vec<ShutdownHook>,
+    start_hooks: Vec<StartHook>,
+    connection_hooks: Vec<ConnectionHook>,
+    max_decompressed_body_size: usize,
+    strict_parsing: bool,
+    trailing_slash: TrailingSlash,
+    max_header_bytes: usize,
+    max_headers: usize,
+    max_body_size: usize,
+    buffer_size: usize,
+    buffer_pool_capacity: usize,
+    read_timeout: Option<Duration>,
+    write_timeout: Option<Duration>,
+    header_read_timeout: Option<Duration>,
+    access_log: Option<AccessLogger>,
+    metrics_endpoint: Option<String>,
+    tunnel: Option<Tunnel>,
+    trusted_proxies: TrustedProxies,
+    proxy_protocol: bool,
+    #[cfg(feature = "openapi")]
+    openapi: Option<(String, OpenApi)>,
+}
+
+impl<T: std::marker::Sync + std::marker::Send + 'static> HTTPServerBuilder<T> {
+    /// a builder listening on `127.0.0.1:8080` with 4 threads growing up to 64 under load,
+    /// sharing `passthrough` with every listener
+    pub fn new(passthrough: T) -> HTTPServerBuilder<T> {
+        HTTPServerBuilder {
+            address: String::from("127.0.0.1"),
+            port: 8080,
+            router: Router::new(),
+            hosts: HashMap::new(),
+            default_404_listener: None,
+            default_405_listener: None,
+            default_500_listener: None,
+            error_pages: HashMap::new(),
+            threads: 4,
+            max_threads: 64,
+            thread_idle_timeout: Duration::from_secs(60),
+            max_queued_connections: Some(1024),
+            dispatch: Dispatch::default(),
+            connection_priority: None,
+            overload_retry_after: Duration::from_secs(5),
+            max_connections: None,
+            shed_connections: false,
+            unix_socket_mode: None,
+            passthrough,
+            keep_alive_timeout: Some(Duration::from_secs(5)),
+            middleware: Vec::new(),
+            server_header: Some(String::from("adhesion")),
+            drain_timeout: Duration::from_secs(30),
+            shutdown_on_signals: false,
+            shutdown_hooks: Vec::new(),
+            shutdown_begin_hooks: Vec::new(),
+            start_hooks: Vec::new(),
+            connection_hooks: Vec::new(),
+            max_decompressed_body_size: 16 * 1024 * 1024,
+            strict_parsing: true,
+            trailing_slash: TrailingSlash::default(),
+            max_header_bytes: 16 * 1024,
+            max_headers: 100,
+            max_body_size: 16 * 1024 * 1024,
+            buffer_size: 8 * 1024,
+            buffer_pool_capacity: 256,
+            read_timeout: Some(Duration::from_secs(30)),
+            write_timeout: Some(Duration::from_secs(30)),
+            header_read_timeout: Some(Duration::from_secs(10)),
+            access_log: None,
+            metrics_endpoint: None,
+            tunnel: None,
+            trusted_proxies: TrustedProxies::new(),
+            proxy_protocol: false,
+            #[cfg(feature = "openapi")]
+            openapi: None,
+        }
+    }
+
+    pub fn address(mut self, address: &str) -> Self {
+        self.address = String::from(address);
+        self
+    }
+
+    pub fn port(mut self, port: u64) -> Self {
+        self.port = port;
+        self
+    }
+
+    /// workers always kept alive
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    /// most workers spawned while connections are waiting. Set it to `threads` for a fixed pool
+    pub fn max_threads(mut self, threads: usize) -> Self {
+        self.max_threads = threads;
+        self
+    }
+
+    /// how long extra workers stay around without work
+    pub fn thread_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.thread_idle_timeout = timeout;
+        self
+    }
+
+    /// most connections waiting for a worker before new ones are answered with 503
+    pub fn max_queued_connections(mut self, limit: Option<usize>) -> Self {
+        self.max_queued_connections = limit;
+        self
+    }
+
+    /// which threads connections are served on, pooled workers by default
+    pub fn dispatch(mut self, dispatch: Dispatch) -> Self {
+        self.dispatch = dispatch;
+        self
+    }
+
+    /// connections `priority` gives `Priority::High` skip ahead of those waiting for a pooled
+    /// worker, e.g. health checks on their own port with
+    /// `|local, _| if local.map(|a| a.port()) == Some(9000) { Priority::High } else { Priority::Normal }`
+    pub fn connection_priority(
+        mut self,
+        priority: impl Fn(Option<SocketAddr>, Option<SocketAddr>) -> Priority + Send + Sync + 'static,
+    ) -> Self {
+        self.connection_priority = Some(Arc::new(priority));
+        self
+    }
+
+    /// how long overloaded clients are told to wait before retrying
+    pub fn overload_retry_after(mut self, delay: Duration) -> Self {
+        self.overload_retry_after = delay;
+        self
+    }
+
+    /// most connections open at once. Beyond that new connections wait in the listen backlog
+    pub fn max_connections(mut self, limit: Option<usize>) -> Self {
+        self.max_connections = limit;
+        self
+    }
+
+    /// answer connections beyond `max_connections` with 503 instead of letting them wait
+    pub fn shed_connections(mut self, shed: bool) -> Self {
+        self.shed_connections = shed;
+        self
+    }
+
+    /// permission bits of the socket file created by `listen_unix`
+    pub fn unix_socket_mode(mut self, mode: Option<u32>) -> Self {
+        self.unix_socket_mode = mode;
+        self
+    }
+
+    /// log every answered request, e.g. with `access_log::print`
+    pub fn access_log(mut self, logger: Option<AccessLogger>) -> Self {
+        self.access_log = logger;
+        self
+    }
+
+    /// serve the metrics in the Prometheus text format at `path`, e.g. `/metrics`
+    pub fn metrics_endpoint(mut self, path: &str) -> Self {
+        self.metrics_endpoint = Some(String::from(path));
+        self
+    }
+
+    /// answer CONNECT requests to the destinations `tunnel` allows by piping the connection to
+    /// them, like a forward proxy
+    pub fn tunnel(mut self, tunnel: Tunnel) -> Self {
+        self.tunnel = Some(tunnel);
+        self
+    }
+
+    /// believe the forwarding headers of these proxies for `HTTPRequest::client_ip`
+    pub fn trusted_proxies(mut self, proxies: TrustedProxies) -> Self {
+        self.trusted_proxies = proxies;
+        self
+    }
+
+    /// read a PROXY protocol header before the first request of every connection, for servers
+    /// behind haproxy or a load balancer speaking it
+    pub fn proxy_protocol(mut self, enabled: bool) -> Self {
+        self.proxy_protocol = enabled;
+        self
+    }
+
+    pub fn keep_alive_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.keep_alive_timeout = timeout;
+        self
+    }
+
+    /// how long a single read of a request may block
+    pub fn read_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.read_timeout = timeout;
+        self
+    }
+
+    /// how long writing a response may block
+    pub fn write_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.write_timeout = timeout;
+        self
+    }
+
+    /// how long a client may take to send a request head in total
+    pub fn header_read_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.header_read_timeout = timeout;
+        self
+    }
+
+    /// how long a graceful shutdown waits for in-flight requests
+    pub fn drain_timeout(mut self, timeout: Duration) -> Self {
+        self.drain_timeout = timeout;
+        self
+    }
+
+    /// shut down gracefully on SIGINT or SIGTERM, letting `listen` return. Only on unix
+    pub fn shutdown_on_signals(mut self, enabled: bool) -> Self {
+        self.shutdown_on_signals = enabled;
+        self
+    }
+
+    /// run `hook` once the server has shut down and its requests are finished
+    pub fn on_shutdown(mut self, hook: impl Fn() + Send + Sync + 'static) -> Self {
+        self.shutdown_hooks.push(Arc::new(hook));
+        self
+    }
+
+    /// run `hook` when a graceful shutdown begins, while requests may still be in flight
+    pub fn on_shutdown_begin(mut self, hook: impl Fn() + Send + Sync + 'static) -> Self {
+        self.shutdown_begin_hooks.push(Arc::new(hook));
+        self
+    }
+
+    /// run `hook` with the url of every listener once it accepts connections, e.g. to announce
+    /// readiness
+    pub fn on_start(mut self, hook: impl Fn(&str) + Send + Sync + 'static) -> Self {
+        self.start_hooks.push(Arc::new(hook));
+        self
+    }
+
+    /// run `hook` on the worker when a connection is accepted and when it's closed
+    pub fn on_connection(
+        mut self,
+        hook: impl Fn(ConnectionEvent, Option<SocketAddr>) + Send + Sync + 'static,
+    ) -> Self {
+        self.connection_hooks.push(Arc::new(hook));
+        self
+    }
+
+    /// largest an encoded request body may grow when decoded
+    pub fn max_decompressed_body_size(mut self, size: usize) -> Self {
+        self.max_decompressed_body_size = size;
+        self
+    }
+
+    /// largest request line and headers together
+    pub fn max_header_bytes(mut self, size: usize) -> Self {
+        self.max_header_bytes = size;
+        self
+    }
+
+    pub fn max_headers(mut self, count: usize) -> Self {
+        self.max_headers = count;
+        self
+    }
+
+    /// largest request body as sent, before any Content-Encoding is undone
+    pub fn max_body_size(mut self, size: usize) -> Self {
+        self.max_body_size = size;
+        self
+    }
+
+    /// bytes read from or written to a connection at once, 8 KiB by default. Response bodies
+    /// this small are sent together with their head
+    pub fn buffer_size(mut self, size: usize) -> Self {
+        self.buffer_size = size;
+        self
+    }
+
+    /// most idle buffers kept for reuse across connections and workers, 256 by default. 0
+    /// allocates fresh buffers every time
+    pub fn buffer_pool_capacity(mut self, count: usize) -> Self {
+        self.buffer_pool_capacity = count;
+        self
+    }
+
+    /// reject malformed request heads with 400, or tolerate what can be made sense of
+    pub fn strict_parsing(mut self, strict: bool) -> Self {
+        self.strict_parsing = strict;
+        self
+    }
+
+    /// how paths ending in a slash are matched, stripping the slash by default
+    pub fn trailing_slash(mut self, policy: TrailingSlash) -> Self {
+        self.trailing_slash = policy;
+        self
+    }
+
+    /// `Server` header sent with every response, `None` to omit it
+    pub fn server_header(mut self, server: Option<&str>) -> Self {
+        self.server_header = server.map(String::from);
+        self
+    }
+
+    /// register `listener` for all `methods` on `pattern`
+    pub fn route<R: IntoResponse + 'static>(
+        mut self,
+        pattern: &str,
+        methods: Vec<HTTPMethod>,
+        listener: impl Fn(&HTTPRequest, &T) -> R + Send + Sync + 'static,
+    ) -> Self {
+        self.router.add(pattern, Route::new(methods, listener));
+        self
+    }
+
+    /// register a prepared route, e.g. one with a name: `.add("/", Route::new(...).name("home"))`
+    pub fn add(mut self, pattern: &str, route: Route<T>) -> Self {
+        self.router.add(pattern, route);
+        self
+    }
+
+    pub fn get<R: IntoResponse + 'static>(
+        self,
+        pattern: &str,
+        listener: impl Fn(&HTTPRequest, &T) -> R + Send + Sync + 'static,
+    ) -> Self {
+        self.route(pattern, vec![HTTPMethod::GET], listener)
+    }
+
+    pub fn head<R: IntoResponse + 'static>(
+        self,
+        pattern: &str,
+        listener: impl Fn(&HTTPRequest, &T) -> R + Send + Sync + 'static,
+    ) -> Self {
+        self.route(pattern, vec![HTTPMethod::HEAD], listener)
+    }
+
+    pub fn post<R: IntoResponse + 'static>(
+        self,
+        pattern: &str,
+        listener: impl Fn(&HTTPRequest, &T) -> R + Send + Sync + 'static,
+    ) -> Self {
+        self.route(pattern, vec![HTTPMethod::POST], listener)
+    }
+
+    pub fn put<R: IntoResponse + 'static>(
+        self,
+        pattern: &str,
+        listener: impl Fn(&HTTPRequest, &T) -> R + Send + Sync + 'static,
+    ) -> Self {
+        self.route(pattern, vec![HTTPMethod::PUT], listener)
+    }
+
+    pub fn delete<R: IntoResponse + 'static>(
+        self,
+        pattern: &str,
+        listener: impl Fn(&HTTPRequest, &T) -> R + Send + Sync + 'static,
+    ) -> Self {
+        self.route(pattern, vec![HTTPMethod::DELETE], listener)
+    }
+
+    pub fn patch<R: IntoResponse + 'static>(
+        self,
+        pattern: &str,
+        listener: impl Fn(&HTTPRequest, &T) -> R + Send + Sync + 'static,
+    ) -> Self {
+        self.route(pattern, vec![HTTPMethod::PATCH], listener)
+    }
+
+    /// answer OPTIONS for `pattern` instead of the server's automatic 204 with an Allow header
+    pub fn options<R: IntoResponse + 'static>(
+        self,
+        pattern: &str,
+        listener: impl Fn(&HTTPRequest, &T) -> R + Send + Sync + 'static,
+    ) -> Self {
+        self.route(pattern, vec![HTTPMethod::OPTIONS], listener)
+    }
+
+    /// listener for requests no route matches
+    pub fn not_found<R: IntoResponse + 'static>(
+        mut self,
+        listener: impl Fn(&HTTPRequest, &T) -> R + Send + Sync + 'static,
+    ) -> Self {
+        self.default_404_listener = Some(into_listener(listener));
+        self
+    }
+
+    /// listener for requests to a path whose routes don't accept the method.
+    /// The `Allow` header is added to its response
+    pub fn method_not_allowed<R: IntoResponse + 'static>(
+        mut self,
+        listener: impl Fn(&HTTPRequest, &T) -> R + Send + Sync + 'static,
+    ) -> Self {
+        self.default_405_listener = Some(into_listener(listener));
+        self
+    }
+
+    /// listener producing the response when a listener or middleware panics
+    pub fn internal_server_error<R: IntoResponse + 'static>(
+        mut self,
+        listener: impl Fn(&HTTPRequest, &T) -> R + Send + Sync + 'static,
+    ) -> Self {
+        self.default_500_listener = Some(into_listener(listener));
+        self
+    }
+
+    /// replace the body of the server's own `status` responses, like 404 for paths without
+    /// a route when there's no `not_found` listener, or 400 for malformed requests
+    pub fn error_page(mut self, status: u16, page: ErrorPage) -> Self {
+        self.error_pages.insert(status, page);
+        self
+    }
+
+    /// register the routes of a scope below `prefix`, e.g.
+    /// `.scope("/api", |api| api.middleware(auth).get("/users", users))`
+    pub fn scope(mut self, prefix: &str, build: impl FnOnce(Scope<T>) -> Scope<T>) -> Self {
+        for (pattern, route) in build(Scope::new(prefix)).into_routes() {
+            self.router.add(&pattern, route);
+        }
+        self
+    }
+
+    /// serve requests for `host`, like `api.example.com` or `*.example.com`, from their own
+    /// routes. Other hosts keep using the routes registered on the builder directly
+    pub fn host(mut self, host: &str, build: impl FnOnce(Scope<T>) -> Scope<T>) -> Self {
+        let router = self.hosts.entry(host.to_ascii_lowercase()).or_default();
+        for (pattern, route) in build(Scope::new("")).into_routes() {
+            router.add(&pattern, route);
+        }
+        self
+    }
+
+    /// register all routes of a separately built `router` below `prefix`
+    pub fn mount(mut self, prefix: &str, router: Router<T>) -> Self {
+        self.router.mount(prefix, router);
+        self
+    }
+
+    /// append `middleware` to the chain. Middleware runs in the order it was added
+    pub fn middleware(
+        mut self,
+        middleware: impl Fn(&mut HTTPRequest, &T, Next<T>) -> HTTPResponse + Send + Sync + 'static,
+    ) -> Self {
+        self.middleware.push(Arc::new(middleware));
+        self
+    }
+
+    /// answer GET requests for `path`, like `/openapi.json`, with the document `spec` makes of
+    /// the routes registered without a host. It is generated once when the server is built
+    #[cfg(feature = "openapi")]
+    pub fn openapi(mut self, path: &str, spec: OpenApi) -> Self {
+        self.openapi = Some((String::from(path), spec));
+        self
+    }
+
+    #[cfg_attr(not(feature = "openapi"), allow(unused_mut))]
+    pub fn build(mut self) -> HTTPServer<T> {
+        #[cfg(feature = "openapi")]
+        if let Some((path, spec)) = self.openapi.take() {
+            let document = spec.document(&self.router.routes()).to_string();
+            self = self.get(&path, move |_: &HTTPRequest, _: &T| {
+                HTTPResponse::builder().json(document.clone())
+            });
+        }
+        HTTPServer {
+            address: self.address,
+            port: self.port,
+            router: Arc::new(self.router),
+            hosts: Arc::new(self.hosts),
+            default_404_listener: Arc::new(self.default_404_listener),
+            default_405_listener: Arc::new(self.default_405_listener),
+            default_500_listener: Arc::new(self.default_500_listener),
+            error_pages: Arc::new(self.error_pages),
+            threads: self.threads,
+            max_threads: self.max_threads,
+            thread_idle_timeout: self.thread_idle_timeout,
+            max_queued_connections: self.max_queued_connections,
+            dispatch: self.dispatch,
+            connection_priority: self.connection_priority,
+            overload_retry_after: self.overload_retry_after,
+            max_connections: self.max_connections,
+            shed_connections: self.shed_connections,
+            unix_socket_mode: self.unix_socket_mode,
+            connections: ConnectionGauge::new(),
+            passthrough: Arc::new(self.passthrough),
+            keep_alive_timeout: self.keep_alive_timeout,
+            middleware: Arc::new(self.middleware),
+            server_header: self.server_header,
+            drain_timeout: self.drain_timeout,
+            shutdown_on_signals: self.shutdown_on_signals,
+            shutdown_hooks: Arc::new(self.shutdown_hooks),
+            shutdown_begin_hooks: Arc::new(self.shutdown_begin_hooks),
+            start_hooks: Arc::new(self.start_hooks),
+            connection_hooks: Arc::new(self.connection_hooks),
+            max_decompressed_body_size: self.max_decompressed_body_size,
+            strict_parsing: self.strict_parsing,
+            trailing_slash: self.trailing_slash,
+            max_header_bytes: self.max_header_bytes,
+            max_headers: self.max_headers,
+            max_body_size: self.max_body_size,
+            buffer_size: self.buffer_size,
+            buffer_pool_capacity: self.buffer_pool_capacity,
+            read_timeout: self.read_timeout,
+            write_timeout: self.write_timeout,
+            header_read_timeout: self.header_read_timeout,
+            access_log: self.access_log,
+            metrics: Arc::new(Metrics::new()),
+            metrics_endpoint: self.metrics_endpoint,
+            tunnel: self.tunnel,
+            trusted_proxies: self.trusted_proxies,
+            proxy_protocol: self.proxy_protocol,
+        }
+    }
+}
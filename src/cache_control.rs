@@ -0,0 +1,201 @@
+use std::{fmt, time::Duration};
+
+use crate::{http_server::HTTPResponse, response::HTTPResponseBuilder};
+
+/// who may store a response
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Visibility {
+    /// shared caches like proxies and CDNs too
+    Public,
+    /// only the client's own cache
+    Private,
+}
+
+/// the directives of a `Cache-Control` response header
+#[derive(Clone, Debug, Default)]
+pub struct CacheControl {
+    pub visibility: Option<Visibility>,
+    pub max_age: Option<Duration>,
+    /// `s-maxage`, the max age for shared caches
+    pub shared_max_age: Option<Duration>,
+    pub no_cache: bool,
+    pub no_store: bool,
+    pub must_revalidate: bool,
+    pub immutable: bool,
+    pub stale_while_revalidate: Option<Duration>,
+    pub stale_if_error: Option<Duration>,
+}
+
+impl CacheControl {
+    pub fn new() -> CacheControl {
+        CacheControl::default()
+    }
+
+    pub fn public(mut self) -> Self {
+        self.visibility = Some(Visibility::Public);
+        self
+    }
+
+    pub fn private(mut self) -> Self {
+        self.visibility = Some(Visibility::Private);
+        self
+    }
+
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    pub fn shared_max_age(mut self, max_age: Duration) -> Self {
+        self.shared_max_age = Some(max_age);
+        self
+    }
+
+    /// caches have to revalidate before every use
+    pub fn no_cache(mut self) -> Self {
+        self.no_cache = true;
+        self
+    }
+
+    /// nothing may store the response at all
+    pub fn no_store(mut self) -> Self {
+        self.no_store = true;
+        self
+    }
+
+    pub fn must_revalidate(mut self) -> Self {
+        self.must_revalidate = true;
+        self
+    }
+
+    /// the response never changes while fresh, like assets with a hash in their name
+    pub fn immutable(mut self) -> Self {
+        self.immutable = true;
+        self
+    }
+
+    /// how long a stale response may still be used while it's revalidated in the background
+    pub fn stale_while_revalidate(mut self, window: Duration) -> Self {
+        self.stale_while_revalidate = Some(window);
+        self
+    }
+
+    /// how long a stale response may still be used when revalidating fails
+    pub fn stale_if_error(mut self, window: Duration) -> Self {
+        self.stale_if_error = Some(window);
+        self
+    }
+}
+
+/// the value of a `Cache-Control` header
+impl fmt::Display for CacheControl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut directives = Vec::new();
+        match self.visibility {
+            Some(Visibility::Public) => directives.push(String::from("public")),
+            Some(Visibility::Private) => directives.push(String::from("private")),
+            None => {}
+        }
+        if self.no_store {
+            directives.push(String::from("no-store"));
+        }
+        if self.no_cache {
+            directives.push(String::from("no-cache"));
+        }
+        if let Some(max_age) = self.max_age {
+            directives.push(format!("max-age={}", max_age.as_secs()));
+        }
+        if let Some(max_age) = self.shared_max_age {
+            directives.push(format!("s-maxage={}", max_age.as_secs()));
+        }
+        if self.must_revalidate {
+            directives.push(String::from("must-revalidate"));
+        }
+        if self.immutable {
+            directives.push(String::from("immutable"));
+        }
+        if let Some(window) = self.stale_while_revalidate {
+            directives.push(format!("stale-while-revalidate={}", window.as_secs()));
+        }
+        if let Some(window) = self.stale_if_error {
+            directives.push(format!("stale-if-error={}", window.as_secs()));
+        }
+        f.write_str(&directives.join(", "))
+    }
+}
+
+impl HTTPResponse {
+    /// set the `Cache-Control` header, replacing any previous one
+    pub fn set_cache_control(&mut self, cache_control: &CacheControl) {
+        self.headers
+            .insert("Cache-Control", cache_control.to_string());
+    }
+}
+
+impl HTTPResponseBuilder {
+    pub fn cache_control(self, cache_control: &CacheControl) -> Self {
+        self.header("Cache-Control", &cache_control.to_string())
+    }
+
+    /// let any cache keep the response for `max_age`
+    pub fn cache_for(self, max_age: Duration) -> Self {
+        self.cache_control(&CacheControl::new().public().max_age(max_age))
+    }
+
+    /// keep the response out of every cache, like for personal data
+    pub fn no_store(self) -> Self {
+        self.cache_control(&CacheControl::new().no_store())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_directives() {
+        assert_eq!(CacheControl::new().to_string(), "");
+        let cache_control = CacheControl::new()
+            .immutable()
+            .stale_if_error(Duration::from_secs(600))
+            .must_revalidate()
+            .shared_max_age(Duration::from_secs(3600))
+            .max_age(Duration::from_millis(60_900))
+            .stale_while_revalidate(Duration::from_secs(30))
+            .public();
+        // directives keep a fixed order, and durations are whole seconds
+        assert_eq!(
+            cache_control.to_string(),
+            "public, max-age=60, s-maxage=3600, must-revalidate, immutable, \
+            stale-while-revalidate=30, stale-if-error=600"
+        );
+        assert_eq!(
+            CacheControl::new()
+                .private()
+                .no_cache()
+                .no_store()
+                .to_string(),
+            "private, no-store, no-cache"
+        );
+        // the last visibility wins
+        assert_eq!(
+            CacheControl::new().public().private().to_string(),
+            "private"
+        );
+    }
+
+    #[test]
+    fn sets_the_header() {
+        let response = HTTPResponse::builder()
+            .cache_for(Duration::from_secs(300))
+            .empty();
+        assert_eq!(
+            response.headers.get("Cache-Control"),
+            Some("public, max-age=300")
+        );
+        let mut response = HTTPResponse::builder().no_store().empty();
+        assert_eq!(response.headers.get("Cache-Control"), Some("no-store"));
+        response.set_cache_control(&CacheControl::new().no_cache());
+        assert_eq!(response.headers.get_all("Cache-Control"), ["no-cache"]);
+    }
+}
@@ -0,0 +1,224 @@
+use crate::{
+    deflate::{self, InflateError},
+    http_server::{Body, HTTPRequest, HTTPResponse},
+    middleware::Next,
+    negotiation::{coding_matches, quality_of},
+};
+
+/// when responses are worth compressing
+pub struct GzipConfig {
+    /// bodies smaller than this are sent as is
+    pub min_size: usize,
+    /// Content-Type prefixes that compress well
+    pub content_types: Vec<String>,
+}
+
+impl Default for GzipConfig {
+    fn default() -> GzipConfig {
+        GzipConfig {
+            min_size: 1024,
+            content_types: [
+                "text/",
+                "application/json",
+                "application/javascript",
+                "application/xml",
+                "application/wasm",
+                "image/svg+xml",
+            ]
+            .map(String::from)
+            .to_vec(),
+        }
+    }
+}
+
+/// middleware compressing responses with the default `GzipConfig`
+pub fn gzip<T>(request: &mut HTTPRequest, passthrough: &T, next: Next<T>) -> HTTPResponse {
+    let accepts_gzip = accepts_encoding(request, "gzip");
+    let response = next.run(request, passthrough);
+    if accepts_gzip {
+        compress(response, &GzipConfig::default())
+    } else {
+        response
+    }
+}
+
+/// compress `response` if `request` accepts gzip and the body qualifies under `config`,
+/// for use in single routes or with a custom configuration
+pub fn gzip_response(
+    request: &HTTPRequest,
+    response: HTTPResponse,
+    config: &GzipConfig,
+) -> HTTPResponse {
+    if accepts_encoding(request, "gzip") {
+        compress(response, config)
+    } else {
+        response
+    }
+}
+
+fn compress(mut response: HTTPResponse, config: &GzipConfig) -> HTTPResponse {
+    let compressible = response
+        .headers
+        .get("Content-Type")
+        .is_some_and(|content_type| {
+            config
+                .content_types
+                .iter()
+                .any(|prefix| content_type.starts_with(prefix.as_str()))
+        });
+    if !compressible
+        || response.headers.contains("Content-Encoding")
+        || matches!(response.status.status, 204 | 206 | 304)
+    {
+        return response;
+    }
+
+    let Body::Full(ref body) = response.body else {
+        return response;
+    };
+    if body.len() < config.min_size {
+        return response;
+    }
+    let compressed = deflate::gzip(body);
+
+    let vary = match response.headers.get("Vary") {
+        Some(vary) => format!("{}, Accept-Encoding", vary),
+        None => String::from("Accept-Encoding"),
+    };
+    // the compressed body is a different representation, so a strong ETag no longer applies
+    let etag = response
+        .headers
+        .get("ETag")
+        .map(|etag| match etag.starts_with("W/") {
+            true => String::from(etag),
+            false => format!("W/{}", etag),
+        });
+
+    response
+        .headers
+        .insert("Content-Length", compressed.len().to_string());
+    response.headers.insert("Content-Encoding", "gzip");
+    response.headers.insert("Vary", vary);
+    if let Some(etag) = etag {
+        response.headers.insert("ETag", etag);
+    }
+    response.body = Body::Full(compressed);
+    response
+}
+
+/// check if `encoding` is given a non zero quality in `Accept-Encoding`, directly or by `*`
+pub fn accepts_encoding(request: &HTTPRequest, encoding: &str) -> bool {
+    let Some(accepted) = request.header("Accept-Encoding") else {
+        return false;
+    };
+    quality_of(accepted, encoding, coding_matches) > 0.0
+}
+
+#[derive(Debug)]
+pub(crate) enum DecodeError {
+    Unsupported,
+    Invalid,
+    TooLarge,
+}
+
+impl DecodeError {
+    pub(crate) fn response(&self) -> HTTPResponse {
+        match self {
+            DecodeError::Unsupported => HTTPResponse::builder()
+                .status(415)
+                .header("Accept-Encoding", "gzip, deflate")
+                .text("Unsupported Content-Encoding"),
+            DecodeError::Invalid => HTTPResponse::builder()
+                .status(400)
+                .text("Received invalid data"),
+            DecodeError::TooLarge => HTTPResponse::builder()
+                .status(413)
+                .text("Decoded request body too large"),
+        }
+    }
+}
+
+/// undo the comma separated `Content-Encoding`s of a request body, producing at most `limit` bytes
+pub(crate) fn decode_body(
+    encodings: &str,
+    mut body: Vec<u8>,
+    limit: usize,
+) -> Result<Vec<u8>, DecodeError> {
+    // encodings are listed in the order they were applied
+    for encoding in encodings.rsplit(',').map(str::trim) {
+        let decoded = match encoding.to_ascii_lowercase().as_str() {
+            "identity" | "" => continue,
+            "gzip" | "x-gzip" => deflate::gunzip(&body, limit),
+            // deflate is meant to be zlib wrapped, but some clients send raw streams
+            "deflate" => match deflate::zlib_decompress(&body, limit) {
+                Err(InflateError::Invalid) => {
+                    deflate::inflate_raw(&body, limit).map(|(data, _)| data)
+                }
+                decoded => decoded,
+            },
+            _ => return Err(DecodeError::Unsupported),
+        };
+        body = decoded.map_err(|error| match error {
+            InflateError::Invalid => DecodeError::Invalid,
+            InflateError::TooLarge => DecodeError::TooLarge,
+        })?;
+    }
+    Ok(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodings_are_undone_in_reverse() {
+        let body = b"encoded twice".to_vec();
+        let encoded = deflate::gzip(&deflate::deflate(&body));
+        assert_eq!(decode_body("deflate, gzip", encoded, 1024).unwrap(), body);
+    }
+
+    #[test]
+    fn deflate_may_be_raw_or_zlib_wrapped() {
+        let raw = deflate::deflate(b"body");
+        assert_eq!(decode_body("deflate", raw, 1024).unwrap(), b"body");
+        let zlib = b"\x78\x9c\x4b\xca\x4f\xa9\x04\x00\x04\x1a\x01\xaf".to_vec();
+        assert_eq!(decode_body("deflate", zlib, 1024).unwrap(), b"body");
+    }
+
+    #[test]
+    fn identity_and_case_are_ignored() {
+        let encoded = deflate::gzip(b"body");
+        assert_eq!(
+            decode_body("identity, GZIP", encoded, 1024).unwrap(),
+            b"body"
+        );
+        assert_eq!(
+            decode_body("identity", b"raw".to_vec(), 1024).unwrap(),
+            b"raw"
+        );
+    }
+
+    #[test]
+    fn unknown_encodings_are_unsupported() {
+        let error = decode_body("br", b"body".to_vec(), 1024).unwrap_err();
+        assert!(matches!(error, DecodeError::Unsupported));
+        assert_eq!(error.response().status.status, 415);
+    }
+
+    #[test]
+    fn malformed_bodies_are_invalid() {
+        let mut encoded = deflate::gzip(b"body");
+        encoded.truncate(encoded.len() - 4);
+        let error = decode_body("gzip", encoded, 1024).unwrap_err();
+        assert!(matches!(error, DecodeError::Invalid));
+        assert_eq!(error.response().status.status, 400);
+    }
+
+    #[test]
+    fn decoded_bodies_beyond_the_limit_are_too_large() {
+        let encoded = deflate::gzip(&[b'x'; 4096]);
+        let error = decode_body("gzip", encoded, 4095).unwrap_err();
+        assert!(matches!(error, DecodeError::TooLarge));
+        assert_eq!(error.response().status.status, 413);
+    }
+}
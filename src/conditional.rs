@@ -0,0 +1,215 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{
+    http_date::{format_http_date, parse_http_date},
+    http_server::{Body, HTTPMethod, HTTPRequest, HTTPResponse},
+    response::HTTPResponseBuilder,
+};
+
+/// strong ETag derived from the content itself
+pub fn etag_for(content: &[u8]) -> String {
+    // 64 bit FNV-1a, plenty to tell versions of a representation apart
+    let hash = content.iter().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    });
+    format!("\"{:016x}\"", hash)
+}
+
+/// weak ETag derived from a file's size and modification time, avoiding reading its content
+pub fn weak_etag(length: u64, modified: SystemTime) -> String {
+    let modified = modified
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    format!("W/\"{:x}-{:x}\"", length, modified)
+}
+
+/// evaluate `If-None-Match` and `If-Modified-Since` against the current validators of the resource.
+/// Returns true if the client's copy of a GET or HEAD is still fresh and 304 should be sent
+pub fn is_not_modified(
+    request: &HTTPRequest,
+    etag: Option<&str>,
+    last_modified: Option<SystemTime>,
+) -> bool {
+    if !is_safe(request) {
+        return false;
+    }
+    // If-None-Match takes precedence over If-Modified-Since
+    if let Some(matched) = none_match(request, etag) {
+        return matched;
+    }
+
+    match (
+        request
+            .header("If-Modified-Since")
+            .and_then(parse_http_date),
+        last_modified,
+    ) {
+        (Some(since), Some(modified)) => truncate_secs(modified) <= truncate_secs(since),
+        _ => false,
+    }
+}
+
+/// whether `If-None-Match` matches on a method other than GET and HEAD, which must then fail
+/// with 412 rather than act on the resource
+pub fn precondition_failed(request: &HTTPRequest, etag: Option<&str>) -> bool {
+    !is_safe(request) && none_match(request, etag) == Some(true)
+}
+
+/// the 304 or 412 the request's preconditions call for, `None` if it should be answered as usual
+pub fn check_preconditions(
+    request: &HTTPRequest,
+    etag: Option<&str>,
+    last_modified: Option<SystemTime>,
+) -> Option<HTTPResponse> {
+    if is_not_modified(request, etag, last_modified) {
+        return Some(not_modified(etag, last_modified));
+    }
+    if precondition_failed(request, etag) {
+        return Some(HTTPResponse::builder().status(412).empty());
+    }
+    None
+}
+
+/// 304 Not Modified carrying the validators of the resource
+pub fn not_modified(etag: Option<&str>, last_modified: Option<SystemTime>) -> HTTPResponse {
+    validators(HTTPResponse::builder().status(304), etag, last_modified).empty()
+}
+
+/// add an ETag (computed from the body unless the handler set one) to a full response and
+/// answer with 304 if the client already has it
+pub fn conditional_response(request: &HTTPRequest, mut response: HTTPResponse) -> HTTPResponse {
+    if response.status.status != 200 {
+        return response;
+    }
+
+    let etag = match response.headers.get("ETag") {
+        Some(etag) => String::from(etag),
+        None => match response.body {
+            Body::Full(ref body) => {
+                let etag = etag_for(body);
+                response.headers.insert("ETag", etag.clone());
+                etag
+            }
+            _ => return response,
+        },
+    };
+    let last_modified = response
+        .headers
+        .get("Last-Modified")
+        .and_then(parse_http_date);
+
+    check_preconditions(request, Some(&etag), last_modified).unwrap_or(response)
+}
+
+/// whether `If-None-Match` lists `etag` or `*`, `None` without the header
+fn none_match(request: &HTTPRequest, etag: Option<&str>) -> Option<bool> {
+    let if_none_match = request.header("If-None-Match")?;
+    Some(match etag {
+        Some(etag) => if_none_match
+            .split(',')
+            .map(str::trim)
+            .any(|candidate| candidate == "*" || weak_eq(candidate, etag)),
+        None => false,
+    })
+}
+
+fn is_safe(request: &HTTPRequest) -> bool {
+    request.method == HTTPMethod::GET || request.method == HTTPMethod::HEAD
+}
+
+/// set `ETag` and `Last-Modified` if present
+fn validators(
+    mut builder: HTTPResponseBuilder,
+    etag: Option<&str>,
+    last_modified: Option<SystemTime>,
+) -> HTTPResponseBuilder {
+    if let Some(etag) = etag {
+        builder = builder.header("ETag", etag);
+    }
+    if let Some(modified) = last_modified {
+        builder = builder.header("Last-Modified", &format_http_date(modified));
+    }
+    builder
+}
+
+/// weak comparison, ignoring the `W/` prefix
+fn weak_eq(a: &str, b: &str) -> bool {
+    a.trim_start_matches("W/") == b.trim_start_matches("W/")
+}
+
+/// http dates have second precision
+fn truncate_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::*;
+
+    fn request(method: HTTPMethod, name: &str, value: &str) -> HTTPRequest {
+        HTTPRequest::builder()
+            .method(method)
+            .path("/")
+            .header(name, value)
+            .build()
+    }
+
+    #[test]
+    fn matching_etag_on_get_is_not_modified() {
+        let request = request(HTTPMethod::GET, "If-None-Match", "\"a\", W/\"b\"");
+        let response = check_preconditions(&request, Some("\"b\""), None).unwrap();
+        assert_eq!(response.status.status, 304);
+        assert_eq!(response.headers.get("ETag"), Some("\"b\""));
+    }
+
+    #[test]
+    fn matching_etag_on_other_methods_fails() {
+        for method in [HTTPMethod::POST, HTTPMethod::PUT, HTTPMethod::DELETE] {
+            let request = request(method, "If-None-Match", "*");
+            assert!(!is_not_modified(&request, Some("\"a\""), None));
+            let response = check_preconditions(&request, Some("\"a\""), None).unwrap();
+            assert_eq!(response.status.status, 412);
+        }
+    }
+
+    #[test]
+    fn other_etag_is_answered_as_usual() {
+        let request = request(HTTPMethod::PUT, "If-None-Match", "\"a\"");
+        assert!(check_preconditions(&request, Some("\"b\""), None).is_none());
+        assert!(check_preconditions(&request, None, None).is_none());
+    }
+
+    #[test]
+    fn if_modified_since_only_applies_to_get_and_head() {
+        let modified = UNIX_EPOCH + Duration::from_secs(784111777);
+        let since = "Sun, 06 Nov 1994 08:49:37 GMT";
+        let get = request(HTTPMethod::GET, "If-Modified-Since", since);
+        assert!(is_not_modified(&get, None, Some(modified)));
+        assert!(!is_not_modified(
+            &get,
+            None,
+            Some(modified + Duration::from_secs(1))
+        ));
+        let post = request(HTTPMethod::POST, "If-Modified-Since", since);
+        assert!(check_preconditions(&post, None, Some(modified)).is_none());
+    }
+
+    #[test]
+    fn conditional_response_adds_an_etag() {
+        let response = conditional_response(
+            &HTTPRequest::builder().path("/").build(),
+            HTTPResponse::builder().text("hello"),
+        );
+        let etag = response.headers.get("ETag").unwrap().to_owned();
+        assert_eq!(etag, etag_for(b"hello"));
+
+        let request = request(HTTPMethod::GET, "If-None-Match", &etag);
+        let response = conditional_response(&request, HTTPResponse::builder().text("hello"));
+        assert_eq!(response.status.status, 304);
+    }
+}
@@ -0,0 +1,225 @@
+use std::{collections::HashMap, fmt, time::Duration, time::SystemTime};
+
+use crate::{
+    http_date::format_http_date,
+    http_server::{HTTPRequest, HTTPResponse},
+    response::HTTPResponseBuilder,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SameSite {
+    Strict,
+    Lax,
+    None,
+}
+
+/// a cookie to be sent with `Set-Cookie`
+#[derive(Clone, Debug)]
+pub struct Cookie {
+    pub name: String,
+    pub value: String,
+    pub path: Option<String>,
+    pub domain: Option<String>,
+    pub max_age: Option<Duration>,
+    pub expires: Option<SystemTime>,
+    pub http_only: bool,
+    pub secure: bool,
+    pub same_site: Option<SameSite>,
+}
+
+impl Cookie {
+    pub fn new(name: &str, value: &str) -> Cookie {
+        Cookie {
+            name: String::from(name),
+            value: String::from(value),
+            path: None,
+            domain: None,
+            max_age: None,
+            expires: None,
+            http_only: false,
+            secure: false,
+            same_site: None,
+        }
+    }
+
+    /// a cookie telling the client to delete `name`
+    pub fn removal(name: &str) -> Cookie {
+        Cookie::new(name, "")
+            .max_age(Duration::ZERO)
+            .expires(SystemTime::UNIX_EPOCH)
+    }
+
+    pub fn path(mut self, path: &str) -> Self {
+        self.path = Some(String::from(path));
+        self
+    }
+
+    pub fn domain(mut self, domain: &str) -> Self {
+        self.domain = Some(String::from(domain));
+        self
+    }
+
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    pub fn expires(mut self, expires: SystemTime) -> Self {
+        self.expires = Some(expires);
+        self
+    }
+
+    pub fn http_only(mut self, http_only: bool) -> Self {
+        self.http_only = http_only;
+        self
+    }
+
+    pub fn secure(mut self, secure: bool) -> Self {
+        self.secure = secure;
+        self
+    }
+
+    pub fn same_site(mut self, same_site: SameSite) -> Self {
+        self.same_site = Some(same_site);
+        self
+    }
+}
+
+/// the value of a `Set-Cookie` header
+impl fmt::Display for Cookie {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.name, self.value)?;
+        if let Some(ref path) = self.path {
+            write!(f, "; Path={}", path)?;
+        }
+        if let Some(ref domain) = self.domain {
+            write!(f, "; Domain={}", domain)?;
+        }
+        if let Some(max_age) = self.max_age {
+            write!(f, "; Max-Age={}", max_age.as_secs())?;
+        }
+        if let Some(expires) = self.expires {
+            write!(f, "; Expires={}", format_http_date(expires))?;
+        }
+        if self.http_only {
+            f.write_str("; HttpOnly")?;
+        }
+        if self.secure {
+            f.write_str("; Secure")?;
+        }
+        match self.same_site {
+            Some(SameSite::Strict) => f.write_str("; SameSite=Strict"),
+            Some(SameSite::Lax) => f.write_str("; SameSite=Lax"),
+            Some(SameSite::None) => f.write_str("; SameSite=None"),
+            None => Ok(()),
+        }
+    }
+}
+
+/// parse a `Cookie` request header into name/value pairs
+pub fn parse_cookies(header: &str) -> HashMap<&str, &str> {
+    header
+        .split(';')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(name, value)| {
+            let value = value.trim();
+            let value = value
+                .strip_prefix('"')
+                .and_then(|v| v.strip_suffix('"'))
+                .unwrap_or(value);
+            (name.trim(), value)
+        })
+        .filter(|(name, _)| !name.is_empty())
+        .collect()
+}
+
+impl HTTPRequest {
+    /// cookies sent by the client
+    pub fn cookies(&self) -> HashMap<&str, &str> {
+        self.header("Cookie").map(parse_cookies).unwrap_or_default()
+    }
+
+    pub fn cookie(&self, name: &str) -> Option<&str> {
+        self.cookies().get(name).copied()
+    }
+}
+
+impl HTTPResponse {
+    /// add a `Set-Cookie` header, keeping previously set cookies
+    pub fn set_cookie(&mut self, cookie: &Cookie) {
+        self.headers.append("Set-Cookie", cookie.to_string());
+    }
+}
+
+impl HTTPResponseBuilder {
+    pub fn cookie(self, cookie: &Cookie) -> Self {
+        self.append_header("Set-Cookie", &cookie.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_cookie_headers() {
+        let cookies =
+            parse_cookies("session=abc123; theme=\"dark\"; empty=; =nameless; flag; a=b=c");
+        assert_eq!(cookies.len(), 4);
+        assert_eq!(cookies["session"], "abc123");
+        assert_eq!(cookies["theme"], "dark");
+        assert_eq!(cookies["empty"], "");
+        assert_eq!(cookies["a"], "b=c");
+
+        let request = HTTPRequest::builder()
+            .header("Cookie", " lang = en ;id=7")
+            .build();
+        assert_eq!(request.cookie("lang"), Some("en"));
+        assert_eq!(request.cookie("id"), Some("7"));
+        assert_eq!(request.cookie("session"), None);
+        assert!(HTTPRequest::builder().build().cookies().is_empty());
+    }
+
+    #[test]
+    fn formats_attributes() {
+        assert_eq!(Cookie::new("id", "7").to_string(), "id=7");
+        let cookie = Cookie::new("session", "abc")
+            .path("/app")
+            .domain("example.com")
+            .max_age(Duration::from_secs(3600))
+            .expires(SystemTime::UNIX_EPOCH + Duration::from_secs(784111777))
+            .http_only(true)
+            .secure(true)
+            .same_site(SameSite::Lax);
+        assert_eq!(
+            cookie.to_string(),
+            "session=abc; Path=/app; Domain=example.com; Max-Age=3600; \
+            Expires=Sun, 06 Nov 1994 08:49:37 GMT; HttpOnly; Secure; SameSite=Lax"
+        );
+        let cookie = Cookie::new("a", "b").same_site(SameSite::None).secure(true);
+        assert_eq!(cookie.to_string(), "a=b; Secure; SameSite=None");
+        let cookie = Cookie::new("a", "b").same_site(SameSite::Strict);
+        assert_eq!(cookie.to_string(), "a=b; SameSite=Strict");
+    }
+
+    #[test]
+    fn removal_expires_the_cookie() {
+        assert_eq!(
+            Cookie::removal("session").to_string(),
+            "session=; Max-Age=0; Expires=Thu, 01 Jan 1970 00:00:00 GMT"
+        );
+    }
+
+    #[test]
+    fn responses_keep_every_cookie() {
+        let mut response = HTTPResponse::builder()
+            .cookie(&Cookie::new("a", "1"))
+            .cookie(&Cookie::new("b", "2"))
+            .text("");
+        response.set_cookie(&Cookie::new("c", "3").path("/"));
+        assert_eq!(
+            response.headers.get_all("Set-Cookie"),
+            ["a=1", "b=2", "c=3; Path=/"]
+        );
+    }
+}
@@ -0,0 +1,681 @@
+// minimal DEFLATE (RFC 1951), zlib (RFC 1950) and gzip (RFC 1952) support, so compression doesn't need a dependency.
+// Blocks are encoded with the fixed huffman codes after a hash chain LZ77 pass, decoding handles all block types
+
+const WINDOW_SIZE: usize = 32768;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const MAX_CHAIN: usize = 64;
+const HASH_BITS: usize = 15;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+/// compress `data` into a gzip member
+pub fn gzip(data: &[u8]) -> Vec<u8> {
+    // magic, deflate, no flags, no mtime, no extra flags, unknown os
+    let mut out = vec![0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 255];
+    out.extend(deflate(data));
+    out.extend(crc32(data).to_le_bytes());
+    out.extend((data.len() as u32).to_le_bytes());
+    out
+}
+
+/// compress `data` into a raw deflate stream
+pub fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut writer = BitWriter::new();
+    writer.write_bits(1, 1); // final block
+    writer.write_bits(1, 2); // fixed huffman codes
+
+    let mut head = vec![usize::MAX; 1 << HASH_BITS];
+    let mut prev = vec![usize::MAX; WINDOW_SIZE];
+    let mut i = 0;
+
+    while i < data.len() {
+        let (length, distance) = longest_match(data, i, &head, &prev);
+
+        let advance = if length >= MIN_MATCH {
+            write_length(&mut writer, length);
+            write_distance(&mut writer, distance);
+            length
+        } else {
+            write_literal(&mut writer, data[i] as u16);
+            1
+        };
+
+        for position in i..(i + advance).min(data.len().saturating_sub(MIN_MATCH - 1)) {
+            let hash = hash(data, position);
+            prev[position % WINDOW_SIZE] = head[hash];
+            head[hash] = position;
+        }
+        i += advance;
+    }
+
+    write_literal(&mut writer, 256); // end of block
+    writer.finish()
+}
+
+/// the standard CRC-32 used by gzip
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xedb88320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn hash(data: &[u8], i: usize) -> usize {
+    let value = (data[i] as usize) << 16 | (data[i + 1] as usize) << 8 | data[i + 2] as usize;
+    (value.wrapping_mul(2654435761) >> 8) & ((1 << HASH_BITS) - 1)
+}
+
+fn longest_match(data: &[u8], i: usize, head: &[usize], prev: &[usize]) -> (usize, usize) {
+    if i + MIN_MATCH > data.len() {
+        return (0, 0);
+    }
+
+    let max_length = MAX_MATCH.min(data.len() - i);
+    let mut best = (0, 0);
+    let mut candidate = head[hash(data, i)];
+    let mut chain = 0;
+
+    while candidate != usize::MAX && i - candidate <= WINDOW_SIZE && chain < MAX_CHAIN {
+        let length = data[candidate..]
+            .iter()
+            .zip(&data[i..i + max_length])
+            .take_while(|(a, b)| a == b)
+            .count();
+        if length > best.0 {
+            best = (length, i - candidate);
+            if length == max_length {
+                break;
+            }
+        }
+
+        let next = prev[candidate % WINDOW_SIZE];
+        // older entries of the ring buffer may have been overwritten by newer positions
+        if next == usize::MAX || next >= candidate {
+            break;
+        }
+        candidate = next;
+        chain += 1;
+    }
+    best
+}
+
+fn write_literal(writer: &mut BitWriter, symbol: u16) {
+    let (code, length) = match symbol {
+        0..=143 => (0x30 + symbol, 8),
+        144..=255 => (0x190 + symbol - 144, 9),
+        256..=279 => (symbol - 256, 7),
+        _ => (0xc0 + symbol - 280, 8),
+    };
+    writer.write_code(code as u32, length);
+}
+
+fn write_length(writer: &mut BitWriter, length: usize) {
+    let index = LENGTH_BASE
+        .iter()
+        .rposition(|base| *base as usize <= length)
+        .unwrap();
+    write_literal(writer, 257 + index as u16);
+    writer.write_bits(
+        (length - LENGTH_BASE[index] as usize) as u32,
+        LENGTH_EXTRA[index],
+    );
+}
+
+fn write_distance(writer: &mut BitWriter, distance: usize) {
+    let index = DIST_BASE
+        .iter()
+        .rposition(|base| *base as usize <= distance)
+        .unwrap();
+    writer.write_code(index as u32, 5);
+    writer.write_bits(
+        (distance - DIST_BASE[index] as usize) as u32,
+        DIST_EXTRA[index],
+    );
+}
+
+/// packs bits least significant first, as deflate expects
+struct BitWriter {
+    out: Vec<u8>,
+    buffer: u64,
+    count: u8,
+}
+
+impl BitWriter {
+    fn new() -> BitWriter {
+        BitWriter {
+            out: Vec::new(),
+            buffer: 0,
+            count: 0,
+        }
+    }
+
+    fn write_bits(&mut self, value: u32, count: u8) {
+        self.buffer |= (value as u64) << self.count;
+        self.count += count;
+        while self.count >= 8 {
+            self.out.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    /// huffman codes are packed starting with their most significant bit
+    fn write_code(&mut self, code: u32, length: u8) {
+        let reversed = code.reverse_bits() >> (32 - length as u32);
+        self.write_bits(reversed, length);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.out.push(self.buffer as u8);
+        }
+        self.out
+    }
+}
+
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum InflateError {
+    /// the data isn't a valid stream
+    Invalid,
+    /// the decompressed data would exceed the limit
+    TooLarge,
+}
+
+/// decompress a gzip member, refusing to produce more than `limit` bytes
+pub fn gunzip(data: &[u8], limit: usize) -> Result<Vec<u8>, InflateError> {
+    if data.len() < 18 || data[0] != 0x1f || data[1] != 0x8b || data[2] != 8 {
+        return Err(InflateError::Invalid);
+    }
+    let flags = data[3];
+    let mut position = 10;
+
+    let skip_to = |position: usize, length: usize| {
+        let end = position + length;
+        (end <= data.len())
+            .then_some(end)
+            .ok_or(InflateError::Invalid)
+    };
+    let skip_zero_terminated = |position: usize| {
+        data[position.min(data.len())..]
+            .iter()
+            .position(|byte| *byte == 0)
+            .map(|end| position + end + 1)
+            .ok_or(InflateError::Invalid)
+    };
+
+    if flags & 0x04 != 0 {
+        // FEXTRA
+        let length = *data.get(position).ok_or(InflateError::Invalid)? as usize
+            | (*data.get(position + 1).ok_or(InflateError::Invalid)? as usize) << 8;
+        position = skip_to(position + 2, length)?;
+    }
+    if flags & 0x08 != 0 {
+        // FNAME
+        position = skip_zero_terminated(position)?;
+    }
+    if flags & 0x10 != 0 {
+        // FCOMMENT
+        position = skip_zero_terminated(position)?;
+    }
+    if flags & 0x02 != 0 {
+        // FHCRC
+        position = skip_to(position, 2)?;
+    }
+
+    let (out, consumed) = inflate_raw(&data[position.min(data.len())..], limit)?;
+    let trailer = data
+        .get(position + consumed..position + consumed + 8)
+        .ok_or(InflateError::Invalid)?;
+    let crc = u32::from_le_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+    let size = u32::from_le_bytes([trailer[4], trailer[5], trailer[6], trailer[7]]);
+    if crc != crc32(&out) || size != out.len() as u32 {
+        return Err(InflateError::Invalid);
+    }
+    Ok(out)
+}
+
+/// decompress a zlib stream, refusing to produce more than `limit` bytes
+pub fn zlib_decompress(data: &[u8], limit: usize) -> Result<Vec<u8>, InflateError> {
+    if data.len() < 6
+        || data[0] & 0x0f != 8
+        || !(data[0] as u16 * 256 + data[1] as u16).is_multiple_of(31)
+        || data[1] & 0x20 != 0
+    {
+        return Err(InflateError::Invalid);
+    }
+    let (out, consumed) = inflate_raw(&data[2..], limit)?;
+    let trailer = data
+        .get(2 + consumed..2 + consumed + 4)
+        .ok_or(InflateError::Invalid)?;
+    if u32::from_be_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]) != adler32(&out) {
+        return Err(InflateError::Invalid);
+    }
+    Ok(out)
+}
+
+/// decompress a raw deflate stream. Returns the data and the number of input bytes consumed
+pub fn inflate_raw(data: &[u8], limit: usize) -> Result<(Vec<u8>, usize), InflateError> {
+    let mut reader = BitReader {
+        data,
+        position: 0,
+        bit: 0,
+    };
+    let mut out = Vec::new();
+
+    loop {
+        let last = reader.bits(1)? == 1;
+        match reader.bits(2)? {
+            0 => {
+                reader.align();
+                let length = reader.bytes(2)?;
+                let length = u16::from_le_bytes([length[0], length[1]]);
+                let inverted = reader.bytes(2)?;
+                if length != !u16::from_le_bytes([inverted[0], inverted[1]]) {
+                    return Err(InflateError::Invalid);
+                }
+                if out.len() + length as usize > limit {
+                    return Err(InflateError::TooLarge);
+                }
+                out.extend_from_slice(reader.bytes(length as usize)?);
+            }
+            1 => {
+                let mut lengths = [0u8; 288 + 30];
+                lengths[..144].fill(8);
+                lengths[144..256].fill(9);
+                lengths[256..280].fill(7);
+                lengths[280..288].fill(8);
+                lengths[288..].fill(5);
+                let literals = Huffman::new(&lengths[..288])?;
+                let distances = Huffman::new(&lengths[288..])?;
+                inflate_block(&mut reader, &mut out, &literals, &distances, limit)?;
+            }
+            2 => {
+                let (literals, distances) = read_dynamic_tables(&mut reader)?;
+                inflate_block(&mut reader, &mut out, &literals, &distances, limit)?;
+            }
+            _ => return Err(InflateError::Invalid),
+        }
+        if last {
+            break;
+        }
+    }
+
+    reader.align();
+    Ok((out, reader.position))
+}
+
+fn read_dynamic_tables(reader: &mut BitReader) -> Result<(Huffman, Huffman), InflateError> {
+    let literal_count = reader.bits(5)? as usize + 257;
+    let distance_count = reader.bits(5)? as usize + 1;
+    let code_length_count = reader.bits(4)? as usize + 4;
+
+    let mut code_lengths = [0u8; 19];
+    for index in CODE_LENGTH_ORDER.iter().take(code_length_count) {
+        code_lengths[*index] = reader.bits(3)? as u8;
+    }
+    let code_lengths = Huffman::new(&code_lengths)?;
+
+    let mut lengths = Vec::with_capacity(literal_count + distance_count);
+    while lengths.len() < literal_count + distance_count {
+        let (value, repeat) = match code_lengths.decode(reader)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => (
+                *lengths.last().ok_or(InflateError::Invalid)?,
+                3 + reader.bits(2)?,
+            ),
+            17 => (0, 3 + reader.bits(3)?),
+            _ => (0, 11 + reader.bits(7)?),
+        };
+        if lengths.len() + repeat as usize > literal_count + distance_count {
+            return Err(InflateError::Invalid);
+        }
+        lengths.extend(std::iter::repeat_n(value, repeat as usize));
+    }
+
+    Ok((
+        Huffman::new(&lengths[..literal_count])?,
+        Huffman::new(&lengths[literal_count..])?,
+    ))
+}
+
+fn inflate_block(
+    reader: &mut BitReader,
+    out: &mut Vec<u8>,
+    literals: &Huffman,
+    distances: &Huffman,
+    limit: usize,
+) -> Result<(), InflateError> {
+    loop {
+        let symbol = literals.decode(reader)? as usize;
+        match symbol {
+            0..=255 => {
+                if out.len() >= limit {
+                    return Err(InflateError::TooLarge);
+                }
+                out.push(symbol as u8);
+            }
+            256 => return Ok(()),
+            257..=285 => {
+                let index = symbol - 257;
+                let length =
+                    LENGTH_BASE[index] as usize + reader.bits(LENGTH_EXTRA[index])? as usize;
+                let index = distances.decode(reader)? as usize;
+                if index >= 30 {
+                    return Err(InflateError::Invalid);
+                }
+                let distance = DIST_BASE[index] as usize + reader.bits(DIST_EXTRA[index])? as usize;
+                if distance > out.len() {
+                    return Err(InflateError::Invalid);
+                }
+                if out.len() + length > limit {
+                    return Err(InflateError::TooLarge);
+                }
+                // copies may overlap with the bytes they produce
+                let start = out.len() - distance;
+                for i in 0..length {
+                    out.push(out[start + i]);
+                }
+            }
+            _ => return Err(InflateError::Invalid),
+        }
+    }
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in data.chunks(5552) {
+        for byte in chunk {
+            a += *byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    b << 16 | a
+}
+
+/// canonical huffman decoding table
+struct Huffman {
+    /// number of codes of each length
+    counts: [u16; 16],
+    /// symbols ordered by their code
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Huffman, InflateError> {
+        let mut counts = [0u16; 16];
+        for length in lengths {
+            counts[*length as usize] += 1;
+        }
+        counts[0] = 0;
+
+        // reject over-subscribed code sets
+        let mut left = 1i32;
+        for count in &counts[1..] {
+            left = (left << 1) - *count as i32;
+            if left < 0 {
+                return Err(InflateError::Invalid);
+            }
+        }
+
+        let mut offsets = [0u16; 16];
+        for length in 1..15 {
+            offsets[length + 1] = offsets[length] + counts[length];
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, length) in lengths.iter().enumerate() {
+            if *length != 0 {
+                symbols[offsets[*length as usize] as usize] = symbol as u16;
+                offsets[*length as usize] += 1;
+            }
+        }
+        Ok(Huffman { counts, symbols })
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u16, InflateError> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for length in 1..16 {
+            code |= reader.bits(1)? as i32;
+            let count = self.counts[length] as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(InflateError::Invalid)
+    }
+}
+
+/// reads bits least significant first
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    bit: u8,
+}
+
+impl BitReader<'_> {
+    fn bits(&mut self, count: u8) -> Result<u32, InflateError> {
+        let mut value = 0;
+        for i in 0..count {
+            let byte = *self.data.get(self.position).ok_or(InflateError::Invalid)?;
+            value |= ((byte >> self.bit) as u32 & 1) << i;
+            self.bit += 1;
+            if self.bit == 8 {
+                self.bit = 0;
+                self.position += 1;
+            }
+        }
+        Ok(value)
+    }
+
+    fn align(&mut self) {
+        if self.bit != 0 {
+            self.bit = 0;
+            self.position += 1;
+        }
+    }
+
+    fn bytes(&mut self, count: usize) -> Result<&[u8], InflateError> {
+        let bytes = self
+            .data
+            .get(self.position..self.position + count)
+            .ok_or(InflateError::Invalid)?;
+        self.position += count;
+        Ok(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// bytes that don't repeat, from a xorshift generator
+    fn noise(length: usize) -> Vec<u8> {
+        let mut state = 0x2545f4914f6cdd1du64;
+        (0..length)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect()
+    }
+
+    fn roundtrip(data: &[u8]) {
+        let (inflated, consumed) = inflate_raw(&deflate(data), data.len()).unwrap();
+        assert_eq!(inflated, data);
+        assert_eq!(consumed, deflate(data).len());
+        assert_eq!(gunzip(&gzip(data), data.len()).unwrap(), data);
+    }
+
+    #[test]
+    fn empty_roundtrip() {
+        roundtrip(b"");
+    }
+
+    #[test]
+    fn incompressible_roundtrip() {
+        roundtrip(&noise(100_000));
+    }
+
+    #[test]
+    fn long_repeat_roundtrip() {
+        let data = vec![b'a'; 1_000_000];
+        let compressed = deflate(&data);
+        // every match is at most 258 bytes, which fixed codes encode in about two bytes
+        assert!(compressed.len() < data.len() / 100);
+        roundtrip(&data);
+    }
+
+    #[test]
+    fn repeats_beyond_the_window_roundtrip() {
+        let block = noise(WINDOW_SIZE / 2);
+        let data: Vec<u8> = block
+            .iter()
+            .cycle()
+            .take(WINDOW_SIZE * 3)
+            .copied()
+            .collect();
+        roundtrip(&data);
+    }
+
+    #[test]
+    fn text_roundtrip() {
+        let data: String = (0..500)
+            .map(|i| format!("line {} of {}, mostly the same\n", i % 7, i % 13))
+            .collect();
+        roundtrip(data.as_bytes());
+    }
+
+    const HELLO: &[u8] = b"hello hello hello hello, adhesion!";
+    // python's zlib at level 9
+    const HELLO_RAW: &[u8] =
+        b"\xcb\x48\xcd\xc9\xc9\x57\xc8\x40\x27\x75\x14\x12\x53\x32\x52\x8b\x33\xf3\xf3\x14\x01";
+    const HELLO_ZLIB: &[u8] = b"\x78\xda\xcb\x48\xcd\xc9\xc9\x57\xc8\x40\x27\x75\x14\x12\x53\x32\x52\x8b\x33\xf3\xf3\x14\x01\xdc\xae\x0c\x69";
+    const HELLO_GZIP: &[u8] = b"\x1f\x8b\x08\x00\x00\x00\x00\x00\x02\x03\xcb\x48\xcd\xc9\xc9\x57\xc8\x40\x27\x75\x14\x12\x53\x32\x52\x8b\x33\xf3\xf3\x14\x01\x43\x6f\x04\xa4\x22\x00\x00\x00";
+
+    fn unhex(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn known_streams() {
+        assert_eq!(
+            inflate_raw(HELLO_RAW, 1024).unwrap(),
+            (HELLO.to_vec(), HELLO_RAW.len())
+        );
+        assert_eq!(zlib_decompress(HELLO_ZLIB, 1024).unwrap(), HELLO);
+        assert_eq!(gunzip(HELLO_GZIP, 1024).unwrap(), HELLO);
+    }
+
+    #[test]
+    fn stored_block() {
+        let stored = b"\x01\x06\x00\xf9\xffstored";
+        assert_eq!(inflate_raw(stored, 1024).unwrap(), (b"stored".to_vec(), 11));
+    }
+
+    #[test]
+    fn dynamic_huffman_block() {
+        let stream = unhex(concat!(
+            "78da95d1bb0182401445c1dc2a5e095e0195721056418545dce557bd1600c1c9279b503bfbc4a67cd96df053",
+            "67773fdbd19eb1edbfe6473758f88377b12e56f9c7216c68217d423a413a453a43fa8cf405e92bd239db8199",
+            "6c53ac53ec532c546c54ac54ec542c55fbab3fe9df48d0",
+        ));
+        assert_eq!((stream[2] >> 1) & 3, 2);
+        let text: String = (0..20)
+            .map(|i| format!("the quick brown fox {} jumps over the lazy dog\n", i))
+            .collect();
+        assert_eq!(zlib_decompress(&stream, 4096).unwrap(), text.as_bytes());
+    }
+
+    #[test]
+    fn gzip_header_fields_are_skipped() {
+        // FNAME "a.txt" inserted after the fixed header
+        let mut named = HELLO_GZIP[..10].to_vec();
+        named[3] = 0x08;
+        named.extend(b"a.txt\0");
+        named.extend(&HELLO_GZIP[10..]);
+        assert_eq!(gunzip(&named, 1024).unwrap(), HELLO);
+    }
+
+    #[test]
+    fn truncated_streams_are_invalid() {
+        for end in 0..HELLO_GZIP.len() {
+            assert_eq!(gunzip(&HELLO_GZIP[..end], 1024), Err(InflateError::Invalid));
+        }
+        for end in 0..HELLO_ZLIB.len() {
+            assert_eq!(
+                zlib_decompress(&HELLO_ZLIB[..end], 1024),
+                Err(InflateError::Invalid)
+            );
+        }
+        assert_eq!(
+            inflate_raw(&HELLO_RAW[..10], 1024),
+            Err(InflateError::Invalid)
+        );
+    }
+
+    #[test]
+    fn corrupted_checksums_are_invalid() {
+        let mut gzip = HELLO_GZIP.to_vec();
+        let crc = gzip.len() - 8;
+        gzip[crc] ^= 1;
+        assert_eq!(gunzip(&gzip, 1024), Err(InflateError::Invalid));
+        let mut zlib = HELLO_ZLIB.to_vec();
+        *zlib.last_mut().unwrap() ^= 1;
+        assert_eq!(zlib_decompress(&zlib, 1024), Err(InflateError::Invalid));
+    }
+
+    #[test]
+    fn output_beyond_the_limit_is_too_large() {
+        let limit = HELLO.len() - 1;
+        assert_eq!(gunzip(HELLO_GZIP, limit), Err(InflateError::TooLarge));
+        assert_eq!(
+            zlib_decompress(HELLO_ZLIB, limit),
+            Err(InflateError::TooLarge)
+        );
+        assert_eq!(gunzip(HELLO_GZIP, HELLO.len()).unwrap(), HELLO);
+        let bomb = deflate(&vec![0; 1 << 20]);
+        assert_eq!(inflate_raw(&bomb, 1 << 16), Err(InflateError::TooLarge));
+    }
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xcbf43926);
+        assert_eq!(crc32(b""), 0);
+    }
+}
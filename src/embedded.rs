@@ -0,0 +1,133 @@
+use std::{collections::HashMap, io::Cursor, path::Path};
+
+use crate::{
+    compression::accepts_encoding,
+    conditional::{check_preconditions, etag_for},
+    http_server::{get_404_default_response, HTTPRequest, HTTPResponse},
+    mime,
+    range::range_response,
+    static_files::PRECOMPRESSED,
+};
+
+/// files compiled into the binary, served like a directory by `static_files::serve_dir`.
+/// Usually made with `embed_assets!`
+pub struct EmbeddedDir {
+    /// the contents and ETag of every asset by its path, like `css/site.css`
+    assets: HashMap<&'static str, (&'static [u8], String)>,
+}
+
+/// embed files below `root`, relative to the manifest of the crate using the macro, as an
+/// `EmbeddedDir`. `embed_assets!("public", ["index.html", "app.js", "app.js.gz"])`
+#[macro_export]
+macro_rules! embed_assets {
+    ($root:literal, [$($path:literal),* $(,)?]) => {
+        $crate::embedded::EmbeddedDir::new(&[$((
+            $path,
+            include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/", $root, "/", $path)) as &[u8],
+        )),*])
+    };
+}
+
+impl EmbeddedDir {
+    /// assets as `(path, contents)`, with paths relative to the root like `css/site.css`
+    pub fn new(assets: &[(&'static str, &'static [u8])]) -> EmbeddedDir {
+        EmbeddedDir {
+            assets: assets
+                .iter()
+                .map(|(path, bytes)| (path.trim_start_matches('/'), (*bytes, etag_for(bytes))))
+                .collect(),
+        }
+    }
+
+    /// the contents of the asset at `path`
+    pub fn get(&self, path: &str) -> Option<&'static [u8]> {
+        self.assets.get(path).map(|(bytes, _)| *bytes)
+    }
+
+    /// answer with the asset at `relative` like `serve_dir`, including its `index.html` for
+    /// directories, conditional and range requests and precompressed siblings
+    pub fn serve(&self, request: &HTTPRequest, relative: &str) -> HTTPResponse {
+        match self.resolve(relative) {
+            Some(path) => self.serve_asset(request, &path),
+            None => get_404_default_response(),
+        }
+    }
+
+    /// `serve` for single page applications like `static_files::serve_spa`
+    pub fn serve_spa(&self, request: &HTTPRequest, relative: &str) -> HTTPResponse {
+        if let Some(path) = self.resolve(relative) {
+            return self.serve_asset(request, &path);
+        }
+        if relative.split('/').any(|segment| segment == "..") {
+            return get_404_default_response();
+        }
+        match Path::new(relative).extension() {
+            Some(_) => get_404_default_response(),
+            None => self.serve(request, "index.html"),
+        }
+    }
+
+    /// the path of the asset for `relative`, refusing anything leaving the root
+    fn resolve(&self, relative: &str) -> Option<String> {
+        let mut segments = Vec::new();
+        for segment in relative.split('/') {
+            match segment {
+                "" | "." => {}
+                ".." => return None,
+                segment => segments.push(segment),
+            }
+        }
+        let path = segments.join("/");
+        if self.assets.contains_key(path.as_str()) {
+            return Some(path);
+        }
+        let index = match path.is_empty() {
+            true => String::from("index.html"),
+            false => format!("{}/index.html", path),
+        };
+        self.assets.contains_key(index.as_str()).then_some(index)
+    }
+
+    fn serve_asset(&self, request: &HTTPRequest, path: &str) -> HTTPResponse {
+        let mut varies = false;
+        for (encoding, extension) in PRECOMPRESSED {
+            let Some(sibling) = self.assets.get(format!("{}.{}", path, extension).as_str()) else {
+                continue;
+            };
+            varies = true;
+            if accepts_encoding(request, encoding) {
+                return send_asset(request, sibling, path, Some(encoding), varies);
+            }
+        }
+        send_asset(request, &self.assets[path], path, None, varies)
+    }
+}
+
+fn send_asset(
+    request: &HTTPRequest,
+    (bytes, etag): &(&'static [u8], String),
+    path: &str,
+    encoding: Option<&str>,
+    varies: bool,
+) -> HTTPResponse {
+    if let Some(mut response) = check_preconditions(request, Some(etag), None) {
+        if varies {
+            response.headers.insert("Vary", "Accept-Encoding");
+        }
+        return response;
+    }
+
+    // reading from memory doesn't fail
+    let Ok(mut response) = range_response(request, Cursor::new(*bytes), &mime::from_path(path))
+    else {
+        return get_404_default_response();
+    };
+    response.headers.insert("ETag", etag.as_str());
+    if let Some(encoding) = encoding {
+        response.headers.insert("Content-Encoding", encoding);
+    }
+    if varies {
+        response.headers.insert("Vary", "Accept-Encoding");
+    }
+    response
+}
@@ -0,0 +1,83 @@
+use std::{fs, io, path::Path};
+
+use crate::{
+    http_server::{Body, HTTPResponse},
+    mime,
+    redirect::escape_html,
+};
+
+/// a body replacing one of the server's built-in error responses, like its 404 for unknown
+/// paths or 400 for malformed requests. `{method}`, `{path}` and `{status}` are filled in,
+/// HTML escaped for HTML pages. Method and path are empty when the request couldn't be read
+#[derive(Clone, Debug)]
+pub struct ErrorPage {
+    content_type: String,
+    template: String,
+}
+
+impl ErrorPage {
+    pub fn html(template: &str) -> ErrorPage {
+        ErrorPage::new(template, "text/html; charset=utf-8")
+    }
+
+    pub fn text(template: &str) -> ErrorPage {
+        ErrorPage::new(template, "text/plain; charset=utf-8")
+    }
+
+    /// the page in the file at `path`, typed after its extension. It's read once, right away
+    pub fn file(path: impl AsRef<Path>) -> io::Result<ErrorPage> {
+        let path = path.as_ref();
+        let template = fs::read_to_string(path)?;
+        Ok(ErrorPage::new(&template, &mime::from_path(path)))
+    }
+
+    pub fn new(template: &str, content_type: &str) -> ErrorPage {
+        ErrorPage {
+            content_type: String::from(content_type),
+            template: String::from(template),
+        }
+    }
+
+    /// swap the body of `response` for the page, keeping its status and other headers
+    pub(crate) fn apply(&self, response: &mut HTTPResponse, method: &str, path: &str) {
+        let html = self.content_type.starts_with("text/html");
+        let fill = |value: &str| match html {
+            true => escape_html(value),
+            false => String::from(value),
+        };
+        let status = response.status.status.to_string();
+        let placeholders = [
+            ("{method}", fill(method)),
+            ("{path}", fill(path)),
+            ("{status}", status),
+        ];
+        // in one pass, so a path containing `{status}` stays as it is
+        let mut body = String::with_capacity(self.template.len());
+        let mut rest = self.template.as_str();
+        while let Some(start) = rest.find('{') {
+            body.push_str(&rest[..start]);
+            rest = &rest[start..];
+            match placeholders
+                .iter()
+                .find(|(placeholder, _)| rest.starts_with(placeholder))
+            {
+                Some((placeholder, value)) => {
+                    body.push_str(value);
+                    rest = &rest[placeholder.len()..];
+                }
+                None => {
+                    body.push('{');
+                    rest = &rest[1..];
+                }
+            }
+        }
+        body.push_str(rest);
+        response
+            .headers
+            .insert("Content-Type", self.content_type.as_str());
+        response
+            .headers
+            .insert("Content-Length", body.len().to_string());
+        response.body = Body::Full(body.into_bytes());
+    }
+}
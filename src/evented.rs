@@ -0,0 +1,357 @@
+use std::{
+    io::{ErrorKind, Read, Write},
+    os::{
+        raw::{c_int, c_short},
+        unix::{
+            io::{AsRawFd, RawFd},
+            net::UnixStream,
+        },
+    },
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, Weak,
+    },
+    time::Instant,
+};
+
+use crate::thread_pool::Spawner;
+
+#[repr(C)]
+struct PollFd {
+    fd: c_int,
+    events: c_short,
+    revents: c_short,
+}
+
+#[cfg(target_os = "linux")]
+type NFds = std::os::raw::c_ulong;
+#[cfg(not(target_os = "linux"))]
+type NFds = std::os::raw::c_uint;
+
+extern "C" {
+    fn poll(fds: *mut PollFd, nfds: NFds, timeout: c_int) -> c_int;
+}
+
+/// the same bit on linux and the BSDs. Hang ups and errors are reported without asking
+const POLLIN: c_short = 0x1;
+
+/// why a parked connection is resumed
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum Wake {
+    /// what it waited for happened, or its socket was closed
+    Ready,
+    /// its deadline passed first
+    TimedOut,
+    /// the server stopped
+    Stopped,
+}
+
+/// what a parked connection waits for, besides its deadline
+pub(crate) enum Interest {
+    /// something to read on the socket
+    Readable,
+    /// the flag of a `Notifier` being set. A connection parked with the flag already set is
+    /// resumed right away
+    Notified(Arc<AtomicBool>),
+}
+
+struct Parked {
+    fd: RawFd,
+    interest: Interest,
+    deadline: Option<Instant>,
+    resume: Box<dyn FnOnce(Wake) + Send>,
+}
+
+/// waits on parked connections with a single thread, resuming each on the pool once it's
+/// ready or its deadline passed
+pub(crate) struct Reactor {
+    parking: Mutex<Parking>,
+    /// written to whenever the polling thread has to look at `parking` again
+    waker: UnixStream,
+    /// what the polling thread waits on along with the sockets
+    woken: UnixStream,
+}
+
+struct Parking {
+    /// parked since the polling thread last looked
+    incoming: Vec<Parked>,
+    stopped: bool,
+}
+
+/// resumes a connection parked with its `interest`, from any thread
+#[derive(Clone)]
+pub(crate) struct Notifier {
+    flag: Arc<AtomicBool>,
+    reactor: Weak<Reactor>,
+}
+
+impl Reactor {
+    pub(crate) fn new() -> std::io::Result<Reactor> {
+        let (waker, woken) = UnixStream::pair()?;
+        // a full socket already wakes the thread, further writes can be dropped
+        waker.set_nonblocking(true)?;
+        woken.set_nonblocking(true)?;
+        Ok(Reactor {
+            parking: Mutex::new(Parking {
+                incoming: Vec::new(),
+                stopped: false,
+            }),
+            waker,
+            woken,
+        })
+    }
+
+    /// call `resume` on the pool once `fd` has what `interest` asks for or `deadline` passed.
+    /// After the reactor stopped it's called right away, with `Wake::Stopped`
+    pub(crate) fn park(
+        &self,
+        fd: RawFd,
+        interest: Interest,
+        deadline: Option<Instant>,
+        resume: impl FnOnce(Wake) + Send + 'static,
+    ) {
+        let mut parking = self.parking.lock().unwrap();
+        if parking.stopped {
+            drop(parking);
+            resume(Wake::Stopped);
+            return;
+        }
+        parking.incoming.push(Parked {
+            fd,
+            interest,
+            deadline,
+            resume: Box::new(resume),
+        });
+        drop(parking);
+        self.wake();
+    }
+
+    pub(crate) fn notifier(self: &Arc<Reactor>) -> Notifier {
+        Notifier {
+            flag: Arc::new(AtomicBool::new(false)),
+            reactor: Arc::downgrade(self),
+        }
+    }
+
+    /// have `run` resume everything still parked and return
+    pub(crate) fn stop(&self) {
+        self.parking.lock().unwrap().stopped = true;
+        self.wake();
+    }
+
+    fn wake(&self) {
+        let _ = (&self.waker).write(&[1]);
+    }
+
+    /// poll the parked connections until the reactor is stopped, resuming them on `pool`
+    pub(crate) fn run(&self, pool: &Spawner) {
+        let mut parked: Vec<Parked> = Vec::new();
+        let mut fds = Vec::new();
+        loop {
+            let mut parking = self.parking.lock().unwrap();
+            parked.append(&mut parking.incoming);
+            if parking.stopped {
+                drop(parking);
+                for parked in parked {
+                    pool.execute(move || (parked.resume)(Wake::Stopped));
+                }
+                return;
+            }
+            drop(parking);
+
+            fds.clear();
+            fds.push(PollFd {
+                fd: self.woken.as_raw_fd(),
+                events: POLLIN,
+                revents: 0,
+            });
+            let mut notified = false;
+            for parked in &parked {
+                let events = match parked.interest {
+                    Interest::Readable => POLLIN,
+                    Interest::Notified(ref flag) => {
+                        notified |= flag.load(Ordering::SeqCst);
+                        0
+                    }
+                };
+                fds.push(PollFd {
+                    fd: parked.fd,
+                    events,
+                    revents: 0,
+                });
+            }
+            let now = Instant::now();
+            let timeout = match notified {
+                true => 0,
+                false => parked
+                    .iter()
+                    .filter_map(|parked| parked.deadline)
+                    .min()
+                    .map_or(-1, |deadline| {
+                        // rounded up, so a deadline isn't polled again just before it passes
+                        let left = deadline.saturating_duration_since(now).as_micros();
+                        left.div_ceil(1000).min(c_int::MAX as u128) as c_int
+                    }),
+            };
+            let polled = unsafe { poll(fds.as_mut_ptr(), fds.len() as NFds, timeout) };
+            if polled < 0 {
+                let error = std::io::Error::last_os_error();
+                if error.kind() != ErrorKind::Interrupted {
+                    println!("failed polling parked connections: {}", error);
+                }
+                continue;
+            }
+            let mut drained = [0; 64];
+            while matches!((&self.woken).read(&mut drained), Ok(read) if read > 0) {}
+
+            let now = Instant::now();
+            let mut waiting = Vec::with_capacity(parked.len());
+            for (parked, fd) in parked.drain(..).zip(&fds[1..]) {
+                let ready = match parked.interest {
+                    Interest::Readable => fd.revents != 0,
+                    Interest::Notified(ref flag) => flag.load(Ordering::SeqCst) || fd.revents != 0,
+                };
+                let wake = match ready {
+                    true => Wake::Ready,
+                    false if parked.deadline.is_some_and(|deadline| deadline <= now) => {
+                        Wake::TimedOut
+                    }
+                    false => {
+                        waiting.push(parked);
+                        continue;
+                    }
+                };
+                pool.execute(move || (parked.resume)(wake));
+            }
+            parked = waiting;
+        }
+    }
+}
+
+impl Notifier {
+    pub(crate) fn notify(&self) {
+        self.flag.store(true, Ordering::SeqCst);
+        if let Some(reactor) = self.reactor.upgrade() {
+            reactor.wake();
+        }
+    }
+
+    /// forget earlier notifications, before taking care of what they were about
+    pub(crate) fn reset(&self) {
+        self.flag.store(false, Ordering::SeqCst);
+    }
+
+    /// what to park with to wait for the notifier
+    pub(crate) fn interest(&self) -> Interest {
+        Interest::Notified(Arc::clone(&self.flag))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::mpsc::{self, Receiver},
+        thread,
+        time::Duration,
+    };
+
+    use super::*;
+    use crate::thread_pool::ThreadPool;
+
+    /// run `test` with a reactor polling on a pool of its own
+    fn with_reactor(test: impl FnOnce(&Arc<Reactor>)) {
+        let pool = ThreadPool::new(2);
+        let reactor = Arc::new(Reactor::new().unwrap());
+        thread::scope(|scope| {
+            scope.spawn(|| reactor.run(&pool.spawner()));
+            test(&reactor);
+            reactor.stop();
+        });
+    }
+
+    fn park(
+        reactor: &Reactor,
+        fd: RawFd,
+        interest: Interest,
+        deadline: Option<Instant>,
+    ) -> Receiver<Wake> {
+        let (sender, receiver) = mpsc::channel();
+        reactor.park(fd, interest, deadline, move |wake| {
+            sender.send(wake).unwrap()
+        });
+        receiver
+    }
+
+    const WAIT: Duration = Duration::from_secs(5);
+
+    #[test]
+    fn resumes_readable_sockets() {
+        with_reactor(|reactor| {
+            let (server, mut client) = UnixStream::pair().unwrap();
+            let woken = park(reactor, server.as_raw_fd(), Interest::Readable, None);
+            assert!(woken.recv_timeout(Duration::from_millis(50)).is_err());
+            client.write_all(b"GET").unwrap();
+            assert_eq!(woken.recv_timeout(WAIT), Ok(Wake::Ready));
+        });
+    }
+
+    #[test]
+    fn resumes_closed_sockets() {
+        with_reactor(|reactor| {
+            let (server, client) = UnixStream::pair().unwrap();
+            let woken = park(reactor, server.as_raw_fd(), Interest::Readable, None);
+            drop(client);
+            assert_eq!(woken.recv_timeout(WAIT), Ok(Wake::Ready));
+        });
+    }
+
+    #[test]
+    fn times_out() {
+        with_reactor(|reactor| {
+            let (server, _client) = UnixStream::pair().unwrap();
+            let deadline = Instant::now() + Duration::from_millis(30);
+            let woken = park(
+                reactor,
+                server.as_raw_fd(),
+                Interest::Readable,
+                Some(deadline),
+            );
+            assert_eq!(woken.recv_timeout(WAIT), Ok(Wake::TimedOut));
+            assert!(Instant::now() >= deadline);
+        });
+    }
+
+    #[test]
+    fn resumes_notified() {
+        with_reactor(|reactor| {
+            let (server, _client) = UnixStream::pair().unwrap();
+            let notifier = reactor.notifier();
+            let woken = park(reactor, server.as_raw_fd(), notifier.interest(), None);
+            assert!(woken.recv_timeout(Duration::from_millis(50)).is_err());
+            let remote = notifier.clone();
+            thread::spawn(move || remote.notify());
+            assert_eq!(woken.recv_timeout(WAIT), Ok(Wake::Ready));
+
+            // a notification before parking isn't lost
+            notifier.reset();
+            notifier.notify();
+            let woken = park(reactor, server.as_raw_fd(), notifier.interest(), None);
+            assert_eq!(woken.recv_timeout(WAIT), Ok(Wake::Ready));
+        });
+    }
+
+    #[test]
+    fn stopping_resumes_everything() {
+        let (server, _client) = UnixStream::pair().unwrap();
+        let mut parked = None;
+        with_reactor(|reactor| {
+            parked = Some(park(reactor, server.as_raw_fd(), Interest::Readable, None));
+        });
+        assert_eq!(parked.unwrap().recv_timeout(WAIT), Ok(Wake::Stopped));
+
+        let reactor = Reactor::new().unwrap();
+        reactor.stop();
+        let late = park(&reactor, server.as_raw_fd(), Interest::Readable, None);
+        assert_eq!(late.try_recv(), Ok(Wake::Stopped));
+    }
+}
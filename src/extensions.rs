@@ -0,0 +1,65 @@
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    fmt,
+};
+
+/// values of any type attached to a request, one per type. Middleware can store e.g. the
+/// authenticated user here for the listener to pick up
+#[derive(Default)]
+pub struct Extensions {
+    values: HashMap<TypeId, Box<dyn Any + Send + Sync>>,
+}
+
+impl Extensions {
+    pub fn new() -> Extensions {
+        Extensions::default()
+    }
+
+    /// store `value`, returning the previous value of its type
+    pub fn insert<V: Any + Send + Sync>(&mut self, value: V) -> Option<V> {
+        self.values
+            .insert(TypeId::of::<V>(), Box::new(value))
+            .and_then(|previous| previous.downcast().ok())
+            .map(|previous| *previous)
+    }
+
+    pub fn get<V: Any + Send + Sync>(&self) -> Option<&V> {
+        self.values
+            .get(&TypeId::of::<V>())
+            .and_then(|value| value.downcast_ref())
+    }
+
+    pub fn get_mut<V: Any + Send + Sync>(&mut self) -> Option<&mut V> {
+        self.values
+            .get_mut(&TypeId::of::<V>())
+            .and_then(|value| value.downcast_mut())
+    }
+
+    pub fn remove<V: Any + Send + Sync>(&mut self) -> Option<V> {
+        self.values
+            .remove(&TypeId::of::<V>())
+            .and_then(|value| value.downcast().ok())
+            .map(|value| *value)
+    }
+
+    pub fn contains<V: Any + Send + Sync>(&self) -> bool {
+        self.values.contains_key(&TypeId::of::<V>())
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+}
+
+impl fmt::Debug for Extensions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Extensions")
+            .field("len", &self.values.len())
+            .finish()
+    }
+}
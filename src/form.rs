@@ -0,0 +1,166 @@
+use std::collections::HashMap;
+
+use crate::http_server::HTTPRequest;
+
+/// parse an `application/x-www-form-urlencoded` body like `name=John+Doe&city=K%C3%B6ln`.
+/// Later values of a repeated name replace earlier ones
+pub fn parse_form(body: &str) -> HashMap<String, String> {
+    parse_pairs(body).collect()
+}
+
+/// the decoded `name=value` pairs of an urlencoded string
+fn parse_pairs(encoded: &str) -> impl Iterator<Item = (String, String)> + '_ {
+    encoded
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (form_decode(name), form_decode(value))
+        })
+}
+
+/// the decoded parameters of a query string, in the order they were given. Names may repeat,
+/// as in `?tag=a&tag=b`
+#[derive(Clone, Debug, Default)]
+pub struct QueryParams {
+    params: Vec<(String, String)>,
+}
+
+impl QueryParams {
+    /// parse a query string, with or without the leading `?`
+    pub fn parse(query: &str) -> QueryParams {
+        QueryParams {
+            params: parse_pairs(query.strip_prefix('?').unwrap_or(query)).collect(),
+        }
+    }
+
+    /// the first value of `name`
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// every value of `name`, in order
+    pub fn get_all(&self, name: &str) -> Vec<&str> {
+        self.params
+            .iter()
+            .filter(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+            .collect()
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.params.iter().any(|(key, _)| key == name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.params
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.params.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.params.is_empty()
+    }
+}
+
+/// decode a form component, where `+` stands for a space
+pub fn form_decode(component: &str) -> String {
+    percent_decode(&component.replace('+', " "))
+}
+
+/// decode `%XX` escapes. Malformed escapes are kept as they are, and invalid utf8 is replaced
+pub fn percent_decode(encoded: &str) -> String {
+    let bytes = encoded.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = match bytes[i] {
+            b'%' => bytes
+                .get(i + 1..i + 3)
+                .filter(|hex| hex.iter().all(u8::is_ascii_hexdigit))
+                .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok()),
+            _ => None,
+        };
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+impl HTTPRequest {
+    /// fields of an `application/x-www-form-urlencoded` body, none if it can't be read
+    pub fn form(&self) -> HashMap<String, String> {
+        parse_form(self.body.text().unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_forms() {
+        let form = parse_form("name=John+Doe&city=K%C3%B6ln&empty=&flag&name=Jane&&a%3Db=1%262");
+        assert_eq!(form.len(), 5);
+        assert_eq!(form["name"], "Jane");
+        assert_eq!(form["city"], "Köln");
+        assert_eq!(form["empty"], "");
+        assert_eq!(form["flag"], "");
+        assert_eq!(form["a=b"], "1&2");
+        assert!(parse_form("").is_empty());
+    }
+
+    #[test]
+    fn decodes_percent_escapes() {
+        assert_eq!(percent_decode("a%20b%2Fc%2fd"), "a b/c/d");
+        // plus only stands for a space in forms
+        assert_eq!(percent_decode("a+b"), "a+b");
+        assert_eq!(form_decode("a+b%2B"), "a b+");
+        // malformed escapes stay as they are
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%zz%4"), "%zz%4");
+        assert_eq!(percent_decode("%+1%-1"), "%+1%-1");
+        assert_eq!(percent_decode("%FF"), "\u{FFFD}");
+    }
+
+    #[test]
+    fn keeps_repeated_query_params() {
+        let query = QueryParams::parse("?tag=a&tag=b+c&page=2&flag");
+        assert_eq!(query.len(), 4);
+        assert_eq!(query.get("tag"), Some("a"));
+        assert_eq!(query.get_all("tag"), ["a", "b c"]);
+        assert_eq!(query.get("flag"), Some(""));
+        assert!(query.contains("page"));
+        assert!(!query.contains("missing"));
+        assert_eq!(
+            query.iter().collect::<Vec<_>>(),
+            [("tag", "a"), ("tag", "b c"), ("page", "2"), ("flag", "")]
+        );
+        assert!(QueryParams::parse("?").is_empty());
+    }
+
+    #[test]
+    fn reads_form_bodies() {
+        let request = HTTPRequest::builder()
+            .body(b"user=ada&lang=en%2Dgb".to_vec())
+            .build();
+        let form = request.form();
+        assert_eq!(form["user"], "ada");
+        assert_eq!(form["lang"], "en-gb");
+    }
+}
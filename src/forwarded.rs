@@ -0,0 +1,103 @@
+use std::net::{IpAddr, SocketAddr};
+
+use crate::{
+    headers::HeaderMap,
+    ip_filter::{canonical, IpRange},
+};
+
+/// the proxies allowed to tell the server who the client is, as addresses or CIDR ranges
+#[derive(Clone, Default, Debug)]
+pub struct TrustedProxies {
+    ranges: Vec<IpRange>,
+}
+
+impl TrustedProxies {
+    /// trust nobody, the connection's peer is the client
+    pub fn new() -> TrustedProxies {
+        TrustedProxies::default()
+    }
+
+    /// also trust `range`, like `10.0.0.0/8`, `::1` or `127.0.0.1`. Panics on anything else
+    pub fn trust(mut self, range: &str) -> Self {
+        self.ranges.push(
+            IpRange::parse(range)
+                .unwrap_or_else(|| panic!("trusted proxy `{range}` isn't an ip range")),
+        );
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// check if `ip` lies in one of the trusted ranges
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        self.ranges.iter().any(|range| range.contains(ip))
+    }
+
+    /// the client's ip for a connection from `peer`. Forwarding headers are only believed when
+    /// sent by a trusted proxy, and are followed back through further trusted proxies.
+    /// Connections over unix sockets are local and count as trusted
+    pub(crate) fn client_ip(
+        &self,
+        peer: Option<SocketAddr>,
+        headers: &HeaderMap,
+    ) -> Option<IpAddr> {
+        let peer = peer.map(|peer| canonical(peer.ip()));
+        if self.is_empty() || peer.is_some_and(|peer| !self.contains(peer)) {
+            return peer;
+        }
+
+        let chain = match headers.contains("Forwarded") {
+            true => forwarded_for(headers),
+            false => headers
+                .get_all("X-Forwarded-For")
+                .iter()
+                .flat_map(|value| value.split(','))
+                .map(|entry| parse_node(entry.trim()))
+                .collect(),
+        };
+        // the nearest untrusted hop is the client, anything before it may be made up
+        let mut client = peer;
+        for hop in chain.into_iter().rev() {
+            match hop {
+                Some(ip) if self.contains(ip) => client = Some(ip),
+                Some(ip) => return Some(ip),
+                // an obfuscated or unknown hop ends what can be known
+                None => return client,
+            }
+        }
+        client
+    }
+}
+
+/// the `for=` nodes of all `Forwarded` headers, in order
+fn forwarded_for(headers: &HeaderMap) -> Vec<Option<IpAddr>> {
+    headers
+        .get_all("Forwarded")
+        .iter()
+        .flat_map(|value| value.split(','))
+        .filter_map(|element| {
+            element.split(';').find_map(|pair| {
+                let (name, value) = pair.split_once('=')?;
+                name.trim()
+                    .eq_ignore_ascii_case("for")
+                    .then(|| parse_node(value.trim().trim_matches('"')))
+            })
+        })
+        .collect()
+}
+
+/// an ip from a forwarding header, which may carry a port and brackets
+fn parse_node(node: &str) -> Option<IpAddr> {
+    if let Ok(ip) = node.parse::<IpAddr>() {
+        return Some(canonical(ip));
+    }
+    if let Ok(addr) = node.parse::<SocketAddr>() {
+        return Some(canonical(addr.ip()));
+    }
+    node.strip_prefix('[')
+        .and_then(|node| node.strip_suffix(']'))
+        .and_then(|ip| ip.parse().ok())
+        .map(canonical)
+}
@@ -0,0 +1,124 @@
+use std::collections::HashMap;
+
+/// HTTP headers in the order they were added. Names are matched ignoring case, and a name may
+/// occur several times, like `Set-Cookie`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HeaderMap {
+    entries: Vec<(String, String)>,
+}
+
+impl HeaderMap {
+    pub fn new() -> HeaderMap {
+        HeaderMap {
+            entries: Vec::new(),
+        }
+    }
+
+    /// an empty map with room for `capacity` headers
+    pub fn with_capacity(capacity: usize) -> HeaderMap {
+        HeaderMap {
+            entries: Vec::with_capacity(capacity),
+        }
+    }
+
+    /// the first value of `name`
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// every value of `name`, in order
+    pub fn get_all(&self, name: &str) -> Vec<&str> {
+        self.entries
+            .iter()
+            .filter(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+            .collect()
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.entries
+            .iter()
+            .any(|(key, _)| key.eq_ignore_ascii_case(name))
+    }
+
+    /// set `name` to `value`, replacing all previous values of it
+    pub fn insert(&mut self, name: &str, value: impl Into<String>) {
+        self.remove(name);
+        self.append(name, value);
+    }
+
+    /// add another value for `name`, keeping the previous ones
+    pub fn append(&mut self, name: &str, value: impl Into<String>) {
+        self.entries.push((String::from(name), value.into()));
+    }
+
+    /// remove all values of `name`, returning the first one
+    pub fn remove(&mut self, name: &str) -> Option<String> {
+        let mut removed = None;
+        self.entries.retain_mut(|(key, value)| {
+            if !key.eq_ignore_ascii_case(name) {
+                return true;
+            }
+            if removed.is_none() {
+                removed = Some(std::mem::take(value));
+            }
+            false
+        });
+        removed
+    }
+
+    /// check if the comma separated header `name` lists `token`, ignoring case
+    pub fn has_token(&self, name: &str, token: &str) -> bool {
+        self.get_all(name).iter().any(|value| {
+            value
+                .split(',')
+                .any(|t| t.trim().eq_ignore_ascii_case(token))
+        })
+    }
+
+    /// all headers as `(name, value)` in order. Names keep the case they were added with
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+
+    /// the value of the header added last, for continuation lines of a folded header
+    pub(crate) fn last_value_mut(&mut self) -> Option<&mut String> {
+        self.entries.last_mut().map(|(_, value)| value)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl<N: Into<String>, V: Into<String>> FromIterator<(N, V)> for HeaderMap {
+    fn from_iter<I: IntoIterator<Item = (N, V)>>(iter: I) -> HeaderMap {
+        HeaderMap {
+            entries: iter
+                .into_iter()
+                .map(|(name, value)| (name.into(), value.into()))
+                .collect(),
+        }
+    }
+}
+
+impl From<HashMap<String, String>> for HeaderMap {
+    fn from(headers: HashMap<String, String>) -> HeaderMap {
+        headers.into_iter().collect()
+    }
+}
+
+impl<N: Into<String>, V: Into<String>, const L: usize> From<[(N, V); L]> for HeaderMap {
+    fn from(headers: [(N, V); L]) -> HeaderMap {
+        headers.into_iter().collect()
+    }
+}
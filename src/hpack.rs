@@ -0,0 +1,896 @@
+use std::{collections::VecDeque, sync::OnceLock};
+
+/// the static table of RFC 7541, appendix A. Index 1 is the first entry
+const STATIC_TABLE: [(&str, &str); 61] = [
+    (":authority", ""),
+    (":method", "GET"),
+    (":method", "POST"),
+    (":path", "/"),
+    (":path", "/index.html"),
+    (":scheme", "http"),
+    (":scheme", "https"),
+    (":status", "200"),
+    (":status", "204"),
+    (":status", "206"),
+    (":status", "304"),
+    (":status", "400"),
+    (":status", "404"),
+    (":status", "500"),
+    ("accept-charset", ""),
+    ("accept-encoding", "gzip, deflate"),
+    ("accept-language", ""),
+    ("accept-ranges", ""),
+    ("accept", ""),
+    ("access-control-allow-origin", ""),
+    ("age", ""),
+    ("allow", ""),
+    ("authorization", ""),
+    ("cache-control", ""),
+    ("content-disposition", ""),
+    ("content-encoding", ""),
+    ("content-language", ""),
+    ("content-length", ""),
+    ("content-location", ""),
+    ("content-range", ""),
+    ("content-type", ""),
+    ("cookie", ""),
+    ("date", ""),
+    ("etag", ""),
+    ("expect", ""),
+    ("expires", ""),
+    ("from", ""),
+    ("host", ""),
+    ("if-match", ""),
+    ("if-modified-since", ""),
+    ("if-none-match", ""),
+    ("if-range", ""),
+    ("if-unmodified-since", ""),
+    ("last-modified", ""),
+    ("link", ""),
+    ("location", ""),
+    ("max-forwards", ""),
+    ("proxy-authenticate", ""),
+    ("proxy-authorization", ""),
+    ("range", ""),
+    ("referer", ""),
+    ("refresh", ""),
+    ("retry-after", ""),
+    ("server", ""),
+    ("set-cookie", ""),
+    ("strict-transport-security", ""),
+    ("transfer-encoding", ""),
+    ("user-agent", ""),
+    ("vary", ""),
+    ("via", ""),
+    ("www-authenticate", ""),
+];
+
+/// the Huffman code and its length in bits for every byte, and for EOS last. RFC 7541,
+/// appendix B
+const HUFFMAN_CODES: [(u32, u8); 257] = [
+    (0x1ff8, 13),
+    (0x7fffd8, 23),
+    (0xfffffe2, 28),
+    (0xfffffe3, 28),
+    (0xfffffe4, 28),
+    (0xfffffe5, 28),
+    (0xfffffe6, 28),
+    (0xfffffe7, 28),
+    (0xfffffe8, 28),
+    (0xffffea, 24),
+    (0x3ffffffc, 30),
+    (0xfffffe9, 28),
+    (0xfffffea, 28),
+    (0x3ffffffd, 30),
+    (0xfffffeb, 28),
+    (0xfffffec, 28),
+    (0xfffffed, 28),
+    (0xfffffee, 28),
+    (0xfffffef, 28),
+    (0xffffff0, 28),
+    (0xffffff1, 28),
+    (0xffffff2, 28),
+    (0x3ffffffe, 30),
+    (0xffffff3, 28),
+    (0xffffff4, 28),
+    (0xffffff5, 28),
+    (0xffffff6, 28),
+    (0xffffff7, 28),
+    (0xffffff8, 28),
+    (0xffffff9, 28),
+    (0xffffffa, 28),
+    (0xffffffb, 28),
+    (0x14, 6),
+    (0x3f8, 10),
+    (0x3f9, 10),
+    (0xffa, 12),
+    (0x1ff9, 13),
+    (0x15, 6),
+    (0xf8, 8),
+    (0x7fa, 11),
+    (0x3fa, 10),
+    (0x3fb, 10),
+    (0xf9, 8),
+    (0x7fb, 11),
+    (0xfa, 8),
+    (0x16, 6),
+    (0x17, 6),
+    (0x18, 6),
+    (0x0, 5),
+    (0x1, 5),
+    (0x2, 5),
+    (0x19, 6),
+    (0x1a, 6),
+    (0x1b, 6),
+    (0x1c, 6),
+    (0x1d, 6),
+    (0x1e, 6),
+    (0x1f, 6),
+    (0x5c, 7),
+    (0xfb, 8),
+    (0x7ffc, 15),
+    (0x20, 6),
+    (0xffb, 12),
+    (0x3fc, 10),
+    (0x1ffa, 13),
+    (0x21, 6),
+    (0x5d, 7),
+    (0x5e, 7),
+    (0x5f, 7),
+    (0x60, 7),
+    (0x61, 7),
+    (0x62, 7),
+    (0x63, 7),
+    (0x64, 7),
+    (0x65, 7),
+    (0x66, 7),
+    (0x67, 7),
+    (0x68, 7),
+    (0x69, 7),
+    (0x6a, 7),
+    (0x6b, 7),
+    (0x6c, 7),
+    (0x6d, 7),
+    (0x6e, 7),
+    (0x6f, 7),
+    (0x70, 7),
+    (0x71, 7),
+    (0x72, 7),
+    (0xfc, 8),
+    (0x73, 7),
+    (0xfd, 8),
+    (0x1ffb, 13),
+    (0x7fff0, 19),
+    (0x1ffc, 13),
+    (0x3ffc, 14),
+    (0x22, 6),
+    (0x7ffd, 15),
+    (0x3, 5),
+    (0x23, 6),
+    (0x4, 5),
+    (0x24, 6),
+    (0x5, 5),
+    (0x25, 6),
+    (0x26, 6),
+    (0x27, 6),
+    (0x6, 5),
+    (0x74, 7),
+    (0x75, 7),
+    (0x28, 6),
+    (0x29, 6),
+    (0x2a, 6),
+    (0x7, 5),
+    (0x2b, 6),
+    (0x76, 7),
+    (0x2c, 6),
+    (0x8, 5),
+    (0x9, 5),
+    (0x2d, 6),
+    (0x77, 7),
+    (0x78, 7),
+    (0x79, 7),
+    (0x7a, 7),
+    (0x7b, 7),
+    (0x7ffe, 15),
+    (0x7fc, 11),
+    (0x3ffd, 14),
+    (0x1ffd, 13),
+    (0xffffffc, 28),
+    (0xfffe6, 20),
+    (0x3fffd2, 22),
+    (0xfffe7, 20),
+    (0xfffe8, 20),
+    (0x3fffd3, 22),
+    (0x3fffd4, 22),
+    (0x3fffd5, 22),
+    (0x7fffd9, 23),
+    (0x3fffd6, 22),
+    (0x7fffda, 23),
+    (0x7fffdb, 23),
+    (0x7fffdc, 23),
+    (0x7fffdd, 23),
+    (0x7fffde, 23),
+    (0xffffeb, 24),
+    (0x7fffdf, 23),
+    (0xffffec, 24),
+    (0xffffed, 24),
+    (0x3fffd7, 22),
+    (0x7fffe0, 23),
+    (0xffffee, 24),
+    (0x7fffe1, 23),
+    (0x7fffe2, 23),
+    (0x7fffe3, 23),
+    (0x7fffe4, 23),
+    (0x1fffdc, 21),
+    (0x3fffd8, 22),
+    (0x7fffe5, 23),
+    (0x3fffd9, 22),
+    (0x7fffe6, 23),
+    (0x7fffe7, 23),
+    (0xffffef, 24),
+    (0x3fffda, 22),
+    (0x1fffdd, 21),
+    (0xfffe9, 20),
+    (0x3fffdb, 22),
+    (0x3fffdc, 22),
+    (0x7fffe8, 23),
+    (0x7fffe9, 23),
+    (0x1fffde, 21),
+    (0x7fffea, 23),
+    (0x3fffdd, 22),
+    (0x3fffde, 22),
+    (0xfffff0, 24),
+    (0x1fffdf, 21),
+    (0x3fffdf, 22),
+    (0x7fffeb, 23),
+    (0x7fffec, 23),
+    (0x1fffe0, 21),
+    (0x1fffe1, 21),
+    (0x3fffe0, 22),
+    (0x1fffe2, 21),
+    (0x7fffed, 23),
+    (0x3fffe1, 22),
+    (0x7fffee, 23),
+    (0x7fffef, 23),
+    (0xfffea, 20),
+    (0x3fffe2, 22),
+    (0x3fffe3, 22),
+    (0x3fffe4, 22),
+    (0x7ffff0, 23),
+    (0x3fffe5, 22),
+    (0x3fffe6, 22),
+    (0x7ffff1, 23),
+    (0x3ffffe0, 26),
+    (0x3ffffe1, 26),
+    (0xfffeb, 20),
+    (0x7fff1, 19),
+    (0x3fffe7, 22),
+    (0x7ffff2, 23),
+    (0x3fffe8, 22),
+    (0x1ffffec, 25),
+    (0x3ffffe2, 26),
+    (0x3ffffe3, 26),
+    (0x3ffffe4, 26),
+    (0x7ffffde, 27),
+    (0x7ffffdf, 27),
+    (0x3ffffe5, 26),
+    (0xfffff1, 24),
+    (0x1ffffed, 25),
+    (0x7fff2, 19),
+    (0x1fffe3, 21),
+    (0x3ffffe6, 26),
+    (0x7ffffe0, 27),
+    (0x7ffffe1, 27),
+    (0x3ffffe7, 26),
+    (0x7ffffe2, 27),
+    (0xfffff2, 24),
+    (0x1fffe4, 21),
+    (0x1fffe5, 21),
+    (0x3ffffe8, 26),
+    (0x3ffffe9, 26),
+    (0xffffffd, 28),
+    (0x7ffffe3, 27),
+    (0x7ffffe4, 27),
+    (0x7ffffe5, 27),
+    (0xfffec, 20),
+    (0xfffff3, 24),
+    (0xfffed, 20),
+    (0x1fffe6, 21),
+    (0x3fffe9, 22),
+    (0x1fffe7, 21),
+    (0x1fffe8, 21),
+    (0x7ffff3, 23),
+    (0x3fffea, 22),
+    (0x3fffeb, 22),
+    (0x1ffffee, 25),
+    (0x1ffffef, 25),
+    (0xfffff4, 24),
+    (0xfffff5, 24),
+    (0x3ffffea, 26),
+    (0x7ffff4, 23),
+    (0x3ffffeb, 26),
+    (0x7ffffe6, 27),
+    (0x3ffffec, 26),
+    (0x3ffffed, 26),
+    (0x7ffffe7, 27),
+    (0x7ffffe8, 27),
+    (0x7ffffe9, 27),
+    (0x7ffffea, 27),
+    (0x7ffffeb, 27),
+    (0xffffffe, 28),
+    (0x7ffffec, 27),
+    (0x7ffffed, 27),
+    (0x7ffffee, 27),
+    (0x7ffffef, 27),
+    (0x7fffff0, 27),
+    (0x3ffffee, 26),
+    (0x3fffffff, 30),
+];
+
+const EOS: u16 = 256;
+
+/// a header block that doesn't decode, a connection error
+#[derive(Debug)]
+pub(crate) struct CompressionError;
+
+/// decodes the header blocks of one connection, keeping the dynamic table they build up
+pub(crate) struct Decoder {
+    /// newest entry first
+    table: VecDeque<(String, String)>,
+    size: usize,
+    max_size: usize,
+    /// the table size advertised to the peer, which size updates may not exceed
+    limit: usize,
+}
+
+impl Decoder {
+    pub(crate) fn new(limit: usize) -> Decoder {
+        Decoder {
+            table: VecDeque::new(),
+            size: 0,
+            max_size: limit,
+            limit,
+        }
+    }
+
+    /// the fields of a complete header block, `None` if they add up to more than
+    /// `max_list_size` bytes as counted by RFC 7541. The whole block is decoded either way, so
+    /// the dynamic table stays in sync with the peer's
+    pub(crate) fn decode(
+        &mut self,
+        mut block: &[u8],
+        max_list_size: usize,
+    ) -> Result<Option<Vec<(String, String)>>, CompressionError> {
+        let mut fields = Vec::new();
+        let mut list_size = 0;
+        let mut too_large = false;
+        while let Some(&first) = block.first() {
+            let (field, index) = match first {
+                // indexed field
+                0x80.. => (self.entry(integer(&mut block, 7)?)?, false),
+                // literal with incremental indexing
+                0x40.. => (self.literal(&mut block, 6)?, true),
+                // dynamic table size update
+                0x20.. => {
+                    let size = integer(&mut block, 5)?;
+                    if size > self.limit {
+                        return Err(CompressionError);
+                    }
+                    self.max_size = size;
+                    self.evict(0);
+                    continue;
+                }
+                // literal without indexing, or never indexed
+                _ => (self.literal(&mut block, 4)?, false),
+            };
+            if index {
+                self.insert(field.clone());
+            }
+            list_size += field.0.len() + field.1.len() + 32;
+            too_large |= list_size > max_list_size;
+            if !too_large {
+                fields.push(field);
+            }
+        }
+        Ok((!too_large).then_some(fields))
+    }
+
+    fn entry(&self, index: usize) -> Result<(String, String), CompressionError> {
+        match index {
+            0 => Err(CompressionError),
+            1..=61 => {
+                let (name, value) = STATIC_TABLE[index - 1];
+                Ok((String::from(name), String::from(value)))
+            }
+            _ => self.table.get(index - 62).cloned().ok_or(CompressionError),
+        }
+    }
+
+    /// a literal field whose name index has `prefix` bits, a new name following for index 0
+    fn literal(&self, block: &mut &[u8], prefix: u8) -> Result<(String, String), CompressionError> {
+        let name = match integer(block, prefix)? {
+            0 => string(block)?,
+            index => self.entry(index)?.0,
+        };
+        Ok((name, string(block)?))
+    }
+
+    fn insert(&mut self, entry: (String, String)) {
+        let size = entry.0.len() + entry.1.len() + 32;
+        // an entry larger than the table empties it without being added
+        self.evict(size);
+        if size <= self.max_size {
+            self.size += size;
+            self.table.push_front(entry);
+        }
+    }
+
+    /// drop the oldest entries until `room` more bytes fit
+    fn evict(&mut self, room: usize) {
+        while self.size + room > self.max_size {
+            let Some((name, value)) = self.table.pop_back() else {
+                break;
+            };
+            self.size -= name.len() + value.len() + 32;
+        }
+    }
+}
+
+/// encode `fields` as a header block. Fields in the static table are referenced, everything
+/// else is sent as a literal that isn't indexed, so the peer's dynamic table stays untouched
+pub(crate) fn encode<'a>(fields: impl IntoIterator<Item = (&'a str, &'a str)>, out: &mut Vec<u8>) {
+    for (name, value) in fields {
+        if let Some(index) = STATIC_TABLE
+            .iter()
+            .position(|entry| *entry == (name, value))
+        {
+            write_integer(out, 0x80, 7, index + 1);
+            continue;
+        }
+        match STATIC_TABLE.iter().position(|(entry, _)| *entry == name) {
+            Some(index) => write_integer(out, 0x00, 4, index + 1),
+            None => {
+                out.push(0x00);
+                write_string(out, name);
+            }
+        }
+        write_string(out, value);
+    }
+}
+
+/// an integer with a `prefix` bit prefix, RFC 7541 section 5.1
+fn integer(block: &mut &[u8], prefix: u8) -> Result<usize, CompressionError> {
+    let (&first, rest) = block.split_first().ok_or(CompressionError)?;
+    *block = rest;
+    let max = (1 << prefix) - 1;
+    let mut value = first as usize & max;
+    if value < max {
+        return Ok(value);
+    }
+    let mut shift = 0;
+    loop {
+        let (&byte, rest) = block.split_first().ok_or(CompressionError)?;
+        *block = rest;
+        // anything longer is a bogus encoding, or more than any header needs
+        if shift > 21 {
+            return Err(CompressionError);
+        }
+        value += ((byte & 0x7f) as usize) << shift;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+}
+
+fn write_integer(out: &mut Vec<u8>, flags: u8, prefix: u8, mut value: usize) {
+    let max = (1 << prefix) - 1;
+    if value < max {
+        out.push(flags | value as u8);
+        return;
+    }
+    out.push(flags | max as u8);
+    value -= max;
+    while value >= 0x80 {
+        out.push((value & 0x7f) as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+/// a string literal, Huffman coded if its first bit is set
+fn string(block: &mut &[u8]) -> Result<String, CompressionError> {
+    let huffman = block.first().is_some_and(|first| first & 0x80 != 0);
+    let length = integer(block, 7)?;
+    if length > block.len() {
+        return Err(CompressionError);
+    }
+    let (bytes, rest) = block.split_at(length);
+    *block = rest;
+    let bytes = match huffman {
+        true => huffman_decode(bytes)?,
+        false => bytes.to_vec(),
+    };
+    String::from_utf8(bytes).map_err(|_| CompressionError)
+}
+
+/// a raw string literal. Header values are short, so Huffman coding them isn't worth it
+fn write_string(out: &mut Vec<u8>, value: &str) {
+    write_integer(out, 0x00, 7, value.len());
+    out.extend_from_slice(value.as_bytes());
+}
+
+/// the code as a binary tree. Each node holds its two children, leaves are `LEAF | symbol`
+fn huffman_tree() -> &'static [[u16; 2]] {
+    static TREE: OnceLock<Vec<[u16; 2]>> = OnceLock::new();
+    TREE.get_or_init(|| {
+        let mut tree = vec![[0; 2]];
+        for (symbol, &(code, length)) in HUFFMAN_CODES.iter().enumerate() {
+            let mut node = 0;
+            for bit in (0..length).rev() {
+                let branch = (code >> bit & 1) as usize;
+                if bit == 0 {
+                    tree[node][branch] = LEAF | symbol as u16;
+                } else {
+                    if tree[node][branch] == 0 {
+                        tree.push([0; 2]);
+                        tree[node][branch] = (tree.len() - 1) as u16;
+                    }
+                    node = tree[node][branch] as usize;
+                }
+            }
+        }
+        tree
+    })
+}
+
+const LEAF: u16 = 0x8000;
+
+fn huffman_decode(bytes: &[u8]) -> Result<Vec<u8>, CompressionError> {
+    let tree = huffman_tree();
+    let mut decoded = Vec::with_capacity(bytes.len() * 8 / 5);
+    let mut node = 0;
+    // bits since the last symbol, all of which have to be ones at the end
+    let mut pending = 0;
+    let mut all_ones = true;
+    for byte in bytes {
+        for bit in (0..8).rev() {
+            let branch = (byte >> bit & 1) as usize;
+            let next = tree[node][branch];
+            pending += 1;
+            all_ones &= branch == 1;
+            if next & LEAF != 0 {
+                let symbol = next & !LEAF;
+                if symbol == EOS {
+                    return Err(CompressionError);
+                }
+                decoded.push(symbol as u8);
+                node = 0;
+                pending = 0;
+                all_ones = true;
+            } else {
+                node = next as usize;
+            }
+        }
+    }
+    // the padding is a prefix of EOS, at most 7 bits long
+    match pending <= 7 && all_ones {
+        true => Ok(decoded),
+        false => Err(CompressionError),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// bytes from hex, ignoring the spaces the RFC groups them with
+    fn unhex(hex: &str) -> Vec<u8> {
+        let hex: String = hex.split_whitespace().collect();
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    fn fields(fields: &[(&str, &str)]) -> Vec<(String, String)> {
+        fields
+            .iter()
+            .map(|(name, value)| (String::from(*name), String::from(*value)))
+            .collect()
+    }
+
+    /// decode `block`, checking the fields and the resulting dynamic table
+    fn check(
+        decoder: &mut Decoder,
+        block: &str,
+        expected: &[(&str, &str)],
+        table: &[(&str, &str)],
+        size: usize,
+    ) {
+        let decoded = decoder.decode(&unhex(block), usize::MAX).unwrap();
+        assert_eq!(decoded, Some(fields(expected)));
+        assert_eq!(Vec::from(decoder.table.clone()), fields(table));
+        assert_eq!(decoder.size, size);
+    }
+
+    #[test]
+    fn integers() {
+        // RFC 7541 C.1
+        for (bytes, prefix, value) in [
+            (&[0x0a][..], 5, 10),
+            (&[0x1f, 0x9a, 0x0a][..], 5, 1337),
+            (&[0x2a][..], 8, 42),
+        ] {
+            let mut block = bytes;
+            assert_eq!(integer(&mut block, prefix).unwrap(), value);
+            assert!(block.is_empty());
+            let mut out = Vec::new();
+            write_integer(&mut out, 0, prefix, value);
+            assert_eq!(out, bytes);
+        }
+        assert!(integer(&mut &[0x1f, 0x9a][..], 5).is_err());
+        assert!(integer(&mut &[0x1f, 0xff, 0xff, 0xff, 0xff, 0x0f][..], 5).is_err());
+    }
+
+    #[test]
+    fn literals() {
+        // RFC 7541 C.2
+        let mut decoder = Decoder::new(4096);
+        check(
+            &mut decoder,
+            "400a 6375 7374 6f6d 2d6b 6579 0d63 7573 746f 6d2d 6865 6164 6572",
+            &[("custom-key", "custom-header")],
+            &[("custom-key", "custom-header")],
+            55,
+        );
+        let mut decoder = Decoder::new(4096);
+        check(
+            &mut decoder,
+            "040c 2f73 616d 706c 652f 7061 7468",
+            &[(":path", "/sample/path")],
+            &[],
+            0,
+        );
+        check(
+            &mut decoder,
+            "1008 7061 7373 776f 7264 0673 6563 7265 74",
+            &[("password", "secret")],
+            &[],
+            0,
+        );
+        check(&mut decoder, "82", &[(":method", "GET")], &[], 0);
+    }
+
+    const FIRST_REQUEST: &[(&str, &str)] = &[
+        (":method", "GET"),
+        (":scheme", "http"),
+        (":path", "/"),
+        (":authority", "www.example.com"),
+    ];
+    const SECOND_REQUEST: &[(&str, &str)] = &[
+        (":method", "GET"),
+        (":scheme", "http"),
+        (":path", "/"),
+        (":authority", "www.example.com"),
+        ("cache-control", "no-cache"),
+    ];
+    const THIRD_REQUEST: &[(&str, &str)] = &[
+        (":method", "GET"),
+        (":scheme", "https"),
+        (":path", "/index.html"),
+        (":authority", "www.example.com"),
+        ("custom-key", "custom-value"),
+    ];
+
+    /// the requests of RFC 7541 C.3 and C.4, which build the same table
+    fn requests(blocks: [&str; 3]) {
+        let mut decoder = Decoder::new(4096);
+        check(
+            &mut decoder,
+            blocks[0],
+            FIRST_REQUEST,
+            &[(":authority", "www.example.com")],
+            57,
+        );
+        check(
+            &mut decoder,
+            blocks[1],
+            SECOND_REQUEST,
+            &[
+                ("cache-control", "no-cache"),
+                (":authority", "www.example.com"),
+            ],
+            110,
+        );
+        check(
+            &mut decoder,
+            blocks[2],
+            THIRD_REQUEST,
+            &[
+                ("custom-key", "custom-value"),
+                ("cache-control", "no-cache"),
+                (":authority", "www.example.com"),
+            ],
+            164,
+        );
+    }
+
+    #[test]
+    fn requests_without_huffman() {
+        requests([
+            "8286 8441 0f77 7777 2e65 7861 6d70 6c65 2e63 6f6d",
+            "8286 84be 5808 6e6f 2d63 6163 6865",
+            "8287 85bf 400a 6375 7374 6f6d 2d6b 6579 0c63 7573 746f 6d2d 7661 6c75 65",
+        ]);
+    }
+
+    #[test]
+    fn requests_with_huffman() {
+        requests([
+            "8286 8441 8cf1 e3c2 e5f2 3a6b a0ab 90f4 ff",
+            "8286 84be 5886 a8eb 1064 9cbf",
+            "8287 85bf 4088 25a8 49e9 5ba9 7d7f 8925 a849 e95b b8e8 b4bf",
+        ]);
+    }
+
+    const DATE: &str = "Mon, 21 Oct 2013 20:13:21 GMT";
+    const LATER: &str = "Mon, 21 Oct 2013 20:13:22 GMT";
+    const LOCATION: &str = "https://www.example.com";
+    const COOKIE: &str = "foo=ASDJKHQKBZXOQWEOPIUAXQWEOIU; max-age=3600; version=1";
+
+    /// the responses of RFC 7541 C.5 and C.6, which evict entries from a 256 byte table
+    fn responses(blocks: [&str; 3]) {
+        let mut decoder = Decoder::new(256);
+        check(
+            &mut decoder,
+            blocks[0],
+            &[
+                (":status", "302"),
+                ("cache-control", "private"),
+                ("date", DATE),
+                ("location", LOCATION),
+            ],
+            &[
+                ("location", LOCATION),
+                ("date", DATE),
+                ("cache-control", "private"),
+                (":status", "302"),
+            ],
+            222,
+        );
+        check(
+            &mut decoder,
+            blocks[1],
+            &[
+                (":status", "307"),
+                ("cache-control", "private"),
+                ("date", DATE),
+                ("location", LOCATION),
+            ],
+            &[
+                (":status", "307"),
+                ("location", LOCATION),
+                ("date", DATE),
+                ("cache-control", "private"),
+            ],
+            222,
+        );
+        check(
+            &mut decoder,
+            blocks[2],
+            &[
+                (":status", "200"),
+                ("cache-control", "private"),
+                ("date", LATER),
+                ("location", LOCATION),
+                ("content-encoding", "gzip"),
+                ("set-cookie", COOKIE),
+            ],
+            &[
+                ("set-cookie", COOKIE),
+                ("content-encoding", "gzip"),
+                ("date", LATER),
+            ],
+            215,
+        );
+    }
+
+    #[test]
+    fn responses_without_huffman() {
+        responses([
+            "4803 3330 3258 0770 7269 7661 7465 611d 4d6f 6e2c 2032 3120 4f63 7420 3230 3133 \
+             2032 303a 3133 3a32 3120 474d 546e 1768 7474 7073 3a2f 2f77 7777 2e65 7861 6d70 \
+             6c65 2e63 6f6d",
+            "4803 3330 37c1 c0bf",
+            "88c1 611d 4d6f 6e2c 2032 3120 4f63 7420 3230 3133 2032 303a 3133 3a32 3220 474d \
+             54c0 5a04 677a 6970 7738 666f 6f3d 4153 444a 4b48 514b 425a 584f 5157 454f 5049 \
+             5541 5851 5745 4f49 553b 206d 6178 2d61 6765 3d33 3630 303b 2076 6572 7369 6f6e \
+             3d31",
+        ]);
+    }
+
+    #[test]
+    fn responses_with_huffman() {
+        responses([
+            "4882 6402 5885 aec3 771a 4b61 96d0 7abe 9410 54d4 44a8 2005 9504 0b81 66e0 82a6 \
+             2d1b ff6e 919d 29ad 1718 63c7 8f0b 97c8 e9ae 82ae 43d3",
+            "4883 640e ffc1 c0bf",
+            "88c1 6196 d07a be94 1054 d444 a820 0595 040b 8166 e084 a62d 1bff c05a 839b d9ab \
+             77ad 94e7 821d d7f2 e6c7 b335 dfdf cd5b 3960 d5af 2708 7f36 72c1 ab27 0fb5 291f \
+             9587 3160 65c0 03ed 4ee5 b106 3d50 07",
+        ]);
+    }
+
+    #[test]
+    fn table_size_updates() {
+        let mut decoder = Decoder::new(256);
+        check(
+            &mut decoder,
+            "400a 6375 7374 6f6d 2d6b 6579 0d63 7573 746f 6d2d 6865 6164 6572",
+            &[("custom-key", "custom-header")],
+            &[("custom-key", "custom-header")],
+            55,
+        );
+        // shrinking the table evicts what no longer fits
+        check(&mut decoder, "3f11", &[], &[], 0);
+        // growing it beyond what was advertised is an error
+        assert!(decoder.decode(&unhex("3fe2 01"), usize::MAX).is_err());
+    }
+
+    #[test]
+    fn malformed_blocks() {
+        let mut decoder = Decoder::new(4096);
+        // index 0, and one past the empty dynamic table
+        assert!(decoder.decode(&[0x80], usize::MAX).is_err());
+        assert!(decoder.decode(&[0xbe], usize::MAX).is_err());
+        // a string longer than the block
+        assert!(decoder.decode(&unhex("0005 6162"), usize::MAX).is_err());
+        // Huffman padding that isn't all ones, and padding longer than 7 bits
+        assert!(huffman_decode(&[0x1c]).is_err());
+        assert!(huffman_decode(&[0x1f, 0xff]).is_err());
+        assert_eq!(huffman_decode(&[0x1f]).unwrap(), b"a");
+    }
+
+    #[test]
+    fn header_list_size() {
+        let block = unhex("8286 8441 0f77 7777 2e65 7861 6d70 6c65 2e63 6f6d");
+        let mut decoder = Decoder::new(4096);
+        // 42 + 43 + 38 + 57 bytes of fields
+        assert_eq!(decoder.decode(&block, 179).unwrap(), None);
+        // the table was still updated
+        assert_eq!(decoder.size, 57);
+        let mut decoder = Decoder::new(4096);
+        assert_eq!(
+            decoder.decode(&block, 180).unwrap(),
+            Some(fields(FIRST_REQUEST))
+        );
+    }
+
+    #[test]
+    fn encode_roundtrip() {
+        let sent = [
+            (":status", "200"),
+            (":status", "418"),
+            ("content-type", "text/html"),
+            (
+                "x-long",
+                "a value longer than the 127 bytes a single length byte holds, so its \
+                       length spills over into a second byte of the integer encoding",
+            ),
+            ("accept-encoding", "gzip, deflate"),
+        ];
+        let mut block = Vec::new();
+        encode(sent, &mut block);
+        // the static match is one byte, the others keep their names' index or spell them out
+        assert_eq!(block[0], 0x88);
+        assert_eq!(block[1], 0x08);
+        let mut decoder = Decoder::new(4096);
+        assert_eq!(
+            decoder.decode(&block, usize::MAX).unwrap(),
+            Some(fields(&sent))
+        );
+        // nothing was indexed
+        assert_eq!(decoder.size, 0);
+    }
+}
@@ -0,0 +1,1230 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    io::{self, BufRead, BufReader, ErrorKind, Read, Write},
+};
+
+use crate::{
+    headers::HeaderMap,
+    hpack::{self, Decoder},
+    http_server::{
+        get_413_default_response, get_431_default_response, is_field_text, is_token,
+        sendable_trailers, Body, HTTPResponse,
+    },
+};
+
+/// what a client sends first on an HTTP/2 connection
+pub(crate) const PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+const DATA: u8 = 0x0;
+const HEADERS: u8 = 0x1;
+const PRIORITY: u8 = 0x2;
+const RST_STREAM: u8 = 0x3;
+const SETTINGS: u8 = 0x4;
+const PUSH_PROMISE: u8 = 0x5;
+const PING: u8 = 0x6;
+const GOAWAY: u8 = 0x7;
+const WINDOW_UPDATE: u8 = 0x8;
+const CONTINUATION: u8 = 0x9;
+
+const END_STREAM: u8 = 0x1;
+const ACK: u8 = 0x1;
+const END_HEADERS: u8 = 0x4;
+const PADDED: u8 = 0x8;
+const PRIORITY_FLAG: u8 = 0x20;
+
+pub(crate) const NO_ERROR: u32 = 0x0;
+const PROTOCOL_ERROR: u32 = 0x1;
+const FLOW_CONTROL_ERROR: u32 = 0x3;
+const STREAM_CLOSED: u32 = 0x5;
+const FRAME_SIZE_ERROR: u32 = 0x6;
+const REFUSED_STREAM: u32 = 0x7;
+const COMPRESSION_ERROR: u32 = 0x9;
+const ENHANCE_YOUR_CALM: u32 = 0xb;
+
+const SETTINGS_HEADER_TABLE_SIZE: u16 = 0x1;
+const SETTINGS_ENABLE_PUSH: u16 = 0x2;
+const SETTINGS_MAX_CONCURRENT_STREAMS: u16 = 0x3;
+const SETTINGS_INITIAL_WINDOW_SIZE: u16 = 0x4;
+const SETTINGS_MAX_FRAME_SIZE: u16 = 0x5;
+const SETTINGS_MAX_HEADER_LIST_SIZE: u16 = 0x6;
+
+/// the defaults of RFC 9113, which the server keeps for itself
+const DEFAULT_WINDOW_SIZE: i64 = 65_535;
+const DEFAULT_MAX_FRAME_SIZE: usize = 16_384;
+const HEADER_TABLE_SIZE: usize = 4_096;
+const MAX_CONCURRENT_STREAMS: u32 = 100;
+const MAX_WINDOW_SIZE: i64 = (1 << 31) - 1;
+
+/// headers of HTTP/1.1 connections that have no meaning in HTTP/2
+const CONNECTION_HEADERS: [&str; 5] = [
+    "connection",
+    "keep-alive",
+    "proxy-connection",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// a request whose head and body have arrived in full
+pub(crate) struct Request {
+    pub(crate) method: String,
+    pub(crate) scheme: String,
+    pub(crate) authority: Option<String>,
+    pub(crate) path: String,
+    pub(crate) headers: HeaderMap,
+    pub(crate) body: Vec<u8>,
+    pub(crate) trailers: HeaderMap,
+}
+
+/// a stream whose request is complete, or one the server answers itself
+pub(crate) enum Received {
+    Request(u32, Request),
+    /// a request with too large a head or body, to be answered with the response
+    Refused(u32, HTTPResponse),
+}
+
+struct Stream {
+    /// the header block, while CONTINUATION frames are still adding to it
+    block: Vec<u8>,
+    /// whether the HEADERS frame ended the stream
+    ends_with_headers: bool,
+    /// the request, once its head is decoded
+    request: Option<Request>,
+    /// whether the client is done sending
+    remote_closed: bool,
+    /// whether the request was refused, so the rest of its body is dropped
+    refused: bool,
+    send_window: i64,
+}
+
+/// a frame the peer broke the protocol with, ending the connection
+struct ConnectionError(u32, &'static str);
+
+enum SendError {
+    Io(io::Error),
+    /// the client reset the stream, there's no one to send to
+    Reset,
+}
+
+impl From<io::Error> for SendError {
+    fn from(error: io::Error) -> SendError {
+        SendError::Io(error)
+    }
+}
+
+/// the server side of an HTTP/2 connection. Streams are answered one after another, in the
+/// order their requests complete. The bodies waiting for that share one budget of
+/// `max_body_size` bytes, or the default window if that's smaller, which the connection's
+/// flow control window is kept within
+pub(crate) struct Connection<'a, S: Read + Write> {
+    stream: &'a mut BufReader<S>,
+    decoder: Decoder,
+    streams: HashMap<u32, Stream>,
+    ready: VecDeque<Received>,
+    /// the highest stream id the client opened
+    last_stream_id: u32,
+    /// the stream whose header block continues in CONTINUATION frames
+    continuation: Option<u32>,
+    send_window: i64,
+    peer_initial_window: i64,
+    peer_max_frame_size: usize,
+    max_header_list_size: usize,
+    max_body_size: usize,
+    /// the bytes the client may still send before the server extends the window
+    recv_window: i64,
+    /// the bytes of bodies kept, on open streams or in requests not yet handed out
+    buffered: usize,
+    going_away: bool,
+}
+
+impl<'a, S: Read + Write> Connection<'a, S> {
+    pub(crate) fn new(
+        stream: &'a mut BufReader<S>,
+        max_header_list_size: usize,
+        max_body_size: usize,
+    ) -> Connection<'a, S> {
+        Connection {
+            stream,
+            decoder: Decoder::new(HEADER_TABLE_SIZE),
+            streams: HashMap::new(),
+            ready: VecDeque::new(),
+            last_stream_id: 0,
+            continuation: None,
+            send_window: DEFAULT_WINDOW_SIZE,
+            peer_initial_window: DEFAULT_WINDOW_SIZE,
+            peer_max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            max_header_list_size,
+            max_body_size,
+            recv_window: DEFAULT_WINDOW_SIZE,
+            buffered: 0,
+            going_away: false,
+        }
+    }
+
+    /// send the server's settings and read the client's preface. `settings` are those of an
+    /// `HTTP2-Settings` header, and `upgraded` the request a connection upgraded with, which
+    /// becomes stream 1
+    pub(crate) fn handshake(&mut self, settings: Option<&[u8]>, upgraded: bool) -> io::Result<()> {
+        let mut payload = Vec::new();
+        for (id, value) in [
+            (SETTINGS_MAX_CONCURRENT_STREAMS, MAX_CONCURRENT_STREAMS),
+            (
+                SETTINGS_MAX_HEADER_LIST_SIZE,
+                self.max_header_list_size as u32,
+            ),
+        ] {
+            payload.extend_from_slice(&id.to_be_bytes());
+            payload.extend_from_slice(&value.to_be_bytes());
+        }
+        self.write_frame(SETTINGS, 0, 0, &payload)?;
+        self.stream.get_mut().flush()?;
+
+        if let Some(settings) = settings {
+            // acknowledged by the 101 already
+            if let Err(error) = self.apply_settings(settings) {
+                return Err(self.fail(error));
+            }
+        }
+        if upgraded {
+            let mut preface = [0; PREFACE.len()];
+            self.stream.read_exact(&mut preface)?;
+            if preface != PREFACE {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidData,
+                    "missing HTTP/2 preface",
+                ));
+            }
+            self.last_stream_id = 1;
+            self.streams.insert(1, self.new_stream(true));
+        }
+        Ok(())
+    }
+
+    /// the next complete request, `None` once the client closed the connection or said
+    /// goodbye. Fails on broken frames after telling the client with a GOAWAY
+    pub(crate) fn next_request(&mut self) -> io::Result<Option<Received>> {
+        loop {
+            if let Some(received) = self.ready.pop_front() {
+                // the body is the listener's now, making room for others
+                if let Received::Request(_, request) = &received {
+                    self.buffered -= request.body.len();
+                    self.update_window()?;
+                }
+                return Ok(Some(received));
+            }
+            if self.going_away && self.streams.values().all(|stream| stream.remote_closed) {
+                return Ok(None);
+            }
+            if !self.read_frame()? {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// answer `stream_id` with `response`, its body left out unless `send_body`. Returns the
+    /// bytes of body sent
+    pub(crate) fn send_response(
+        &mut self,
+        stream_id: u32,
+        response: HTTPResponse,
+        send_body: bool,
+    ) -> io::Result<u64> {
+        let sent = match self.send(stream_id, response, send_body) {
+            Ok(sent) => sent,
+            Err(SendError::Reset) => 0,
+            Err(SendError::Io(error)) => return Err(error),
+        };
+        // a client still sending is told it can stop
+        if let Some(stream) = self.streams.remove(&stream_id) {
+            if !stream.remote_closed {
+                self.write_frame(RST_STREAM, 0, stream_id, &NO_ERROR.to_be_bytes())?;
+            }
+        }
+        self.stream.get_mut().flush()?;
+        Ok(sent)
+    }
+
+    /// tell the client no more streams are answered, with `code` as the reason
+    pub(crate) fn go_away(&mut self, code: u32) -> io::Result<()> {
+        let mut payload = self.last_stream_id.to_be_bytes().to_vec();
+        payload.extend_from_slice(&code.to_be_bytes());
+        self.write_frame(GOAWAY, 0, 0, &payload)?;
+        self.stream.get_mut().flush()
+    }
+
+    fn send(
+        &mut self,
+        stream_id: u32,
+        response: HTTPResponse,
+        send_body: bool,
+    ) -> Result<u64, SendError> {
+        let status = response.status.status;
+        let send_body = send_body && !matches!(status, 100..=199 | 204 | 304);
+        let body = match response.body {
+            Body::Full(body) if body.is_empty() => None,
+            Body::Upgrade(_) => None,
+            body => send_body.then_some(body),
+        };
+
+        let status = status.to_string();
+        let end_stream = match body {
+            Some(_) => 0,
+            None => END_STREAM,
+        };
+        self.send_headers(stream_id, Some(&status), &response.headers, end_stream)?;
+
+        let mut sent = 0;
+        match body {
+            None => {}
+            Some(Body::Full(body)) => sent += self.send_data(stream_id, &body, true)?,
+            Some(Body::Chunks(chunks)) => {
+                for chunk in chunks {
+                    sent += self.send_data(stream_id, &chunk, false)?;
+                }
+                self.send_data(stream_id, &[], true)?;
+            }
+            Some(Body::Events(events)) => {
+                for chunk in events {
+                    sent += self.send_data(stream_id, &chunk, false)?;
+                }
+                self.send_data(stream_id, &[], true)?;
+            }
+            Some(Body::Trailed(chunks, trailers)) => {
+                for chunk in chunks {
+                    sent += self.send_data(stream_id, &chunk, false)?;
+                }
+                let trailers = sendable_trailers(trailers());
+                match trailers.is_empty() {
+                    true => self.send_data(stream_id, &[], true)?,
+                    false => {
+                        if !self.streams.contains_key(&stream_id) {
+                            return Err(SendError::Reset);
+                        }
+                        self.send_headers(stream_id, None, &trailers, END_STREAM)?;
+                        0
+                    }
+                };
+            }
+            Some(Body::Reader(reader)) => sent += self.send_reader(stream_id, reader)?,
+            Some(Body::Sized(reader, length)) => {
+                sent += self.send_reader(stream_id, reader.take(length))?
+            }
+            Some(Body::File(file, length)) => {
+                sent += self.send_reader(stream_id, file.take(length))?
+            }
+            Some(Body::Upgrade(_)) => {}
+        }
+        Ok(sent)
+    }
+
+    /// send `headers` as a header block, led by a `:status` unless they're trailers
+    fn send_headers(
+        &mut self,
+        stream_id: u32,
+        status: Option<&str>,
+        headers: &HeaderMap,
+        end_stream: u8,
+    ) -> io::Result<()> {
+        let names: Vec<(String, &str)> = headers
+            .iter()
+            .map(|(name, value)| (name.to_ascii_lowercase(), value))
+            .filter(|(name, _)| !CONNECTION_HEADERS.contains(&name.as_str()))
+            .collect();
+        let mut block = Vec::new();
+        hpack::encode(
+            status
+                .map(|status| (":status", status))
+                .into_iter()
+                .chain(names.iter().map(|(name, value)| (name.as_str(), *value))),
+            &mut block,
+        );
+        let mut fragments = block.chunks(self.peer_max_frame_size).peekable();
+        let mut first = true;
+        while let Some(fragment) = fragments.next() {
+            let end_headers = match fragments.peek() {
+                Some(_) => 0,
+                None => END_HEADERS,
+            };
+            match first {
+                true => self.write_frame(HEADERS, end_stream | end_headers, stream_id, fragment)?,
+                false => self.write_frame(CONTINUATION, end_headers, stream_id, fragment)?,
+            }
+            first = false;
+        }
+        Ok(())
+    }
+
+    fn send_reader(&mut self, stream_id: u32, mut reader: impl Read) -> Result<u64, SendError> {
+        let mut buffer = vec![0; DEFAULT_MAX_FRAME_SIZE];
+        let mut sent = 0;
+        loop {
+            match reader.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(read) => sent += self.send_data(stream_id, &buffer[..read], false)?,
+                Err(error) if error.kind() == ErrorKind::Interrupted => {}
+                Err(error) => return Err(SendError::Io(error)),
+            }
+        }
+        self.send_data(stream_id, &[], true)?;
+        Ok(sent)
+    }
+
+    /// send `data` in DATA frames as the flow control windows allow, reading the client's
+    /// frames while they're exhausted
+    fn send_data(&mut self, stream_id: u32, mut data: &[u8], end: bool) -> Result<u64, SendError> {
+        let sent = data.len() as u64;
+        loop {
+            let stream_window = match self.streams.get(&stream_id) {
+                Some(stream) => stream.send_window,
+                None => return Err(SendError::Reset),
+            };
+            if data.is_empty() {
+                if end {
+                    self.write_frame(DATA, END_STREAM, stream_id, &[])?;
+                }
+                return Ok(sent);
+            }
+            let window = self.send_window.min(stream_window);
+            if window <= 0 {
+                self.stream.get_mut().flush()?;
+                if !self.read_frame()? {
+                    return Err(SendError::Io(ErrorKind::ConnectionAborted.into()));
+                }
+                continue;
+            }
+            let size = data
+                .len()
+                .min(window as usize)
+                .min(self.peer_max_frame_size);
+            let flags = match end && size == data.len() {
+                true => END_STREAM,
+                false => 0,
+            };
+            self.write_frame(DATA, flags, stream_id, &data[..size])?;
+            self.send_window -= size as i64;
+            if let Some(stream) = self.streams.get_mut(&stream_id) {
+                stream.send_window -= size as i64;
+            }
+            data = &data[size..];
+            if flags == END_STREAM {
+                return Ok(sent);
+            }
+        }
+    }
+
+    /// read and handle one frame. Returns false once the client closed the connection
+    fn read_frame(&mut self) -> io::Result<bool> {
+        if self.stream.fill_buf()?.is_empty() {
+            return Ok(false);
+        }
+        let mut header = [0; 9];
+        self.stream.read_exact(&mut header)?;
+        let length = u32::from_be_bytes([0, header[0], header[1], header[2]]) as usize;
+        let kind = header[3];
+        let flags = header[4];
+        let stream_id =
+            u32::from_be_bytes([header[5], header[6], header[7], header[8]]) & 0x7fff_ffff;
+        if length > DEFAULT_MAX_FRAME_SIZE {
+            return Err(self.fail(ConnectionError(FRAME_SIZE_ERROR, "frame too large")));
+        }
+        let mut payload = vec![0; length];
+        self.stream.read_exact(&mut payload)?;
+        match self.handle_frame(kind, flags, stream_id, payload) {
+            Ok(()) => Ok(true),
+            Err(error) => Err(self.fail(error)),
+        }
+    }
+
+    /// send a GOAWAY for `error`, returning it as an io error
+    fn fail(&mut self, ConnectionError(code, reason): ConnectionError) -> io::Error {
+        let _ = self.go_away(code);
+        io::Error::new(ErrorKind::InvalidData, reason)
+    }
+
+    fn handle_frame(
+        &mut self,
+        kind: u8,
+        flags: u8,
+        stream_id: u32,
+        payload: Vec<u8>,
+    ) -> Result<(), ConnectionError> {
+        if let Some(expected) = self.continuation {
+            if kind != CONTINUATION || stream_id != expected {
+                return Err(ConnectionError(PROTOCOL_ERROR, "expected CONTINUATION"));
+            }
+        }
+        match kind {
+            DATA => self.handle_data(flags, stream_id, payload),
+            HEADERS => self.handle_headers(flags, stream_id, payload),
+            CONTINUATION => self.handle_continuation(flags, stream_id, payload),
+            PRIORITY => Ok(()),
+            RST_STREAM => {
+                if stream_id == 0 || payload.len() != 4 {
+                    return Err(ConnectionError(PROTOCOL_ERROR, "malformed RST_STREAM"));
+                }
+                if let Some(request) = self.streams.remove(&stream_id).and_then(|s| s.request) {
+                    self.buffered -= request.body.len();
+                    self.update_window().map_err(io_error)?;
+                }
+                Ok(())
+            }
+            SETTINGS => {
+                if stream_id != 0 {
+                    return Err(ConnectionError(PROTOCOL_ERROR, "SETTINGS on a stream"));
+                }
+                if flags & ACK != 0 {
+                    return Ok(());
+                }
+                self.apply_settings(&payload)?;
+                self.write_frame(SETTINGS, ACK, 0, &[]).map_err(io_error)
+            }
+            PUSH_PROMISE => Err(ConnectionError(PROTOCOL_ERROR, "clients can't push")),
+            PING => {
+                if stream_id != 0 || payload.len() != 8 {
+                    return Err(ConnectionError(FRAME_SIZE_ERROR, "malformed PING"));
+                }
+                if flags & ACK != 0 {
+                    return Ok(());
+                }
+                self.write_frame(PING, ACK, 0, &payload)
+                    .and_then(|_| self.stream.get_mut().flush())
+                    .map_err(io_error)
+            }
+            GOAWAY => {
+                self.going_away = true;
+                Ok(())
+            }
+            WINDOW_UPDATE => {
+                if payload.len() != 4 {
+                    return Err(ConnectionError(FRAME_SIZE_ERROR, "malformed WINDOW_UPDATE"));
+                }
+                let increment =
+                    (u32::from_be_bytes([payload[0], payload[1], payload[2], payload[3]])
+                        & 0x7fff_ffff) as i64;
+                let window = match stream_id {
+                    0 => &mut self.send_window,
+                    id => match self.streams.get_mut(&id) {
+                        Some(stream) => &mut stream.send_window,
+                        None => return Ok(()),
+                    },
+                };
+                *window += increment;
+                match increment == 0 || *window > MAX_WINDOW_SIZE {
+                    true => Err(ConnectionError(FLOW_CONTROL_ERROR, "invalid window update")),
+                    false => Ok(()),
+                }
+            }
+            // frames of unknown types are ignored
+            _ => Ok(()),
+        }
+    }
+
+    fn handle_data(
+        &mut self,
+        flags: u8,
+        stream_id: u32,
+        payload: Vec<u8>,
+    ) -> Result<(), ConnectionError> {
+        if stream_id == 0 {
+            return Err(ConnectionError(PROTOCOL_ERROR, "DATA outside a stream"));
+        }
+        let length = payload.len() as u32;
+        if length as i64 > self.recv_window {
+            return Err(ConnectionError(
+                FLOW_CONTROL_ERROR,
+                "DATA beyond the window",
+            ));
+        }
+        self.recv_window -= length as i64;
+        let data = strip_padding(flags, &payload)?;
+        let kept = self.keep_data(flags, stream_id, data, length)?;
+        self.buffered += kept;
+        // padding and dropped data are given back right away, bodies as they're handed out
+        self.update_window().map_err(io_error)?;
+        if self.recv_window == 0
+            && !self
+                .ready
+                .iter()
+                .any(|r| matches!(r, Received::Request(..)))
+        {
+            self.refuse_largest().map_err(io_error)?;
+        }
+        Ok(())
+    }
+
+    /// add `data` to the body of `stream_id`, returning how many bytes were kept
+    fn keep_data(
+        &mut self,
+        flags: u8,
+        stream_id: u32,
+        data: &[u8],
+        length: u32,
+    ) -> Result<usize, ConnectionError> {
+        let max_body_size = self.max_body_size;
+        let Some(stream) = self.streams.get_mut(&stream_id) else {
+            return match stream_id > self.last_stream_id {
+                true => Err(ConnectionError(PROTOCOL_ERROR, "DATA on an idle stream")),
+                // a stream that was reset or answered early
+                false => Ok(0),
+            };
+        };
+        if stream.remote_closed {
+            return Err(ConnectionError(STREAM_CLOSED, "DATA on a closed stream"));
+        }
+        let end_stream = flags & END_STREAM != 0;
+        stream.remote_closed = end_stream;
+        if stream.refused {
+            return Ok(0);
+        }
+        let request = stream.request.as_mut().unwrap();
+        if request.body.len() + data.len() > max_body_size {
+            let dropped = request.body.len();
+            stream.refused = true;
+            stream.request = None;
+            self.buffered -= dropped;
+            let response = get_413_default_response();
+            self.ready.push_back(Received::Refused(stream_id, response));
+            return Ok(0);
+        }
+        request.body.extend_from_slice(data);
+        if end_stream {
+            let request = stream.request.take().unwrap();
+            self.ready.push_back(Received::Request(stream_id, request));
+        } else if length > 0 {
+            self.write_frame(WINDOW_UPDATE, 0, stream_id, &length.to_be_bytes())
+                .map_err(io_error)?;
+        }
+        Ok(data.len())
+    }
+
+    /// extend the connection's window to what the body budget has room for
+    fn update_window(&mut self) -> io::Result<()> {
+        let budget = self.max_body_size.max(DEFAULT_WINDOW_SIZE as usize);
+        let increment = budget.saturating_sub(self.buffered) as i64 - self.recv_window;
+        if increment <= 0 {
+            return Ok(());
+        }
+        // an increment can't take the window past what a WINDOW_UPDATE allows
+        let increment = increment.min(MAX_WINDOW_SIZE - self.recv_window);
+        self.recv_window += increment;
+        self.write_frame(WINDOW_UPDATE, 0, 0, &(increment as u32).to_be_bytes())
+    }
+
+    /// reset the open stream with the largest body, once the budget is used up by bodies that
+    /// are still incomplete and the client couldn't finish any of them. It's free to retry
+    fn refuse_largest(&mut self) -> io::Result<()> {
+        let largest = self
+            .streams
+            .iter()
+            .filter_map(|(&id, stream)| Some((id, stream.request.as_ref()?.body.len())))
+            .max_by_key(|&(_, length)| length);
+        let Some((stream_id, length)) = largest else {
+            return Ok(());
+        };
+        self.streams.remove(&stream_id);
+        self.buffered -= length;
+        self.write_frame(RST_STREAM, 0, stream_id, &REFUSED_STREAM.to_be_bytes())?;
+        self.update_window()
+    }
+
+    fn handle_headers(
+        &mut self,
+        flags: u8,
+        stream_id: u32,
+        payload: Vec<u8>,
+    ) -> Result<(), ConnectionError> {
+        if stream_id == 0 || stream_id.is_multiple_of(2) {
+            return Err(ConnectionError(
+                PROTOCOL_ERROR,
+                "HEADERS on an invalid stream",
+            ));
+        }
+        let mut fragment = strip_padding(flags, &payload)?;
+        if flags & PRIORITY_FLAG != 0 {
+            fragment = fragment
+                .get(5..)
+                .ok_or(ConnectionError(FRAME_SIZE_ERROR, "short HEADERS"))?;
+        }
+        let fragment = fragment.to_vec();
+        let end_stream = flags & END_STREAM != 0;
+
+        match self.streams.get_mut(&stream_id) {
+            // trailers, which have to end the stream
+            Some(stream) => {
+                if stream.remote_closed || !end_stream {
+                    return Err(ConnectionError(PROTOCOL_ERROR, "unexpected HEADERS"));
+                }
+                stream.block = fragment;
+                stream.ends_with_headers = true;
+            }
+            None => {
+                if stream_id <= self.last_stream_id {
+                    return Err(ConnectionError(STREAM_CLOSED, "HEADERS on a closed stream"));
+                }
+                self.last_stream_id = stream_id;
+                let mut stream = self.new_stream(false);
+                stream.block = fragment;
+                stream.ends_with_headers = end_stream;
+                self.streams.insert(stream_id, stream);
+            }
+        }
+        match flags & END_HEADERS != 0 {
+            true => self.finish_headers(stream_id),
+            false => {
+                self.continuation = Some(stream_id);
+                Ok(())
+            }
+        }
+    }
+
+    fn handle_continuation(
+        &mut self,
+        flags: u8,
+        stream_id: u32,
+        payload: Vec<u8>,
+    ) -> Result<(), ConnectionError> {
+        if self.continuation != Some(stream_id) {
+            return Err(ConnectionError(PROTOCOL_ERROR, "unexpected CONTINUATION"));
+        }
+        let limit = self.max_header_list_size + DEFAULT_MAX_FRAME_SIZE;
+        let stream = self.streams.get_mut(&stream_id).unwrap();
+        stream.block.extend_from_slice(&payload);
+        if stream.block.len() > limit {
+            return Err(ConnectionError(ENHANCE_YOUR_CALM, "header block too large"));
+        }
+        if flags & END_HEADERS != 0 {
+            self.continuation = None;
+            return self.finish_headers(stream_id);
+        }
+        Ok(())
+    }
+
+    /// decode the complete header block of `stream_id`
+    fn finish_headers(&mut self, stream_id: u32) -> Result<(), ConnectionError> {
+        let stream = self.streams.get_mut(&stream_id).unwrap();
+        let block = std::mem::take(&mut stream.block);
+        let fields = self
+            .decoder
+            .decode(&block, self.max_header_list_size)
+            .map_err(|_| ConnectionError(COMPRESSION_ERROR, "undecodable header block"))?;
+        let end_stream = stream.ends_with_headers;
+
+        // trailers end a request whose head is already there
+        if stream.refused || stream.request.is_some() {
+            stream.remote_closed = true;
+            let Some(mut request) = stream.request.take() else {
+                return Ok(());
+            };
+            let trailers: Vec<_> = fields.into_iter().flatten().collect();
+            // pseudo-headers aren't tokens, they have no place in trailers either
+            let malformed = trailers.iter().any(|(name, value)| {
+                !is_token(name)
+                    || name.bytes().any(|b| b.is_ascii_uppercase())
+                    || !is_field_text(value)
+            });
+            if malformed {
+                self.streams.remove(&stream_id);
+                self.buffered -= request.body.len();
+                self.update_window().map_err(io_error)?;
+                return self
+                    .write_frame(RST_STREAM, 0, stream_id, &PROTOCOL_ERROR.to_be_bytes())
+                    .map_err(io_error);
+            }
+            for (name, value) in trailers {
+                request.trailers.append(&name, value);
+            }
+            self.ready.push_back(Received::Request(stream_id, request));
+            return Ok(());
+        }
+        // refused only once decoded, so the decoder's table stays in step with the client's
+        if self.streams.len() > MAX_CONCURRENT_STREAMS as usize {
+            self.streams.remove(&stream_id);
+            return self
+                .write_frame(RST_STREAM, 0, stream_id, &REFUSED_STREAM.to_be_bytes())
+                .map_err(io_error);
+        }
+        let stream = self.streams.get_mut(&stream_id).unwrap();
+        stream.remote_closed = end_stream;
+        let Some(fields) = fields else {
+            stream.refused = true;
+            let response = get_431_default_response();
+            self.ready.push_back(Received::Refused(stream_id, response));
+            return Ok(());
+        };
+        let Some(request) = parse_request(fields) else {
+            self.streams.remove(&stream_id);
+            return self
+                .write_frame(RST_STREAM, 0, stream_id, &PROTOCOL_ERROR.to_be_bytes())
+                .map_err(io_error);
+        };
+        match end_stream {
+            true => self.ready.push_back(Received::Request(stream_id, request)),
+            false => stream.request = Some(request),
+        }
+        Ok(())
+    }
+
+    fn apply_settings(&mut self, payload: &[u8]) -> Result<(), ConnectionError> {
+        if !payload.len().is_multiple_of(6) {
+            return Err(ConnectionError(FRAME_SIZE_ERROR, "malformed SETTINGS"));
+        }
+        for setting in payload.chunks(6) {
+            let id = u16::from_be_bytes([setting[0], setting[1]]);
+            let value = u32::from_be_bytes([setting[2], setting[3], setting[4], setting[5]]);
+            match id {
+                SETTINGS_ENABLE_PUSH if value > 1 => {
+                    return Err(ConnectionError(PROTOCOL_ERROR, "invalid ENABLE_PUSH"));
+                }
+                SETTINGS_INITIAL_WINDOW_SIZE => {
+                    let value = value as i64;
+                    if value > MAX_WINDOW_SIZE {
+                        return Err(ConnectionError(FLOW_CONTROL_ERROR, "window too large"));
+                    }
+                    let delta = value - self.peer_initial_window;
+                    self.peer_initial_window = value;
+                    for stream in self.streams.values_mut() {
+                        stream.send_window += delta;
+                    }
+                }
+                SETTINGS_MAX_FRAME_SIZE => {
+                    if !(16_384..=16_777_215).contains(&value) {
+                        return Err(ConnectionError(PROTOCOL_ERROR, "invalid MAX_FRAME_SIZE"));
+                    }
+                    self.peer_max_frame_size = value as usize;
+                }
+                // the encoder never adds to the peer's table, so its size doesn't matter
+                SETTINGS_HEADER_TABLE_SIZE => {}
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn new_stream(&self, remote_closed: bool) -> Stream {
+        Stream {
+            block: Vec::new(),
+            ends_with_headers: remote_closed,
+            request: None,
+            remote_closed,
+            refused: false,
+            send_window: self.peer_initial_window,
+        }
+    }
+
+    fn write_frame(
+        &mut self,
+        kind: u8,
+        flags: u8,
+        stream_id: u32,
+        payload: &[u8],
+    ) -> io::Result<()> {
+        let length = (payload.len() as u32).to_be_bytes();
+        let mut frame = Vec::with_capacity(9 + payload.len());
+        frame.extend_from_slice(&length[1..]);
+        frame.push(kind);
+        frame.push(flags);
+        frame.extend_from_slice(&stream_id.to_be_bytes());
+        frame.extend_from_slice(payload);
+        self.stream.get_mut().write_all(&frame)
+    }
+}
+
+/// the request of a decoded header block, `None` if it's malformed
+fn parse_request(fields: Vec<(String, String)>) -> Option<Request> {
+    let mut method = None;
+    let mut scheme = None;
+    let mut authority = None;
+    let mut path = None;
+    let mut headers = HeaderMap::with_capacity(fields.len());
+    let mut cookies = Vec::new();
+    for (name, value) in fields {
+        // a field that couldn't be written in HTTP/1.1 makes the request malformed, rather
+        // than reaching listeners and upstreams that do
+        if !is_field_text(&value) {
+            return None;
+        }
+        if let Some(pseudo) = name.strip_prefix(':') {
+            // pseudo-headers come first, once each
+            if !headers.is_empty() {
+                return None;
+            }
+            let slot = match pseudo {
+                "method" => &mut method,
+                "scheme" => &mut scheme,
+                "authority" => &mut authority,
+                "path" => &mut path,
+                _ => return None,
+            };
+            if slot.replace(value).is_some() {
+                return None;
+            }
+            continue;
+        }
+        if !is_token(&name)
+            || name.bytes().any(|b| b.is_ascii_uppercase())
+            || CONNECTION_HEADERS.contains(&name.as_str())
+        {
+            return None;
+        }
+        // cookies may be split into several fields, which HTTP/1.1 joins into one
+        match name.as_str() {
+            "cookie" => cookies.push(value),
+            "te" if value != "trailers" => return None,
+            _ => headers.append(&name, value),
+        }
+    }
+    if !cookies.is_empty() {
+        headers.append("cookie", cookies.join("; "));
+    }
+    if let Some(ref authority) = authority {
+        headers.insert("host", authority.as_str());
+    }
+    let method = method.filter(|method| is_token(method))?;
+    // the target of the request line, which ends at whitespace
+    let path = path.filter(|path| !path.is_empty() && !path.contains([' ', '\t']))?;
+    Some(Request {
+        method,
+        scheme: scheme?,
+        authority,
+        path,
+        headers,
+        body: Vec::new(),
+        trailers: HeaderMap::new(),
+    })
+}
+
+/// the payload of a frame with its padding removed
+fn strip_padding(flags: u8, payload: &[u8]) -> Result<&[u8], ConnectionError> {
+    if flags & PADDED == 0 {
+        return Ok(payload);
+    }
+    let (&padding, rest) = payload
+        .split_first()
+        .ok_or(ConnectionError(PROTOCOL_ERROR, "missing padding length"))?;
+    rest.len()
+        .checked_sub(padding as usize)
+        .map(|length| &rest[..length])
+        .ok_or(ConnectionError(PROTOCOL_ERROR, "padding exceeds the frame"))
+}
+
+fn io_error(_: io::Error) -> ConnectionError {
+    ConnectionError(PROTOCOL_ERROR, "failed writing to the connection")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    /// a connection's two directions in memory: what the client sent, and what the server wrote
+    struct Pipe {
+        input: Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl Read for Pipe {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for Pipe {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn frame(kind: u8, flags: u8, stream_id: u32, payload: &[u8]) -> Vec<u8> {
+        let mut frame = (payload.len() as u32).to_be_bytes()[1..].to_vec();
+        frame.extend_from_slice(&[kind, flags]);
+        frame.extend_from_slice(&stream_id.to_be_bytes());
+        frame.extend_from_slice(payload);
+        frame
+    }
+
+    fn headers(stream_id: u32, end_stream: bool) -> Vec<u8> {
+        fields(stream_id, end_stream, &[])
+    }
+
+    /// a HEADERS frame of a POST to `/upload` with `extra` fields, or the pseudo-headers
+    /// `extra` replaces
+    fn fields(stream_id: u32, end_stream: bool, extra: &[(&str, &str)]) -> Vec<u8> {
+        let mut fields = vec![
+            (":method", "POST"),
+            (":scheme", "https"),
+            (":path", "/upload"),
+            (":authority", "example.com"),
+        ];
+        for &(name, value) in extra {
+            match fields.iter_mut().find(|(known, _)| *known == name) {
+                Some(field) => field.1 = value,
+                None => fields.push((name, value)),
+            }
+        }
+        let flags = match end_stream {
+            true => END_HEADERS | END_STREAM,
+            false => END_HEADERS,
+        };
+        frame(HEADERS, flags, stream_id, &block(&fields))
+    }
+
+    fn block(fields: &[(&str, &str)]) -> Vec<u8> {
+        let mut block = Vec::new();
+        hpack::encode(fields.iter().copied(), &mut block);
+        block
+    }
+
+    /// the streams the server reset, with their error codes
+    fn resets(output: &[u8]) -> Vec<(u32, u32)> {
+        frames(output)
+            .into_iter()
+            .filter(|&(kind, _, _, _)| kind == RST_STREAM)
+            .map(|(_, _, stream_id, payload)| {
+                (stream_id, u32::from_be_bytes(payload.try_into().unwrap()))
+            })
+            .collect()
+    }
+
+    /// `length` bytes of body for `stream_id` in frames as large as allowed
+    fn data(stream_id: u32, length: usize, end_stream: bool) -> Vec<u8> {
+        let mut frames = Vec::new();
+        let mut left = length;
+        while left > 0 {
+            let size = left.min(DEFAULT_MAX_FRAME_SIZE);
+            left -= size;
+            let flags = match end_stream && left == 0 {
+                true => END_STREAM,
+                false => 0,
+            };
+            frames.extend(frame(DATA, flags, stream_id, &vec![b'x'; size]));
+        }
+        frames
+    }
+
+    /// the frames the server wrote, as kind, flags, stream id and payload
+    fn frames(mut output: &[u8]) -> Vec<(u8, u8, u32, Vec<u8>)> {
+        let mut frames = Vec::new();
+        while !output.is_empty() {
+            let length = u32::from_be_bytes([0, output[0], output[1], output[2]]) as usize;
+            let stream_id = u32::from_be_bytes([output[5], output[6], output[7], output[8]]);
+            frames.push((
+                output[3],
+                output[4],
+                stream_id,
+                output[9..9 + length].to_vec(),
+            ));
+            output = &output[9 + length..];
+        }
+        frames
+    }
+
+    /// the increments of the connection's window the server sent
+    fn window_updates(output: &[u8]) -> Vec<u32> {
+        frames(output)
+            .into_iter()
+            .filter(|&(kind, _, stream_id, _)| kind == WINDOW_UPDATE && stream_id == 0)
+            .map(|(_, _, _, payload)| u32::from_be_bytes(payload.try_into().unwrap()))
+            .collect()
+    }
+
+    fn pipe(input: Vec<Vec<u8>>) -> BufReader<Pipe> {
+        BufReader::new(Pipe {
+            input: Cursor::new(input.concat()),
+            output: Vec::new(),
+        })
+    }
+
+    #[test]
+    fn requests() {
+        let mut stream = pipe(vec![
+            headers(1, true),
+            headers(3, false),
+            data(3, 5, true),
+            frame(PING, 0, 0, b"pingpong"),
+        ]);
+        let mut connection = Connection::new(&mut stream, 8192, 1024);
+        connection.handshake(None, false).unwrap();
+        let Some(Received::Request(1, request)) = connection.next_request().unwrap() else {
+            panic!("expected stream 1");
+        };
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/upload");
+        assert_eq!(request.authority.as_deref(), Some("example.com"));
+        assert!(request.body.is_empty());
+        let Some(Received::Request(3, request)) = connection.next_request().unwrap() else {
+            panic!("expected stream 3");
+        };
+        assert_eq!(request.body, b"xxxxx");
+        assert!(connection.next_request().unwrap().is_none());
+
+        let written = frames(&connection.stream.get_ref().output);
+        assert_eq!(written[0].0, SETTINGS);
+        assert!(written.contains(&(PING, ACK, 0, b"pingpong".to_vec())));
+    }
+
+    #[test]
+    fn malformed_fields_reset_the_stream() {
+        let mut stream = pipe(vec![
+            fields(1, true, &[("x-forwarded", "a\r\nx-admin: 1")]),
+            fields(3, true, &[("x name", "a")]),
+            fields(5, true, &[("x-nul", "a\0b")]),
+            fields(7, true, &[(":path", "/upload HTTP/1.1\r\nhost: other")]),
+            fields(9, true, &[(":method", "GET /")]),
+            fields(11, true, &[("x-fine", "a\tb")]),
+        ]);
+        let mut connection = Connection::new(&mut stream, 8192, 1024);
+        let Some(Received::Request(11, request)) = connection.next_request().unwrap() else {
+            panic!("expected only stream 11");
+        };
+        assert_eq!(request.headers.get("x-fine"), Some("a\tb"));
+        let reset = resets(&connection.stream.get_ref().output);
+        let malformed = [1, 3, 5, 7, 9].map(|stream_id| (stream_id, PROTOCOL_ERROR));
+        assert_eq!(reset, malformed);
+    }
+
+    #[test]
+    fn malformed_trailers_reset_the_stream() {
+        let trailers = block(&[("x-checksum", "a\r\nx-admin: 1")]);
+        let mut stream = pipe(vec![
+            headers(1, false),
+            data(1, 5, false),
+            frame(HEADERS, END_HEADERS | END_STREAM, 1, &trailers),
+        ]);
+        let mut connection = Connection::new(&mut stream, 8192, 1024);
+        assert!(connection.next_request().unwrap().is_none());
+        assert_eq!(connection.buffered, 0);
+        let reset = resets(&connection.stream.get_ref().output);
+        assert_eq!(reset, [(1, PROTOCOL_ERROR)]);
+    }
+
+    #[test]
+    fn streams_past_the_limit_are_refused() {
+        let mut input: Vec<_> = (0..MAX_CONCURRENT_STREAMS)
+            .map(|i| headers(2 * i + 1, false))
+            .collect();
+        let refused = 2 * MAX_CONCURRENT_STREAMS + 1;
+        input.push(headers(refused, false));
+        input.push(frame(DATA, END_STREAM, 1, &[]));
+        input.push(headers(refused + 2, true));
+        let mut stream = pipe(input);
+        let mut connection = Connection::new(&mut stream, 8192, 1024);
+        assert!(matches!(
+            connection.next_request().unwrap(),
+            Some(Received::Request(1, _))
+        ));
+        assert_eq!(
+            resets(&connection.stream.get_ref().output),
+            [(refused, REFUSED_STREAM)]
+        );
+
+        // an answered stream makes room for the next one
+        connection
+            .send_response(1, get_413_default_response(), false)
+            .unwrap();
+        let Some(Received::Request(stream_id, _)) = connection.next_request().unwrap() else {
+            panic!("expected the stream after the answered one");
+        };
+        assert_eq!(stream_id, refused + 2);
+    }
+
+    #[test]
+    fn bodies_over_the_limit_are_refused() {
+        let mut stream = pipe(vec![headers(1, false), data(1, 2000, true)]);
+        let mut connection = Connection::new(&mut stream, 8192, 1024);
+        let Some(Received::Refused(1, response)) = connection.next_request().unwrap() else {
+            panic!("expected stream 1 to be refused");
+        };
+        assert_eq!(response.status.status, 413);
+        // none of it was kept, so all of it is given back
+        assert_eq!(connection.buffered, 0);
+        assert_eq!(window_updates(&connection.stream.get_ref().output), [2000]);
+    }
+
+    #[test]
+    fn window_returns_as_bodies_are_handed_out() {
+        let mut stream = pipe(vec![
+            headers(1, false),
+            data(1, 20_000, false),
+            headers(3, false),
+            data(3, 40_000, true),
+            frame(DATA, END_STREAM, 1, &[]),
+        ]);
+        let mut connection = Connection::new(&mut stream, 8192, 65_535);
+        assert!(matches!(
+            connection.next_request().unwrap(),
+            Some(Received::Request(3, _))
+        ));
+        // the body still being sent keeps its share of the window
+        assert_eq!(connection.buffered, 20_000);
+        assert_eq!(connection.recv_window, 65_535 - 20_000);
+        assert_eq!(
+            window_updates(&connection.stream.get_ref().output),
+            [40_000]
+        );
+        let Some(Received::Request(1, request)) = connection.next_request().unwrap() else {
+            panic!("expected stream 1");
+        };
+        assert_eq!(request.body.len(), 20_000);
+        assert_eq!(connection.buffered, 0);
+        assert_eq!(connection.recv_window, 65_535);
+        assert_eq!(
+            window_updates(&connection.stream.get_ref().output),
+            [40_000, 20_000]
+        );
+    }
+
+    #[test]
+    fn incomplete_bodies_filling_the_budget_are_reset() {
+        let mut stream = pipe(vec![
+            headers(1, false),
+            data(1, 40_000, false),
+            headers(3, false),
+            data(3, 25_535, false),
+        ]);
+        let mut connection = Connection::new(&mut stream, 8192, 65_535);
+        assert!(connection.next_request().unwrap().is_none());
+        // the client can't finish either, so the larger one makes room for the other
+        let written = frames(&connection.stream.get_ref().output);
+        assert!(written.contains(&(RST_STREAM, 0, 1, REFUSED_STREAM.to_be_bytes().to_vec())));
+        assert!(!connection.streams.contains_key(&1));
+        assert_eq!(connection.buffered, 25_535);
+        assert_eq!(connection.recv_window, 40_000);
+        assert_eq!(
+            window_updates(&connection.stream.get_ref().output),
+            [40_000]
+        );
+    }
+
+    #[test]
+    fn data_beyond_the_window_ends_the_connection() {
+        let mut stream = pipe(vec![
+            headers(1, false),
+            data(1, 16_384 * 3, false),
+            headers(3, false),
+            data(3, 16_384 * 2, false),
+        ]);
+        let mut connection = Connection::new(&mut stream, 8192, 65_535);
+        assert!(connection.next_request().is_err());
+        let written = frames(&connection.stream.get_ref().output);
+        let (kind, _, _, payload) = written.last().unwrap();
+        assert_eq!(*kind, GOAWAY);
+        assert_eq!(payload[4..], FLOW_CONTROL_ERROR.to_be_bytes());
+    }
+
+    #[test]
+    fn resets_release_their_bodies() {
+        let mut stream = pipe(vec![
+            headers(1, false),
+            data(1, 30_000, false),
+            frame(RST_STREAM, 0, 1, &NO_ERROR.to_be_bytes()),
+        ]);
+        let mut connection = Connection::new(&mut stream, 8192, 65_535);
+        assert!(connection.next_request().unwrap().is_none());
+        assert_eq!(connection.buffered, 0);
+        assert_eq!(connection.recv_window, 65_535);
+    }
+}
@@ -1,279 +1,3145 @@
 use std::{
     collections::HashMap,
-    io::{prelude::*, BufReader},
-    net::{TcpListener, TcpStream},
-    sync::Arc,
+    fs::{self, File},
+    io::{prelude::*, BufReader, ErrorKind},
+    net::{IpAddr, Shutdown, SocketAddr, TcpListener, TcpStream},
+    ops::Range,
+    panic::{self, AssertUnwindSafe},
+    path::Path,
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant, SystemTime},
 };
 
-use crate::thread_pool::ThreadPool;
+use crate::{
+    access_log::{AccessLogEntry, AccessLogger},
+    body::{BodySource, Framing, RequestBody, Source},
+    buffer_pool::{BufferPool, PooledBuffer},
+    builder::HTTPServerBuilder,
+    error::Error,
+    error_pages::ErrorPage,
+    extensions::Extensions,
+    form::QueryParams,
+    forwarded::TrustedProxies,
+    headers::HeaderMap,
+    http_date::format_http_date,
+    informational::Interim,
+    lifecycle::{run_hooks, ConnectionEvent, ConnectionHook, StartHook},
+    metrics::{Metrics, MetricsSnapshot},
+    middleware::{Middleware, Next},
+    proxy_protocol, redirect,
+    response::IntoResponse,
+    router::{RouteInfo, RouteMatch, Router},
+    shutdown::{self, ConnectionGauge, Connections, ShutdownHandle, ShutdownHook},
+    socket::{Address, Listener, Socket},
+    sse::EventStream,
+    thread_pool::{panic_message, Priority, ThreadPool},
+    tunnel::{Tunnel, Upstream},
+    upgrade::{self, UpgradeHandler},
+};
+#[cfg(feature = "http2")]
+use crate::{
+    base64,
+    http2::{self, Received},
+};
+#[cfg(feature = "evented")]
+use crate::{
+    evented::{Interest, Notifier, Reactor, Wake},
+    shutdown::ConnectionSlot,
+    sse::{Pending, KEEP_ALIVE},
+};
+#[cfg(feature = "evented")]
+use std::os::unix::io::AsRawFd;
+
+/// how long a closing connection is drained of what the client still sends
+const LINGER_TIMEOUT: Duration = Duration::from_secs(2);
+/// most bytes drained from a closing connection
+const LINGER_LIMIT: usize = 64 * 1024;
+/// largest unread request body skipped to keep the connection open, larger ones close it
+const DISCARD_LIMIT: usize = 64 * 1024;
+
+/// answers a request. Any function or closure fits, so listeners can capture their own state
+pub type HTTPListener<T> = Arc<dyn Fn(&HTTPRequest, &T) -> HTTPResponse + Send + Sync>;
+
+/// the trailers of a `Body::Trailed`, asked for once its last chunk is sent
+pub type Trailers = Box<dyn FnOnce() -> HeaderMap + Send>;
+
+/// a listener returning anything that converts into a response, like `Result<_, HTTPError>`
+pub(crate) fn into_listener<T, R: IntoResponse + 'static>(
+    listener: impl Fn(&HTTPRequest, &T) -> R + Send + Sync + 'static,
+) -> HTTPListener<T> {
+    Arc::new(move |request: &HTTPRequest, passthrough: &T| {
+        listener(request, passthrough).into_response()
+    })
+}
+
+pub struct HTTPServer<T: std::marker::Sync + std::marker::Send + 'static> {
+    pub address: String,
+    pub port: u64,
+    /// routes of requests for hosts without their own entry in `hosts`
+    pub router: Arc<Router<T>>,
+    /// routes per lowercase host name, matched against the `Host` header without its port.
+    /// A name like `*.example.com` matches all subdomains not listed on their own
+    pub hosts: Arc<HashMap<String, Router<T>>>,
+    pub default_404_listener: Arc<Option<HTTPListener<T>>>,
+    /// called when the path exists but none of its routes accept the method
+    pub default_405_listener: Arc<Option<HTTPListener<T>>>,
+    /// called when a listener or middleware panicked while handling the request
+    pub default_500_listener: Arc<Option<HTTPListener<T>>>,
+    /// bodies replacing the server's own error responses, by status
+    pub error_pages: Arc<HashMap<u16, ErrorPage>>,
+    /// workers kept alive even when there's nothing to do
+    pub threads: usize,
+    /// most workers spawned under load
+    pub max_threads: usize,
+    /// how long workers beyond `threads` wait for a connection before exiting
+    pub thread_idle_timeout: Duration,
+    /// most connections waiting for a worker once `max_threads` are busy. Further connections
+    /// are answered with 503 right away. `None` queues without limit
+    pub max_queued_connections: Option<usize>,
+    /// which threads connections are served on
+    pub dispatch: Dispatch,
+    /// the priority of a connection waiting for a pooled worker, `Normal` for all if `None`
+    pub connection_priority: Option<ConnectionPriority>,
+    /// value of the `Retry-After` header sent with those 503 responses
+    pub overload_retry_after: Duration,
+    /// most connections open at once, counting those waiting for a worker. `None` for no limit
+    pub max_connections: Option<usize>,
+    /// answer connections beyond `max_connections` with 503 instead of leaving them in the
+    /// listen backlog until a connection closes
+    pub shed_connections: bool,
+    /// permission bits of the socket file created by `listen_unix`, e.g. `0o660`.
+    /// `None` leaves them to the umask
+    pub unix_socket_mode: Option<u32>,
+    /// the number of open connections, shared with every server started from this one
+    pub connections: ConnectionGauge,
+    /// handed to every listener and middleware. It is shared by all connections rather than
+    /// cloned, so mutable state needs interior mutability like a `Mutex` or atomics
+    pub passthrough: Arc<T>,
+    /// how long an idle persistent connection is kept open. `None` closes the connection after every response
+    pub keep_alive_timeout: Option<Duration>,
+    /// run in order around every request, the first one being the outermost
+    pub middleware: Arc<Vec<Middleware<T>>>,
+    /// value of the `Server` header added to responses that don't set one. `None` omits it
+    pub server_header: Option<String>,
+    /// how long a graceful shutdown waits for in-flight requests before closing their connections
+    pub drain_timeout: Duration,
+    /// shut down gracefully on SIGINT or SIGTERM instead of being killed, letting `listen`
+    /// return. `shutdown::received_signal` tells which one arrived. Only on unix
+    pub shutdown_on_signals: bool,
+    /// run in order once the server has shut down and its requests are finished
+    pub shutdown_hooks: Arc<Vec<ShutdownHook>>,
+    /// run in order when a graceful shutdown begins, before in-flight requests are drained
+    pub shutdown_begin_hooks: Arc<Vec<ShutdownHook>>,
+    /// run in order when a listener starts accepting connections
+    pub start_hooks: Arc<Vec<StartHook>>,
+    /// run in order when a connection is accepted and when it's closed
+    pub connection_hooks: Arc<Vec<ConnectionHook>>,
+    /// largest a gzip or deflate encoded request body may grow when decoded, guarding against decompression bombs
+    pub max_decompressed_body_size: usize,
+    /// reject requests with bare `\n` line endings, malformed header lines, folded headers or
+    /// ambiguous body framing with 400 instead of tolerating them. Tolerated ambiguous framing
+    /// closes the connection after the request
+    pub strict_parsing: bool,
+    /// how paths ending in a slash are matched against routes
+    pub trailing_slash: TrailingSlash,
+    /// largest request line and headers accepted, answering 431 beyond
+    pub max_header_bytes: usize,
+    /// most header lines accepted, answering 431 beyond
+    pub max_headers: usize,
+    /// largest request body accepted before decoding, answering 413 beyond
+    pub max_body_size: usize,
+    /// bytes buffered when reading from and writing to a connection
+    pub buffer_size: usize,
+    /// most idle buffers kept around for reuse by later connections and responses
+    pub buffer_pool_capacity: usize,
+    /// how long a single read of a request may block. `None` waits forever
+    pub read_timeout: Option<Duration>,
+    /// how long writing to a client may block. `None` waits forever
+    pub write_timeout: Option<Duration>,
+    /// how long a client may take to send a complete request head, answering 408 beyond.
+    /// Guards against clients holding a worker by sending bytes slowly
+    pub header_read_timeout: Option<Duration>,
+    /// called for every answered request, e.g. `access_log::print`. `None` turns logging off
+    pub access_log: Option<AccessLogger>,
+    /// request counts and latencies per route, shared with every server started from this one
+    pub metrics: Arc<Metrics>,
+    /// path answering GET requests with `metrics` in the Prometheus text format, after
+    /// running the middleware. `None` for no such endpoint
+    pub metrics_endpoint: Option<String>,
+    /// the destinations CONNECT requests may open tunnels to. `None` leaves CONNECT to the
+    /// routes
+    pub tunnel: Option<Tunnel>,
+    /// proxies whose `Forwarded` and `X-Forwarded-For` headers decide `HTTPRequest::client_ip`
+    pub trusted_proxies: TrustedProxies,
+    /// expect every connection to open with a PROXY protocol header, version 1 or 2, and take
+    /// the client and local address from it. Connections without one are dropped
+    pub proxy_protocol: bool,
+}
+
+pub struct HTTPRequest {
+    pub method: HTTPMethod,
+    pub version: HTTPVersion,
+    /// the request target including the query, like `/users?page=2`. Absolute-form targets
+    /// like `http://example.com/users` sent to proxies are reduced to their path and query
+    pub target: String,
+    /// the lowercase scheme of an absolute-form target, like `http`, or of an HTTP/2 request.
+    /// `None` for the usual `/path` targets of HTTP/1
+    pub scheme: Option<String>,
+    /// the `host:port` of an absolute-form target, or the `:authority` of an HTTP/2 request. It
+    /// also replaces the Host header
+    pub authority: Option<String>,
+    /// the requested path without query, and without trailing slashes unless the server is
+    /// `TrailingSlash::Strict`
+    pub path: String,
+    pub headers: HeaderMap,
+    pub query_params: QueryParams,
+    /// named segments of the matched route pattern. Empty until the request has been routed
+    pub path_params: HashMap<String, String>,
+    /// the pattern of the matched route, like `/users/:id`. `None` until the request has been
+    /// routed or if no route matched
+    pub route: Option<String>,
+    /// the body, read from the connection when first asked for
+    pub body: RequestBody,
+    /// address of the client, if the request came in over a socket
+    pub peer_addr: Option<SocketAddr>,
+    /// the address the request was accepted on, `None` for unix sockets
+    pub local_addr: Option<SocketAddr>,
+    /// ip of the client behind any trusted proxies, otherwise that of the peer
+    pub client_ip: Option<IpAddr>,
+    /// whether the request came in over tls
+    pub secure: bool,
+    /// data attached by middleware for the listener, empty at first
+    pub extensions: Extensions,
+}
+
+/// everything a worker needs to serve a connection
+pub(crate) struct Shared<T: std::marker::Sync + std::marker::Send + 'static> {
+    router: Arc<Router<T>>,
+    hosts: Arc<HashMap<String, Router<T>>>,
+    default_404_listener: Arc<Option<HTTPListener<T>>>,
+    default_405_listener: Arc<Option<HTTPListener<T>>>,
+    default_500_listener: Arc<Option<HTTPListener<T>>>,
+    error_pages: Arc<HashMap<u16, ErrorPage>>,
+    middleware: Arc<Vec<Middleware<T>>>,
+    keep_alive_timeout: Option<Duration>,
+    server_header: Option<String>,
+    connections: Arc<Connections>,
+    drain_timeout: Duration,
+    shutdown_on_signals: bool,
+    shutdown_hooks: Arc<Vec<ShutdownHook>>,
+    start_hooks: Arc<Vec<StartHook>>,
+    connection_hooks: Arc<Vec<ConnectionHook>>,
+    max_decompressed_body_size: usize,
+    strict_parsing: bool,
+    pub(crate) trailing_slash: TrailingSlash,
+    dispatch: Dispatch,
+    connection_priority: Option<ConnectionPriority>,
+    max_header_bytes: usize,
+    max_headers: usize,
+    max_body_size: usize,
+    buffers: BufferPool,
+    read_timeout: Option<Duration>,
+    write_timeout: Option<Duration>,
+    header_read_timeout: Option<Duration>,
+    overload_retry_after: Duration,
+    max_connections: Option<usize>,
+    shed_connections: bool,
+    access_log: Option<AccessLogger>,
+    metrics: Arc<Metrics>,
+    metrics_endpoint: Option<String>,
+    tunnel: Option<Tunnel>,
+    pub(crate) trusted_proxies: TrustedProxies,
+    proxy_protocol: bool,
+}
+
+pub struct HTTPStatus {
+    pub status: u16,
+    pub reason: String,
+}
+
+pub struct HTTPResponse {
+    pub status: HTTPStatus,
+    pub headers: HeaderMap,
+    pub body: Body,
+}
+
+pub enum Body {
+    /// the whole body, sent with the Content-Length header supplied by the handler
+    Full(Vec<u8>),
+    /// chunks sent with `Transfer-Encoding: chunked` as the iterator yields them
+    Chunks(Box<dyn Iterator<Item = Vec<u8>> + Send>),
+    /// chunks as with `Chunks`, followed by the fields `Trailers` returns, like a checksum of
+    /// what was sent. Announce them with a `Trailer` header. HTTP/1.0 clients only get the chunks
+    Trailed(Box<dyn Iterator<Item = Vec<u8>> + Send>, Trailers),
+    /// a source read until EOF and sent with `Transfer-Encoding: chunked`
+    Reader(Box<dyn Read + Send>),
+    /// a source of known length, streamed with a matching Content-Length
+    Sized(Box<dyn Read + Send>, u64),
+    /// `length` bytes of a file from its current position, with a matching Content-Length.
+    /// Plain connections have the kernel send it without copying it through the server
+    File(File, u64),
+    /// no body, the connection is handed to the handler once a 101 response is sent. Made by
+    /// `HTTPResponse::upgrade`, and empty with any other status
+    Upgrade(UpgradeHandler),
+    /// events sent like `Chunks` as they're pushed, made by `sse::event_stream`. With
+    /// `Dispatch::Evented` the connection doesn't hold a worker while it waits for them
+    Events(EventStream),
+}
+
+pub struct Route<T: std::marker::Sync + std::marker::Send + 'static> {
+    pub methods: Vec<HTTPMethod>,
+    pub listener: HTTPListener<T>,
+    /// runs around the listener only, after the server's middleware
+    pub middleware: Vec<Middleware<T>>,
+    /// identifies the route in `HTTPServer::routes`, e.g. `users.show`
+    pub name: Option<String>,
+    /// what the route is for, e.g. to list it on a debug page
+    pub description: Option<String>,
+}
+
+impl<T: std::marker::Sync + std::marker::Send + 'static> Route<T> {
+    pub fn new<R: IntoResponse + 'static>(
+        methods: Vec<HTTPMethod>,
+        listener: impl Fn(&HTTPRequest, &T) -> R + Send + Sync + 'static,
+    ) -> Route<T> {
+        Route {
+            methods,
+            listener: into_listener(listener),
+            middleware: Vec::new(),
+            name: None,
+            description: None,
+        }
+    }
+
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = Some(String::from(name));
+        self
+    }
+
+    pub fn description(mut self, description: &str) -> Self {
+        self.description = Some(String::from(description));
+        self
+    }
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Debug)]
+pub enum HTTPMethod {
+    GET,
+    HEAD,
+    POST,
+    PUT,
+    DELETE,
+    CONNECT,
+    OPTIONS,
+    TRACE,
+    PATCH,
+    /// an extension method like WebDAV's `PROPFIND`, case sensitive
+    Custom(String),
+    /// a request line whose method isn't a valid token
+    INVALID,
+}
+
+/// what a server does with request paths ending in a slash
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub enum TrailingSlash {
+    /// match `/a/` as `/a`
+    #[default]
+    Strip,
+    /// answer `/a/` with a 301 to `/a`, or a 308 for methods other than GET and HEAD
+    RedirectToCanonical,
+    /// match paths as sent, `/a` and `/a/` are different routes
+    Strict,
+}
+
+/// which threads a server serves its connections on. Each connection keeps its thread for as
+/// long as it's open, including while a keep-alive connection waits for its next request,
+/// unless it's `Evented`
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub enum Dispatch {
+    /// workers of a pool sized by `threads` and `max_threads`, with connections beyond them
+    /// queued up to `max_queued_connections`
+    #[default]
+    Pooled,
+    /// a thread of its own for every connection, bounded only by `max_connections`. Suits
+    /// long-lived connections like streams and tunnels, which would tie up pooled workers
+    ThreadPerConnection,
+    /// pooled workers while one is free or can be added, a thread of its own for connections
+    /// that would otherwise have to wait in the queue
+    Hybrid,
+    /// pooled workers for handling requests only. Plaintext HTTP/1 connections waiting for
+    /// their next request, or for the next event of an `sse::event_stream`, are parked on one
+    /// thread polling all of them, so thousands of idle keep-alive and event stream connections
+    /// don't need a thread each. Tls and HTTP/2 connections, tunnels and upgrades keep their
+    /// worker as with `Pooled`. Needs the `evented` feature
+    #[cfg(feature = "evented")]
+    Evented,
+}
+
+/// picks the priority of a connection from its local and peer address, which are `None` for
+/// unix sockets
+pub type ConnectionPriority =
+    Arc<dyn Fn(Option<SocketAddr>, Option<SocketAddr>) -> Priority + Send + Sync>;
+
+impl<T: std::marker::Sync + std::marker::Send + 'static> Shared<T> {
+    /// `response`, one of the server's own, with the error page for its status if there is one
+    fn error_page(&self, mut response: HTTPResponse, request: &HTTPRequest) -> HTTPResponse {
+        if let Some(page) = self.error_pages.get(&response.status.status) {
+            page.apply(&mut response, &request.method.to_string(), &request.path);
+        }
+        response
+    }
+
+    /// the Date and Server headers every response gets, unless it brings its own
+    fn set_default_headers(&self, headers: &mut HeaderMap) {
+        set_default_header(headers, "Date", || format_http_date(SystemTime::now()));
+        if let Some(ref server) = self.server_header {
+            set_default_header(headers, "Server", || server.clone());
+        }
+    }
+}
+
+/// the buffered stream of a connection, shared with the body of the request being handled
+type Input<S> = Arc<Mutex<BufReader<S>>>;
+
+/// where a connection comes from, as told by the socket or a PROXY protocol header
+struct ConnectionInfo {
+    peer_addr: Option<SocketAddr>,
+    local_addr: Option<SocketAddr>,
+    secure: bool,
+    /// parked between requests and while an event stream waits, rather than keeping a worker
+    #[cfg(feature = "evented")]
+    evented: bool,
+}
+
+impl ConnectionInfo {
+    fn new(socket: &Socket, secure: bool) -> ConnectionInfo {
+        ConnectionInfo {
+            peer_addr: socket.peer_addr(),
+            local_addr: socket.local_addr(),
+            secure,
+            #[cfg(feature = "evented")]
+            evented: false,
+        }
+    }
+}
+
+/// how far serving a connection got
+#[derive(Clone, Copy)]
+struct Progress {
+    /// when the connection was accepted, until its first request arrives
+    connected: Option<Instant>,
+    /// whether it was parked and has something to read since
+    woken: bool,
+}
+
+impl Progress {
+    fn accepted(connected: Instant) -> Progress {
+        Progress {
+            connected: Some(connected),
+            woken: false,
+        }
+    }
+}
+
+/// where serving a connection stopped
+enum Served {
+    /// it's done, `true` if right after a response while the client may still be sending
+    Closed(bool),
+    /// an evented connection waiting for its next request, or its first one since `Instant`
+    #[cfg(feature = "evented")]
+    Idle(Option<Instant>),
+    /// an evented connection sending an event stream
+    #[cfg(feature = "evented")]
+    Streaming(Box<EventExchange>),
+}
+
+/// how a connection goes on after a request
+enum Handled {
+    Close,
+    KeepAlive,
+    /// the head of an event stream is sent, its events follow as they come
+    #[cfg(feature = "evented")]
+    Streaming(Box<EventExchange>),
+}
+
+/// an event stream whose head is sent, with what its access log entry needs once it ends
+#[cfg(feature = "evented")]
+struct EventExchange {
+    events: EventStream,
+    /// whether the connection is kept open after the stream
+    keep_alive: bool,
+    sent: u64,
+    started: Instant,
+    route: Option<String>,
+    status: u16,
+    method: HTTPMethod,
+    target: String,
+    peer_addr: Option<SocketAddr>,
+}
+
+#[cfg(feature = "evented")]
+impl EventExchange {
+    /// count and log the exchange once the stream ended
+    fn finish<T: std::marker::Sync + std::marker::Send + 'static>(&self, shared: &Shared<T>) {
+        let duration = self.started.elapsed();
+        shared
+            .metrics
+            .record(self.route.as_deref(), self.status, duration);
+        if let Some(logger) = shared.access_log {
+            logger(&AccessLogEntry {
+                method: &self.method,
+                target: &self.target,
+                version: HTTPVersion::HTTP11,
+                status: self.status,
+                body_size: self.sent,
+                duration,
+                peer_addr: self.peer_addr,
+            });
+        }
+    }
+}
+
+/// the head of the current request, kept for the whole connection so its allocation is reused
+/// by every request on it
+struct HeadBuffer<'a> {
+    bytes: PooledBuffer<'a>,
+    /// the request line and header lines, without their line endings
+    lines: Vec<Range<usize>>,
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum HTTPVersion {
+    HTTP10,
+    HTTP11,
+    /// only spoken with the `http2` feature
+    HTTP2,
+}
+
+/// a server whose socket is bound but which doesn't accept connections yet
+pub struct BoundServer<T: std::marker::Sync + std::marker::Send + 'static> {
+    listener: Listener,
+    address: Address,
+    shared: Arc<Shared<T>>,
+    pool: ThreadPool,
+    passthrough: Arc<T>,
+}
+
+impl<T: std::marker::Sync + std::marker::Send + 'static> BoundServer<T> {
+    /// the address the socket is bound to, `None` for a unix socket
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        match self.address {
+            Address::Tcp(addr) => Some(addr),
+            Address::Unix(_) => None,
+        }
+    }
+
+    /// the path of the unix socket the server is bound to
+    pub fn unix_path(&self) -> Option<&Path> {
+        match self.address {
+            Address::Tcp(_) => None,
+            Address::Unix(ref path) => Some(path),
+        }
+    }
+
+    /// accept connections on the current thread
+    pub fn listen(self) -> Result<(), Error> {
+        println!("listening on {}", self.address.url("http"));
+        self.run(true, Ok);
+        Ok(())
+    }
+
+    /// serve https like `HTTPServer::listen_tls`. Connections other than tcp are dropped
+    pub fn listen_tls<S, F>(self, acceptor: F) -> Result<(), Error>
+    where
+        S: Read + Write + Send + 'static,
+        F: Fn(TcpStream) -> std::io::Result<S> + Send + Sync + 'static,
+    {
+        println!("listening on {}", self.address.url("https"));
+        self.run(false, move |socket: Socket| acceptor(socket.into_tcp()?));
+        Ok(())
+    }
+
+    /// accept connections on a background thread. The returned handle stops the server gracefully
+    pub fn listen_with_shutdown(self) -> Result<ShutdownHandle, Error> {
+        println!("listening on {}", self.address.url("http"));
+        let connections = Arc::clone(&self.shared.connections);
+        let address = self.address.clone();
+        let thread = thread::spawn(move || self.run(true, Ok));
+        Ok(ShutdownHandle::new(connections, address, thread))
+    }
+
+    fn run<S, F>(self, plaintext: bool, acceptor: F)
+    where
+        S: Read + Write + Send + 'static,
+        F: Fn(Socket) -> std::io::Result<S> + Send + Sync + 'static,
+    {
+        let shared = Arc::clone(&self.shared);
+        if shared.shutdown_on_signals {
+            shutdown::stop_on_signals(Arc::clone(&shared.connections), vec![self.address.clone()]);
+        }
+        let url = self.address.url(if plaintext { "http" } else { "https" });
+        run_hooks(&shared.start_hooks, |hook| hook(&url));
+        HTTPServer::<T>::serve(
+            self.listener,
+            self.shared,
+            &self.pool,
+            &self.passthrough,
+            plaintext,
+            acceptor,
+        );
+        if let Address::Unix(ref path) = self.address {
+            let _ = fs::remove_file(path);
+        }
+        drop(self.pool);
+        run_hooks(&shared.shutdown_hooks, |hook| hook());
+    }
+}
+
+type AcceptLoop<T> = Box<dyn FnOnce(Arc<Shared<T>>, Arc<ThreadPool>, Arc<T>) + Send>;
+
+/// several listeners of one server, each with its own accept loop but sharing the routes,
+/// the thread pool and the connection limits
+pub struct Listeners<T: std::marker::Sync + std::marker::Send + 'static> {
+    loops: Vec<(Address, &'static str, AcceptLoop<T>)>,
+    shared: Arc<Shared<T>>,
+    pool: ThreadPool,
+    passthrough: Arc<T>,
+    unix_socket_mode: Option<u32>,
+}
+
+impl<T: std::marker::Sync + std::marker::Send + 'static> Listeners<T> {
+    /// serve plain http on `address`, e.g. `127.0.0.1:8080` or `[::1]:8080`
+    pub fn bind(self, address: &str) -> Result<Self, Error> {
+        let (listener, bound) = bind_tcp(address)?;
+        Ok(self.push(listener, bound, "http", true, Ok))
+    }
+
+    /// serve https on `address` like `HTTPServer::listen_tls`
+    pub fn bind_tls<S, F>(self, address: &str, acceptor: F) -> Result<Self, Error>
+    where
+        S: Read + Write + Send + 'static,
+        F: Fn(TcpStream) -> std::io::Result<S> + Send + Sync + 'static,
+    {
+        let (listener, bound) = bind_tcp(address)?;
+        Ok(
+            self.push(listener, bound, "https", false, move |socket: Socket| {
+                acceptor(socket.into_tcp()?)
+            }),
+        )
+    }
+
+    /// serve plain http on a unix socket like `HTTPServer::listen_unix`
+    #[cfg(unix)]
+    pub fn bind_unix(self, path: impl AsRef<Path>) -> Result<Self, Error> {
+        let (listener, bound) = bind_unix(path.as_ref(), self.unix_socket_mode)?;
+        Ok(self.push(listener, bound, "http", true, Ok))
+    }
+
+    fn push<S, F>(
+        mut self,
+        listener: Listener,
+        address: Address,
+        scheme: &'static str,
+        plaintext: bool,
+        acceptor: F,
+    ) -> Self
+    where
+        S: Read + Write + Send + 'static,
+        F: Fn(Socket) -> std::io::Result<S> + Send + Sync + 'static,
+    {
+        let unix_path = match address {
+            Address::Unix(ref path) => Some(path.clone()),
+            Address::Tcp(_) => None,
+        };
+        let accept_loop: AcceptLoop<T> = Box::new(move |shared, pool, passthrough| {
+            HTTPServer::<T>::serve(listener, shared, &pool, &passthrough, plaintext, acceptor);
+            if let Some(path) = unix_path {
+                let _ = fs::remove_file(path);
+            }
+        });
+        self.loops.push((address, scheme, accept_loop));
+        self
+    }
+
+    /// the tcp addresses bound so far, in order
+    pub fn local_addrs(&self) -> Vec<SocketAddr> {
+        self.loops
+            .iter()
+            .filter_map(|(address, _, _)| match address {
+                Address::Tcp(addr) => Some(*addr),
+                Address::Unix(_) => None,
+            })
+            .collect()
+    }
+
+    /// accept connections on all listeners until they stop
+    pub fn listen(self) -> Result<(), Error> {
+        let thread = self.start()?;
+        if thread.join().is_err() {
+            println!("accept loop panicked");
+        }
+        Ok(())
+    }
+
+    /// accept connections in the background. The returned handle stops all listeners gracefully
+    pub fn listen_with_shutdown(self) -> Result<ShutdownHandle, Error> {
+        let connections = Arc::clone(&self.shared.connections);
+        let addresses = self
+            .loops
+            .iter()
+            .map(|(address, _, _)| address.clone())
+            .collect();
+        let thread = self.start()?;
+        Ok(ShutdownHandle::with_addresses(
+            connections,
+            addresses,
+            thread,
+        ))
+    }
+
+    /// spawn an accept loop per listener, and a thread joining them before joining the pool
+    fn start(self) -> Result<thread::JoinHandle<()>, Error> {
+        if self.loops.is_empty() {
+            return Err(Error::Bind(std::io::Error::new(
+                ErrorKind::InvalidInput,
+                "no listeners bound",
+            )));
+        }
+        if self.shared.shutdown_on_signals {
+            let addresses = self.loops.iter().map(|(address, _, _)| address.clone());
+            shutdown::stop_on_signals(Arc::clone(&self.shared.connections), addresses.collect());
+        }
+        let pool = Arc::new(self.pool);
+        let mut threads = Vec::new();
+        let shared = Arc::clone(&self.shared);
+        for (address, scheme, accept_loop) in self.loops {
+            let url = address.url(scheme);
+            println!("listening on {}", url);
+            run_hooks(&self.shared.start_hooks, |hook| hook(&url));
+            let shared = Arc::clone(&self.shared);
+            let pool = Arc::clone(&pool);
+            let passthrough = Arc::clone(&self.passthrough);
+            threads.push(thread::spawn(move || {
+                accept_loop(shared, pool, passthrough)
+            }));
+        }
+        Ok(thread::spawn(move || {
+            for thread in threads {
+                if thread.join().is_err() {
+                    println!("accept loop panicked");
+                }
+            }
+            drop(pool);
+            run_hooks(&shared.shutdown_hooks, |hook| hook());
+        }))
+    }
+}
+
+impl<T: std::marker::Sync + std::marker::Send + 'static> HTTPServer<T> {
+    pub fn builder(passthrough: T) -> HTTPServerBuilder<T> {
+        HTTPServerBuilder::new(passthrough)
+    }
+
+    /// every registered route, one entry per method, those of the default router first.
+    /// Sorted by host and pattern
+    pub fn routes(&self) -> Vec<RouteInfo> {
+        let mut routes = self.router.routes();
+        let mut hosts: Vec<(&String, &Router<T>)> = self.hosts.iter().collect();
+        hosts.sort_by_key(|(host, _)| *host);
+        for (host, router) in hosts {
+            routes.extend(router.routes().into_iter().map(|route| RouteInfo {
+                host: Some(host.clone()),
+                ..route
+            }));
+        }
+        routes
+    }
+
+    /// request counts and latencies per route so far
+    pub fn metrics(&self) -> MetricsSnapshot {
+        self.metrics.snapshot()
+    }
+
+    pub fn listen(&self) -> Result<(), Error> {
+        self.bind()?.listen()
+    }
+
+    /// serve https by wrapping every accepted socket with `acceptor`, e.g. in a rustls `StreamOwned`.
+    /// The handshake runs on the worker thread, so a slow client doesn't block accepting.
+    /// To serve plain http as well, listen on a second server sharing the same `router`.
+    /// With the `http2` feature, an acceptor offering `h2` through ALPN gets HTTP/2 connections
+    pub fn listen_tls<S, F>(&self, acceptor: F) -> Result<(), Error>
+    where
+        S: Read + Write + Send + 'static,
+        F: Fn(TcpStream) -> std::io::Result<S> + Send + Sync + 'static,
+    {
+        self.bind()?.listen_tls(acceptor)
+    }
+
+    /// listen on a background thread. The returned handle stops the server gracefully
+    pub fn listen_with_shutdown(&self) -> Result<ShutdownHandle, Error> {
+        self.bind()?.listen_with_shutdown()
+    }
+
+    /// serve plain http on a unix socket at `path`, e.g. behind a reverse proxy.
+    /// A socket file left behind by a dead server is replaced, and the file is removed again
+    /// when the server stops
+    #[cfg(unix)]
+    pub fn listen_unix(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        self.bind_unix(path)?.listen()
+    }
+
+    /// like `listen_unix` on a background thread. The returned handle stops the server gracefully
+    #[cfg(unix)]
+    pub fn listen_unix_with_shutdown(
+        &self,
+        path: impl AsRef<Path>,
+    ) -> Result<ShutdownHandle, Error> {
+        self.bind_unix(path)?.listen_with_shutdown()
+    }
+
+    /// bind the listening socket without accepting connections yet, e.g. to learn the port
+    /// picked by the OS for port 0
+    pub fn bind(&self) -> Result<BoundServer<T>, Error> {
+        let (listener, address) = bind_tcp(&format!("{}:{}", self.address, self.port))?;
+        Ok(self.bound(listener, address))
+    }
+
+    /// bind a unix socket at `path` without accepting connections yet
+    #[cfg(unix)]
+    pub fn bind_unix(&self, path: impl AsRef<Path>) -> Result<BoundServer<T>, Error> {
+        let (listener, address) = bind_unix(path.as_ref(), self.unix_socket_mode)?;
+        Ok(self.bound(listener, address))
+    }
+
+    /// serve on several addresses at once, ignoring `address` and `port`. The listeners share
+    /// routes, thread pool and limits
+    pub fn listeners(&self) -> Listeners<T> {
+        Listeners {
+            loops: Vec::new(),
+            shared: self.shared(),
+            pool: self.thread_pool(),
+            passthrough: Arc::clone(&self.passthrough),
+            unix_socket_mode: self.unix_socket_mode,
+        }
+    }
+
+    fn bound(&self, listener: Listener, address: Address) -> BoundServer<T> {
+        BoundServer {
+            listener,
+            address,
+            shared: self.shared(),
+            pool: self.thread_pool(),
+            passthrough: Arc::clone(&self.passthrough),
+        }
+    }
+
+    pub(crate) fn shared(&self) -> Arc<Shared<T>> {
+        Arc::new(Shared {
+            router: Arc::clone(&self.router),
+            hosts: Arc::clone(&self.hosts),
+            default_404_listener: Arc::clone(&self.default_404_listener),
+            default_405_listener: Arc::clone(&self.default_405_listener),
+            default_500_listener: Arc::clone(&self.default_500_listener),
+            error_pages: Arc::clone(&self.error_pages),
+            middleware: Arc::clone(&self.middleware),
+            keep_alive_timeout: self.keep_alive_timeout,
+            server_header: self.server_header.clone(),
+            connections: Arc::new(Connections::new(
+                self.connections.clone(),
+                Arc::clone(&self.shutdown_begin_hooks),
+            )),
+            drain_timeout: self.drain_timeout,
+            shutdown_on_signals: self.shutdown_on_signals,
+            shutdown_hooks: Arc::clone(&self.shutdown_hooks),
+            start_hooks: Arc::clone(&self.start_hooks),
+            connection_hooks: Arc::clone(&self.connection_hooks),
+            max_decompressed_body_size: self.max_decompressed_body_size,
+            strict_parsing: self.strict_parsing,
+            trailing_slash: self.trailing_slash,
+            dispatch: self.dispatch,
+            connection_priority: self.connection_priority.clone(),
+            max_header_bytes: self.max_header_bytes,
+            max_headers: self.max_headers,
+            max_body_size: self.max_body_size,
+            buffers: BufferPool::new(self.buffer_size, self.buffer_pool_capacity),
+            read_timeout: self.read_timeout,
+            write_timeout: self.write_timeout,
+            header_read_timeout: self.header_read_timeout,
+            overload_retry_after: self.overload_retry_after,
+            max_connections: self.max_connections,
+            shed_connections: self.shed_connections,
+            access_log: self.access_log,
+            metrics: Arc::clone(&self.metrics),
+            metrics_endpoint: self.metrics_endpoint.clone(),
+            tunnel: self.tunnel.clone(),
+            trusted_proxies: self.trusted_proxies.clone(),
+            proxy_protocol: self.proxy_protocol,
+        })
+    }
+
+    fn thread_pool(&self) -> ThreadPool {
+        match self.dispatch {
+            // nothing runs on the pool, it doesn't need workers
+            Dispatch::ThreadPerConnection => ThreadPool::new(0),
+            _ => {
+                let metrics = Arc::clone(&self.metrics);
+                ThreadPool::dynamic(self.threads, self.max_threads, self.thread_idle_timeout)
+                    .limit_queue(self.max_queued_connections)
+                    .on_panic(move || metrics.record_panic())
+            }
+        }
+    }
+
+    /// accept connections until shutdown, then drain them.
+    /// Connections that are shed or the pool rejects get a plaintext 503 if `plaintext`,
+    /// otherwise they're closed
+    fn serve<S, F>(
+        listener: Listener,
+        shared: Arc<Shared<T>>,
+        pool: &ThreadPool,
+        passthrough: &Arc<T>,
+        plaintext: bool,
+        acceptor: F,
+    ) where
+        S: Read + Write + Send + 'static,
+        F: Fn(Socket) -> std::io::Result<S> + Send + Sync + 'static,
+    {
+        let acceptor = Arc::new(acceptor);
+        let gauge = &shared.connections.gauge;
+        // tls connections keep their worker, only plaintext ones are parked
+        #[cfg(feature = "evented")]
+        let polling = match shared.dispatch {
+            Dispatch::Evented if plaintext => start_reactor(pool),
+            _ => None,
+        };
+
+        loop {
+            // at the limit, leave new connections in the listen backlog until one closes
+            if let (Some(max), false) = (shared.max_connections, shared.shed_connections) {
+                while gauge.get() >= max && !shared.connections.is_shutting_down() {
+                    gauge.wait_below(max, Duration::from_millis(100));
+                }
+            }
+            if shared.connections.is_shutting_down() {
+                break;
+            }
+            let stream = listener.accept();
+            if shared.connections.is_shutting_down() {
+                break;
+            }
+            match stream {
+                Ok(stream) => {
+                    let Some(slot) = gauge.try_acquire(shared.max_connections) else {
+                        if plaintext {
+                            HTTPServer::<T>::send_overloaded(&shared, stream);
+                        }
+                        continue;
+                    };
+                    // keep a handle on the raw socket for timeouts and addresses
+                    let socket = match stream.try_clone() {
+                        Ok(socket) => socket,
+                        Err(error) => {
+                            println!("connection dropped because of error: {}", error);
+                            continue;
+                        }
+                    };
+                    let overloaded = match socket.try_clone() {
+                        Ok(overloaded) => overloaded,
+                        Err(error) => {
+                            println!("connection dropped because of error: {}", error);
+                            continue;
+                        }
+                    };
+                    let priority = shared
+                        .connection_priority
+                        .as_ref()
+                        .map_or(Priority::Normal, |priority| {
+                            priority(socket.local_addr(), socket.peer_addr())
+                        });
+                    // plaintext connections are served on the socket as accepted
+                    #[cfg(feature = "evented")]
+                    if let Some((ref reactor, _)) = polling {
+                        let connection = EventedConnection {
+                            shared: Arc::clone(&shared),
+                            passthrough: Arc::clone(passthrough),
+                            reactor: Arc::clone(reactor),
+                            input: Arc::new(Mutex::new(BufReader::with_capacity(
+                                shared.buffers.buffer_size(),
+                                stream,
+                            ))),
+                            info: ConnectionInfo {
+                                evented: true,
+                                ..ConnectionInfo::new(&socket, false)
+                            },
+                            socket,
+                            id: 0,
+                            _slot: slot,
+                        };
+                        if pool
+                            .try_execute_with_priority(priority, move || connection.open())
+                            .is_err()
+                        {
+                            HTTPServer::<T>::send_overloaded(&shared, overloaded);
+                        }
+                        continue;
+                    }
+                    let job_shared = Arc::clone(&shared);
+                    let pt = Arc::clone(passthrough);
+                    let acceptor = Arc::clone(&acceptor);
+                    let job = move || {
+                        let _slot = slot;
+                        let shared = job_shared;
+                        let Some(id) = shared.connections.register(&socket) else {
+                            return;
+                        };
+                        let peer_addr = socket.peer_addr();
+                        run_hooks(&shared.connection_hooks, |hook| {
+                            hook(ConnectionEvent::Opened, peer_addr)
+                        });
+                        let served =
+                            panic::catch_unwind(AssertUnwindSafe(|| match acceptor(stream) {
+                                Ok(stream) => HTTPServer::<T>::handle_stream(
+                                    &shared, id, &socket, stream, !plaintext, &pt,
+                                ),
+                                Err(error) => println!("failed establishing connection: {}", error),
+                            }));
+                        // the registry holds on to the socket, a panic must not leave it open
+                        shared.connections.remove(id);
+                        run_hooks(&shared.connection_hooks, |hook| {
+                            hook(ConnectionEvent::Closed, peer_addr)
+                        });
+                        if let Err(payload) = served {
+                            panic::resume_unwind(payload);
+                        }
+                    };
+                    let dispatched = match shared.dispatch {
+                        Dispatch::Pooled => pool.try_execute_with_priority(priority, job).is_ok(),
+                        Dispatch::ThreadPerConnection => {
+                            spawn_connection_thread(job, &shared.metrics)
+                        }
+                        Dispatch::Hybrid => match pool.try_execute_now(job) {
+                            Ok(()) => true,
+                            Err(job) => spawn_connection_thread(job, &shared.metrics),
+                        },
+                        #[cfg(feature = "evented")]
+                        Dispatch::Evented => pool.try_execute_with_priority(priority, job).is_ok(),
+                    };
+                    if !dispatched && plaintext {
+                        HTTPServer::<T>::send_overloaded(&shared, overloaded);
+                    }
+                }
+                Err(error) => println!("connection dropped because of error: {}", error),
+            }
+        }
+
+        shared.connections.drain(shared.drain_timeout);
+        // whatever is still parked is closed by now
+        #[cfg(feature = "evented")]
+        if let Some((reactor, thread)) = polling {
+            reactor.stop();
+            let _ = thread.join();
+        }
+    }
+
+    fn handle_stream<S: Read + Write + Send + 'static>(
+        shared: &Shared<T>,
+        id: usize,
+        socket: &Socket,
+        stream: S,
+        secure: bool,
+        passthrough: &T,
+    ) {
+        // the reader outlives single requests so bytes buffered past the end of one request aren't lost
+        let input = Arc::new(Mutex::new(BufReader::with_capacity(
+            shared.buffers.buffer_size(),
+            stream,
+        )));
+        let connected = Instant::now();
+        let mut connection = ConnectionInfo::new(socket, secure);
+        let answered = HTTPServer::<T>::open_connection(shared, socket, &input, &mut connection)
+            && match HTTPServer::<T>::serve_connection(
+                shared,
+                id,
+                socket,
+                &input,
+                &connection,
+                Progress::accepted(connected),
+                passthrough,
+            ) {
+                Served::Closed(answered) => answered,
+                #[cfg(feature = "evented")]
+                Served::Idle(_) | Served::Streaming(_) => {
+                    unreachable!("only evented connections are parked")
+                }
+            };
+        HTTPServer::<T>::close_connection(shared, id, socket, &input, answered);
+    }
+
+    /// flush and close the connection. `answered` is whether it ends right after a response
+    fn close_connection<S: Read + Write>(
+        shared: &Shared<T>,
+        id: usize,
+        socket: &Socket,
+        input: &Input<S>,
+        answered: bool,
+    ) {
+        let mut reader = input.lock().unwrap();
+        if reader.get_mut().flush().is_ok() {
+            // a shutdown wakes idle connections, it shouldn't wait on clients to finish sending
+            let drain = answered && shared.connections.set_idle(id, true);
+            close_gracefully(socket, drain);
+        }
+    }
+
+    /// set the timeouts of a fresh connection and read its PROXY header. Returns whether the
+    /// connection can be served
+    fn open_connection<S: Read + Write>(
+        shared: &Shared<T>,
+        socket: &Socket,
+        input: &Input<S>,
+        connection: &mut ConnectionInfo,
+    ) -> bool {
+        // a fresh connection has to send its first request head within the header timeout
+        if let Err(error) = socket
+            .set_write_timeout(shared.write_timeout)
+            .and_then(|_| socket.set_read_timeout(shared.header_read_timeout))
+        {
+            println!("failed setting socket timeouts: {}", error);
+            return false;
+        }
+        if shared.proxy_protocol {
+            match proxy_protocol::read_header(&mut *input.lock().unwrap()) {
+                Ok(Some(addrs)) => {
+                    connection.peer_addr = Some(addrs.source);
+                    connection.local_addr = Some(addrs.destination);
+                }
+                Ok(None) => {}
+                // the proxy is trusted to always send one, anything else isn't spoken to
+                Err(error) => {
+                    println!("dropping connection without PROXY header: {}", error);
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    /// answer requests on the connection until either side wants to close it, or an evented
+    /// connection has to wait.
+    /// Pipelined requests wait in the connection's buffer and are parsed one at a time, each
+    /// once the one before it has been answered and its body consumed, so responses go out in
+    /// the order the requests came in
+    fn serve_connection<S: Read + Write + Send + 'static>(
+        shared: &Shared<T>,
+        id: usize,
+        socket: &Socket,
+        input: &Input<S>,
+        connection: &ConnectionInfo,
+        mut progress: Progress,
+        passthrough: &T,
+    ) -> Served {
+        let mut head = HeadBuffer {
+            bytes: shared.buffers.take(),
+            lines: Vec::new(),
+        };
+
+        loop {
+            // wait for the next request while idle, so shutdown can close the connection
+            if !shared.connections.set_idle(id, true) {
+                return Served::Closed(false);
+            }
+            let mut reader = input.lock().unwrap();
+            // rather than waiting here, an evented connection is parked until there's more
+            #[cfg(feature = "evented")]
+            if connection.evented && !progress.woken && reader.buffer().is_empty() {
+                return Served::Idle(progress.connected);
+            }
+            progress.woken = false;
+            match reader.fill_buf() {
+                Ok(buffer) if !buffer.is_empty() => {}
+                Err(error) if progress.connected.is_some() && is_timeout(&error) => {
+                    HTTPServer::<T>::send_error_response(
+                        shared,
+                        reader.get_mut(),
+                        get_408_default_response(),
+                    );
+                    return Served::Closed(false);
+                }
+                _ => return Served::Closed(false),
+            }
+            drop(reader);
+            if !shared.connections.set_idle(id, false) {
+                return Served::Closed(false);
+            }
+
+            let started = progress.connected.take().unwrap_or_else(Instant::now);
+            let deadline = shared.header_read_timeout.map(|timeout| started + timeout);
+            match HTTPServer::<T>::handle_request(
+                shared,
+                input,
+                socket,
+                connection,
+                &mut head,
+                deadline,
+                passthrough,
+            ) {
+                Handled::KeepAlive => {}
+                Handled::Close => return Served::Closed(true),
+                #[cfg(feature = "evented")]
+                Handled::Streaming(exchange) => return Served::Streaming(exchange),
+            }
+
+            // idle persistent connections are closed once the timeout elapses
+            if let Err(error) = socket.set_read_timeout(shared.keep_alive_timeout) {
+                println!("failed setting keep-alive timeout: {}", error);
+                return Served::Closed(false);
+            }
+        }
+    }
+
+    /// handle a single request on the stream, whose head has to arrive before `deadline`.
+    /// Returns how the connection goes on
+    fn handle_request<S: Read + Write + Send + 'static>(
+        shared: &Shared<T>,
+        input: &Input<S>,
+        socket: &Socket,
+        connection: &ConnectionInfo,
+        head: &mut HeadBuffer,
+        deadline: Option<Instant>,
+        passthrough: &T,
+    ) -> Handled {
+        let started = Instant::now();
+        let mut reader = input.lock().unwrap();
+        head.bytes.clear();
+        head.lines.clear();
+
+        loop {
+            // a client trickling in the head is cut off at the deadline, no matter how active it is
+            let timeout = match deadline {
+                Some(deadline) => {
+                    let left = deadline.saturating_duration_since(Instant::now());
+                    if left.is_zero() {
+                        HTTPServer::<T>::send_error_response(
+                            shared,
+                            reader.get_mut(),
+                            get_408_default_response(),
+                        );
+                        return Handled::Close;
+                    }
+                    Some(
+                        shared
+                            .read_timeout
+                            .map_or(left, |timeout| timeout.min(left)),
+                    )
+                }
+                None => shared.read_timeout,
+            };
+            if let Err(error) = socket.set_read_timeout(timeout) {
+                println!("failed setting read timeout: {}", error);
+                return Handled::Close;
+            }
+
+            let start = head.bytes.len();
+            // one more byte than allowed, to tell a head that just fits from one that's too large
+            let remaining = (shared.max_header_bytes - start) as u64 + 1;
+            let size = match reader
+                .by_ref()
+                .take(remaining)
+                .read_until(b'\n', &mut head.bytes)
+            {
+                Ok(size) => size,
+                Err(error) if is_timeout(&error) => {
+                    HTTPServer::<T>::send_error_response(
+                        shared,
+                        reader.get_mut(),
+                        get_408_default_response(),
+                    );
+                    return Handled::Close;
+                }
+                Err(error) => {
+                    println!("fatal error reading request stream: {}", error);
+                    HTTPServer::<T>::send_400_default_response(shared, reader.get_mut()); // TODO: test if response is being sent
+                    return Handled::Close;
+                }
+            };
+            if size == 0 {
+                if !head.lines.is_empty() {
+                    // the connection closed in the middle of the head
+                    HTTPServer::<T>::send_400_default_response(shared, reader.get_mut());
+                }
+                // otherwise the client closed the connection between requests
+                return Handled::Close;
+            }
+            if head.bytes.len() > shared.max_header_bytes {
+                HTTPServer::<T>::send_error_response(
+                    shared,
+                    reader.get_mut(),
+                    get_431_default_response(),
+                );
+                return Handled::Close;
+            }
+
+            let line = &head.bytes[start..];
+            let length = match line.strip_suffix(b"\r\n") {
+                Some(line) => line.len(),
+                None if shared.strict_parsing => {
+                    HTTPServer::<T>::send_400_default_response(shared, reader.get_mut());
+                    return Handled::Close;
+                }
+                None => line
+                    .iter()
+                    .rposition(|b| !matches!(b, b'\r' | b'\n'))
+                    .map_or(0, |last| last + 1),
+            };
+            if length == 0 {
+                // the empty line ending the head
+                if !head.lines.is_empty() {
+                    break;
+                }
+                // empty lines before the request line are ignored, but count towards the limit
+                continue;
+            }
+
+            // the request line comes first
+            if head.lines.len() > shared.max_headers {
+                HTTPServer::<T>::send_error_response(
+                    shared,
+                    reader.get_mut(),
+                    get_431_default_response(),
+                );
+                return Handled::Close;
+            }
+            head.lines.push(start..start + length);
+        }
+
+        // the head is parsed in place, only what the request keeps is copied out of the buffer
+        let Ok(text) = std::str::from_utf8(&head.bytes) else {
+            HTTPServer::<T>::send_400_default_response(shared, reader.get_mut());
+            return Handled::Close;
+        };
+        let mut lines = head.lines.iter().map(|range| &text[range.clone()]);
+        let request_line = lines.next().unwrap_or_default();
+        let mut headers = HeaderMap::with_capacity(head.lines.len() - 1);
+        for line in lines {
+            if !parse_header_line(&mut headers, line, shared.strict_parsing) {
+                HTTPServer::<T>::send_400_default_response(shared, reader.get_mut());
+                return Handled::Close;
+            }
+        }
+
+        // proxies in front of the server may frame ambiguous requests differently, which lets a
+        // request hide inside another
+        let ambiguous_framing = framing_problem(&headers);
+        if let Some(problem) = ambiguous_framing.filter(|_| shared.strict_parsing) {
+            println!("rejecting request with {}", problem);
+            HTTPServer::<T>::send_400_default_response(shared, reader.get_mut());
+            return Handled::Close;
+        }
+
+        let mut content_size = 0;
+        if let Some(length) = headers.get("Content-Length") {
+            // in case of invalid data, ignore the contents
+            content_size = length
+                .split(',')
+                .next()
+                .unwrap_or("")
+                .trim()
+                .parse::<usize>()
+                .unwrap_or(0);
+        }
+
+        let Some(context) = split_request_line(request_line, shared.strict_parsing) else {
+            HTTPServer::<T>::send_400_default_response(shared, reader.get_mut());
+            return Handled::Close;
+        };
+
+        let version = match context[2] {
+            "HTTP/1.1" => HTTPVersion::HTTP11,
+            "HTTP/1.0" => HTTPVersion::HTTP10,
+            // the preface of a client speaking HTTP/2 right away, either knowing the server
+            // does or having negotiated it with ALPN during the tls handshake
+            #[cfg(feature = "http2")]
+            "HTTP/2.0" if context[0] == "PRI" && context[1] == "*" && head.lines.len() == 1 => {
+                let mut rest = [0; 6];
+                if reader.read_exact(&mut rest).is_err() || rest != *b"SM\r\n\r\n" {
+                    HTTPServer::<T>::send_400_default_response(shared, reader.get_mut());
+                    return Handled::Close;
+                }
+                HTTPServer::<T>::serve_http2(
+                    shared,
+                    &mut reader,
+                    socket,
+                    connection,
+                    None,
+                    passthrough,
+                );
+                return Handled::Close;
+            }
+            other if is_http_version(other) => {
+                HTTPServer::<T>::send_error_response(
+                    shared,
+                    reader.get_mut(),
+                    get_505_default_response(),
+                );
+                return Handled::Close;
+            }
+            _ => {
+                HTTPServer::<T>::send_400_default_response(shared, reader.get_mut());
+                return Handled::Close;
+            }
+        };
+
+        // HTTP/1.0 connections only persist when the client asks for it
+        let keep_alive = shared.keep_alive_timeout.is_some()
+            && ambiguous_framing.is_none()
+            && match version {
+                HTTPVersion::HTTP11 | HTTPVersion::HTTP2 => {
+                    !headers.has_token("Connection", "close")
+                }
+                HTTPVersion::HTTP10 => headers.has_token("Connection", "keep-alive"),
+            };
+
+        if let Err(error) = socket.set_read_timeout(shared.read_timeout) {
+            println!("failed setting read timeout: {}", error);
+            return Handled::Close;
+        }
+        if content_size > shared.max_body_size {
+            HTTPServer::<T>::send_error_page(
+                shared,
+                reader.get_mut(),
+                get_413_default_response(),
+                context[0],
+                context[1],
+            );
+            return Handled::Close;
+        }
+
+        // the body stays on the connection until the listener asks for it
+        let chunked = headers.has_token("Transfer-Encoding", "chunked");
+        let (body, pending) = match (chunked, content_size) {
+            (false, 0) => (RequestBody::default(), None),
+            _ => {
+                let input: Arc<Mutex<dyn BufRead + Send>> = input.clone();
+                let (body, pending) = RequestBody::pending(BodySource {
+                    input,
+                    framing: match chunked {
+                        true => Framing::Chunked,
+                        false => Framing::Length(content_size),
+                    },
+                    max_body_size: shared.max_body_size,
+                    encoding: match headers.contains("Content-Encoding") {
+                        true => Some(headers.get_all("Content-Encoding").join(",")),
+                        false => None,
+                    },
+                    max_decompressed_body_size: shared.max_decompressed_body_size,
+                });
+                (body, Some(pending))
+            }
+        };
+
+        let (scheme, authority, target) = match split_absolute_form(context[1]) {
+            Some((scheme, authority, target)) => {
+                // the authority of the target takes precedence over any Host header
+                headers.insert("Host", authority);
+                (Some(scheme), Some(String::from(authority)), target)
+            }
+            None => (None, None, String::from(context[1])),
+        };
+        let query_index = match target.find('?') {
+            Some(x) => x,
+            None => target.len(),
+        };
+
+        let location = &target[..query_index];
+        let query = &target[query_index..];
+
+        let query_params = QueryParams::parse(query);
+
+        let client_ip = shared
+            .trusted_proxies
+            .client_ip(connection.peer_addr, &headers);
+        let mut request = HTTPRequest {
+            method: get_method(context[0]),
+            version,
+            path: trim_path(location, shared.trailing_slash),
+            target,
+            scheme,
+            authority,
+            headers,
+            query_params,
+            path_params: HashMap::new(),
+            route: None,
+            body,
+            peer_addr: connection.peer_addr,
+            local_addr: connection.local_addr,
+            client_ip,
+            secure: connection.secure,
+            extensions: Extensions::new(),
+        };
+        if request.headers.remove("Content-Encoding").is_some() {
+            // handlers see the decoded body, whose length isn't known before it's read
+            request.headers.remove("Content-Length");
+        }
+        // HTTP/1.0 clients don't expect responses before the final one
+        if version == HTTPVersion::HTTP11 {
+            request.extensions.insert(Interim::new(Arc::clone(input)));
+        }
+
+        // a request without body may switch the connection to HTTP/2, and is answered as its
+        // first stream
+        #[cfg(feature = "http2")]
+        if version == HTTPVersion::HTTP11 && pending.is_none() && request.wants_upgrade("h2c") {
+            let settings = request
+                .headers
+                .get("HTTP2-Settings")
+                .and_then(|settings| base64::decode(settings.trim(), base64::URL_SAFE));
+            if let Some(settings) = settings {
+                let mut switching = HTTPResponse::builder()
+                    .status(101)
+                    .header("Connection", "Upgrade")
+                    .header("Upgrade", "h2c")
+                    .empty();
+                switching.headers.remove("Content-Length");
+                let mut buffer = shared.buffers.take();
+                write_head(&mut buffer, &switching.status, &switching.headers);
+                if let Err(error) = reader.get_mut().write_all(&buffer) {
+                    println!("failed writing response: {}", error);
+                    return Handled::Close;
+                }
+                for name in ["Connection", "Upgrade", "HTTP2-Settings"] {
+                    request.headers.remove(name);
+                }
+                request.version = HTTPVersion::HTTP2;
+                if request.scheme.is_none() {
+                    let scheme = match connection.secure {
+                        true => "https",
+                        false => "http",
+                    };
+                    request.scheme = Some(String::from(scheme));
+                }
+                HTTPServer::<T>::serve_http2(
+                    shared,
+                    &mut reader,
+                    socket,
+                    connection,
+                    Some((settings, request)),
+                    passthrough,
+                );
+                return Handled::Close;
+            }
+        }
+
+        drop(reader);
+        let mut response = HTTPServer::<T>::respond(shared, &mut request, passthrough);
+        let mut reader = input.lock().unwrap();
+
+        // requests finishing during a shutdown close their connection, and HTTP/1.0 has no
+        // chunked encoding, so streamed bodies are ended by closing it
+        let streamed = matches!(
+            response.body,
+            Body::Chunks(_) | Body::Trailed(..) | Body::Reader(_) | Body::Events(_)
+        );
+        let mut keep_alive = keep_alive
+            && !shared.connections.is_shutting_down()
+            && !(version == HTTPVersion::HTTP10 && streamed);
+        // the next request starts after the body, whether the listener read it or not
+        match pending.map(|pending| pending.reclaim()) {
+            Some(Source::Unread(source)) if keep_alive => {
+                drop(reader);
+                keep_alive = source.discard(DISCARD_LIMIT);
+                reader = input.lock().unwrap();
+            }
+            Some(Source::Failed) => keep_alive = false,
+            _ => {}
+        }
+        // an accepted CONNECT turns the connection into a tunnel once the response head is out
+        let tunnel = match (request.extensions.remove::<Upstream>(), &shared.tunnel) {
+            (Some(Upstream(upstream)), Some(tunnel)) if response.status.status / 100 == 2 => {
+                Some((tunnel, upstream))
+            }
+            _ => None,
+        };
+        // as is a connection switching protocols, once the 101 is out
+        let upgrade = match response.body {
+            Body::Upgrade(_) if response.status.status == 101 => {
+                match std::mem::replace(&mut response.body, Body::Full(Vec::new())) {
+                    Body::Upgrade(handler) => Some(handler),
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+        if tunnel.is_some() || upgrade.is_some() {
+            keep_alive = false;
+        } else if !keep_alive {
+            response.headers.insert("Connection", "close");
+        } else if version == HTTPVersion::HTTP10 {
+            response.headers.insert("Connection", "keep-alive");
+        }
+
+        let send_body = request.method != HTTPMethod::HEAD;
+        let status = response.status.status;
+        // files are sent by the kernel, which only sees the socket beneath any tls
+        let direct = (!connection.secure).then_some(socket);
+        // an evented connection sends only the head of an event stream here, its events follow
+        // from the pool as they come in
+        #[cfg(feature = "evented")]
+        if connection.evented
+            && send_body
+            && version == HTTPVersion::HTTP11
+            && status_allows_body(status)
+            && tunnel.is_none()
+            && matches!(response.body, Body::Events(_))
+        {
+            let placeholder = Body::Chunks(Box::new(std::iter::empty()));
+            let Body::Events(events) = std::mem::replace(&mut response.body, placeholder) else {
+                unreachable!("the body was just matched");
+            };
+            // the head only, as for HEAD requests, with the headers of a chunked body
+            let written = HTTPServer::<T>::close_stream(
+                shared,
+                reader.get_mut(),
+                None,
+                response,
+                version,
+                false,
+            );
+            if let Err(error) = written {
+                println!("failed writing response: {}", error);
+                return Handled::Close;
+            }
+            return Handled::Streaming(Box::new(EventExchange {
+                events,
+                keep_alive,
+                sent: 0,
+                started,
+                route: request.route.clone(),
+                status,
+                method: request.method.clone(),
+                target: String::from(context[1]),
+                peer_addr: request.peer_addr,
+            }));
+        }
+        let written = match tunnel {
+            Some((tunnel, upstream)) => HTTPServer::<T>::open_tunnel(
+                shared,
+                &mut reader,
+                socket,
+                response,
+                tunnel,
+                upstream,
+            ),
+            None => HTTPServer::<T>::close_stream(
+                shared,
+                reader.get_mut(),
+                direct,
+                response,
+                version,
+                send_body,
+            ),
+        };
+        let body_size = match written {
+            Ok(body_size) => body_size,
+            Err(error) => {
+                println!("failed writing response: {}", error);
+                return Handled::Close;
+            }
+        };
+        if let Some(handler) = upgrade {
+            upgrade::run(handler, &mut reader, socket);
+        }
+        let duration = started.elapsed();
+        shared
+            .metrics
+            .record(request.route.as_deref(), status, duration);
+        if let Some(logger) = shared.access_log {
+            logger(&AccessLogEntry {
+                method: &request.method,
+                target: context[1],
+                version,
+                status,
+                body_size,
+                duration,
+                peer_addr: request.peer_addr,
+            });
+        }
+        match keep_alive {
+            true => Handled::KeepAlive,
+            false => Handled::Close,
+        }
+    }
+
+    /// run the middleware and the route for a parsed request, turning panics and responses that
+    /// can't be sent into 500
+    pub(crate) fn respond(
+        shared: &Shared<T>,
+        request: &mut HTTPRequest,
+        passthrough: &T,
+    ) -> HTTPResponse {
+        let endpoint = |request: &mut HTTPRequest, passthrough: &T| {
+            HTTPServer::<T>::dispatch(shared, request, passthrough)
+        };
+        // a panicking listener must not take the worker down with it
+        let handled = panic::catch_unwind(AssertUnwindSafe(|| {
+            Next::new(&shared.middleware, &endpoint).run(request, passthrough)
+        }));
+        let mut response = match handled {
+            Ok(response) => response,
+            Err(_) => {
+                println!("listener for {} {} panicked", request.method, request.path);
+                shared.metrics.record_panic();
+                let internal_error = || shared.error_page(get_500_default_response(), request);
+                match *shared.default_500_listener {
+                    Some(ref handler) => {
+                        panic::catch_unwind(AssertUnwindSafe(|| handler(request, passthrough)))
+                            .unwrap_or_else(|_| internal_error())
+                    }
+                    None => internal_error(),
+                }
+            }
+        };
+        // a line break in a header would let the handler's input forge further headers or responses
+        if let Some(problem) = invalid_response_head(&response) {
+            println!(
+                "refusing response to {} {}: {}",
+                request.method, request.path, problem
+            );
+            return shared.error_page(get_500_default_response(), request);
+        }
+        if !status_allows_body(response.status.status) && !body_is_empty(&response.body) {
+            println!(
+                "discarding body of {} response to {} {}",
+                response.status.status, request.method, request.path
+            );
+            response.body = Body::Full(Vec::new());
+        }
+        response
+    }
+
+    /// hand the request to its route, the end of the middleware chain
+    fn dispatch(shared: &Shared<T>, request: &mut HTTPRequest, passthrough: &T) -> HTTPResponse {
+        if let Some(ref endpoint) = shared.metrics_endpoint {
+            if request.path == *endpoint
+                && matches!(request.method, HTTPMethod::GET | HTTPMethod::HEAD)
+            {
+                request.route = Some(endpoint.clone());
+                return HTTPResponse::builder()
+                    .header("Content-Type", "text/plain; version=0.0.4")
+                    .text(shared.metrics.snapshot().to_prometheus());
+            }
+        }
+        if let Some(ref tunnel) = shared.tunnel {
+            if request.method == HTTPMethod::CONNECT {
+                return tunnel.open(request);
+            }
+        }
+        if shared.trailing_slash == TrailingSlash::RedirectToCanonical {
+            if let Some(response) = redirect::canonical_redirect(request) {
+                return response;
+            }
+        }
+
+        // scoped middleware takes the request mutably while the match still borrows the path
+        let path = request.path.clone();
+        let router = match request.header("Host") {
+            Some(host) if !shared.hosts.is_empty() => host_router(shared, host),
+            _ => &shared.router,
+        };
+        // the asterisk form, `OPTIONS *`, asks about the server as a whole. No other method can
+        // use it
+        if path == "*" && request.method != HTTPMethod::OPTIONS {
+            return shared.error_page(get_400_default_response(), request);
+        }
+        match router.route(&path, &request.method) {
+            RouteMatch::Found(route, path_params, pattern) => {
+                request.route = Some(String::from(pattern));
+                request.path_params = path_params
+                    .into_iter()
+                    .map(|(name, value)| (String::from(name), String::from(value)))
+                    .collect();
+                if route.middleware.is_empty() {
+                    return (route.listener)(request, passthrough);
+                }
+                let listener = |request: &mut HTTPRequest, passthrough: &T| {
+                    (route.listener)(request, passthrough)
+                };
+                Next::new(&route.middleware, &listener).run(request, passthrough)
+            }
+            RouteMatch::MethodNotAllowed(_) if request.method == HTTPMethod::INVALID => {
+                shared.error_page(get_400_default_response(), request)
+            }
+            RouteMatch::NotFound | RouteMatch::MethodNotAllowed(_) if path == "*" => {
+                get_options_response(router.methods())
+            }
+            RouteMatch::MethodNotAllowed(allowed) if request.method == HTTPMethod::OPTIONS => {
+                get_options_response(allowed)
+            }
+            RouteMatch::MethodNotAllowed(allowed) => {
+                let allow = allow_header(allowed);
+                let mut response = match *shared.default_405_listener {
+                    Some(ref handler) => handler(request, passthrough),
+                    None => shared.error_page(
+                        get_405_default_response(&request.path, &request.method.to_string()),
+                        request,
+                    ),
+                };
+                set_default_header(&mut response.headers, "Allow", || allow);
+                response
+            }
+            RouteMatch::NotFound => match *shared.default_404_listener {
+                Some(ref handler) => handler(request, passthrough),
+                None => shared.error_page(get_404_default_response(), request),
+            },
+        }
+    }
+
+    /// speak HTTP/2 on the connection until either side ends it, answering its streams one after
+    /// another. `upgraded` is the `HTTP2-Settings` and the request of an `Upgrade: h2c`, which
+    /// is answered as stream 1
+    #[cfg(feature = "http2")]
+    fn serve_http2<S: Read + Write>(
+        shared: &Shared<T>,
+        stream: &mut BufReader<S>,
+        socket: &Socket,
+        connection: &ConnectionInfo,
+        upgraded: Option<(Vec<u8>, HTTPRequest)>,
+        passthrough: &T,
+    ) {
+        // the connection waits for new streams as long as a persistent one waits for requests
+        let timeout = shared.keep_alive_timeout.or(shared.read_timeout);
+        if let Err(error) = socket.set_read_timeout(timeout) {
+            println!("failed setting read timeout: {}", error);
+            return;
+        }
+        let mut h2 = http2::Connection::new(stream, shared.max_header_bytes, shared.max_body_size);
+        let (settings, first) = match upgraded {
+            Some((settings, request)) => (Some(settings), Some(request)),
+            None => (None, None),
+        };
+        if let Err(error) = h2.handshake(settings.as_deref(), first.is_some()) {
+            println!("failed starting HTTP/2: {}", error);
+            return;
+        }
+        if let Some(request) = first {
+            if !HTTPServer::<T>::serve_http2_stream(shared, &mut h2, 1, request, passthrough) {
+                return;
+            }
+        }
+
+        loop {
+            let served = match h2.next_request() {
+                Ok(Some(Received::Request(id, request))) => {
+                    let request = HTTPServer::<T>::http2_request(shared, connection, request);
+                    HTTPServer::<T>::serve_http2_stream(shared, &mut h2, id, request, passthrough)
+                }
+                Ok(Some(Received::Refused(id, mut response))) => {
+                    if let Some(page) = shared.error_pages.get(&response.status.status) {
+                        page.apply(&mut response, "", "");
+                    }
+                    shared.set_default_headers(&mut response.headers);
+                    h2.send_response(id, response, true).is_ok()
+                }
+                Ok(None) => break,
+                Err(error) if is_timeout(&error) => break,
+                Err(error) => {
+                    println!("closing HTTP/2 connection: {}", error);
+                    return;
+                }
+            };
+            // streams finishing during a shutdown end the connection, as requests do
+            if !served || shared.connections.is_shutting_down() {
+                break;
+            }
+        }
+        let _ = h2.go_away(http2::NO_ERROR);
+    }
+
+    /// the request of an HTTP/2 stream, as `handle_request` builds it from a HTTP/1 head
+    #[cfg(feature = "http2")]
+    fn http2_request(
+        shared: &Shared<T>,
+        connection: &ConnectionInfo,
+        request: http2::Request,
+    ) -> HTTPRequest {
+        let mut headers = request.headers;
+        let body = match request.body.is_empty() {
+            true => RequestBody::default(),
+            false => {
+                let length = request.body.len();
+                let input: Arc<Mutex<dyn BufRead + Send>> =
+                    Arc::new(Mutex::new(std::io::Cursor::new(request.body)));
+                let (body, _) = RequestBody::pending(BodySource {
+                    input,
+                    framing: Framing::Length(length),
+                    max_body_size: shared.max_body_size,
+                    encoding: match headers.contains("Content-Encoding") {
+                        true => Some(headers.get_all("Content-Encoding").join(",")),
+                        false => None,
+                    },
+                    max_decompressed_body_size: shared.max_decompressed_body_size,
+                });
+                body
+            }
+        }
+        .with_trailers(request.trailers);
+        if headers.remove("Content-Encoding").is_some() {
+            headers.remove("Content-Length");
+        }
+
+        let target = request.path;
+        let query_index = target.find('?').unwrap_or(target.len());
+        let client_ip = shared
+            .trusted_proxies
+            .client_ip(connection.peer_addr, &headers);
+        HTTPRequest {
+            method: get_method(&request.method),
+            version: HTTPVersion::HTTP2,
+            path: trim_path(&target[..query_index], shared.trailing_slash),
+            query_params: QueryParams::parse(&target[query_index..]),
+            target,
+            scheme: Some(request.scheme),
+            authority: request.authority,
+            headers,
+            path_params: HashMap::new(),
+            route: None,
+            body,
+            peer_addr: connection.peer_addr,
+            local_addr: connection.local_addr,
+            client_ip,
+            secure: connection.secure,
+            extensions: Extensions::new(),
+        }
+    }
+
+    /// answer `request` on the HTTP/2 stream `id`. Returns whether the connection still works
+    #[cfg(feature = "http2")]
+    fn serve_http2_stream<S: Read + Write>(
+        shared: &Shared<T>,
+        h2: &mut http2::Connection<S>,
+        id: u32,
+        mut request: HTTPRequest,
+        passthrough: &T,
+    ) -> bool {
+        let started = Instant::now();
+        let mut response = HTTPServer::<T>::respond(shared, &mut request, passthrough);
+        let status = response.status.status;
+        // streams carry their own framing, only the length is told
+        match response.body {
+            _ if !status_allows_body(status) => {
+                response.headers.remove("Content-Length");
+            }
+            Body::Full(ref body) => {
+                set_default_header(&mut response.headers, "Content-Length", || {
+                    body.len().to_string()
+                });
+            }
+            Body::Sized(_, length) | Body::File(_, length) => {
+                response
+                    .headers
+                    .insert("Content-Length", length.to_string());
+            }
+            Body::Chunks(_)
+            | Body::Trailed(..)
+            | Body::Reader(_)
+            | Body::Upgrade(_)
+            | Body::Events(_) => {
+                response.headers.remove("Content-Length");
+            }
+        }
+        shared.set_default_headers(&mut response.headers);
+
+        let send_body = request.method != HTTPMethod::HEAD;
+        let body_size = match h2.send_response(id, response, send_body) {
+            Ok(body_size) => body_size,
+            Err(error) => {
+                println!("failed writing response: {}", error);
+                return false;
+            }
+        };
+        let duration = started.elapsed();
+        shared
+            .metrics
+            .record(request.route.as_deref(), status, duration);
+        if let Some(logger) = shared.access_log {
+            logger(&AccessLogEntry {
+                method: &request.method,
+                target: &request.target,
+                version: HTTPVersion::HTTP2,
+                status,
+                body_size,
+                duration,
+                peer_addr: request.peer_addr,
+            });
+        }
+        true
+    }
+
+    /// send the head of a CONNECT's 2xx response, which has no body and so no framing headers,
+    /// and pipe the connection to `upstream` until it closes. Returns the bytes sent through
+    /// the tunnel to the client
+    fn open_tunnel<S: Read + Write>(
+        shared: &Shared<T>,
+        client: &mut BufReader<S>,
+        socket: &Socket,
+        mut response: HTTPResponse,
+        tunnel: &Tunnel,
+        upstream: TcpStream,
+    ) -> std::io::Result<u64> {
+        response.headers.remove("Content-Length");
+        response.headers.remove("Transfer-Encoding");
+        shared.set_default_headers(&mut response.headers);
+        let mut buffer = shared.buffers.take();
+        write_head(&mut buffer, &response.status, &response.headers);
+        client.get_mut().write_all(&buffer)?;
+        client.get_mut().flush()?;
+        tunnel.pipe(client, socket, upstream)
+    }
+
+    /// write `response`. The status line always claims HTTP/1.1, the highest version supported,
+    /// but an HTTP/1.0 client gets streamed bodies without chunked encoding.
+    /// Without `send_body`, as for HEAD requests, only the headers describing the body are sent.
+    /// 1xx, 204 and 304 responses never have a body, nor headers describing one.
+    /// `direct` is the socket `stream` writes to unencrypted, for sending files from the kernel
+    fn close_stream(
+        shared: &Shared<T>,
+        stream: &mut impl Write,
+        direct: Option<&Socket>,
+        mut response: HTTPResponse,
+        version: HTTPVersion,
+        send_body: bool,
+    ) -> std::io::Result<u64> {
+        let chunked = version == HTTPVersion::HTTP11;
+        let send_body = send_body && status_allows_body(response.status.status);
+        match response.body {
+            _ if !status_allows_body(response.status.status) => {
+                response.headers.remove("Content-Length");
+                response.headers.remove("Transfer-Encoding");
+            }
+            Body::Full(ref body) => {
+                set_default_header(&mut response.headers, "Content-Length", || {
+                    body.len().to_string()
+                });
+            }
+            Body::Upgrade(_) => {
+                response.headers.insert("Content-Length", "0");
+            }
+            Body::Sized(_, length) | Body::File(_, length) => {
+                response
+                    .headers
+                    .insert("Content-Length", length.to_string());
+            }
+            Body::Chunks(_) | Body::Trailed(..) | Body::Reader(_) | Body::Events(_) => {
+                response.headers.remove("Content-Length");
+                if chunked {
+                    response.headers.insert("Transfer-Encoding", "chunked");
+                }
+            }
+        }
+        shared.set_default_headers(&mut response.headers);
+
+        let mut buffer = shared.buffers.take();
+        write_head(&mut buffer, &response.status, &response.headers);
+        if !send_body {
+            stream.write_all(&buffer)?;
+            stream.flush()?;
+            return Ok(0);
+        }
+        // small bodies go out together with the head
+        if let Body::Full(ref body) = response.body {
+            if buffer.len() + body.len() <= shared.buffers.buffer_size() {
+                buffer.extend_from_slice(body);
+                stream.write_all(&buffer)?;
+                stream.flush()?;
+                return Ok(body.len() as u64);
+            }
+        }
+        stream.write_all(&buffer)?;
+
+        let mut sent = 0;
+        match response.body {
+            Body::Full(body) => {
+                stream.write_all(&body)?;
+                sent = body.len() as u64;
+            }
+            Body::Chunks(chunks) => {
+                sent = write_chunks(stream, chunks, chunked)?;
+                if chunked {
+                    stream.write_all(b"0\r\n\r\n")?;
+                }
+            }
+            Body::Events(events) => {
+                sent = write_chunks(stream, events, chunked)?;
+                if chunked {
+                    stream.write_all(b"0\r\n\r\n")?;
+                }
+            }
+            Body::Trailed(chunks, trailers) => {
+                sent = write_chunks(stream, chunks, chunked)?;
+                if chunked {
+                    buffer.clear();
+                    buffer.extend_from_slice(b"0\r\n");
+                    write_fields(&mut buffer, &sendable_trailers(trailers()));
+                    stream.write_all(&buffer)?;
+                }
+            }
+            Body::Reader(mut reader) => {
+                buffer.clear();
+                buffer.resize(shared.buffers.buffer_size(), 0);
+                loop {
+                    let read = reader.read(&mut buffer)?;
+                    if read == 0 {
+                        break;
+                    }
+                    sent += read as u64;
+                    match chunked {
+                        true => write_chunk(stream, &buffer[..read])?,
+                        false => stream.write_all(&buffer[..read])?,
+                    }
+                }
+                if chunked {
+                    stream.write_all(b"0\r\n\r\n")?;
+                }
+            }
+            Body::Sized(reader, length) => {
+                let copied = std::io::copy(&mut reader.take(length), stream)?;
+                sent = check_length(copied, length)?;
+            }
+            Body::File(file, length) => {
+                let mut file = file.take(length);
+                let copied = match direct {
+                    Some(socket) => socket.send_file(&mut file)?,
+                    None => std::io::copy(&mut file, stream)?,
+                };
+                sent = check_length(copied, length)?;
+            }
+            Body::Upgrade(_) => {}
+        }
+        stream.flush()?;
+        Ok(sent)
+    }
+
+    /// answer a connection the pool has no room for without reading its request
+    fn send_overloaded(shared: &Shared<T>, mut stream: Socket) {
+        // the accept loop must not wait on a client that doesn't read
+        if stream
+            .set_write_timeout(Some(Duration::from_secs(1)))
+            .is_err()
+        {
+            return;
+        }
+        let mut response = get_503_default_response();
+        response.headers.insert(
+            "Retry-After",
+            shared.overload_retry_after.as_secs().max(1).to_string(),
+        );
+        HTTPServer::<T>::send_error_response(shared, &mut stream, response);
+    }
+
+    fn send_400_default_response(shared: &Shared<T>, stream: &mut impl Write) {
+        HTTPServer::<T>::send_error_response(shared, stream, get_400_default_response());
+    }
+
+    /// answer a request that can't be processed. The connection is closed afterwards
+    fn send_error_response(shared: &Shared<T>, stream: &mut impl Write, response: HTTPResponse) {
+        HTTPServer::<T>::send_error_page(shared, stream, response, "", "");
+    }
+
+    /// `send_error_response` for a request whose method and target are known, in case there's
+    /// an error page for the status
+    fn send_error_page(
+        shared: &Shared<T>,
+        stream: &mut impl Write,
+        mut response: HTTPResponse,
+        method: &str,
+        path: &str,
+    ) {
+        if let Some(page) = shared.error_pages.get(&response.status.status) {
+            page.apply(&mut response, method, path);
+        }
+        response.headers.insert("Connection", "close");
+        // error responses have full bodies, so the version doesn't matter
+        if let Err(error) =
+            HTTPServer::<T>::close_stream(shared, stream, None, response, HTTPVersion::HTTP11, true)
+        {
+            println!("failed writing response: {}", error);
+        }
+    }
+}
+
+impl HTTPRequest {
+    /// value of the header `name`, ignoring case
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name)
+    }
+}
+
+impl std::fmt::Display for HTTPMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            HTTPMethod::GET => "GET",
+            HTTPMethod::HEAD => "HEAD",
+            HTTPMethod::POST => "POST",
+            HTTPMethod::PUT => "PUT",
+            HTTPMethod::DELETE => "DELETE",
+            HTTPMethod::CONNECT => "CONNECT",
+            HTTPMethod::OPTIONS => "OPTIONS",
+            HTTPMethod::TRACE => "TRACE",
+            HTTPMethod::PATCH => "PATCH",
+            HTTPMethod::Custom(name) => name,
+            HTTPMethod::INVALID => "INVALID",
+        };
+        f.write_str(name)
+    }
+}
+
+impl std::fmt::Display for HTTPVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            HTTPVersion::HTTP10 => "HTTP/1.0",
+            HTTPVersion::HTTP11 => "HTTP/1.1",
+            HTTPVersion::HTTP2 => "HTTP/2.0",
+        })
+    }
+}
+
+impl Body {
+    /// stream the items of `chunks` as they become available
+    pub fn chunks(chunks: impl Iterator<Item = Vec<u8>> + Send + 'static) -> Body {
+        Body::Chunks(Box::new(chunks))
+    }
+
+    /// stream the items of `chunks`, then the fields `trailers` returns
+    pub fn trailed(
+        chunks: impl Iterator<Item = Vec<u8>> + Send + 'static,
+        trailers: impl FnOnce() -> HeaderMap + Send + 'static,
+    ) -> Body {
+        Body::Trailed(Box::new(chunks), Box::new(trailers))
+    }
+
+    /// stream everything read from `reader`
+    pub fn reader(reader: impl Read + Send + 'static) -> Body {
+        Body::Reader(Box::new(reader))
+    }
+
+    /// stream the first `length` bytes of `reader`
+    pub fn sized(reader: impl Read + Send + 'static, length: u64) -> Body {
+        Body::Sized(Box::new(reader), length)
+    }
+
+    /// send the next `length` bytes of `file`
+    pub fn file(file: File, length: u64) -> Body {
+        Body::File(file, length)
+    }
+
+    /// the whole body, reading streamed ones to their end
+    pub fn into_bytes(self) -> std::io::Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        match self {
+            Body::Full(body) => return Ok(body),
+            Body::Chunks(chunks) | Body::Trailed(chunks, _) => {
+                chunks.for_each(|chunk| bytes.extend(chunk))
+            }
+            Body::Events(events) => events.for_each(|chunk| bytes.extend(chunk)),
+            Body::Reader(mut reader) => {
+                reader.read_to_end(&mut bytes)?;
+            }
+            Body::Sized(reader, length) => {
+                reader.take(length).read_to_end(&mut bytes)?;
+            }
+            Body::File(file, length) => {
+                file.take(length).read_to_end(&mut bytes)?;
+            }
+            Body::Upgrade(_) => {}
+        }
+        Ok(bytes)
+    }
+}
+
+impl From<String> for Body {
+    fn from(body: String) -> Body {
+        Body::Full(body.into_bytes())
+    }
+}
+
+impl From<&str> for Body {
+    fn from(body: &str) -> Body {
+        Body::Full(body.as_bytes().to_vec())
+    }
+}
 
-pub type HTTPListener<T> = fn(
-    &HashMap<&str, &str>, /* headers */
-    &String,              /* body */
-    &HashMap<&str, &str>, /* query params */
-    &T,
-) -> HTTPResponse;
+impl From<Vec<u8>> for Body {
+    fn from(body: Vec<u8>) -> Body {
+        Body::Full(body)
+    }
+}
 
-pub struct HTTPServer<T: Clone + std::marker::Sync + std::marker::Send + 'static> {
-    pub address: String,
-    pub port: u64,
-    pub listeners: Arc<HashMap<String, Route<T>>>,
-    pub default_404_listener: Arc<Option<HTTPListener<T>>>,
-    pub threads: usize,
-    pub passthrough: T,
+impl HTTPStatus {
+    /// `code` with its usual reason phrase. Codes without one, like 599, get that of their
+    /// class, e.g. `Server Error`
+    pub fn new(code: u16) -> HTTPStatus {
+        let reason = http_code_reason(code).unwrap_or(match code / 100 {
+            1 => "Informational",
+            2 => "Success",
+            3 => "Redirection",
+            4 => "Client Error",
+            5 => "Server Error",
+            _ => "Unknown",
+        });
+        HTTPStatus::with_reason(code, reason)
+    }
+
+    /// `code` with a reason phrase of its own
+    pub fn with_reason(code: u16, reason: &str) -> HTTPStatus {
+        HTTPStatus {
+            status: code,
+            reason: String::from(reason),
+        }
+    }
 }
 
-pub struct HTTPStatus {
-    pub status: u16,
-    pub reason: String,
+// http server internal utils
+
+/// the status line and headers of a response, ending in the empty line
+pub(crate) fn write_head(out: &mut Vec<u8>, status: &HTTPStatus, headers: &HeaderMap) {
+    // writing to a vector can't fail
+    let _ = write!(out, "HTTP/1.1 {} {}\r\n", status.status, status.reason);
+    write_fields(out, headers);
 }
 
-pub struct HTTPResponse {
-    pub status: HTTPStatus,
-    pub headers: HashMap<String, String>,
-    pub body: String,
+/// `headers` and the empty line ending them, as in a head or the trailers of a chunked body
+fn write_fields(out: &mut Vec<u8>, headers: &HeaderMap) {
+    for (name, value) in headers.iter() {
+        out.extend_from_slice(name.as_bytes());
+        out.extend_from_slice(b": ");
+        out.extend_from_slice(value.as_bytes());
+        out.extend_from_slice(b"\r\n");
+    }
+    out.extend_from_slice(b"\r\n");
 }
 
-pub struct Route<T: Clone + std::marker::Sync + std::marker::Send + 'static> {
-    pub methods: Vec<HTTPMethod>,
-    pub listener: HTTPListener<T>,
+/// what makes the body of a request with `headers` end in different places depending on who
+/// reads it: both Content-Length and Transfer-Encoding, Content-Lengths that don't agree or
+/// aren't numbers, or a Transfer-Encoding other than chunked
+fn framing_problem(headers: &HeaderMap) -> Option<&'static str> {
+    let lengths: Vec<&str> = headers
+        .get_all("Content-Length")
+        .into_iter()
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .collect();
+    let codings: Vec<&str> = headers
+        .get_all("Transfer-Encoding")
+        .into_iter()
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .collect();
+
+    if !lengths.is_empty() && !codings.is_empty() {
+        return Some("both Content-Length and Transfer-Encoding");
+    }
+    if lengths
+        .iter()
+        .any(|length| length.is_empty() || !length.bytes().all(|b| b.is_ascii_digit()))
+    {
+        return Some("invalid Content-Length");
+    }
+    if lengths.windows(2).any(|pair| pair[0] != pair[1]) {
+        return Some("conflicting Content-Length");
+    }
+    match codings.last() {
+        Some(coding) if !coding.eq_ignore_ascii_case("chunked") => {
+            Some("Transfer-Encoding not ending in chunked")
+        }
+        _ => None,
+    }
 }
 
-#[derive(PartialEq, Eq, PartialOrd, Ord, Hash)]
-pub enum HTTPMethod {
-    GET,
-    HEAD,
-    POST,
-    PUT,
-    DELETE,
-    CONNECT,
-    OPTION,
-    TRACE,
-    PATCH,
-    INVALID,
+/// the method, target and version of a request line. A lenient parser allows any whitespace
+/// between them
+fn split_request_line(line: &str, strict: bool) -> Option<[&str; 3]> {
+    match strict {
+        true => exactly_three(line.split(' ')),
+        false => exactly_three(line.split_whitespace()),
+    }
 }
 
-impl<T: Clone + std::marker::Sync + std::marker::Send + 'static> HTTPServer<T> {
-    pub fn listen(&self) {
-        let listener = TcpListener::bind(format!("{}:{}", self.address, self.port))
-            .expect("failed binding to socket!");
-        let pool = ThreadPool::new(self.threads);
+fn exactly_three<'a>(mut parts: impl Iterator<Item = &'a str>) -> Option<[&'a str; 3]> {
+    let three = [parts.next()?, parts.next()?, parts.next()?];
+    match parts.next() {
+        Some(_) => None,
+        None => Some(three),
+    }
+}
 
-        println!("listening on http://{}:{}", self.address, self.port);
+/// add a `Name: value` line of a request head to `headers`. Returns `false` if the line is
+/// malformed. A lenient parser skips such lines instead, and appends folded continuation lines
+/// to the previous header
+fn parse_header_line(headers: &mut HeaderMap, line: &str, strict: bool) -> bool {
+    if line.starts_with([' ', '\t']) {
+        if strict {
+            return false;
+        }
+        if let Some(value) = headers.last_value_mut() {
+            value.push(' ');
+            value.push_str(line.trim());
+        }
+        return true;
+    }
 
-        for stream in listener.incoming() {
-            match stream {
-                Ok(stream) => {
-                    let cloned_listeners = Arc::clone(&self.listeners);
-                    let cloned_404_handler = Arc::clone(&self.default_404_listener);
-                    let pt = self.passthrough.clone();
-                    pool.execute(move || {
-                        HTTPServer::<T>::handle_stream(
-                            &stream,
-                            cloned_listeners,
-                            cloned_404_handler,
-                            &pt,
-                        )
-                    });
-                }
-                Err(error) => println!("connection dropped because of error: {}", error),
-            }
+    // only the first colon separates the name, values like urls contain more
+    match line.split_once(':') {
+        Some((name, value)) if is_token(name) => {
+            headers.append(name, value.trim_matches([' ', '\t']));
+            true
         }
+        _ => !strict,
     }
+}
 
-    fn handle_stream(
-        stream: &TcpStream,
-        listeners: Arc<HashMap<String, Route<T>>>,
-        default_404_handler: Arc<Option<HTTPListener<T>>>,
-        passthrough: &T,
-    ) {
-        let mut reader = BufReader::new(stream);
-        let mut request = String::new(); // string to be fed bytes of the stream
+/// the routes for `host`, falling back to the default routes for unknown hosts
+fn host_router<'a, T: std::marker::Sync + std::marker::Send + 'static>(
+    shared: &'a Shared<T>,
+    host: &str,
+) -> &'a Router<T> {
+    // strip the port, keeping bracketed ipv6 addresses whole
+    let name = match host.rsplit_once(':') {
+        Some((name, port)) if port.bytes().all(|b| b.is_ascii_digit()) => name,
+        _ => host,
+    };
+    let name = name.trim_end_matches('.').to_ascii_lowercase();
+    if let Some(router) = shared.hosts.get(&name) {
+        return router;
+    }
+    let mut rest = name.as_str();
+    while let Some((_, parent)) = rest.split_once('.') {
+        if let Some(router) = shared.hosts.get(&format!("*.{}", parent)) {
+            return router;
+        }
+        rest = parent;
+    }
+    &shared.router
+}
 
-        loop {
-            let size: usize;
-            match reader.read_line(&mut request) {
-                Ok(line) => size = line,
-                Err(error) => {
-                    println!("fatal error reading request stream: {}", error);
-                    HTTPServer::<T>::send_400_default_response(stream); // TODO: test if response is being sent
-                    return;
-                }
-            }
-            if size < 3 {
-                //detect empty line
-                break;
-            }
+/// end the connection with a FIN before closing it. Closing a socket with unread data makes
+/// the kernel reset the connection, which can destroy a response the client hasn't read yet,
+/// so with `drain` whatever the client still sends is read and discarded for a little while
+fn close_gracefully(socket: &Socket, drain: bool) {
+    let Ok(mut socket) = socket.try_clone() else {
+        return;
+    };
+    if socket.shutdown(Shutdown::Write).is_err() || !drain {
+        return;
+    }
+    let deadline = Instant::now() + LINGER_TIMEOUT;
+    let mut buffer = [0; 4096];
+    let mut drained = 0;
+    while drained < LINGER_LIMIT {
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() || socket.set_read_timeout(Some(left)).is_err() {
+            return;
         }
+        match socket.read(&mut buffer) {
+            Ok(0) | Err(_) => return,
+            Ok(read) => drained += read,
+        }
+    }
+}
 
-        let mut content_size = 0;
-        let lines: Vec<&str> = request.split("\n").collect();
+/// `copied`, unless it falls short of the announced `length`
+fn check_length(copied: u64, length: u64) -> std::io::Result<u64> {
+    if copied != length {
+        // the announced length can't be honored anymore, the connection has to go
+        return Err(std::io::Error::new(
+            ErrorKind::UnexpectedEof,
+            "body ended before its announced length",
+        ));
+    }
+    Ok(copied)
+}
 
-        if lines.len() < 3 {
-            HTTPServer::<T>::send_400_default_response(stream);
-            return;
+/// 1xx, 204 and 304 responses end with their head
+fn status_allows_body(status: u16) -> bool {
+    !matches!(status, 100..=199 | 204 | 304)
+}
+
+/// `false` for any streamed body, even one that would turn out empty
+fn body_is_empty(body: &Body) -> bool {
+    match body {
+        Body::Full(body) => body.is_empty(),
+        Body::Sized(_, length) | Body::File(_, length) => *length == 0,
+        Body::Chunks(_) | Body::Trailed(..) | Body::Reader(_) | Body::Events(_) => false,
+        Body::Upgrade(_) => true,
+    }
+}
+
+/// the lowercase scheme, the authority and the path with query of an absolute-form target
+/// like `http://example.com/a?b`. The path is `/` if the target has none
+pub(crate) fn split_absolute_form(target: &str) -> Option<(String, &str, String)> {
+    let (scheme, rest) = target.split_once("://")?;
+    let valid_scheme = scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        && scheme
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'));
+    if !valid_scheme {
+        return None;
+    }
+    let end = rest.find(['/', '?']).unwrap_or(rest.len());
+    let (authority, path) = rest.split_at(end);
+    let target = match path.starts_with('/') {
+        true => String::from(path),
+        false => format!("/{}", path),
+    };
+    Some((scheme.to_ascii_lowercase(), authority, target))
+}
+
+/// the path of a request target without its query, with trailing slashes removed unless
+/// they're significant
+pub(crate) fn trim_path(location: &str, trailing_slash: TrailingSlash) -> String {
+    let mut trimmed = location;
+    while trailing_slash != TrailingSlash::Strict && trimmed.ends_with('/') && trimmed.len() > 1 {
+        trimmed = &trimmed[..trimmed.len() - 1];
+    }
+    String::from(trimmed)
+}
+
+fn bind_tcp(address: &str) -> Result<(Listener, Address), Error> {
+    let listener = TcpListener::bind(address).map_err(Error::Bind)?;
+    let address = Address::Tcp(listener.local_addr()?);
+    Ok((Listener::Tcp(listener), address))
+}
+
+#[cfg(unix)]
+fn bind_unix(path: &Path, mode: Option<u32>) -> Result<(Listener, Address), Error> {
+    use std::os::unix::{fs::PermissionsExt, net::UnixListener};
+
+    crate::socket::remove_stale_socket(path);
+    let listener = UnixListener::bind(path).map_err(Error::Bind)?;
+    if let Some(mode) = mode {
+        if let Err(error) = fs::set_permissions(path, fs::Permissions::from_mode(mode)) {
+            let _ = fs::remove_file(path);
+            return Err(Error::Bind(error));
         }
+    }
+    Ok((Listener::Unix(listener), Address::Unix(path.to_path_buf())))
+}
 
-        let mut headers: HashMap<&str, &str> = HashMap::new();
+/// check if `error` is a socket timeout running out, which is reported differently per platform
+fn is_timeout(error: &std::io::Error) -> bool {
+    matches!(error.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut)
+}
 
-        for l in &lines[1..] {
-            let pair: Vec<&str> = l.split(":").collect();
-            if pair.len() == 2 {
-                headers.insert(pair[0], pair[1].trim());
+/// what keeps the status line or headers of `response` from being sent as they are
+fn invalid_response_head(response: &HTTPResponse) -> Option<String> {
+    // the status line holds exactly three digits
+    if !(100..=999).contains(&response.status.status) {
+        return Some(format!("invalid status code {}", response.status.status));
+    }
+    if !is_field_text(&response.status.reason) {
+        return Some(format!(
+            "invalid reason phrase {:?}",
+            response.status.reason
+        ));
+    }
+    for (name, value) in response.headers.iter() {
+        if !is_token(name) {
+            return Some(format!("invalid header name {:?}", name));
+        }
+        if !is_field_text(value) {
+            return Some(format!("invalid value of header {}: {:?}", name, value));
+        }
+    }
+    None
+}
 
-                if l.starts_with("Content-Length") {
-                    content_size = match pair[1].trim().parse::<usize>() {
-                        Ok(size) => size,
-                        Err(_err) => 0, // in case of invalid data, ignore the contents
-                    }; // Get Content-Length
-                }
-            }
+/// `trailers` without the fields that can't be sent as they are. The head is out by then, so
+/// rather than answering with 500 as for a bad header, they're dropped
+pub(crate) fn sendable_trailers(trailers: HeaderMap) -> HeaderMap {
+    let mut sendable = HeaderMap::with_capacity(trailers.len());
+    for (name, value) in trailers.iter() {
+        match is_token(name) && is_field_text(value) {
+            true => sendable.append(name, value),
+            false => println!("dropping invalid trailer {:?}: {:?}", name, value),
         }
+    }
+    sendable
+}
 
-        let context: Vec<&str> = lines[0].split(" ").collect();
-        if context.len() < 3 {
-            HTTPServer::<T>::send_400_default_response(stream);
-            return;
+/// check if `text` can be sent as a header value or reason phrase: no control characters
+/// other than tab, so no CR, LF or NUL to end it early
+pub(crate) fn is_field_text(text: &str) -> bool {
+    text.bytes().all(|b| b == b'\t' || !b.is_ascii_control())
+}
+
+/// check if `name` is a token, the syntax of header names and methods
+pub(crate) fn is_token(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+}
+
+/// a reactor polling on a thread of its own, resuming connections on `pool`. `None` if it
+/// can't be set up, leaving connections to keep their worker
+#[cfg(feature = "evented")]
+fn start_reactor(pool: &ThreadPool) -> Option<(Arc<Reactor>, thread::JoinHandle<()>)> {
+    let reactor = match Reactor::new() {
+        Ok(reactor) => Arc::new(reactor),
+        Err(error) => {
+            println!(
+                "failed setting up the reactor, connections stay pooled: {}",
+                error
+            );
+            return None;
+        }
+    };
+    let polling = Arc::clone(&reactor);
+    let spawner = pool.spawner();
+    match thread::Builder::new()
+        .name(String::from("reactor"))
+        .spawn(move || polling.run(&spawner))
+    {
+        Ok(thread) => Some((reactor, thread)),
+        Err(error) => {
+            println!(
+                "failed spawning reactor thread, connections stay pooled: {}",
+                error
+            );
+            None
         }
+    }
+}
 
-        let mut content_buffer = vec![0; content_size]; //New Vector with size of Content
-        reader.read_exact(&mut content_buffer).unwrap(); //Get the Body Content.
+/// a plaintext connection of `Dispatch::Evented`, moving between pooled workers while it has
+/// something to do and the reactor while it waits
+#[cfg(feature = "evented")]
+struct EventedConnection<T: std::marker::Sync + std::marker::Send + 'static> {
+    shared: Arc<Shared<T>>,
+    passthrough: Arc<T>,
+    reactor: Arc<Reactor>,
+    socket: Socket,
+    input: Input<Socket>,
+    info: ConnectionInfo,
+    /// in the server's registry, set once the connection is opened
+    id: usize,
+    _slot: ConnectionSlot,
+}
 
-        let query_index = match context[1].find("?") {
-            Some(x) => x,
-            None => context[1].len(),
+#[cfg(feature = "evented")]
+impl<T: std::marker::Sync + std::marker::Send + 'static> EventedConnection<T> {
+    fn open(mut self) {
+        let Some(id) = self.shared.connections.register(&self.socket) else {
+            return;
         };
+        self.id = id;
+        let peer_addr = self.socket.peer_addr();
+        run_hooks(&self.shared.connection_hooks, |hook| {
+            hook(ConnectionEvent::Opened, peer_addr)
+        });
+        let connected = Instant::now();
+        let opened = panic::catch_unwind(AssertUnwindSafe(|| {
+            HTTPServer::<T>::open_connection(
+                &self.shared,
+                &self.socket,
+                &self.input,
+                &mut self.info,
+            )
+        }));
+        match opened {
+            Ok(true) => self.serve(Progress::accepted(connected)),
+            Ok(false) => self.close(false),
+            Err(payload) => {
+                self.forget();
+                panic::resume_unwind(payload);
+            }
+        }
+    }
 
-        let location = &context[1][..query_index];
-        let query = &context[1][query_index..];
+    /// answer requests until the connection has to wait, and park it then
+    fn serve(self, progress: Progress) {
+        let served = panic::catch_unwind(AssertUnwindSafe(|| {
+            HTTPServer::<T>::serve_connection(
+                &self.shared,
+                self.id,
+                &self.socket,
+                &self.input,
+                &self.info,
+                progress,
+                &self.passthrough,
+            )
+        }));
+        match served {
+            Ok(Served::Closed(answered)) => self.close(answered),
+            Ok(Served::Idle(connected)) => self.park_idle(connected),
+            Ok(Served::Streaming(exchange)) => {
+                let notifier = self.reactor.notifier();
+                let wake = notifier.clone();
+                exchange.events.on_wake(move || wake.notify());
+                self.send_events(exchange, notifier, Wake::Ready);
+            }
+            // the registry holds on to the socket, a panic must not leave it open
+            Err(payload) => {
+                self.forget();
+                panic::resume_unwind(payload);
+            }
+        }
+    }
+
+    /// wait for the next request, or the first since `connected`, within its timeout
+    fn park_idle(self, connected: Option<Instant>) {
+        let deadline = match connected {
+            Some(connected) => self
+                .shared
+                .header_read_timeout
+                .map(|timeout| connected + timeout),
+            None => self
+                .shared
+                .keep_alive_timeout
+                .map(|timeout| Instant::now() + timeout),
+        };
+        let reactor = Arc::clone(&self.reactor);
+        let fd = self.socket.as_raw_fd();
+        reactor.park(fd, Interest::Readable, deadline, move |wake| match wake {
+            Wake::Ready => self.serve(Progress {
+                connected,
+                woken: true,
+            }),
+            Wake::TimedOut if connected.is_some() => {
+                let mut reader = self.input.lock().unwrap();
+                HTTPServer::<T>::send_error_response(
+                    &self.shared,
+                    reader.get_mut(),
+                    get_408_default_response(),
+                );
+                drop(reader);
+                self.close(false);
+            }
+            Wake::TimedOut | Wake::Stopped => self.close(false),
+        });
+    }
 
-        let mut query_params: HashMap<&str, &str> = HashMap::new();
-        for param in (if query.len() != 0 { &query[1..] } else { query }).split("&") {
-            let arms: Vec<&str> = param.split("=").collect();
-            if arms.len() == 2 {
-                query_params.insert(arms[0], arms[1]);
+    /// send the events that came in, or a keep-alive comment if none did in time, then wait
+    /// for more. Once the stream ends the connection goes on with its next request
+    fn send_events(self, mut exchange: Box<EventExchange>, notifier: Notifier, wake: Wake) {
+        if wake == Wake::Stopped {
+            return self.close(false);
+        }
+        // events pushed from here on are sent now or wake the stream again
+        notifier.reset();
+        let mut reader = self.input.lock().unwrap();
+        let written = write_events(
+            reader.get_mut(),
+            &mut exchange.events,
+            wake == Wake::TimedOut,
+        );
+        drop(reader);
+        let ended = match written {
+            Ok((sent, ended)) => {
+                exchange.sent += sent;
+                ended
             }
+            Err(error) => {
+                println!("failed writing response: {}", error);
+                return self.close(true);
+            }
+        };
+        if !ended {
+            let reactor = Arc::clone(&self.reactor);
+            let fd = self.socket.as_raw_fd();
+            let deadline = Instant::now() + exchange.events.keep_alive();
+            let interest = notifier.interest();
+            reactor.park(fd, interest, Some(deadline), move |wake| {
+                self.send_events(exchange, notifier, wake)
+            });
+            return;
         }
 
-        println!(
-            "full: {}, {:?}, {:?}, {}",
-            context[1],
-            location,
-            query_params,
-            stream.local_addr().unwrap()
+        exchange.finish(&self.shared);
+        if !exchange.keep_alive {
+            return self.close(true);
+        }
+        // idle persistent connections are closed once the timeout elapses
+        if let Err(error) = self.socket.set_read_timeout(self.shared.keep_alive_timeout) {
+            println!("failed setting keep-alive timeout: {}", error);
+            return self.close(false);
+        }
+        self.serve(Progress {
+            connected: None,
+            woken: false,
+        });
+    }
+
+    /// flush and close the connection. `answered` is whether it ends right after a response
+    fn close(self, answered: bool) {
+        HTTPServer::<T>::close_connection(
+            &self.shared,
+            self.id,
+            &self.socket,
+            &self.input,
+            answered,
         );
+        self.forget();
+    }
+
+    /// take the connection out of the registry, as it's closed
+    fn forget(&self) {
+        self.shared.connections.remove(self.id);
+        let peer_addr = self.socket.peer_addr();
+        run_hooks(&self.shared.connection_hooks, |hook| {
+            hook(ConnectionEvent::Closed, peer_addr)
+        });
+    }
+}
+
+/// write the events `events` has ready as chunks, or a keep-alive comment if there are none and
+/// the stream was `idle` too long. Returns the bytes written and whether the stream ended, in
+/// which case its last chunk is written
+#[cfg(feature = "evented")]
+fn write_events(
+    stream: &mut impl Write,
+    events: &mut EventStream,
+    idle: bool,
+) -> std::io::Result<(u64, bool)> {
+    let mut sent = 0;
+    loop {
+        match events.try_next() {
+            Pending::Event(event) => {
+                write_chunk(stream, &event)?;
+                sent += event.len() as u64;
+            }
+            Pending::Ended => {
+                stream.write_all(b"0\r\n\r\n")?;
+                stream.flush()?;
+                return Ok((sent, true));
+            }
+            Pending::Empty => break,
+        }
+    }
+    if sent == 0 && idle {
+        write_chunk(stream, KEEP_ALIVE)?;
+        sent += KEEP_ALIVE.len() as u64;
+    }
+    Ok((sent, false))
+}
 
-        let body = String::from_utf8(content_buffer).unwrap_or_else(|err| {
+/// run `job` serving a connection on a thread of its own, counting a panic in `metrics` as
+/// pooled workers do. Returns whether the thread could be started
+fn spawn_connection_thread(job: impl FnOnce() + Send + 'static, metrics: &Arc<Metrics>) -> bool {
+    let metrics = Arc::clone(metrics);
+    let job = move || {
+        if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(job)) {
             println!(
-                "failed parsing utf8 body because of error: {err}. Defaulting to empty string."
+                "connection thread recovered from a panic: {}",
+                panic_message(&*payload)
             );
-            String::from("")
-        });
+            metrics.record_panic();
+        }
+    };
+    match thread::Builder::new()
+        .name(String::from("connection"))
+        .spawn(job)
+    {
+        Ok(_) => true,
+        Err(error) => {
+            println!("failed spawning connection thread: {}", error);
+            false
+        }
+    }
+}
 
-        let mut trimmed_location = location;
+/// insert a header unless the handler already set it
+fn set_default_header(headers: &mut HeaderMap, name: &str, value: impl FnOnce() -> String) {
+    if !headers.contains(name) {
+        headers.insert(name, value());
+    }
+}
 
-        while trimmed_location.ends_with("/") && trimmed_location.len() > 1 {
-            trimmed_location = &location[..trimmed_location.len() - 1];
+/// decode a `Transfer-Encoding: chunked` body and its trailers. Chunk extensions are skipped,
+/// as are trailers that would change how the request is framed or routed.
+/// Fails with `ErrorKind::FileTooLarge` if the body and trailers grow beyond `limit`
+pub(crate) fn read_chunked_body(
+    reader: &mut impl BufRead,
+    limit: usize,
+) -> std::io::Result<(Vec<u8>, HeaderMap)> {
+    let mut decoder = ChunkedDecoder::new(limit);
+    let mut body = Vec::new();
+    let mut buffer = [0; 8192];
+    loop {
+        match decoder.read(reader, &mut buffer)? {
+            0 => return Ok((body, decoder.trailers.unwrap_or_default())),
+            read => body.extend_from_slice(&buffer[..read]),
         }
+    }
+}
 
-        let response = match listeners.get(&String::from(trimmed_location)) {
-            Some(route) => {
-                let method = get_method(context[0]);
-                if route.methods.contains(&method) {
-                    (route.listener)(&headers, &body, &query_params, passthrough)
-                } else {
-                    if method == HTTPMethod::INVALID {
-                        get_400_default_response()
-                    } else {
-                        get_405_default_response(trimmed_location, context[0])
-                    }
-                }
+/// decodes a `Transfer-Encoding: chunked` body a read at a time, so it can be passed on as it
+/// arrives, like `read_chunked_body` does all at once
+pub(crate) struct ChunkedDecoder {
+    /// the most bytes the body and trailers may have
+    limit: usize,
+    /// the data read so far
+    read: usize,
+    /// what's left of the current chunk, 0 before the next size line
+    left: usize,
+    /// set once the last chunk and trailers are read
+    pub(crate) trailers: Option<HeaderMap>,
+}
+
+impl ChunkedDecoder {
+    pub(crate) fn new(limit: usize) -> ChunkedDecoder {
+        ChunkedDecoder {
+            limit,
+            read: 0,
+            left: 0,
+            trailers: None,
+        }
+    }
+
+    /// read body data from `reader` into `buffer`, 0 once the body and its trailers are read
+    pub(crate) fn read(
+        &mut self,
+        reader: &mut impl BufRead,
+        buffer: &mut [u8],
+    ) -> std::io::Result<usize> {
+        if self.trailers.is_some() || buffer.is_empty() {
+            return Ok(0);
+        }
+        if self.left == 0 {
+            let mut size_line = String::new();
+            if reader.by_ref().take(1024).read_line(&mut size_line)? == 0 {
+                return Err(invalid_data("stream ended before the last chunk"));
             }
-            None => match *default_404_handler {
-                Some(ref handler) => handler(&headers, &body, &query_params, passthrough),
-                None => match *default_404_handler {
-                    Some(ref handler) => handler(&headers, &body, &query_params, passthrough),
-                    None => get_404_default_response(),
-                },
-            },
-        };
+            // 1*HEXDIG, then extensions that may be preceded by whitespace
+            let size = size_line.trim_end_matches(['\r', '\n']);
+            let size = size.split(';').next().unwrap_or("");
+            let size = size.trim_end_matches([' ', '\t']);
+            if size.is_empty() || !size.bytes().all(|b| b.is_ascii_hexdigit()) {
+                return Err(invalid_data("invalid chunk size"));
+            }
+            let size =
+                usize::from_str_radix(size, 16).map_err(|_| invalid_data("invalid chunk size"))?;
 
-        // println!("{:#?}", headers);
+            if size == 0 {
+                self.trailers = Some(self.read_trailers(reader)?);
+                return Ok(0);
+            }
+            if size > self.limit - self.read {
+                return Err(std::io::Error::new(
+                    ErrorKind::FileTooLarge,
+                    "chunked body too large",
+                ));
+            }
+            self.left = size;
+        }
 
-        // for byte in content_buffer {
-        //     println!("{}", byte as char);
-        // }
+        let wanted = buffer.len().min(self.left);
+        let read = reader.read(&mut buffer[..wanted])?;
+        if read == 0 {
+            return Err(std::io::Error::new(
+                ErrorKind::UnexpectedEof,
+                "stream ended within a chunk",
+            ));
+        }
+        self.left -= read;
+        self.read += read;
 
-        HTTPServer::<T>::close_stream(stream, &response)
+        if self.left == 0 {
+            let mut line_end = String::new();
+            reader.by_ref().take(2).read_line(&mut line_end)?;
+            if line_end != "\r\n" {
+                return Err(invalid_data("missing CRLF after chunk data"));
+            }
+        }
+        Ok(read)
     }
 
-    fn close_stream(mut stream: &TcpStream, response: &HTTPResponse) {
-        stream
-            .write(
-                format!(
-                    "HTTP/1.1 {} {}\r\n{}\r\n{}",
-                    response.status.status,
-                    response.status.reason,
-                    parse_headers(&response.headers),
-                    response.body,
-                )
-                .as_bytes(),
-            )
-            .unwrap();
-        stream.flush().unwrap();
+    /// trailers are terminated by an empty line
+    fn read_trailers(&self, reader: &mut impl BufRead) -> std::io::Result<HeaderMap> {
+        let mut trailers = HeaderMap::new();
+        let mut remaining = self.limit - self.read;
+        loop {
+            let mut trailer = String::new();
+            let read = reader
+                .by_ref()
+                .take(remaining as u64 + 1)
+                .read_line(&mut trailer)?;
+            if read > remaining {
+                return Err(std::io::Error::new(
+                    ErrorKind::FileTooLarge,
+                    "chunked body too large",
+                ));
+            }
+            remaining -= read;
+            let line = trailer.trim_end_matches(['\r', '\n']);
+            if read == 0 || line.is_empty() {
+                for name in ["Content-Length", "Transfer-Encoding", "Host", "Trailer"] {
+                    trailers.remove(name);
+                }
+                return Ok(trailers);
+            }
+            if !parse_header_line(&mut trailers, line, true) {
+                return Err(invalid_data("invalid trailer"));
+            }
+        }
     }
+}
 
-    fn send_400_default_response(stream: &TcpStream) {
-        HTTPServer::<T>::close_stream(stream, &get_400_default_response());
-    }
+fn invalid_data(msg: &str) -> std::io::Error {
+    std::io::Error::new(ErrorKind::InvalidData, msg.to_owned())
 }
 
-impl HTTPStatus {
-    fn new(code: u16) -> HTTPStatus {
-        HTTPStatus {
-            status: code,
-            reason: http_code_reason(code),
+/// write `chunks` as they come, flushing each. Without `chunked`, as for HTTP/1.0, they're
+/// written as they are. Returns the bytes written, without the last chunk ending the body
+fn write_chunks(
+    stream: &mut impl Write,
+    chunks: impl Iterator<Item = Vec<u8>>,
+    chunked: bool,
+) -> std::io::Result<u64> {
+    let mut sent = 0;
+    for chunk in chunks {
+        sent += chunk.len() as u64;
+        match chunked {
+            true => write_chunk(stream, &chunk)?,
+            false => {
+                stream.write_all(&chunk)?;
+                stream.flush()?;
+            }
         }
     }
+    Ok(sent)
 }
 
-// http server internal utils
-
-fn parse_headers(headers: &HashMap<String, String>) -> String {
-    let mut converted: String = String::from("");
-    for header in headers.iter() {
-        converted.push_str(&format!("{}:{}\n", header.0, header.1));
+/// write a single chunk and flush it to the client. Empty chunks are skipped since they'd end the body
+fn write_chunk(stream: &mut impl Write, chunk: &[u8]) -> std::io::Result<()> {
+    if chunk.is_empty() {
+        return Ok(());
     }
-    return converted;
+    stream.write_all(format!("{:X}\r\n", chunk.len()).as_bytes())?;
+    stream.write_all(chunk)?;
+    stream.write_all(b"\r\n")?;
+    stream.flush()
 }
 
-fn get_404_default_response() -> HTTPResponse {
+pub(crate) fn get_404_default_response() -> HTTPResponse {
     HTTPResponse {
         status: HTTPStatus::new(404),
-        headers: HashMap::from([(
-            String::from("Content-Length"),
+        headers: HeaderMap::from([(
+            "Content-Length",
             56.to_string(), /* 56 : length of string `The requested resource hasn't been found on this server.` */
         )]),
-        body: String::from("The requested resource hasn't been found on this server."),
+        body: Body::from("The requested resource hasn't been found on this server."),
     }
 }
 
 fn get_405_default_response(route: &str, method: &str) -> HTTPResponse {
     let body = format!("Cannot {method} {route}");
-    HTTPResponse { status: HTTPStatus::new(405), headers: default_headers(&body), body }
+    HTTPResponse {
+        status: HTTPStatus::new(405),
+        headers: default_headers(&body),
+        body: Body::from(body),
+    }
+}
+
+/// the answer to OPTIONS for a path without an OPTIONS route of its own
+fn get_options_response(methods: Vec<&HTTPMethod>) -> HTTPResponse {
+    HTTPResponse::builder()
+        .status(204)
+        .header("Allow", &allow_header(methods))
+        .empty()
 }
 
-fn get_400_default_response() -> HTTPResponse {
+/// `methods` and OPTIONS, which every known path answers, as the value of an Allow header
+fn allow_header(mut methods: Vec<&HTTPMethod>) -> String {
+    methods.push(&HTTPMethod::OPTIONS);
+    methods.sort();
+    methods.dedup();
+    methods
+        .iter()
+        .map(|method| method.to_string())
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+pub(crate) fn get_400_default_response() -> HTTPResponse {
     HTTPResponse {
         status: HTTPStatus::new(400),
-        body: String::from("Received invalid data"),
-        headers: HashMap::from([(
-            String::from("Content-Length"),
+        body: Body::from("Received invalid data"),
+        headers: HeaderMap::from([(
+            "Content-Length",
             21.to_string(), /* 21 : length of string `Received invalid data` */
         )]),
     }
 }
 
+pub(crate) fn get_408_default_response() -> HTTPResponse {
+    let body = "Request Timeout";
+    HTTPResponse {
+        status: HTTPStatus::new(408),
+        headers: default_headers(body),
+        body: Body::from(body),
+    }
+}
+
+pub(crate) fn get_413_default_response() -> HTTPResponse {
+    let body = "Request body too large";
+    HTTPResponse {
+        status: HTTPStatus::new(413),
+        headers: default_headers(body),
+        body: Body::from(body),
+    }
+}
+
+pub(crate) fn get_431_default_response() -> HTTPResponse {
+    let body = "Request header fields too large";
+    HTTPResponse {
+        status: HTTPStatus::new(431),
+        headers: default_headers(body),
+        body: Body::from(body),
+    }
+}
+
+fn get_500_default_response() -> HTTPResponse {
+    let body = "Internal Server Error";
+    HTTPResponse {
+        status: HTTPStatus::new(500),
+        headers: default_headers(body),
+        body: Body::from(body),
+    }
+}
+
+fn get_503_default_response() -> HTTPResponse {
+    let body = "Server is overloaded";
+    HTTPResponse {
+        status: HTTPStatus::new(503),
+        headers: default_headers(body),
+        body: Body::from(body),
+    }
+}
+
+fn get_505_default_response() -> HTTPResponse {
+    let body = "Only HTTP/1.0 and HTTP/1.1 are supported";
+    HTTPResponse {
+        status: HTTPStatus::new(505),
+        headers: default_headers(body),
+        body: Body::from(body),
+    }
+}
+
+/// check if `raw` looks like `HTTP/<major>.<minor>`
+fn is_http_version(raw: &str) -> bool {
+    raw.strip_prefix("HTTP/")
+        .and_then(|version| version.split_once('.'))
+        .is_some_and(|(major, minor)| {
+            !major.is_empty()
+                && !minor.is_empty()
+                && major
+                    .bytes()
+                    .chain(minor.bytes())
+                    .all(|b| b.is_ascii_digit())
+        })
+}
+
 fn get_method(raw: &str) -> HTTPMethod {
     match raw {
         "GET" => HTTPMethod::GET,
@@ -282,37 +3148,40 @@ fn get_method(raw: &str) -> HTTPMethod {
         "PUT" => HTTPMethod::PUT,
         "DELETE" => HTTPMethod::DELETE,
         "CONNECT" => HTTPMethod::CONNECT,
-        "OPTION" => HTTPMethod::OPTION,
+        "OPTIONS" => HTTPMethod::OPTIONS,
         "TRACE" => HTTPMethod::TRACE,
         "PATCH" => HTTPMethod::PATCH,
+        name if is_token(name) => HTTPMethod::Custom(String::from(name)),
         &_ => HTTPMethod::INVALID,
     }
 }
 
+impl From<&str> for HTTPMethod {
+    /// the method named `name`, `Custom` for ones without their own variant
+    fn from(name: &str) -> HTTPMethod {
+        get_method(name)
+    }
+}
+
 // public utils
 
 /// get a map with Content-Length prefilled
-pub fn default_headers(content: &String) -> HashMap<String, String> {
-    HashMap::from([(
-        String::from("Content-Length"),
-        content.len().to_owned().to_string(),
-    )])
+pub fn default_headers(content: &str) -> HeaderMap {
+    HeaderMap::from([("Content-Length", content.len().to_owned().to_string())])
 }
 
 pub fn response_200(body: Option<String>) -> HTTPResponse {
-    let body = match body {
-        Some(b) => b,
-        None => String::from(""),
-    };
+    let body = body.unwrap_or_default();
     HTTPResponse {
         status: HTTPStatus::new(200),
         headers: default_headers(&body),
-        body,
+        body: Body::from(body),
     }
 }
 
-pub fn http_code_reason(code: u16) -> String {
-    let r: Option<&str> = match code {
+/// the reason phrase of a registered status code, `None` for others
+pub fn http_code_reason(code: u16) -> Option<&'static str> {
+    match code {
         100 => Some("Continue"),
         101 => Some("Switching Protocols"),
         103 => Some("Early Hints"),
@@ -353,7 +3222,7 @@ pub fn http_code_reason(code: u16) -> String {
         425 => Some("Too Early"),
         426 => Some("Upgrade Required"),
         428 => Some("Precondition Required"),
-        429 => Some("Too Many Requirests"),
+        429 => Some("Too Many Requests"),
         431 => Some("Request Header Fields Too Large"),
         451 => Some("Unavailable For Legal Reasons"),
         500 => Some("Internal Server Error"),
@@ -368,6 +3237,60 @@ pub fn http_code_reason(code: u16) -> String {
         510 => Some("Not Extended"),
         511 => Some("Network Authentication Required"),
         _ => None,
-    };
-    String::from(r.expect("Invalid HTTP Status Code Provided"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufReader, ErrorKind, Read};
+
+    use super::read_chunked_body;
+
+    #[test]
+    fn chunked_body_with_trailers() {
+        let mut input: &[u8] =
+            b"4;ext=1\r\nWiki\r\n5\r\npedia\r\n0\r\nExpires: never\r\nHost: x\r\n\r\nnext";
+        let (body, trailers) = read_chunked_body(&mut input, 1024).unwrap();
+        assert_eq!(body, b"Wikipedia");
+        assert_eq!(trailers.get("Expires"), Some("never"));
+        assert_eq!(trailers.get("Host"), None);
+        assert_eq!(input, b"next");
+    }
+
+    #[test]
+    fn chunk_sizes_are_only_hex_digits() {
+        for size in ["+a", " a", "-0", "0x1", "a b", "", "\u{b}a", "1_0"] {
+            let chunked = format!("{}\r\n0123456789\r\n0\r\n\r\n", size);
+            let error = read_chunked_body(&mut chunked.as_bytes(), 1024).unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::InvalidData, "{:?}", size);
+        }
+        let mut chunked = &b"A ;ext=1\r\n0123456789\r\n0\r\n\r\n"[..];
+        let (body, _) = read_chunked_body(&mut chunked, 1024).unwrap();
+        assert_eq!(body, b"0123456789");
+        // sizes past usize fail rather than wrap
+        let mut chunked = &b"10000000000000000\r\n"[..];
+        assert!(read_chunked_body(&mut chunked, usize::MAX).is_err());
+    }
+
+    #[test]
+    fn chunked_body_over_limit() {
+        let mut input: &[u8] = b"a\r\n0123456789\r\n0\r\n\r\n";
+        let error = read_chunked_body(&mut input, 9).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::FileTooLarge);
+    }
+
+    #[test]
+    fn chunk_data_must_end_with_crlf() {
+        let mut input: &[u8] = b"4\r\nWikiXX\r\n0\r\n\r\n";
+        let error = read_chunked_body(&mut input, 1024).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn endless_line_after_chunk_data_is_not_buffered() {
+        // a reader that never ends, the line after the chunk data would grow without bound
+        let endless = b"4\r\nWiki".chain(std::io::repeat(b'x'));
+        let error = read_chunked_body(&mut BufReader::new(endless), 1024).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
 }
@@ -0,0 +1,118 @@
+use std::{
+    io::{self, BufReader, ErrorKind, Read, Write},
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    headers::HeaderMap,
+    http_server::{is_field_text, is_token, write_head, HTTPRequest, HTTPStatus},
+};
+
+type WriteHead = Box<dyn Fn(&[u8]) -> io::Result<()> + Send + Sync>;
+
+/// writes interim responses to the connection of the request it's in the extensions of
+pub(crate) struct Interim(WriteHead);
+
+impl Interim {
+    /// interim responses written beneath the buffer of `input`, which bodies are read through
+    pub(crate) fn new<S: Read + Write + Send + 'static>(
+        input: Arc<Mutex<BufReader<S>>>,
+    ) -> Interim {
+        Interim(Box::new(move |head: &[u8]| {
+            let mut input = input.lock().unwrap();
+            input.get_mut().write_all(head)?;
+            input.get_mut().flush()
+        }))
+    }
+}
+
+impl HTTPRequest {
+    /// send a `1xx` response ahead of the final one, like `103 Early Hints` with
+    /// `[("Link", "</style.css>; rel=preload; as=style")]` while the page is still being put
+    /// together. Fails with `ErrorKind::Unsupported` for 101, which `HTTPResponse::upgrade`
+    /// sends, for other statuses and for connections that can't carry it: HTTP/1.0, HTTP/2
+    /// and requests not read from a connection. Fails with `ErrorKind::InvalidInput` for a
+    /// header name that isn't a token or a value with CR, LF or other control characters
+    pub fn send_informational(&self, status: u16, headers: &[(&str, &str)]) -> io::Result<()> {
+        if !(100..=199).contains(&status) || status == 101 {
+            return Err(io::Error::new(
+                ErrorKind::Unsupported,
+                "only 1xx other than 101 are informational",
+            ));
+        }
+        for &(name, value) in headers {
+            if !is_token(name) || !is_field_text(value) {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("invalid header {:?}: {:?}", name, value),
+                ));
+            }
+        }
+        let Some(Interim(send)) = self.extensions.get::<Interim>() else {
+            return Err(io::Error::new(
+                ErrorKind::Unsupported,
+                "the connection can't carry informational responses",
+            ));
+        };
+        let mut map = HeaderMap::with_capacity(headers.len());
+        for (name, value) in headers {
+            map.append(name, *value);
+        }
+        let mut head = Vec::new();
+        write_head(&mut head, &HTTPStatus::new(status), &map);
+        send(&head)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// a request whose interim responses are collected in the returned buffer
+    fn request() -> (HTTPRequest, Arc<Mutex<Vec<u8>>>) {
+        let written = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&written);
+        let mut request = HTTPRequest::builder().build();
+        request.extensions.insert(Interim(Box::new(move |head| {
+            sink.lock().unwrap().extend_from_slice(head);
+            Ok(())
+        })));
+        (request, written)
+    }
+
+    #[test]
+    fn sends_interim_heads() {
+        let (request, written) = request();
+        let link = "</style.css>; rel=preload; as=style";
+        request.send_informational(103, &[("Link", link)]).unwrap();
+        let head = String::from_utf8(written.lock().unwrap().clone()).unwrap();
+        assert!(head.starts_with("HTTP/1.1 103 "));
+        assert!(head.ends_with(&format!("\r\nLink: {}\r\n\r\n", link)));
+    }
+
+    #[test]
+    fn refuses_headers_that_would_split_the_head() {
+        let (request, written) = request();
+        let invalid = [
+            ("Link", "</a>\r\nSet-Cookie: session=1"),
+            ("Link", "</a>\nx"),
+            ("Link", "</a>\0"),
+            ("Bad Name", "x"),
+            ("", "x"),
+        ];
+        for header in invalid {
+            let error = request.send_informational(103, &[header]).unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        }
+        assert!(written.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn refuses_statuses_that_arent_informational() {
+        let (request, _) = request();
+        for status in [101, 200] {
+            let error = request.send_informational(status, &[]).unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::Unsupported);
+        }
+    }
+}
@@ -0,0 +1,224 @@
+use std::net::IpAddr;
+
+use crate::{
+    http_server::{HTTPRequest, HTTPResponse},
+    middleware::Next,
+};
+
+/// a CIDR range like `10.0.0.0/8` or `fd00::/8`. A single address is a range of its own
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct IpRange {
+    ip: IpAddr,
+    prefix: u8,
+}
+
+impl IpRange {
+    /// `None` unless `range` is an address, optionally followed by a prefix length
+    pub fn parse(range: &str) -> Option<IpRange> {
+        let (ip, prefix) = match range.split_once('/') {
+            Some((ip, prefix)) => (ip, Some(prefix)),
+            None => (range, None),
+        };
+        let ip: IpAddr = ip.parse().ok()?;
+        let bits = match ip {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        let prefix = match prefix {
+            Some(prefix) => prefix.parse().ok().filter(|prefix| *prefix <= bits)?,
+            None => bits,
+        };
+        Some(IpRange { ip, prefix })
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (canonical(ip), self.ip) {
+            (IpAddr::V4(ip), IpAddr::V4(range)) => same_prefix(
+                u32::from(ip).into(),
+                u32::from(range).into(),
+                self.prefix,
+                32,
+            ),
+            (IpAddr::V6(ip), IpAddr::V6(range)) => {
+                same_prefix(u128::from(ip), u128::from(range), self.prefix, 128)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// which clients may reach a server or a scope. Denied ranges win over allowed ones, and as
+/// soon as a range is allowed everyone outside of it is denied. Checks `HTTPRequest::client_ip`,
+/// so clients behind trusted proxies are filtered by their own address
+#[derive(Clone, Default, Debug)]
+pub struct IpFilter {
+    allowed: Vec<IpRange>,
+    denied: Vec<IpRange>,
+}
+
+impl IpFilter {
+    /// a filter letting everyone through
+    pub fn new() -> IpFilter {
+        IpFilter::default()
+    }
+
+    /// allow `range`, like `10.0.0.0/8`. Panics if it isn't a range
+    pub fn allow(mut self, range: &str) -> Self {
+        self.allowed.push(parse_or_panic(range));
+        self
+    }
+
+    /// deny `range`, like `203.0.113.0/24`. Panics if it isn't a range
+    pub fn deny(mut self, range: &str) -> Self {
+        self.denied.push(parse_or_panic(range));
+        self
+    }
+
+    /// check if a client from `ip` may pass. Clients without an ip, over unix sockets, only
+    /// pass when no range is allowed explicitly
+    pub fn allows(&self, ip: Option<IpAddr>) -> bool {
+        let Some(ip) = ip else {
+            return self.allowed.is_empty();
+        };
+        if self.denied.iter().any(|range| range.contains(ip)) {
+            return false;
+        }
+        self.allowed.is_empty() || self.allowed.iter().any(|range| range.contains(ip))
+    }
+
+    /// middleware answering clients the filter doesn't allow with 403. Register it on the
+    /// server for all routes or on a scope for some
+    pub fn middleware<T>(
+        self,
+    ) -> impl Fn(&mut HTTPRequest, &T, Next<T>) -> HTTPResponse + Send + Sync + 'static {
+        move |request: &mut HTTPRequest, passthrough: &T, next: Next<T>| {
+            if self.allows(request.client_ip) {
+                next.run(request, passthrough)
+            } else {
+                HTTPResponse::builder().status(403).text("Forbidden")
+            }
+        }
+    }
+}
+
+fn parse_or_panic(range: &str) -> IpRange {
+    IpRange::parse(range).unwrap_or_else(|| panic!("`{range}` isn't an ip range"))
+}
+
+/// ipv4 clients of a dual stack socket show up as ipv4-mapped ipv6
+pub(crate) fn canonical(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => v6
+            .to_ipv4_mapped()
+            .map(IpAddr::V4)
+            .unwrap_or(IpAddr::V6(v6)),
+        ip => ip,
+    }
+}
+
+fn same_prefix(ip: u128, range: u128, prefix: u8, bits: u8) -> bool {
+    if prefix == 0 {
+        return true;
+    }
+    let shift = bits - prefix;
+    ip >> shift == range >> shift
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use super::*;
+    use crate::{http_server::HTTPServer, testing::TestClient};
+
+    fn ip(ip: &str) -> IpAddr {
+        ip.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_ranges() {
+        assert_eq!(
+            IpRange::parse("10.0.0.0/8"),
+            Some(IpRange {
+                ip: ip("10.0.0.0"),
+                prefix: 8
+            })
+        );
+        assert_eq!(IpRange::parse("::1").map(|range| range.prefix), Some(128));
+        assert_eq!(
+            IpRange::parse("192.0.2.1").map(|range| range.prefix),
+            Some(32)
+        );
+        for range in [
+            "10.0.0.0/33",
+            "::/129",
+            "10.0.0.0/",
+            "10.0.0/8",
+            "localhost",
+            "10.0.0.0/-1",
+        ] {
+            assert_eq!(IpRange::parse(range), None, "{}", range);
+        }
+    }
+
+    #[test]
+    fn matches_prefixes() {
+        let range = IpRange::parse("192.168.16.0/20").unwrap();
+        assert!(range.contains(ip("192.168.16.0")));
+        assert!(range.contains(ip("192.168.31.255")));
+        assert!(!range.contains(ip("192.168.32.0")));
+        assert!(!range.contains(ip("192.168.15.255")));
+        // ipv4 clients of dual stack sockets match ipv4 ranges
+        assert!(range.contains(ip("::ffff:192.168.20.1")));
+        assert!(!range.contains(ip("fd00::1")));
+
+        let range = IpRange::parse("fd00::/8").unwrap();
+        assert!(range.contains(ip("fdff:ffff::1")));
+        assert!(!range.contains(ip("fe00::1")));
+        assert!(!range.contains(ip("10.0.0.1")));
+
+        assert!(IpRange::parse("0.0.0.0/0")
+            .unwrap()
+            .contains(ip("203.0.113.9")));
+        assert!(IpRange::parse("::/0").unwrap().contains(ip("2001:db8::1")));
+        let single = IpRange::parse("203.0.113.9").unwrap();
+        assert!(single.contains(ip("203.0.113.9")));
+        assert!(!single.contains(ip("203.0.113.8")));
+    }
+
+    #[test]
+    fn denied_ranges_win() {
+        let open = IpFilter::new().deny("203.0.113.0/24");
+        assert!(open.allows(Some(ip("198.51.100.1"))));
+        assert!(!open.allows(Some(ip("203.0.113.1"))));
+        assert!(open.allows(None));
+
+        let closed = IpFilter::new().allow("10.0.0.0/8").deny("10.1.0.0/16");
+        assert!(closed.allows(Some(ip("10.2.0.1"))));
+        assert!(!closed.allows(Some(ip("10.1.0.1"))));
+        assert!(!closed.allows(Some(ip("192.0.2.1"))));
+        assert!(!closed.allows(None));
+    }
+
+    #[test]
+    #[should_panic(expected = "isn't an ip range")]
+    fn invalid_ranges_panic() {
+        let _ = IpFilter::new().allow("10.0.0.0/40");
+    }
+
+    #[test]
+    fn middleware_answers_403() {
+        let server = HTTPServer::builder(())
+            .middleware(IpFilter::new().allow("127.0.0.0/8").middleware())
+            .get("/", |_: &HTTPRequest, _: &()| "hello")
+            .build();
+        let client = TestClient::new(server);
+        let from = |addr: &str| {
+            let addr: SocketAddr = addr.parse().unwrap();
+            client.get("/").peer_addr(addr).send().status.status
+        };
+        assert_eq!(from("127.0.0.1:5000"), 200);
+        assert_eq!(from("[::ffff:127.0.0.1]:5000"), 200);
+        assert_eq!(from("192.0.2.1:5000"), 403);
+    }
+}
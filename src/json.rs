@@ -0,0 +1,773 @@
+use std::{collections::HashMap, fmt};
+
+use crate::http_server::{HTTPRequest, HTTPResponse};
+
+/// a parsed JSON document. Objects keep their keys in document order
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+/// why a JSON body couldn't be read
+#[derive(Debug)]
+pub struct JsonError {
+    pub message: String,
+}
+
+impl JsonError {
+    pub fn new(message: impl Into<String>) -> JsonError {
+        JsonError {
+            message: message.into(),
+        }
+    }
+
+    /// a 400 response describing the error as `{"error": ...}`
+    pub fn response(&self) -> HTTPResponse {
+        let body = Value::Object(vec![(
+            String::from("error"),
+            Value::String(self.message.clone()),
+        )]);
+        HTTPResponse::builder().status(400).json(body.to_string())
+    }
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for JsonError {}
+
+/// a 200 response with the value as `application/json`
+impl From<Value> for HTTPResponse {
+    fn from(value: Value) -> HTTPResponse {
+        HTTPResponse::builder().json(value.to_string())
+    }
+}
+
+impl From<JsonError> for HTTPResponse {
+    fn from(error: JsonError) -> HTTPResponse {
+        error.response()
+    }
+}
+
+/// types that can be read from a JSON value, e.g. a request body
+pub trait FromJson: Sized {
+    fn from_json(value: &Value) -> Result<Self, JsonError>;
+}
+
+/// types that can be written as JSON, e.g. into a response
+pub trait ToJson {
+    fn to_json(&self) -> Value;
+}
+
+impl Value {
+    pub fn parse(text: &str) -> Result<Value, JsonError> {
+        let mut parser = Parser {
+            bytes: text.as_bytes(),
+            position: 0,
+            depth: 0,
+        };
+        let value = parser.value()?;
+        parser.whitespace();
+        if parser.position != parser.bytes.len() {
+            return Err(parser.error("trailing characters"));
+        }
+        Ok(value)
+    }
+
+    /// an object built from `(key, value)` pairs
+    pub fn object<'a>(fields: impl IntoIterator<Item = (&'a str, Value)>) -> Value {
+        Value::Object(
+            fields
+                .into_iter()
+                .map(|(key, value)| (String::from(key), value))
+                .collect(),
+        )
+    }
+
+    /// the member `key` of an object
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// convert the member `key` of an object. A missing member is read as `null`,
+    /// so optional fields can use `Option`
+    pub fn field<T: FromJson>(&self, key: &str) -> Result<T, JsonError> {
+        if !matches!(self, Value::Object(_)) {
+            return Err(JsonError::new("expected an object"));
+        }
+        T::from_json(self.get(key).unwrap_or(&Value::Null))
+            .map_err(|error| JsonError::new(format!("field `{}`: {}", key, error.message)))
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            Value::Null => "null",
+            Value::Bool(_) => "a boolean",
+            Value::Number(_) => "a number",
+            Value::String(_) => "a string",
+            Value::Array(_) => "an array",
+            Value::Object(_) => "an object",
+        }
+    }
+}
+
+/// compact JSON
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Null => f.write_str("null"),
+            Value::Bool(b) => write!(f, "{}", b),
+            // JSON has no representation for them
+            Value::Number(n) if !n.is_finite() => f.write_str("null"),
+            Value::Number(n) => write!(f, "{}", n),
+            Value::String(s) => write_string(f, s),
+            Value::Array(items) => {
+                f.write_str("[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                f.write_str("]")
+            }
+            Value::Object(fields) => {
+                f.write_str("{")?;
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                f.write_str("}")
+            }
+        }
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    f.write_str("\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    f.write_str("\"")
+}
+
+impl HTTPRequest {
+    /// parse the body as JSON into `T`. `JsonError::response` makes a fitting 400
+    pub fn json<T: FromJson>(&self) -> Result<T, JsonError> {
+        let body = self
+            .body
+            .text()
+            .map_err(|error| JsonError::new(error.to_string()))?;
+        T::from_json(&Value::parse(body)?)
+    }
+}
+
+impl HTTPResponse {
+    /// a 200 response with `value` serialized as `application/json`
+    pub fn json(value: &impl ToJson) -> HTTPResponse {
+        HTTPResponse::builder().json(value.to_json().to_string())
+    }
+}
+
+/// nesting deeper than this is rejected instead of overflowing the stack
+const MAX_DEPTH: usize = 128;
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> JsonError {
+        JsonError::new(format!(
+            "invalid JSON at byte {}: {}",
+            self.position, message
+        ))
+    }
+
+    fn whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.bytes.get(self.position) {
+            self.position += 1;
+        }
+    }
+
+    fn expect(&mut self, literal: &str) -> Result<(), JsonError> {
+        if self.bytes[self.position..].starts_with(literal.as_bytes()) {
+            self.position += literal.len();
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected `{}`", literal)))
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, JsonError> {
+        self.whitespace();
+        match self.bytes.get(self.position) {
+            None => Err(self.error("unexpected end")),
+            Some(b'n') => self.expect("null").map(|_| Value::Null),
+            Some(b't') => self.expect("true").map(|_| Value::Bool(true)),
+            Some(b'f') => self.expect("false").map(|_| Value::Bool(false)),
+            Some(b'"') => self.string().map(Value::String),
+            Some(b'[') => self.nested(Parser::array),
+            Some(b'{') => self.nested(Parser::object),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            Some(_) => Err(self.error("unexpected character")),
+        }
+    }
+
+    fn nested(
+        &mut self,
+        parse: fn(&mut Self) -> Result<Value, JsonError>,
+    ) -> Result<Value, JsonError> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(self.error("nested too deeply"));
+        }
+        let value = parse(self);
+        self.depth -= 1;
+        value
+    }
+
+    fn array(&mut self) -> Result<Value, JsonError> {
+        self.position += 1;
+        let mut items = Vec::new();
+        self.whitespace();
+        if self.bytes.get(self.position) == Some(&b']') {
+            self.position += 1;
+            return Ok(Value::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            self.whitespace();
+            match self.bytes.get(self.position) {
+                Some(b',') => self.position += 1,
+                Some(b']') => {
+                    self.position += 1;
+                    return Ok(Value::Array(items));
+                }
+                _ => return Err(self.error("expected `,` or `]`")),
+            }
+        }
+    }
+
+    fn object(&mut self) -> Result<Value, JsonError> {
+        self.position += 1;
+        let mut fields = Vec::new();
+        self.whitespace();
+        if self.bytes.get(self.position) == Some(&b'}') {
+            self.position += 1;
+            return Ok(Value::Object(fields));
+        }
+        loop {
+            self.whitespace();
+            if self.bytes.get(self.position) != Some(&b'"') {
+                return Err(self.error("expected a key"));
+            }
+            let key = self.string()?;
+            self.whitespace();
+            self.expect(":")?;
+            fields.push((key, self.value()?));
+            self.whitespace();
+            match self.bytes.get(self.position) {
+                Some(b',') => self.position += 1,
+                Some(b'}') => {
+                    self.position += 1;
+                    return Ok(Value::Object(fields));
+                }
+                _ => return Err(self.error("expected `,` or `}`")),
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<Value, JsonError> {
+        let start = self.position;
+        let digits = |parser: &mut Self| {
+            let from = parser.position;
+            while parser
+                .bytes
+                .get(parser.position)
+                .is_some_and(u8::is_ascii_digit)
+            {
+                parser.position += 1;
+            }
+            parser.position > from
+        };
+
+        if self.bytes.get(self.position) == Some(&b'-') {
+            self.position += 1;
+        }
+        let integer = self.position;
+        // leading zeros aren't allowed
+        if !digits(self) || (self.bytes[integer] == b'0' && self.position - integer > 1) {
+            return Err(self.error("invalid number"));
+        }
+        if self.bytes.get(self.position) == Some(&b'.') {
+            self.position += 1;
+            if !digits(self) {
+                return Err(self.error("invalid number"));
+            }
+        }
+        if let Some(b'e' | b'E') = self.bytes.get(self.position) {
+            self.position += 1;
+            if let Some(b'+' | b'-') = self.bytes.get(self.position) {
+                self.position += 1;
+            }
+            if !digits(self) {
+                return Err(self.error("invalid number"));
+            }
+        }
+
+        // only ascii was consumed
+        std::str::from_utf8(&self.bytes[start..self.position])
+            .ok()
+            .and_then(|number| number.parse().ok())
+            .map(Value::Number)
+            .ok_or_else(|| self.error("invalid number"))
+    }
+
+    fn string(&mut self) -> Result<String, JsonError> {
+        self.position += 1;
+        let mut string = String::new();
+        loop {
+            let start = self.position;
+            while self
+                .bytes
+                .get(self.position)
+                .is_some_and(|b| *b != b'"' && *b != b'\\' && *b >= 0x20)
+            {
+                self.position += 1;
+            }
+            // the input is a str and the run stops at ascii, so this is on a char boundary
+            string.push_str(
+                std::str::from_utf8(&self.bytes[start..self.position])
+                    .map_err(|_| self.error("invalid utf8"))?,
+            );
+
+            match self.bytes.get(self.position) {
+                Some(b'"') => {
+                    self.position += 1;
+                    return Ok(string);
+                }
+                Some(b'\\') => {
+                    self.position += 1;
+                    let escaped = match self.bytes.get(self.position) {
+                        Some(b'u') => {
+                            self.position += 1;
+                            self.unicode_escape()?
+                        }
+                        Some(escape) => {
+                            let escaped = match escape {
+                                b'"' => '"',
+                                b'\\' => '\\',
+                                b'/' => '/',
+                                b'b' => '\u{8}',
+                                b'f' => '\u{c}',
+                                b'n' => '\n',
+                                b'r' => '\r',
+                                b't' => '\t',
+                                _ => return Err(self.error("invalid escape")),
+                            };
+                            self.position += 1;
+                            escaped
+                        }
+                        None => return Err(self.error("unterminated string")),
+                    };
+                    string.push(escaped);
+                }
+                None => return Err(self.error("unterminated string")),
+                Some(_) => return Err(self.error("control character in string")),
+            }
+        }
+    }
+
+    /// the char of a `\uXXXX` escape, combining surrogate pairs. Positioned after the `u`
+    fn unicode_escape(&mut self) -> Result<char, JsonError> {
+        let hex = |parser: &mut Self| {
+            let code = parser
+                .bytes
+                .get(parser.position..parser.position + 4)
+                .filter(|hex| hex.iter().all(u8::is_ascii_hexdigit))
+                .and_then(|hex| u32::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok())
+                .ok_or_else(|| parser.error("invalid unicode escape"))?;
+            parser.position += 4;
+            Ok(code)
+        };
+
+        let high = hex(self)?;
+        if !(0xD800..0xDC00).contains(&high) {
+            return char::from_u32(high).ok_or_else(|| self.error("invalid unicode escape"));
+        }
+        if !self.bytes[self.position..].starts_with(b"\\u") {
+            return Err(self.error("unpaired surrogate"));
+        }
+        self.position += 2;
+        let low = hex(self)?;
+        if !(0xDC00..0xE000).contains(&low) {
+            return Err(self.error("unpaired surrogate"));
+        }
+        char::from_u32(0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00))
+            .ok_or_else(|| self.error("invalid unicode escape"))
+    }
+}
+
+fn mismatch(expected: &str, value: &Value) -> JsonError {
+    JsonError::new(format!("expected {}, found {}", expected, value.kind()))
+}
+
+impl FromJson for Value {
+    fn from_json(value: &Value) -> Result<Value, JsonError> {
+        Ok(value.clone())
+    }
+}
+
+impl ToJson for Value {
+    fn to_json(&self) -> Value {
+        self.clone()
+    }
+}
+
+impl FromJson for bool {
+    fn from_json(value: &Value) -> Result<bool, JsonError> {
+        match value {
+            Value::Bool(b) => Ok(*b),
+            other => Err(mismatch("a boolean", other)),
+        }
+    }
+}
+
+impl ToJson for bool {
+    fn to_json(&self) -> Value {
+        Value::Bool(*self)
+    }
+}
+
+impl FromJson for String {
+    fn from_json(value: &Value) -> Result<String, JsonError> {
+        match value {
+            Value::String(s) => Ok(s.clone()),
+            other => Err(mismatch("a string", other)),
+        }
+    }
+}
+
+impl ToJson for String {
+    fn to_json(&self) -> Value {
+        Value::String(self.clone())
+    }
+}
+
+impl ToJson for str {
+    fn to_json(&self) -> Value {
+        Value::String(String::from(self))
+    }
+}
+
+impl FromJson for f64 {
+    fn from_json(value: &Value) -> Result<f64, JsonError> {
+        match value {
+            Value::Number(n) => Ok(*n),
+            other => Err(mismatch("a number", other)),
+        }
+    }
+}
+
+impl ToJson for f64 {
+    fn to_json(&self) -> Value {
+        Value::Number(*self)
+    }
+}
+
+impl FromJson for f32 {
+    fn from_json(value: &Value) -> Result<f32, JsonError> {
+        f64::from_json(value).map(|n| n as f32)
+    }
+}
+
+impl ToJson for f32 {
+    fn to_json(&self) -> Value {
+        Value::Number(f64::from(*self))
+    }
+}
+
+macro_rules! json_integer {
+    ($($int:ty),*) => {$(
+        impl FromJson for $int {
+            fn from_json(value: &Value) -> Result<$int, JsonError> {
+                match value {
+                    Value::Number(n) if n.fract() != 0.0 => {
+                        Err(JsonError::new(format!("expected an integer, found {}", n)))
+                    }
+                    // MAX rounds up to a power of two as a float, which is already out of range
+                    Value::Number(n) if *n >= <$int>::MIN as f64 && *n < <$int>::MAX as f64 + 1.0 => {
+                        Ok(*n as $int)
+                    }
+                    Value::Number(n) => Err(JsonError::new(format!(
+                        "{} is out of range for {}",
+                        n,
+                        stringify!($int)
+                    ))),
+                    other => Err(mismatch("an integer", other)),
+                }
+            }
+        }
+
+        impl ToJson for $int {
+            fn to_json(&self) -> Value {
+                Value::Number(*self as f64)
+            }
+        }
+    )*};
+}
+
+json_integer!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+
+impl<T: FromJson> FromJson for Option<T> {
+    fn from_json(value: &Value) -> Result<Option<T>, JsonError> {
+        match value {
+            Value::Null => Ok(None),
+            value => T::from_json(value).map(Some),
+        }
+    }
+}
+
+impl<T: ToJson> ToJson for Option<T> {
+    fn to_json(&self) -> Value {
+        match self {
+            Some(value) => value.to_json(),
+            None => Value::Null,
+        }
+    }
+}
+
+impl<T: FromJson> FromJson for Vec<T> {
+    fn from_json(value: &Value) -> Result<Vec<T>, JsonError> {
+        match value {
+            Value::Array(items) => items
+                .iter()
+                .enumerate()
+                .map(|(i, item)| {
+                    T::from_json(item)
+                        .map_err(|error| JsonError::new(format!("item {}: {}", i, error.message)))
+                })
+                .collect(),
+            other => Err(mismatch("an array", other)),
+        }
+    }
+}
+
+impl<T: ToJson> ToJson for Vec<T> {
+    fn to_json(&self) -> Value {
+        self.as_slice().to_json()
+    }
+}
+
+impl<T: ToJson> ToJson for [T] {
+    fn to_json(&self) -> Value {
+        Value::Array(self.iter().map(ToJson::to_json).collect())
+    }
+}
+
+impl<T: FromJson> FromJson for HashMap<String, T> {
+    fn from_json(value: &Value) -> Result<HashMap<String, T>, JsonError> {
+        match value {
+            Value::Object(fields) => fields
+                .iter()
+                .map(|(key, _)| value.field(key).map(|item| (key.clone(), item)))
+                .collect(),
+            other => Err(mismatch("an object", other)),
+        }
+    }
+}
+
+impl<T: ToJson> ToJson for HashMap<String, T> {
+    fn to_json(&self) -> Value {
+        Value::Object(
+            self.iter()
+                .map(|(key, value)| (key.clone(), value.to_json()))
+                .collect(),
+        )
+    }
+}
+
+impl<T: ToJson + ?Sized> ToJson for &T {
+    fn to_json(&self) -> Value {
+        (**self).to_json()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Value {
+        Value::parse(text).unwrap()
+    }
+
+    #[test]
+    fn literals_and_containers() {
+        let value = parse(r#" {"a": [1, true, null, "x"], "b": {}, "c": []} "#);
+        assert_eq!(
+            value,
+            Value::object([
+                (
+                    "a",
+                    Value::Array(vec![
+                        Value::Number(1.0),
+                        Value::Bool(true),
+                        Value::Null,
+                        Value::String("x".into()),
+                    ])
+                ),
+                ("b", Value::Object(Vec::new())),
+                ("c", Value::Array(Vec::new())),
+            ])
+        );
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(
+            parse(r#""\" \\ \/ \b \f \n \r \t A é""#),
+            Value::String("\" \\ / \u{8} \u{c} \n \r \t A é".into())
+        );
+        for invalid in [r#""\x""#, r#""\u12""#, r#""\u12g4""#, "\"\t\"", r#""open"#] {
+            assert!(Value::parse(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn surrogate_pairs() {
+        assert_eq!(parse(r#""\ud83d\ude00""#), Value::String("😀".into()));
+        assert_eq!(parse(r#""\uD834\uDD1E""#), Value::String("𝄞".into()));
+        assert_eq!(parse(r#""😀""#), Value::String("😀".into()));
+        for unpaired in [r#""\ud83d""#, r#""\ud83dx""#, r#""\ud83dA""#, r#""\ude00""#] {
+            assert!(Value::parse(unpaired).is_err(), "{}", unpaired);
+        }
+    }
+
+    #[test]
+    fn numbers() {
+        for (text, number) in [
+            ("0", 0.0),
+            ("-0", -0.0),
+            ("12", 12.0),
+            ("-1.5", -1.5),
+            ("1e3", 1000.0),
+            ("1E+2", 100.0),
+            ("25e-1", 2.5),
+            ("0.000001", 0.000001),
+            ("1.7976931348623157e308", f64::MAX),
+        ] {
+            assert_eq!(parse(text), Value::Number(number), "{}", text);
+        }
+        for invalid in [
+            "01", "-", "1.", ".5", "+1", "1e", "1e+", "0x10", "NaN", "Infinity",
+        ] {
+            assert!(Value::parse(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn integer_ranges() {
+        assert_eq!(u8::from_json(&parse("255")).unwrap(), 255);
+        assert!(u8::from_json(&parse("256")).is_err());
+        assert!(u32::from_json(&parse("-1")).is_err());
+        assert!(i32::from_json(&parse("1.5")).is_err());
+        assert_eq!(
+            i64::from_json(&parse("-9223372036854775808")).unwrap(),
+            i64::MIN
+        );
+        assert!(i64::from_json(&parse("9223372036854775808")).is_err());
+        assert!(u64::from_json(&parse("18446744073709551616")).is_err());
+    }
+
+    #[test]
+    fn deep_nesting() {
+        let nested = |depth: usize| format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+        assert!(Value::parse(&nested(MAX_DEPTH)).is_ok());
+        assert!(Value::parse(&nested(MAX_DEPTH + 1)).is_err());
+        // far beyond the limit fails without overflowing the stack
+        assert!(Value::parse(&nested(100_000)).is_err());
+    }
+
+    #[test]
+    fn trailing_garbage() {
+        for invalid in [
+            "1 2",
+            "{}x",
+            "[1,]",
+            "{\"a\":1,}",
+            "[1 2]",
+            "nulls",
+            "\"a\"\"b\"",
+            "",
+        ] {
+            assert!(Value::parse(invalid).is_err(), "{}", invalid);
+        }
+        assert!(Value::parse(" \r\n\t[] \n").is_ok());
+    }
+
+    #[test]
+    fn serializes_compactly() {
+        let value = Value::object([
+            (
+                "text",
+                Value::String("quote \" slash \\ line\n\u{1}".into()),
+            ),
+            ("list", Value::Array(vec![Value::Number(1.5), Value::Null])),
+            ("inf", Value::Number(f64::INFINITY)),
+        ]);
+        assert_eq!(
+            value.to_string(),
+            r#"{"text":"quote \" slash \\ line\n\u0001","list":[1.5,null],"inf":null}"#
+        );
+    }
+
+    #[test]
+    fn roundtrips() {
+        for text in [
+            r#"{"a":[1,-2.5,1e-7,1e300],"b":{"c":null,"d":false},"é😀":"\u0000\t"}"#,
+            r#"[[[]],{},"",0,-0]"#,
+            r#""😀 \u001f""#,
+        ] {
+            let value = parse(text);
+            assert_eq!(parse(&value.to_string()), value, "{}", text);
+        }
+    }
+
+    #[test]
+    fn fields() {
+        let value = parse(r#"{"name":"a","count":3}"#);
+        assert_eq!(value.field::<String>("name").unwrap(), "a");
+        assert_eq!(value.field::<Option<u32>>("missing").unwrap(), None);
+        let error = value.field::<String>("count").unwrap_err();
+        assert_eq!(
+            error.message,
+            "field `count`: expected a string, found a number"
+        );
+        assert!(Value::Null.field::<String>("name").is_err());
+    }
+}
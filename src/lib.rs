@@ -1,2 +1,76 @@
+pub mod access_log;
+pub mod auth;
+mod base64;
+pub mod body;
+mod buffer_pool;
+pub mod builder;
+pub mod cache_control;
+pub mod compression;
+pub mod conditional;
+pub mod cookies;
+mod deflate;
+pub mod embedded;
+mod error;
+pub mod error_pages;
+#[cfg(feature = "evented")]
+mod evented;
+pub mod extensions;
+pub mod form;
+pub mod forwarded;
+pub mod headers;
+#[cfg(feature = "http2")]
+mod hpack;
+#[cfg(feature = "http2")]
+mod http2;
+pub mod http_date;
 pub mod http_server;
-pub mod thread_pool;
\ No newline at end of file
+mod informational;
+pub mod ip_filter;
+#[cfg(feature = "json")]
+pub mod json;
+#[cfg(feature = "jwt")]
+pub mod jwt;
+pub mod lifecycle;
+mod listing;
+pub mod method_override;
+pub mod metrics;
+pub mod middleware;
+pub mod mime;
+pub mod multipart;
+pub mod negotiation;
+#[cfg(feature = "openapi")]
+pub mod openapi;
+pub mod problem;
+pub mod proxy;
+mod proxy_protocol;
+pub mod query;
+pub mod range;
+pub mod rate_limit;
+pub mod redirect;
+pub mod request;
+pub mod response;
+pub mod response_cache;
+pub mod router;
+#[cfg(feature = "jwt")]
+mod rsa;
+pub mod scope;
+#[cfg(feature = "jwt")]
+mod sha256;
+pub mod shutdown;
+mod signals;
+mod socket;
+pub mod sse;
+pub mod static_files;
+#[cfg(feature = "templates")]
+pub mod templates;
+pub mod testing;
+pub mod thread_pool;
+pub mod tunnel;
+pub mod typed_headers;
+pub mod upgrade;
+pub mod validation;
+
+pub use error::Error;
+
+#[cfg(all(feature = "evented", not(unix)))]
+compile_error!("the evented feature waits on sockets with poll, which needs a unix platform");
@@ -0,0 +1,26 @@
+use std::{
+    net::SocketAddr,
+    panic::{self, AssertUnwindSafe},
+    sync::Arc,
+};
+
+/// called when a listener starts accepting, with its url like `http://127.0.0.1:8080`
+pub type StartHook = Arc<dyn Fn(&str) + Send + Sync>;
+
+/// called when a connection is accepted and again when it's closed, with the client's address
+pub type ConnectionHook = Arc<dyn Fn(ConnectionEvent, Option<SocketAddr>) + Send + Sync>;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ConnectionEvent {
+    Opened,
+    Closed,
+}
+
+/// call every hook. A panicking one doesn't keep the others from running
+pub(crate) fn run_hooks<H: ?Sized>(hooks: &[Arc<H>], call: impl Fn(&H)) {
+    for hook in hooks {
+        if panic::catch_unwind(AssertUnwindSafe(|| call(hook))).is_err() {
+            println!("lifecycle hook panicked");
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use std::{
+    cmp::Ordering,
+    fmt::Write,
+    fs, io,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    http_date::format_http_date,
+    http_server::{HTTPRequest, HTTPResponse},
+    negotiation::negotiate,
+    redirect::escape_html,
+    response::json_string,
+};
+
+struct Entry {
+    name: String,
+    directory: bool,
+    size: u64,
+    modified: Option<SystemTime>,
+}
+
+/// a page listing the directory at `path`, as JSON if the client prefers it. `?sort=` by
+/// `name`, `size` or `modified` and `?order=desc` reorder it, directories always come first.
+/// Below the `root` of the mount it links to the parent directory
+pub(crate) fn list_directory(
+    request: &HTTPRequest,
+    path: &Path,
+    root: bool,
+    show_hidden: bool,
+) -> io::Result<HTTPResponse> {
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with('.') && !show_hidden {
+            continue;
+        }
+        // follows symlinks, so they're listed as what they point to
+        let Ok(metadata) = fs::metadata(entry.path()) else {
+            continue;
+        };
+        entries.push(Entry {
+            name,
+            directory: metadata.is_dir(),
+            size: metadata.len(),
+            modified: metadata.modified().ok(),
+        });
+    }
+
+    let order = |a: &Entry, b: &Entry| match request.query_params.get("sort") {
+        Some("size") => a.size.cmp(&b.size),
+        Some("modified") => a.modified.cmp(&b.modified),
+        _ => Ordering::Equal,
+    };
+    let descending = request.query_params.get("order") == Some("desc");
+    entries.sort_by(|a, b| {
+        let ordering = order(a, b).then_with(|| a.name.cmp(&b.name));
+        let ordering = if descending {
+            ordering.reverse()
+        } else {
+            ordering
+        };
+        b.directory.cmp(&a.directory).then(ordering)
+    });
+
+    let base = request.path.trim_end_matches('/');
+    Ok(match prefers_json(request) {
+        true => HTTPResponse::builder().json(json_listing(&entries)),
+        false => HTTPResponse::builder().html(html_listing(base, root, &entries)),
+    })
+}
+
+fn prefers_json(request: &HTTPRequest) -> bool {
+    if let Some(format) = request.query_params.get("format") {
+        return format == "json";
+    }
+    negotiate(request, &["text/html", "application/json"]) == Some("application/json")
+}
+
+fn html_listing(base: &str, root: bool, entries: &[Entry]) -> String {
+    let title = escape_html(&format!("Index of {}/", base));
+    let mut page = format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{title}</title></head>\n\
+         <body><h1>{title}</h1>\n<table>\n\
+         <tr><th><a href=\"?sort=name\">Name</a></th><th><a href=\"?sort=size\">Size</a></th>\
+         <th><a href=\"?sort=modified\">Modified</a></th></tr>\n"
+    );
+    if !root {
+        let parent = base.rsplit_once('/').map_or("", |(parent, _)| parent);
+        let _ = writeln!(
+            page,
+            "<tr><td><a href=\"{}/\">../</a></td><td></td><td></td></tr>",
+            escape_html(parent)
+        );
+    }
+    for entry in entries {
+        let slash = if entry.directory { "/" } else { "" };
+        let href = escape_html(&format!(
+            "{}/{}{}",
+            base,
+            percent_encode(&entry.name),
+            slash
+        ));
+        let size = match entry.directory {
+            true => String::from("-"),
+            false => entry.size.to_string(),
+        };
+        let modified = entry.modified.map(format_http_date).unwrap_or_default();
+        let _ = writeln!(
+            page,
+            "<tr><td><a href=\"{}\">{}{}</a></td><td>{}</td><td>{}</td></tr>",
+            href,
+            escape_html(&entry.name),
+            slash,
+            size,
+            modified
+        );
+    }
+    page.push_str("</table></body></html>\n");
+    page
+}
+
+/// `[{"name": ..., "type": "file" or "directory", "size": ..., "modified": seconds}]`
+fn json_listing(entries: &[Entry]) -> String {
+    let entries: Vec<String> = entries
+        .iter()
+        .map(|entry| {
+            let modified = entry
+                .modified
+                .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                .map_or(String::from("null"), |since| since.as_secs().to_string());
+            format!(
+                "{{\"name\":{},\"type\":\"{}\",\"size\":{},\"modified\":{}}}",
+                json_string(&entry.name),
+                if entry.directory { "directory" } else { "file" },
+                entry.size,
+                modified
+            )
+        })
+        .collect();
+    format!("[{}]", entries.join(","))
+}
+
+/// `name` as a path segment, escaping everything but unreserved characters
+fn percent_encode(name: &str) -> String {
+    let mut encoded = String::new();
+    for byte in name.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(byte as char)
+            }
+            byte => {
+                let _ = write!(encoded, "%{:02X}", byte);
+            }
+        }
+    }
+    encoded
+}
@@ -0,0 +1,45 @@
+use crate::{
+    http_server::{HTTPMethod, HTTPRequest, HTTPResponse},
+    middleware::Next,
+};
+
+/// the method a request was sent with, in its extensions once `method_override` rewrote it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OriginalMethod(pub HTTPMethod);
+
+/// middleware routing POST requests as the method named by their `X-HTTP-Method-Override`
+/// header or, for form bodies, their `_method` field, like `<input name="_method"
+/// value="DELETE">` in a HTML form. Only PUT, PATCH and DELETE can be asked for, other
+/// names are ignored. Add it with `HTTPServerBuilder::middleware` to enable overrides
+pub fn method_override<T>(
+    request: &mut HTTPRequest,
+    passthrough: &T,
+    next: Next<T>,
+) -> HTTPResponse {
+    if request.method == HTTPMethod::POST {
+        if let Some(method) = requested_method(request) {
+            let original = std::mem::replace(&mut request.method, method);
+            request.extensions.insert(OriginalMethod(original));
+        }
+    }
+    next.run(request, passthrough)
+}
+
+fn requested_method(request: &HTTPRequest) -> Option<HTTPMethod> {
+    let name = match request.header("X-HTTP-Method-Override") {
+        Some(name) => String::from(name),
+        None if is_form(request) => request.form().remove("_method")?,
+        None => return None,
+    };
+    match HTTPMethod::from(name.trim().to_ascii_uppercase().as_str()) {
+        method @ (HTTPMethod::PUT | HTTPMethod::PATCH | HTTPMethod::DELETE) => Some(method),
+        _ => None,
+    }
+}
+
+fn is_form(request: &HTTPRequest) -> bool {
+    request
+        .headers
+        .content_type()
+        .is_some_and(|content_type| content_type.media_type == "application/x-www-form-urlencoded")
+}
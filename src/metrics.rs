@@ -0,0 +1,164 @@
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+/// upper bounds of the latency histogram buckets, in seconds
+pub const LATENCY_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// the label of requests that matched no route
+pub const UNMATCHED_ROUTE: &str = "<unmatched>";
+
+/// request counts and latencies of a server, per route pattern. Shared by all workers
+#[derive(Default)]
+pub struct Metrics {
+    routes: Mutex<HashMap<String, RouteMetrics>>,
+    panics: AtomicU64,
+}
+
+/// the numbers of a single route
+#[derive(Clone, Default, Debug)]
+pub struct RouteMetrics {
+    pub requests: u64,
+    /// requests per status class, `[1xx, 2xx, 3xx, 4xx, 5xx]`
+    pub status_classes: [u64; 5],
+    /// requests per bucket of `LATENCY_BUCKETS`, the last one counting everything slower
+    pub latency_buckets: [u64; LATENCY_BUCKETS.len() + 1],
+    /// the time spent on all requests together
+    pub latency_sum: Duration,
+}
+
+/// the metrics at one point in time, routes sorted by pattern
+#[derive(Clone, Default, Debug)]
+pub struct MetricsSnapshot {
+    pub routes: Vec<(String, RouteMetrics)>,
+    /// panics caught in listeners, middleware and workers
+    pub panics: u64,
+}
+
+impl Metrics {
+    pub fn new() -> Metrics {
+        Metrics::default()
+    }
+
+    /// count a request to `route`, or to `UNMATCHED_ROUTE` for `None`
+    pub fn record(&self, route: Option<&str>, status: u16, duration: Duration) {
+        let mut routes = self.routes.lock().unwrap();
+        let route = route.unwrap_or(UNMATCHED_ROUTE);
+        let metrics = match routes.get_mut(route) {
+            Some(metrics) => metrics,
+            None => routes.entry(String::from(route)).or_default(),
+        };
+
+        metrics.requests += 1;
+        if let Some(class) = (status / 100).checked_sub(1) {
+            if let Some(count) = metrics.status_classes.get_mut(class as usize) {
+                *count += 1;
+            }
+        }
+        let seconds = duration.as_secs_f64();
+        let bucket = LATENCY_BUCKETS
+            .iter()
+            .position(|bound| seconds <= *bound)
+            .unwrap_or(LATENCY_BUCKETS.len());
+        metrics.latency_buckets[bucket] += 1;
+        metrics.latency_sum += duration;
+    }
+
+    /// count a panic the server recovered from
+    pub fn record_panic(&self) {
+        self.panics.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        let mut routes: Vec<(String, RouteMetrics)> = self
+            .routes
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(route, metrics)| (route.clone(), metrics.clone()))
+            .collect();
+        routes.sort_by(|(a, _), (b, _)| a.cmp(b));
+        MetricsSnapshot {
+            routes,
+            panics: self.panics.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl MetricsSnapshot {
+    /// the metrics in the Prometheus text exposition format
+    pub fn to_prometheus(&self) -> String {
+        let mut out = String::new();
+
+        out.push_str(
+            "# HELP adhesion_requests_total Requests answered, by route and status class\n",
+        );
+        out.push_str("# TYPE adhesion_requests_total counter\n");
+        for (route, metrics) in &self.routes {
+            for (class, count) in metrics.status_classes.iter().enumerate() {
+                if *count > 0 {
+                    let _ = writeln!(
+                        out,
+                        "adhesion_requests_total{{route=\"{}\",status=\"{}xx\"}} {}",
+                        escape_label(route),
+                        class + 1,
+                        count
+                    );
+                }
+            }
+        }
+
+        out.push_str("# HELP adhesion_request_duration_seconds Time from reading a request to sending its response\n");
+        out.push_str("# TYPE adhesion_request_duration_seconds histogram\n");
+        for (route, metrics) in &self.routes {
+            let route = escape_label(route);
+            let mut cumulative = 0;
+            for (bound, count) in LATENCY_BUCKETS.iter().zip(&metrics.latency_buckets) {
+                cumulative += count;
+                let _ = writeln!(
+                    out,
+                    "adhesion_request_duration_seconds_bucket{{route=\"{}\",le=\"{}\"}} {}",
+                    route, bound, cumulative
+                );
+            }
+            let _ = writeln!(
+                out,
+                "adhesion_request_duration_seconds_bucket{{route=\"{}\",le=\"+Inf\"}} {}",
+                route, metrics.requests
+            );
+            let _ = writeln!(
+                out,
+                "adhesion_request_duration_seconds_sum{{route=\"{}\"}} {}",
+                route,
+                metrics.latency_sum.as_secs_f64()
+            );
+            let _ = writeln!(
+                out,
+                "adhesion_request_duration_seconds_count{{route=\"{}\"}} {}",
+                route, metrics.requests
+            );
+        }
+
+        out.push_str(
+            "# HELP adhesion_panics_total Panics caught in listeners, middleware and workers\n",
+        );
+        out.push_str("# TYPE adhesion_panics_total counter\n");
+        let _ = writeln!(out, "adhesion_panics_total {}", self.panics);
+        out
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
@@ -0,0 +1,38 @@
+use std::sync::Arc;
+
+use crate::http_server::{HTTPRequest, HTTPResponse};
+
+/// cross-cutting logic wrapped around every request. A middleware may inspect or modify the
+/// request, call `next.run` to continue down the chain and alter the returned response, or
+/// answer on its own without calling `next` at all. Closures fit too, to carry configuration
+/// or state
+pub type Middleware<T> = Arc<dyn Fn(&mut HTTPRequest, &T, Next<T>) -> HTTPResponse + Send + Sync>;
+
+/// the rest of the middleware chain, ending in the router
+pub struct Next<'a, T> {
+    chain: &'a [Middleware<T>],
+    endpoint: &'a dyn Fn(&mut HTTPRequest, &T) -> HTTPResponse,
+}
+
+impl<'a, T> Next<'a, T> {
+    pub(crate) fn new(
+        chain: &'a [Middleware<T>],
+        endpoint: &'a dyn Fn(&mut HTTPRequest, &T) -> HTTPResponse,
+    ) -> Next<'a, T> {
+        Next { chain, endpoint }
+    }
+
+    pub fn run(self, request: &mut HTTPRequest, passthrough: &T) -> HTTPResponse {
+        match self.chain.split_first() {
+            Some((middleware, chain)) => middleware(
+                request,
+                passthrough,
+                Next {
+                    chain,
+                    endpoint: self.endpoint,
+                },
+            ),
+            None => (self.endpoint)(request, passthrough),
+        }
+    }
+}
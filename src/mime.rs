@@ -0,0 +1,88 @@
+use std::{collections::HashMap, path::Path, sync::RwLock};
+
+/// the Content-Type of files with an unknown extension
+pub const DEFAULT_TYPE: &str = "application/octet-stream";
+
+/// built in types by lowercase extension
+const TYPES: [(&str, &str); 40] = [
+    ("html", "text/html; charset=utf-8"),
+    ("htm", "text/html; charset=utf-8"),
+    ("css", "text/css; charset=utf-8"),
+    ("js", "text/javascript; charset=utf-8"),
+    ("mjs", "text/javascript; charset=utf-8"),
+    ("json", "application/json"),
+    ("map", "application/json"),
+    ("txt", "text/plain; charset=utf-8"),
+    ("csv", "text/csv; charset=utf-8"),
+    ("md", "text/markdown; charset=utf-8"),
+    ("xml", "application/xml"),
+    ("png", "image/png"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("gif", "image/gif"),
+    ("webp", "image/webp"),
+    ("avif", "image/avif"),
+    ("svg", "image/svg+xml"),
+    ("ico", "image/x-icon"),
+    ("bmp", "image/bmp"),
+    ("woff", "font/woff"),
+    ("woff2", "font/woff2"),
+    ("ttf", "font/ttf"),
+    ("otf", "font/otf"),
+    ("wasm", "application/wasm"),
+    ("pdf", "application/pdf"),
+    ("zip", "application/zip"),
+    ("gz", "application/gzip"),
+    ("tar", "application/x-tar"),
+    ("mp3", "audio/mpeg"),
+    ("ogg", "audio/ogg"),
+    ("wav", "audio/wav"),
+    ("flac", "audio/flac"),
+    ("mp4", "video/mp4"),
+    ("webm", "video/webm"),
+    ("ogv", "video/ogg"),
+    ("webmanifest", "application/manifest+json"),
+    ("yaml", "application/yaml"),
+    ("yml", "application/yaml"),
+    ("toml", "application/toml"),
+];
+
+/// types added with `register`, taking precedence over the built in ones
+static CUSTOM: RwLock<Option<HashMap<String, String>>> = RwLock::new(None);
+
+/// map `extension` to `content_type` for the whole process, e.g. `register("ts", "text/x-typescript")`.
+/// Replaces a built in or earlier registered type
+pub fn register(extension: &str, content_type: &str) {
+    CUSTOM
+        .write()
+        .unwrap()
+        .get_or_insert_with(HashMap::new)
+        .insert(normalize(extension), String::from(content_type));
+}
+
+/// the Content-Type for files ending in `extension`, without the dot
+pub fn from_extension(extension: &str) -> Option<String> {
+    let extension = normalize(extension);
+    if let Some(custom) = CUSTOM.read().unwrap().as_ref() {
+        if let Some(content_type) = custom.get(&extension) {
+            return Some(content_type.clone());
+        }
+    }
+    TYPES
+        .iter()
+        .find(|(known, _)| *known == extension)
+        .map(|(_, content_type)| String::from(*content_type))
+}
+
+/// the Content-Type for the file at `path`, `DEFAULT_TYPE` if its extension is unknown
+pub fn from_path(path: impl AsRef<Path>) -> String {
+    path.as_ref()
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .and_then(from_extension)
+        .unwrap_or_else(|| String::from(DEFAULT_TYPE))
+}
+
+fn normalize(extension: &str) -> String {
+    extension.trim_start_matches('.').to_ascii_lowercase()
+}
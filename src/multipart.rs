@@ -0,0 +1,504 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    fs::{self, File},
+    io::{self, BufWriter, Read, Write},
+    path::PathBuf,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::{body::BodyError, http_server::HTTPRequest};
+
+/// how a `multipart/form-data` body is taken apart
+pub struct MultipartConfig {
+    /// file parts are streamed into this directory instead of being kept in memory
+    pub upload_dir: Option<PathBuf>,
+    /// largest part kept in memory
+    pub max_memory_part_size: usize,
+    pub max_parts: usize,
+}
+
+impl Default for MultipartConfig {
+    fn default() -> MultipartConfig {
+        MultipartConfig {
+            upload_dir: None,
+            max_memory_part_size: 16 * 1024 * 1024,
+            max_parts: 128,
+        }
+    }
+}
+
+/// a single field or file of a form
+pub struct Part {
+    /// the `name` of the form field
+    pub name: String,
+    /// the client side name of an uploaded file
+    pub filename: Option<String>,
+    pub content_type: Option<String>,
+    /// all headers of the part, names in lowercase
+    pub headers: HashMap<String, String>,
+    pub data: PartData,
+}
+
+pub enum PartData {
+    Memory(Vec<u8>),
+    /// a file in `MultipartConfig::upload_dir`. Moving or deleting it is up to the listener
+    File {
+        path: PathBuf,
+        size: u64,
+    },
+}
+
+impl Part {
+    pub fn is_file(&self) -> bool {
+        self.filename.is_some()
+    }
+
+    /// the content of an in memory part, if it is valid utf8
+    pub fn text(&self) -> Option<&str> {
+        match self.data {
+            PartData::Memory(ref data) => std::str::from_utf8(data).ok(),
+            PartData::File { .. } => None,
+        }
+    }
+}
+
+/// the parts of a form, in the order they were sent
+pub struct Multipart {
+    pub parts: Vec<Part>,
+}
+
+impl Multipart {
+    /// text of the first field called `name`
+    pub fn field(&self, name: &str) -> Option<&str> {
+        self.parts
+            .iter()
+            .filter(|part| !part.is_file())
+            .find(|part| part.name == name)
+            .and_then(Part::text)
+    }
+
+    /// first uploaded file of the field `name`
+    pub fn file(&self, name: &str) -> Option<&Part> {
+        self.parts
+            .iter()
+            .find(|part| part.is_file() && part.name == name)
+    }
+}
+
+#[derive(Debug)]
+pub enum MultipartError {
+    /// the request isn't `multipart/form-data` or lacks a boundary
+    NotMultipart,
+    /// the body is malformed or ends early
+    Invalid,
+    /// a part exceeds `max_memory_part_size` or there are more than `max_parts`
+    TooLarge,
+    /// the body couldn't be read from the connection
+    Body(BodyError),
+    Io(io::Error),
+}
+
+impl fmt::Display for MultipartError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MultipartError::NotMultipart => write!(f, "not a multipart/form-data request"),
+            MultipartError::Invalid => write!(f, "malformed multipart body"),
+            MultipartError::TooLarge => write!(f, "multipart body exceeds the configured limits"),
+            MultipartError::Body(error) => write!(f, "{}", error),
+            MultipartError::Io(error) => write!(f, "io error: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for MultipartError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            MultipartError::Body(error) => Some(error),
+            MultipartError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for MultipartError {
+    fn from(error: io::Error) -> MultipartError {
+        MultipartError::Io(error)
+    }
+}
+
+impl HTTPRequest {
+    /// parse a `multipart/form-data` body, streaming it from the connection so file parts
+    /// in `upload_dir` are never held in memory
+    pub fn multipart(&self, config: &MultipartConfig) -> Result<Multipart, MultipartError> {
+        let boundary = self
+            .header("Content-Type")
+            .and_then(boundary)
+            .ok_or(MultipartError::NotMultipart)?;
+        let mut body = self.body.reader().map_err(MultipartError::Body)?;
+        let multipart =
+            read_multipart(&mut body, &boundary, config).map_err(|error| match error {
+                MultipartError::Io(error) => match error.get_ref().and_then(|e| e.downcast_ref()) {
+                    Some(error) => MultipartError::Body(*error),
+                    None => MultipartError::Io(error),
+                },
+                error => error,
+            })?;
+        // skip the epilogue so the connection can take another request. If it's malformed,
+        // the connection is closed but the form still counts
+        let _ = io::copy(&mut body, &mut io::sink());
+        Ok(multipart)
+    }
+}
+
+/// the boundary of a `multipart/form-data` Content-Type
+pub fn boundary(content_type: &str) -> Option<String> {
+    let mut params = content_type.split(';');
+    if !params
+        .next()?
+        .trim()
+        .eq_ignore_ascii_case("multipart/form-data")
+    {
+        return None;
+    }
+    header_params(params)
+        .into_iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("boundary"))
+        .map(|(_, value)| value)
+        .filter(|boundary| !boundary.is_empty())
+}
+
+/// parse a multipart body from any source, so large uploads don't have to be buffered.
+/// Parts are written out to `upload_dir` while they're being read
+pub fn read_multipart(
+    reader: impl Read,
+    boundary: &str,
+    config: &MultipartConfig,
+) -> Result<Multipart, MultipartError> {
+    let mut parser = Parser {
+        reader,
+        // the delimiter is preceded by a line break, which the first one may lack
+        buffer: b"\r\n".to_vec(),
+    };
+    let delimiter = [b"\r\n--", boundary.as_bytes()].concat();
+    let mut parts = Vec::new();
+
+    // skip the preamble
+    if parser
+        .copy_until(&delimiter, &mut io::sink(), u64::MAX)?
+        .is_none()
+    {
+        return Err(MultipartError::Invalid);
+    }
+
+    loop {
+        // the rest of the delimiter line tells if this was the last part, which may end the
+        // body without a line break
+        let mut line = Vec::new();
+        let ended = parser.copy_until(b"\r\n", &mut line, 1024)?.is_none();
+        if ended {
+            line.append(&mut parser.buffer);
+        }
+        if line.starts_with(b"--") {
+            break;
+        }
+        if ended || !line.iter().all(|b| *b == b' ' || *b == b'\t') {
+            return Err(MultipartError::Invalid);
+        }
+        if parts.len() == config.max_parts {
+            remove_uploads(&parts);
+            return Err(MultipartError::TooLarge);
+        }
+
+        match read_part(&mut parser, &delimiter, config) {
+            Ok(part) => parts.push(part),
+            Err(error) => {
+                remove_uploads(&parts);
+                return Err(error);
+            }
+        }
+    }
+    Ok(Multipart { parts })
+}
+
+fn read_part<R: Read>(
+    parser: &mut Parser<R>,
+    delimiter: &[u8],
+    config: &MultipartConfig,
+) -> Result<Part, MultipartError> {
+    let mut headers = HashMap::new();
+    loop {
+        let mut line = Vec::new();
+        parser
+            .copy_until(b"\r\n", &mut line, 8 * 1024)?
+            .ok_or(MultipartError::Invalid)?;
+        if line.is_empty() {
+            break;
+        }
+        let line = String::from_utf8(line).map_err(|_| MultipartError::Invalid)?;
+        let (name, value) = line.split_once(':').ok_or(MultipartError::Invalid)?;
+        headers.insert(name.trim().to_ascii_lowercase(), String::from(value.trim()));
+    }
+
+    let disposition = headers
+        .get("content-disposition")
+        .ok_or(MultipartError::Invalid)?;
+    let mut params = disposition.split(';');
+    if !params
+        .next()
+        .is_some_and(|kind| kind.trim().eq_ignore_ascii_case("form-data"))
+    {
+        return Err(MultipartError::Invalid);
+    }
+    let params = header_params(params);
+    let param = |wanted: &str| {
+        params
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(wanted))
+            .map(|(_, value)| value.clone())
+    };
+    let name = param("name").ok_or(MultipartError::Invalid)?;
+    let filename = param("filename");
+    let content_type = headers.get("content-type").cloned();
+
+    let data = match (&filename, &config.upload_dir) {
+        (Some(_), Some(dir)) => {
+            let path = dir.join(upload_name());
+            let mut file = BufWriter::new(File::create(&path)?);
+            let copied = parser
+                .copy_until(delimiter, &mut file, u64::MAX)
+                .and_then(|size| Ok(file.flush().map(|_| size)?));
+            match copied {
+                Ok(Some(size)) => PartData::File { path, size },
+                failed => {
+                    drop(file);
+                    let _ = fs::remove_file(&path);
+                    failed?;
+                    return Err(MultipartError::Invalid);
+                }
+            }
+        }
+        _ => {
+            let mut data = Vec::new();
+            parser
+                .copy_until(delimiter, &mut data, config.max_memory_part_size as u64)?
+                .ok_or(MultipartError::Invalid)?;
+            PartData::Memory(data)
+        }
+    };
+
+    Ok(Part {
+        name,
+        filename,
+        content_type,
+        headers,
+        data,
+    })
+}
+
+/// unique name for an upload in `upload_dir`
+fn upload_name() -> String {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    format!(
+        "adhesion-upload-{}-{}",
+        std::process::id(),
+        NEXT.fetch_add(1, Ordering::SeqCst)
+    )
+}
+
+fn remove_uploads(parts: &[Part]) {
+    for part in parts {
+        if let PartData::File { ref path, .. } = part.data {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// the `name=value` parameters following a header value, with quotes removed
+pub(crate) fn header_params<'a>(params: impl Iterator<Item = &'a str>) -> Vec<(String, String)> {
+    params
+        .filter_map(|param| param.split_once('='))
+        .map(|(name, value)| {
+            let value = value.trim();
+            let value = match value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) {
+                Some(quoted) => quoted.replace("\\\"", "\"").replace("\\\\", "\\"),
+                None => String::from(value),
+            };
+            (String::from(name.trim()), value)
+        })
+        .collect()
+}
+
+/// reads ahead of the source to find delimiters
+struct Parser<R: Read> {
+    reader: R,
+    buffer: Vec<u8>,
+}
+
+impl<R: Read> Parser<R> {
+    /// copy everything up to `delimiter` into `out` and skip the delimiter. Returns the amount
+    /// copied, or `None` if the source ended first. Fails with `TooLarge` past `limit` bytes
+    fn copy_until(
+        &mut self,
+        delimiter: &[u8],
+        out: &mut impl Write,
+        limit: u64,
+    ) -> Result<Option<u64>, MultipartError> {
+        let mut copied = 0;
+        loop {
+            if let Some(i) = find(&self.buffer, delimiter) {
+                copied += i as u64;
+                if copied > limit {
+                    return Err(MultipartError::TooLarge);
+                }
+                out.write_all(&self.buffer[..i])?;
+                self.buffer.drain(..i + delimiter.len());
+                return Ok(Some(copied));
+            }
+
+            // the end of the buffer could be the start of the delimiter
+            let safe = self.buffer.len().saturating_sub(delimiter.len() - 1);
+            copied += safe as u64;
+            if copied > limit {
+                return Err(MultipartError::TooLarge);
+            }
+            out.write_all(&self.buffer[..safe])?;
+            self.buffer.drain(..safe);
+
+            let mut chunk = [0; 8192];
+            let read = self.reader.read(&mut chunk)?;
+            if read == 0 {
+                return Ok(None);
+            }
+            self.buffer.extend_from_slice(&chunk[..read]);
+        }
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// hands out at most `step` bytes per read, so delimiters are split across reads
+    struct Trickle<'a> {
+        data: &'a [u8],
+        step: usize,
+    }
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+            let read = self.step.min(buffer.len()).min(self.data.len());
+            buffer[..read].copy_from_slice(&self.data[..read]);
+            self.data = &self.data[read..];
+            Ok(read)
+        }
+    }
+
+    const FORM: &[u8] = b"this is the preamble\r\n\
+        --XyZ\r\n\
+        Content-Disposition: form-data; name=\"title\"\r\n\
+        \r\n\
+        hello --XY world\r\n\
+        --XyZ\r\n\
+        Content-Disposition: form-data; name=\"upload\"; filename=\"a.txt\"\r\n\
+        Content-Type: text/plain\r\n\
+        \r\n\
+        line one\r\nline two\r\n\
+        --XyZ--\r\n\
+        this is the epilogue --XyZ\r\n";
+
+    #[test]
+    fn delimiters_split_across_reads() {
+        for step in 1..=7 {
+            let reader = Trickle { data: FORM, step };
+            let form = read_multipart(reader, "XyZ", &MultipartConfig::default()).unwrap();
+            assert_eq!(form.parts.len(), 2);
+            assert_eq!(form.field("title"), Some("hello --XY world"));
+            let upload = form.file("upload").unwrap();
+            assert_eq!(upload.filename.as_deref(), Some("a.txt"));
+            assert_eq!(upload.content_type.as_deref(), Some("text/plain"));
+            assert_eq!(upload.text(), Some("line one\r\nline two"));
+        }
+    }
+
+    #[test]
+    fn preamble_and_epilogue_are_skipped() {
+        let body = b"--b\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\n1\r\n--b--";
+        let form = read_multipart(&body[..], "b", &MultipartConfig::default()).unwrap();
+        assert_eq!(form.field("a"), Some("1"));
+
+        let form = read_multipart(FORM, "XyZ", &MultipartConfig::default()).unwrap();
+        assert!(form.parts.iter().all(|part| !part.name.contains("logue")));
+        assert_eq!(form.parts.len(), 2);
+    }
+
+    #[test]
+    fn missing_final_boundary() {
+        let config = MultipartConfig::default();
+        let body = b"--b\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\n1\r\n";
+        assert!(matches!(
+            read_multipart(&body[..], "b", &config),
+            Err(MultipartError::Invalid)
+        ));
+
+        let body = b"--b\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\n1\r\n--b\r\n";
+        assert!(matches!(
+            read_multipart(&body[..], "b", &config),
+            Err(MultipartError::Invalid)
+        ));
+
+        assert!(matches!(
+            read_multipart(&b"no delimiter at all"[..], "b", &config),
+            Err(MultipartError::Invalid)
+        ));
+    }
+
+    #[test]
+    fn file_parts_are_written_to_upload_dir() {
+        let dir = std::env::temp_dir().join(format!("adhesion-multipart-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let config = MultipartConfig {
+            upload_dir: Some(dir.clone()),
+            ..MultipartConfig::default()
+        };
+        let form = read_multipart(
+            Trickle {
+                data: FORM,
+                step: 3,
+            },
+            "XyZ",
+            &config,
+        )
+        .unwrap();
+        // fields without a filename stay in memory
+        assert_eq!(form.field("title"), Some("hello --XY world"));
+        let upload = form.file("upload").unwrap();
+        let PartData::File { ref path, size } = upload.data else {
+            panic!("the file part was kept in memory");
+        };
+        assert!(path.starts_with(&dir));
+        assert_eq!(size, 18);
+        assert_eq!(fs::read(path).unwrap(), b"line one\r\nline two");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn failed_forms_remove_their_uploads() {
+        let dir = std::env::temp_dir().join(format!("adhesion-failed-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let config = MultipartConfig {
+            upload_dir: Some(dir.clone()),
+            ..MultipartConfig::default()
+        };
+        let truncated = &FORM[..FORM.len() - 40];
+        assert!(read_multipart(truncated, "XyZ", &config).is_err());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
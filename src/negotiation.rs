@@ -0,0 +1,262 @@
+use std::cmp::Ordering;
+
+use crate::http_server::{HTTPRequest, HTTPResponse};
+
+/// an entry of an `Accept` style header, like `text/html;q=0.9`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Preference {
+    /// the media range, encoding or language tag, without parameters
+    pub value: String,
+    /// between 0 and 1, where 0 means not acceptable
+    pub quality: f32,
+}
+
+/// the entries of an `Accept`, `Accept-Encoding` or `Accept-Language` value, most preferred
+/// first. Entries of equal quality keep their order
+pub fn parse_preferences(header: &str) -> Vec<Preference> {
+    let mut preferences: Vec<Preference> = header
+        .split(',')
+        .filter_map(|entry| {
+            let mut params = entry.split(';');
+            let value = params.next().unwrap_or("").trim();
+            if value.is_empty() {
+                return None;
+            }
+            let quality = params
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .find_map(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0)
+                .clamp(0.0, 1.0);
+            Some(Preference {
+                value: value.to_ascii_lowercase(),
+                quality,
+            })
+        })
+        .collect();
+    preferences.sort_by(|a, b| b.quality.partial_cmp(&a.quality).unwrap_or(Ordering::Equal));
+    preferences
+}
+
+/// the media types accepted by the client, empty without an `Accept` header
+pub fn accepted_types(request: &HTTPRequest) -> Vec<Preference> {
+    request
+        .header("Accept")
+        .map(parse_preferences)
+        .unwrap_or_default()
+}
+
+/// the content codings accepted by the client, empty without an `Accept-Encoding` header
+pub fn accepted_encodings(request: &HTTPRequest) -> Vec<Preference> {
+    request
+        .header("Accept-Encoding")
+        .map(parse_preferences)
+        .unwrap_or_default()
+}
+
+/// the languages preferred by the client, empty without an `Accept-Language` header
+pub fn accepted_languages(request: &HTTPRequest) -> Vec<Preference> {
+    request
+        .header("Accept-Language")
+        .map(parse_preferences)
+        .unwrap_or_default()
+}
+
+/// the best of the `offered` media types for the client's `Accept`, honouring wildcards like
+/// `text/*`. Ties go to the one offered first, as does a request without `Accept`. Responses
+/// picked this way should carry `Vary: Accept`
+pub fn negotiate<'a>(request: &HTTPRequest, offered: &[&'a str]) -> Option<&'a str> {
+    best(request.header("Accept"), offered, media_range_matches)
+}
+
+/// `negotiate`, or 406 Not Acceptable listing the `offered` types if none is acceptable
+pub fn negotiate_or_406<'a>(
+    request: &HTTPRequest,
+    offered: &[&'a str],
+) -> Result<&'a str, HTTPResponse> {
+    negotiate(request, offered).ok_or_else(|| {
+        HTTPResponse::builder()
+            .status(406)
+            .text(format!("Not Acceptable, available: {}", offered.join(", ")))
+    })
+}
+
+/// the best of the `offered` content codings for the client's `Accept-Encoding`
+pub fn negotiate_encoding<'a>(request: &HTTPRequest, offered: &[&'a str]) -> Option<&'a str> {
+    best(request.header("Accept-Encoding"), offered, coding_matches)
+}
+
+/// the best of the `offered` languages for the client's `Accept-Language`, where `en` also
+/// stands for `en-US`
+pub fn negotiate_language<'a>(request: &HTTPRequest, offered: &[&'a str]) -> Option<&'a str> {
+    best(
+        request.header("Accept-Language"),
+        offered,
+        |range, language| {
+            let language = language.to_ascii_lowercase();
+            let matches = range == "*"
+                || language == range
+                || language
+                    .strip_prefix(range)
+                    .is_some_and(|rest| rest.starts_with('-'));
+            matches.then_some(range.len())
+        },
+    )
+}
+
+/// the quality the client gives `value`, taken from the most specific entry matching it
+pub(crate) fn quality_of(header: &str, value: &str, matches: Matcher) -> f32 {
+    parse_preferences(header)
+        .iter()
+        .filter_map(|preference| {
+            matches(&preference.value, value).map(|specificity| (specificity, preference.quality))
+        })
+        .max_by_key(|(specificity, _)| *specificity)
+        .map_or(0.0, |(_, quality)| quality)
+}
+
+/// how specific an entry is if it covers a value, so `text/html` overrules `text/*`
+pub(crate) type Matcher = fn(&str, &str) -> Option<usize>;
+
+fn best<'a>(header: Option<&str>, offered: &[&'a str], matches: Matcher) -> Option<&'a str> {
+    let Some(header) = header.filter(|header| !header.trim().is_empty()) else {
+        return offered.first().copied();
+    };
+    let mut best: Option<(&str, f32)> = None;
+    for value in offered {
+        let quality = quality_of(header, value, matches);
+        if quality > 0.0 && best.is_none_or(|(_, best)| quality > best) {
+            best = Some((value, quality));
+        }
+    }
+    best.map(|(value, _)| value)
+}
+
+/// content codings only match themselves or `*`
+pub(crate) fn coding_matches(range: &str, encoding: &str) -> Option<usize> {
+    match range {
+        "*" => Some(0),
+        range => range.eq_ignore_ascii_case(encoding).then_some(1),
+    }
+}
+
+fn media_range_matches(range: &str, media_type: &str) -> Option<usize> {
+    let media_type = media_type.split(';').next().unwrap_or("").trim();
+    let (kind, _) = media_type.split_once('/')?;
+    if range == "*/*" {
+        Some(0)
+    } else if range
+        .strip_suffix("/*")
+        .is_some_and(|range| range.eq_ignore_ascii_case(kind))
+    {
+        Some(1)
+    } else {
+        range.eq_ignore_ascii_case(media_type).then_some(2)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(header: &str) -> Vec<(String, f32)> {
+        parse_preferences(header)
+            .into_iter()
+            .map(|preference| (preference.value, preference.quality))
+            .collect()
+    }
+
+    fn accepting(name: &str, value: &str) -> HTTPRequest {
+        HTTPRequest::builder().header(name, value).build()
+    }
+
+    #[test]
+    fn orders_by_quality() {
+        assert_eq!(
+            values("text/plain;q=0.5, text/html, application/json;q=0.9, */*;q=0.1"),
+            [
+                ("text/html".into(), 1.0),
+                ("application/json".into(), 0.9),
+                ("text/plain".into(), 0.5),
+                ("*/*".into(), 0.1),
+            ]
+        );
+    }
+
+    #[test]
+    fn equal_qualities_keep_their_order() {
+        assert_eq!(
+            values("b;q=0.5, a, c;q=0.5, d"),
+            [
+                ("a".into(), 1.0),
+                ("d".into(), 1.0),
+                ("b".into(), 0.5),
+                ("c".into(), 0.5),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_quality_values() {
+        // out of range qualities are clamped, unparsable ones ignored
+        assert_eq!(
+            values("A;level=1;q=0.3, b; q=0.2, c;q=2, d;q=-1, e;q=x, , f;"),
+            [
+                ("c".into(), 1.0),
+                ("e".into(), 1.0),
+                ("f".into(), 1.0),
+                ("a".into(), 0.3),
+                ("b".into(), 0.2),
+                ("d".into(), 0.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn negotiates_media_types() {
+        let offered = ["application/json", "text/html", "text/plain"];
+        let request = accepting("Accept", "text/*;q=0.8, application/json;q=0.5");
+        assert_eq!(negotiate(&request, &offered), Some("text/html"));
+        // the most specific range decides, so text/plain is excluded
+        let request = accepting("Accept", "text/*, text/plain;q=0, application/json;q=0.1");
+        assert_eq!(
+            negotiate(&request, &["text/plain", "application/json"]),
+            Some("application/json")
+        );
+        // ties go to the first offered
+        let request = accepting("Accept", "*/*");
+        assert_eq!(negotiate(&request, &offered), Some("application/json"));
+        assert_eq!(
+            negotiate(&HTTPRequest::builder().build(), &offered),
+            Some("application/json")
+        );
+
+        let request = accepting("Accept", "image/png");
+        assert_eq!(negotiate(&request, &offered), None);
+        let response = negotiate_or_406(&request, &offered).unwrap_err();
+        assert_eq!(response.status.status, 406);
+    }
+
+    #[test]
+    fn negotiates_encodings_and_languages() {
+        let request = accepting("Accept-Encoding", "gzip;q=0.5, br, *;q=0.1");
+        assert_eq!(negotiate_encoding(&request, &["gzip", "br"]), Some("br"));
+        assert_eq!(
+            negotiate_encoding(&request, &["deflate", "gzip"]),
+            Some("gzip")
+        );
+        let request = accepting("Accept-Encoding", "gzip;q=0, *");
+        assert_eq!(negotiate_encoding(&request, &["gzip"]), None);
+
+        let request = accepting("Accept-Language", "de;q=0.7, en;q=0.9, en-GB");
+        assert_eq!(
+            negotiate_language(&request, &["de", "en-US", "en-GB"]),
+            Some("en-GB")
+        );
+        assert_eq!(
+            negotiate_language(&request, &["de", "en-US"]),
+            Some("en-US")
+        );
+        // `en` doesn't stand for `eng`
+        assert_eq!(negotiate_language(&request, &["eng", "fr"]), None);
+    }
+}
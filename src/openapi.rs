@@ -0,0 +1,256 @@
+use crate::{http_server::HTTPMethod, json::Value, router::RouteInfo};
+
+/// the version of the specification documents are written against
+const VERSION: &str = "3.0.3";
+
+/// an OpenAPI document describing the routes of a server, served with
+/// `HTTPServerBuilder::openapi`. Routes are listed on their own, operations add summaries and
+/// schemas to them
+#[derive(Clone, Debug)]
+pub struct OpenApi {
+    title: String,
+    version: String,
+    description: Option<String>,
+    operations: Vec<(HTTPMethod, String, Operation)>,
+}
+
+/// what the document says about one method of a route
+#[derive(Clone, Debug, Default)]
+pub struct Operation {
+    summary: Option<String>,
+    description: Option<String>,
+    tags: Vec<String>,
+    request_body: Option<Value>,
+    responses: Vec<(u16, String, Option<Value>)>,
+}
+
+impl OpenApi {
+    /// a document for the api `title` in its `version`, like `1.2.0`
+    pub fn new(title: &str, version: &str) -> OpenApi {
+        OpenApi {
+            title: String::from(title),
+            version: String::from(version),
+            description: None,
+            operations: Vec::new(),
+        }
+    }
+
+    pub fn description(mut self, description: &str) -> Self {
+        self.description = Some(String::from(description));
+        self
+    }
+
+    /// describe `method` of the route registered as `pattern`, like `/users/:id`
+    pub fn operation(mut self, method: HTTPMethod, pattern: &str, operation: Operation) -> Self {
+        self.operations
+            .push((method, String::from(pattern), operation));
+        self
+    }
+
+    /// the document for `routes`. The name of a route becomes its `operationId`, followed by
+    /// the method if the route has several, and its description the summary unless its
+    /// operation has one. Methods OpenAPI has no field for, like CONNECT or custom ones, are
+    /// left out
+    pub fn document(&self, routes: &[RouteInfo]) -> Value {
+        let mut paths: Vec<(String, Value)> = Vec::new();
+        for route in routes {
+            let Some(method) = method_field(&route.method) else {
+                continue;
+            };
+            let operation = self
+                .operations
+                .iter()
+                .find(|(m, pattern, _)| *m == route.method && *pattern == route.pattern)
+                .map(|(_, _, operation)| operation);
+            // operation ids have to be unique
+            let id = route.name.as_ref().map(|name| {
+                let shared = routes.iter().filter(|r| r.name == route.name).count() > 1;
+                match shared {
+                    true => format!("{}_{}", name, method),
+                    false => name.clone(),
+                }
+            });
+            let entry = (String::from(method), operation_json(route, id, operation));
+
+            let path = openapi_path(&route.pattern);
+            match paths.iter_mut().find(|(p, _)| *p == path) {
+                Some((_, Value::Object(methods))) => methods.push(entry),
+                _ => paths.push((path, Value::Object(vec![entry]))),
+            }
+        }
+
+        let mut info = vec![
+            (String::from("title"), Value::String(self.title.clone())),
+            (String::from("version"), Value::String(self.version.clone())),
+        ];
+        if let Some(ref description) = self.description {
+            info.push((
+                String::from("description"),
+                Value::String(description.clone()),
+            ));
+        }
+        Value::object([
+            ("openapi", Value::String(String::from(VERSION))),
+            ("info", Value::Object(info)),
+            ("paths", Value::Object(paths)),
+        ])
+    }
+}
+
+impl Operation {
+    pub fn new() -> Operation {
+        Operation::default()
+    }
+
+    /// a short line on what the operation does
+    pub fn summary(mut self, summary: &str) -> Self {
+        self.summary = Some(String::from(summary));
+        self
+    }
+
+    pub fn description(mut self, description: &str) -> Self {
+        self.description = Some(String::from(description));
+        self
+    }
+
+    /// group the operation under `tag`, may be repeated
+    pub fn tag(mut self, tag: &str) -> Self {
+        self.tags.push(String::from(tag));
+        self
+    }
+
+    /// the JSON schema of the `application/json` body the operation expects
+    pub fn request_body(mut self, schema: Value) -> Self {
+        self.request_body = Some(schema);
+        self
+    }
+
+    /// a possible response without a body
+    pub fn response(mut self, status: u16, description: &str) -> Self {
+        self.responses
+            .push((status, String::from(description), None));
+        self
+    }
+
+    /// a possible response with an `application/json` body matching `schema`
+    pub fn json_response(mut self, status: u16, description: &str, schema: Value) -> Self {
+        self.responses
+            .push((status, String::from(description), Some(schema)));
+        self
+    }
+}
+
+fn operation_json(route: &RouteInfo, id: Option<String>, operation: Option<&Operation>) -> Value {
+    let default = Operation::default();
+    let operation = operation.unwrap_or(&default);
+    let mut fields = Vec::new();
+    let string = |value: &str| Value::String(String::from(value));
+
+    if let Some(id) = id {
+        fields.push((String::from("operationId"), Value::String(id)));
+    }
+    if let Some(summary) = operation.summary.as_ref().or(route.description.as_ref()) {
+        fields.push((String::from("summary"), string(summary)));
+    }
+    if let Some(ref description) = operation.description {
+        fields.push((String::from("description"), string(description)));
+    }
+    if !operation.tags.is_empty() {
+        let tags = operation.tags.iter().map(|tag| string(tag)).collect();
+        fields.push((String::from("tags"), Value::Array(tags)));
+    }
+
+    let parameters: Vec<Value> = path_params(&route.pattern)
+        .map(|name| {
+            Value::object([
+                ("name", string(name)),
+                ("in", string("path")),
+                ("required", Value::Bool(true)),
+                ("schema", Value::object([("type", string("string"))])),
+            ])
+        })
+        .collect();
+    if !parameters.is_empty() {
+        fields.push((String::from("parameters"), Value::Array(parameters)));
+    }
+
+    if let Some(ref schema) = operation.request_body {
+        fields.push((
+            String::from("requestBody"),
+            Value::object([
+                ("required", Value::Bool(true)),
+                ("content", json_content(schema)),
+            ]),
+        ));
+    }
+
+    // every operation needs at least one response
+    let mut responses: Vec<(String, Value)> = operation
+        .responses
+        .iter()
+        .map(|(status, description, schema)| {
+            let mut response = vec![(String::from("description"), string(description))];
+            if let Some(schema) = schema {
+                response.push((String::from("content"), json_content(schema)));
+            }
+            (status.to_string(), Value::Object(response))
+        })
+        .collect();
+    if responses.is_empty() {
+        responses.push((
+            String::from("200"),
+            Value::object([("description", string("OK"))]),
+        ));
+    }
+    fields.push((String::from("responses"), Value::Object(responses)));
+    Value::Object(fields)
+}
+
+fn json_content(schema: &Value) -> Value {
+    Value::object([(
+        "application/json",
+        Value::object([("schema", schema.clone())]),
+    )])
+}
+
+/// the field of a path item for `method`
+fn method_field(method: &HTTPMethod) -> Option<&'static str> {
+    match method {
+        HTTPMethod::GET => Some("get"),
+        HTTPMethod::HEAD => Some("head"),
+        HTTPMethod::POST => Some("post"),
+        HTTPMethod::PUT => Some("put"),
+        HTTPMethod::DELETE => Some("delete"),
+        HTTPMethod::OPTIONS => Some("options"),
+        HTTPMethod::TRACE => Some("trace"),
+        HTTPMethod::PATCH => Some("patch"),
+        _ => None,
+    }
+}
+
+/// `/users/:id` as `/users/{id}`. Wildcards become a parameter too, one without a name is
+/// called `path`
+fn openapi_path(pattern: &str) -> String {
+    pattern
+        .split('/')
+        .map(|segment| match param_name(segment) {
+            Some(name) => format!("{{{}}}", name),
+            None => String::from(segment),
+        })
+        .collect::<Vec<String>>()
+        .join("/")
+}
+
+fn path_params(pattern: &str) -> impl Iterator<Item = &str> {
+    pattern.split('/').filter_map(param_name)
+}
+
+fn param_name(segment: &str) -> Option<&str> {
+    if let Some(name) = segment.strip_prefix(':') {
+        return Some(name);
+    }
+    match segment.strip_prefix('*')? {
+        "" | "*" => Some("path"),
+        name => Some(name),
+    }
+}
@@ -0,0 +1,191 @@
+use std::{fmt, io};
+
+use crate::{
+    body::BodyError,
+    http_server::{HTTPResponse, HTTPStatus},
+    multipart::MultipartError,
+    response::json_string,
+};
+
+/// an error a listener can return as `Result<HTTPResponse, HTTPError>`, sent as an RFC 7807
+/// `application/problem+json` document. Errors of the crate like `BodyError` convert into
+/// it, so `?` works on them
+#[derive(Clone, Debug)]
+pub struct HTTPError {
+    pub status: u16,
+    /// short summary of the kind of problem, the status' reason phrase by default
+    pub title: String,
+    /// what went wrong in this occurrence
+    pub detail: Option<String>,
+    /// a URI identifying the kind of problem, `about:blank` if left out
+    pub type_uri: Option<String>,
+    /// a URI identifying this occurrence
+    pub instance: Option<String>,
+    /// further members as `(name, serialized JSON)`
+    extensions: Vec<(String, String)>,
+}
+
+impl HTTPError {
+    pub fn new(status: u16) -> HTTPError {
+        HTTPError {
+            status,
+            title: HTTPStatus::new(status).reason,
+            detail: None,
+            type_uri: None,
+            instance: None,
+            extensions: Vec::new(),
+        }
+    }
+
+    pub fn bad_request(detail: &str) -> HTTPError {
+        HTTPError::new(400).detail(detail)
+    }
+
+    pub fn unauthorized(detail: &str) -> HTTPError {
+        HTTPError::new(401).detail(detail)
+    }
+
+    pub fn forbidden(detail: &str) -> HTTPError {
+        HTTPError::new(403).detail(detail)
+    }
+
+    pub fn not_found(detail: &str) -> HTTPError {
+        HTTPError::new(404).detail(detail)
+    }
+
+    pub fn conflict(detail: &str) -> HTTPError {
+        HTTPError::new(409).detail(detail)
+    }
+
+    /// a 500 whose detail is left out, so internals don't leak to clients
+    pub fn internal() -> HTTPError {
+        HTTPError::new(500)
+    }
+
+    pub fn title(mut self, title: &str) -> Self {
+        self.title = String::from(title);
+        self
+    }
+
+    pub fn detail(mut self, detail: &str) -> Self {
+        self.detail = Some(String::from(detail));
+        self
+    }
+
+    pub fn type_uri(mut self, type_uri: &str) -> Self {
+        self.type_uri = Some(String::from(type_uri));
+        self
+    }
+
+    pub fn instance(mut self, instance: &str) -> Self {
+        self.instance = Some(String::from(instance));
+        self
+    }
+
+    /// add a string member, like `.extension("field", "email")`
+    pub fn extension(mut self, name: &str, value: &str) -> Self {
+        self.extensions
+            .push((String::from(name), json_string(value)));
+        self
+    }
+
+    /// add a member of any JSON value, like a list of invalid fields
+    #[cfg(feature = "json")]
+    pub fn extension_json(mut self, name: &str, value: &impl crate::json::ToJson) -> Self {
+        self.extensions
+            .push((String::from(name), value.to_json().to_string()));
+        self
+    }
+
+    /// the problem document
+    pub fn to_json(&self) -> String {
+        let mut members = Vec::new();
+        if let Some(type_uri) = &self.type_uri {
+            members.push(format!("\"type\":{}", json_string(type_uri)));
+        }
+        members.push(format!("\"title\":{}", json_string(&self.title)));
+        members.push(format!("\"status\":{}", self.status));
+        if let Some(detail) = &self.detail {
+            members.push(format!("\"detail\":{}", json_string(detail)));
+        }
+        if let Some(instance) = &self.instance {
+            members.push(format!("\"instance\":{}", json_string(instance)));
+        }
+        for (name, value) in &self.extensions {
+            members.push(format!("{}:{}", json_string(name), value));
+        }
+        format!("{{{}}}", members.join(","))
+    }
+
+    pub fn response(&self) -> HTTPResponse {
+        HTTPResponse::builder()
+            .status(self.status)
+            .header("Content-Type", "application/problem+json")
+            .bytes(self.to_json().into_bytes())
+    }
+}
+
+impl From<HTTPError> for HTTPResponse {
+    fn from(error: HTTPError) -> HTTPResponse {
+        error.response()
+    }
+}
+
+impl fmt::Display for HTTPError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.status, self.title)?;
+        if let Some(detail) = &self.detail {
+            write!(f, ": {}", detail)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for HTTPError {}
+
+impl From<BodyError> for HTTPError {
+    fn from(error: BodyError) -> HTTPError {
+        let status = match error {
+            BodyError::TooLarge => 413,
+            BodyError::Timeout => 408,
+            BodyError::Invalid => 400,
+            BodyError::UnsupportedEncoding => 415,
+        };
+        HTTPError::new(status).detail(&error.to_string())
+    }
+}
+
+impl From<MultipartError> for HTTPError {
+    fn from(error: MultipartError) -> HTTPError {
+        match error {
+            MultipartError::Body(error) => HTTPError::from(error),
+            MultipartError::TooLarge => HTTPError::new(413).detail(&error.to_string()),
+            MultipartError::Io(error) => {
+                println!("failed reading multipart body: {}", error);
+                HTTPError::internal()
+            }
+            error => HTTPError::bad_request(&error.to_string()),
+        }
+    }
+}
+
+/// 404 for missing files, 403 for forbidden ones and 500 for anything else
+impl From<io::Error> for HTTPError {
+    fn from(error: io::Error) -> HTTPError {
+        match error.kind() {
+            io::ErrorKind::NotFound => HTTPError::new(404),
+            io::ErrorKind::PermissionDenied => HTTPError::new(403),
+            _ => {
+                println!("listener failed: {}", error);
+                HTTPError::internal()
+            }
+        }
+    }
+}
+
+#[cfg(feature = "json")]
+impl From<crate::json::JsonError> for HTTPError {
+    fn from(error: crate::json::JsonError) -> HTTPError {
+        HTTPError::bad_request(&error.message)
+    }
+}
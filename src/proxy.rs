@@ -0,0 +1,368 @@
+use std::{
+    io::{self, BufRead, BufReader, ErrorKind, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+use crate::{
+    headers::HeaderMap,
+    http_server::{
+        get_400_default_response, is_field_text, is_token, Body, HTTPMethod, HTTPRequest,
+        HTTPResponse, HTTPStatus,
+    },
+};
+
+/// headers describing a single connection, which a proxy must not pass on
+const HOP_BY_HOP: [&str; 8] = [
+    "Connection",
+    "Keep-Alive",
+    "Proxy-Authenticate",
+    "Proxy-Authorization",
+    "TE",
+    "Trailer",
+    "Transfer-Encoding",
+    "Upgrade",
+];
+
+/// a listener forwarding requests to the plain http `upstream`, like `http://127.0.0.1:3000`.
+/// The request target is appended to the upstream's path, and the upstream's response is
+/// streamed back. `X-Forwarded-For`, `X-Forwarded-Proto` and `X-Forwarded-Host` tell the
+/// upstream about the original request. Unreachable upstreams are answered with 502, slow ones
+/// with 504, and requests whose head can't be passed on as it is with 400
+pub fn proxy_to<T>(
+    upstream: &str,
+) -> impl Fn(&HTTPRequest, &T) -> HTTPResponse + Send + Sync + 'static {
+    let upstream = Upstream::parse(upstream);
+    move |request: &HTTPRequest, _: &T| {
+        let body = match request.body.bytes() {
+            Ok(body) => body,
+            Err(error) => return error.response(),
+        };
+        match upstream.forward(request, body) {
+            Ok(response) => response,
+            Err(error) if error.kind() == ErrorKind::InvalidInput => {
+                println!("not proxying to {}: {}", upstream.authority, error);
+                get_400_default_response()
+            }
+            Err(error) if matches!(error.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                println!("upstream {} timed out: {}", upstream.authority, error);
+                gateway_error(504, "Gateway Timeout")
+            }
+            Err(error) => {
+                println!("failed proxying to {}: {}", upstream.authority, error);
+                gateway_error(502, "Bad Gateway")
+            }
+        }
+    }
+}
+
+struct Upstream {
+    /// `host:port`, also sent as the Host header
+    authority: String,
+    /// path prefix of the upstream without a trailing slash
+    base: String,
+    timeout: Duration,
+}
+
+impl Upstream {
+    fn parse(upstream: &str) -> Upstream {
+        let rest = upstream
+            .strip_prefix("http://")
+            .unwrap_or_else(|| panic!("upstream `{upstream}` has to be a http:// url"));
+        let (authority, base) = match rest.find('/') {
+            Some(i) => rest.split_at(i),
+            None => (rest, ""),
+        };
+        assert!(!authority.is_empty(), "upstream `{upstream}` lacks a host");
+        let authority = match authority.rsplit_once(':') {
+            Some((_, port)) if port.bytes().all(|b| b.is_ascii_digit()) => String::from(authority),
+            _ => format!("{}:80", authority),
+        };
+        Upstream {
+            authority,
+            base: String::from(base.trim_end_matches('/')),
+            timeout: Duration::from_secs(30),
+        }
+    }
+
+    fn forward(&self, request: &HTTPRequest, body: &[u8]) -> io::Result<HTTPResponse> {
+        let head = self.request_head(request, body.len())?;
+        let address = self
+            .authority
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(ErrorKind::NotFound, "upstream has no address"))?;
+        let mut stream = TcpStream::connect_timeout(&address, self.timeout)?;
+        stream.set_read_timeout(Some(self.timeout))?;
+        stream.set_write_timeout(Some(self.timeout))?;
+        stream.write_all(head.as_bytes())?;
+        stream.write_all(body)?;
+        stream.flush()?;
+
+        read_response(BufReader::new(stream), &request.method)
+    }
+
+    /// the head of the request as sent upstream. Fails with `InvalidInput` if a header, the
+    /// method or the target would break out of its line, as they can when they came in over
+    /// HTTP/2
+    fn request_head(&self, request: &HTTPRequest, body_length: usize) -> io::Result<String> {
+        let invalid = |msg: String| io::Error::new(ErrorKind::InvalidInput, msg);
+        let method = request.method.to_string();
+        if !is_token(&method) {
+            return Err(invalid(format!("invalid method {:?}", method)));
+        }
+        if !request
+            .target
+            .bytes()
+            .all(|b| b.is_ascii_graphic() || b >= 0x80)
+        {
+            return Err(invalid(format!("invalid target {:?}", request.target)));
+        }
+        let mut headers = request.headers.clone();
+        for name in HOP_BY_HOP {
+            headers.remove(name);
+        }
+        // headers named in Connection only concern the client's connection as well
+        for name in request.headers.get_all("Connection") {
+            for name in name.split(',') {
+                headers.remove(name.trim());
+            }
+        }
+        if let Some(host) = request.header("Host") {
+            headers.insert("X-Forwarded-Host", host);
+        }
+        headers.insert("Host", self.authority.as_str());
+        if let Some(peer) = request.peer_addr {
+            let forwarded = match request.header("X-Forwarded-For") {
+                Some(previous) => format!("{}, {}", previous, peer.ip()),
+                None => peer.ip().to_string(),
+            };
+            headers.insert("X-Forwarded-For", forwarded);
+        }
+        headers.insert(
+            "X-Forwarded-Proto",
+            if request.secure { "https" } else { "http" },
+        );
+        headers.insert("Content-Length", body_length.to_string());
+        headers.insert("Connection", "close");
+
+        let mut head = format!("{} {}{} HTTP/1.1\r\n", method, self.base, request.target);
+        for (name, value) in headers.iter() {
+            if !is_token(name) {
+                return Err(invalid(format!("invalid header name {:?}", name)));
+            }
+            if !is_field_text(value) {
+                return Err(invalid(format!(
+                    "invalid value of header {}: {:?}",
+                    name, value
+                )));
+            }
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        head.push_str("\r\n");
+        Ok(head)
+    }
+}
+
+/// read the upstream's response head, leaving the body to be streamed
+fn read_response(
+    mut reader: BufReader<TcpStream>,
+    method: &HTTPMethod,
+) -> io::Result<HTTPResponse> {
+    let invalid = |msg: &str| io::Error::new(ErrorKind::InvalidData, msg.to_owned());
+
+    let mut status_line = String::new();
+    reader.by_ref().take(8192).read_line(&mut status_line)?;
+    let mut parts = status_line.trim_end().splitn(3, ' ');
+    if !parts.next().is_some_and(|v| v.starts_with("HTTP/1.")) {
+        return Err(invalid("upstream didn't answer with HTTP/1.x"));
+    }
+    let status: u16 = parts
+        .next()
+        .and_then(|code| code.parse().ok())
+        .filter(|code| (100..1000).contains(code))
+        .ok_or_else(|| invalid("invalid upstream status"))?;
+    let reason = String::from(parts.next().unwrap_or(""));
+
+    let mut headers = HeaderMap::new();
+    let mut head_size = status_line.len();
+    loop {
+        let mut line = String::new();
+        let read = reader.by_ref().take(8192).read_line(&mut line)?;
+        head_size += read;
+        if read == 0 || head_size > 64 * 1024 {
+            return Err(invalid("upstream response head incomplete or too large"));
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        let (name, value) = line
+            .split_once(':')
+            .ok_or_else(|| invalid("invalid upstream header"))?;
+        headers.append(name.trim(), value.trim());
+    }
+
+    let chunked = headers.has_token("Transfer-Encoding", "chunked");
+    let length = headers
+        .get("Content-Length")
+        .and_then(|length| length.trim().parse::<u64>().ok());
+    for name in HOP_BY_HOP {
+        headers.remove(name);
+    }
+
+    let bodiless = *method == HTTPMethod::HEAD || status < 200 || status == 204 || status == 304;
+    let body = if bodiless {
+        Body::Full(Vec::new())
+    } else if chunked {
+        headers.remove("Content-Length");
+        Body::Reader(Box::new(ChunkedReader::new(reader)))
+    } else if let Some(length) = length {
+        Body::Sized(Box::new(reader), length)
+    } else {
+        // the body ends when the upstream closes the connection
+        Body::Reader(Box::new(reader))
+    };
+
+    Ok(HTTPResponse {
+        status: HTTPStatus { status, reason },
+        headers,
+        body,
+    })
+}
+
+fn gateway_error(status: u16, body: &str) -> HTTPResponse {
+    HTTPResponse::builder()
+        .status(status)
+        .header("Connection", "close")
+        .text(body)
+}
+
+/// undoes `Transfer-Encoding: chunked` while reading
+struct ChunkedReader<R: BufRead> {
+    reader: R,
+    /// bytes left in the current chunk
+    remaining: u64,
+    done: bool,
+}
+
+impl<R: BufRead> ChunkedReader<R> {
+    fn new(reader: R) -> ChunkedReader<R> {
+        ChunkedReader {
+            reader,
+            remaining: 0,
+            done: false,
+        }
+    }
+
+    fn read_line(&mut self) -> io::Result<String> {
+        let mut line = String::new();
+        if self.reader.by_ref().take(4096).read_line(&mut line)? == 0 {
+            return Err(io::Error::new(
+                ErrorKind::UnexpectedEof,
+                "upstream body ended before the last chunk",
+            ));
+        }
+        Ok(line)
+    }
+}
+
+impl<R: BufRead> Read for ChunkedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.done || buf.is_empty() {
+            return Ok(0);
+        }
+        if self.remaining == 0 {
+            let line = self.read_line()?;
+            let invalid = || io::Error::new(ErrorKind::InvalidData, "invalid chunk size");
+            let size = line.trim_end_matches(['\r', '\n']);
+            let size = size.split(';').next().unwrap_or("");
+            let size = size.trim_end_matches([' ', '\t']);
+            if size.is_empty() || !size.bytes().all(|b| b.is_ascii_hexdigit()) {
+                return Err(invalid());
+            }
+            self.remaining = u64::from_str_radix(size, 16).map_err(|_| invalid())?;
+            if self.remaining == 0 {
+                // skip the trailers up to the closing empty line
+                while !self.read_line()?.trim().is_empty() {}
+                self.done = true;
+                return Ok(0);
+            }
+        }
+
+        let wanted = buf.len().min(self.remaining as usize);
+        let read = self.reader.read(&mut buf[..wanted])?;
+        if read == 0 {
+            return Err(io::Error::new(
+                ErrorKind::UnexpectedEof,
+                "upstream body ended inside a chunk",
+            ));
+        }
+        self.remaining -= read as u64;
+        if self.remaining == 0 {
+            // the CRLF closing the chunk
+            self.read_line()?;
+        }
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn head(request: HTTPRequest) -> io::Result<String> {
+        Upstream::parse("http://127.0.0.1:3000/api").request_head(&request, 0)
+    }
+
+    #[test]
+    fn forwards_the_request_head() {
+        let request = HTTPRequest::builder()
+            .path("/users?page=2")
+            .header("Host", "example.com")
+            .header("Connection", "keep-alive, x-hop")
+            .header("X-Hop", "1")
+            .header("Accept", "text/html")
+            .build();
+        let head = head(request).unwrap();
+        assert!(head.starts_with("GET /api/users?page=2 HTTP/1.1\r\n"));
+        assert!(head.contains("\r\nHost: 127.0.0.1:3000\r\n"));
+        assert!(head.contains("\r\nX-Forwarded-Host: example.com\r\n"));
+        assert!(head.contains("\r\nAccept: text/html\r\n"));
+        assert!(!head.contains("X-Hop"));
+        assert!(head.ends_with("\r\nConnection: close\r\n\r\n"));
+    }
+
+    #[test]
+    fn refuses_heads_that_would_split() {
+        let value = HTTPRequest::builder()
+            .header("X-Custom", "a\r\nX-Admin: 1")
+            .build();
+        let nul = HTTPRequest::builder().header("X-Custom", "a\0b").build();
+        let name = HTTPRequest::builder().header("X Custom", "a").build();
+        let target = HTTPRequest::builder()
+            .path("/ HTTP/1.1\r\nX-Admin: 1\r\n\r\nGET /")
+            .build();
+        let method = HTTPRequest::builder()
+            .method(HTTPMethod::Custom(String::from("GET / HTTP/1.1\r\n")))
+            .build();
+        for request in [value, nul, name, target, method] {
+            assert_eq!(head(request).unwrap_err().kind(), ErrorKind::InvalidInput);
+        }
+    }
+
+    #[test]
+    fn upstream_chunk_sizes_are_only_hex_digits() {
+        let mut body = String::new();
+        let mut chunked = ChunkedReader::new(&b"3 ;ext\r\nabc\r\n0\r\nX-Sum: 1\r\n\r\n"[..]);
+        chunked.read_to_string(&mut body).unwrap();
+        assert_eq!(body, "abc");
+
+        for size in ["+3", " 3", "0x3", ""] {
+            let response = format!("{}\r\nabc\r\n0\r\n\r\n", size);
+            let mut chunked = ChunkedReader::new(response.as_bytes());
+            let error = chunked.read_to_end(&mut Vec::new()).unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::InvalidData, "{:?}", size);
+        }
+    }
+}
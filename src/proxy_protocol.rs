@@ -0,0 +1,280 @@
+use std::{
+    io::{self, BufRead, ErrorKind, Read},
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr},
+};
+
+/// the start of every version 2 header
+const V2_SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+
+/// longest version 1 header including its CRLF
+const V1_MAX_LENGTH: u64 = 107;
+
+/// the original source and destination of a proxied connection
+pub(crate) struct ProxiedAddrs {
+    pub(crate) source: SocketAddr,
+    pub(crate) destination: SocketAddr,
+}
+
+/// read the PROXY protocol header, version 1 or 2, opening the connection. `None` for a
+/// connection the proxy opened on its own, like a health check, or one of unknown origin
+pub(crate) fn read_header(reader: &mut impl BufRead) -> io::Result<Option<ProxiedAddrs>> {
+    // the first byte tells the versions apart, a short read may not hold more
+    match reader.fill_buf()?.first() {
+        Some(b'P') => read_v1(reader),
+        Some(b'\r') => read_v2(reader),
+        Some(_) => Err(invalid("connection doesn't start with a PROXY header")),
+        None => Err(ErrorKind::UnexpectedEof.into()),
+    }
+}
+
+fn read_v1(reader: &mut impl BufRead) -> io::Result<Option<ProxiedAddrs>> {
+    let mut line = Vec::new();
+    reader.take(V1_MAX_LENGTH).read_until(b'\n', &mut line)?;
+    let line = std::str::from_utf8(&line)
+        .ok()
+        .and_then(|line| line.strip_suffix("\r\n"))
+        .ok_or_else(|| invalid("PROXY header not terminated by CRLF"))?;
+
+    let parts: Vec<&str> = line.split(' ').collect();
+    match parts.as_slice() {
+        ["PROXY", "UNKNOWN", ..] => Ok(None),
+        ["PROXY", family @ ("TCP4" | "TCP6"), source, destination, source_port, destination_port] =>
+        {
+            let address = |ip: &str, port: &str| -> io::Result<SocketAddr> {
+                let port: u16 = port.parse().map_err(|_| invalid("invalid PROXY port"))?;
+                let ip = match *family {
+                    "TCP4" => ip.parse::<Ipv4Addr>().map(Into::into),
+                    _ => ip.parse::<Ipv6Addr>().map(Into::into),
+                };
+                let ip = ip.map_err(|_| invalid("invalid PROXY address"))?;
+                Ok(SocketAddr::new(ip, port))
+            };
+            Ok(Some(ProxiedAddrs {
+                source: address(source, source_port)?,
+                destination: address(destination, destination_port)?,
+            }))
+        }
+        _ => Err(invalid("malformed PROXY header")),
+    }
+}
+
+fn read_v2(reader: &mut impl BufRead) -> io::Result<Option<ProxiedAddrs>> {
+    let mut head = [0; 16];
+    reader.read_exact(&mut head)?;
+    if head[..12] != V2_SIGNATURE || head[12] >> 4 != 2 {
+        return Err(invalid("malformed PROXY header"));
+    }
+    let length = u16::from_be_bytes([head[14], head[15]]);
+    let mut payload = vec![0; length as usize];
+    reader.read_exact(&mut payload)?;
+
+    let command = head[12] & 0x0f;
+    let family = head[13] >> 4;
+    match command {
+        // LOCAL, sent by the proxy itself
+        0 => return Ok(None),
+        1 => {}
+        _ => return Err(invalid("unknown PROXY command")),
+    }
+
+    let port = |at: usize| u16::from_be_bytes([payload[at], payload[at + 1]]);
+    // the addresses come first, type-length-value extensions may follow
+    match family {
+        1 if payload.len() >= 12 => {
+            let ip = |at: usize| Ipv4Addr::from(<[u8; 4]>::try_from(&payload[at..at + 4]).unwrap());
+            Ok(Some(ProxiedAddrs {
+                source: SocketAddr::new(ip(0).into(), port(8)),
+                destination: SocketAddr::new(ip(4).into(), port(10)),
+            }))
+        }
+        2 if payload.len() >= 36 => {
+            let ip =
+                |at: usize| Ipv6Addr::from(<[u8; 16]>::try_from(&payload[at..at + 16]).unwrap());
+            Ok(Some(ProxiedAddrs {
+                source: SocketAddr::new(ip(0).into(), port(32)),
+                destination: SocketAddr::new(ip(16).into(), port(34)),
+            }))
+        }
+        1 | 2 => Err(invalid("PROXY header too short for its addresses")),
+        // unix sockets or an unspecified family, nothing usable
+        _ => Ok(None),
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, message.to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// the header read off `bytes`, and what's left on the connection after it
+    fn read(bytes: &[u8]) -> (io::Result<Option<ProxiedAddrs>>, Vec<u8>) {
+        let mut reader = bytes;
+        let header = read_header(&mut reader);
+        (header, reader.to_vec())
+    }
+
+    fn addrs(header: io::Result<Option<ProxiedAddrs>>) -> (String, String) {
+        let addrs = header.unwrap().unwrap();
+        (addrs.source.to_string(), addrs.destination.to_string())
+    }
+
+    fn v2(command: u8, family: u8, payload: &[u8]) -> Vec<u8> {
+        let mut header = V2_SIGNATURE.to_vec();
+        header.extend([0x20 | command, family << 4 | 1]);
+        header.extend((payload.len() as u16).to_be_bytes());
+        header.extend(payload);
+        header
+    }
+
+    fn assert_invalid(bytes: &[u8], kind: ErrorKind) {
+        match read(bytes).0 {
+            Err(error) => assert_eq!(error.kind(), kind, "{:?}", bytes),
+            Ok(_) => panic!("accepted {:?}", bytes),
+        }
+    }
+
+    #[test]
+    fn v1_tcp4() {
+        let (header, rest) = read(b"PROXY TCP4 192.0.2.1 198.51.100.2 56324 443\r\nGET /");
+        let addrs = addrs(header);
+        assert_eq!(addrs.0, "192.0.2.1:56324");
+        assert_eq!(addrs.1, "198.51.100.2:443");
+        assert_eq!(rest, b"GET /");
+    }
+
+    #[test]
+    fn v1_tcp6() {
+        let (header, rest) = read(b"PROXY TCP6 2001:db8::1 2001:db8::2 4000 80\r\n");
+        assert_eq!(
+            addrs(header),
+            ("[2001:db8::1]:4000".into(), "[2001:db8::2]:80".into())
+        );
+        assert!(rest.is_empty());
+        // the family decides how addresses are read
+        assert_invalid(
+            b"PROXY TCP4 2001:db8::1 2001:db8::2 4000 80\r\n",
+            ErrorKind::InvalidData,
+        );
+    }
+
+    #[test]
+    fn v1_unknown() {
+        let (header, rest) = read(b"PROXY UNKNOWN\r\nGET /");
+        assert!(header.unwrap().is_none());
+        assert_eq!(rest, b"GET /");
+        let (header, _) = read(b"PROXY UNKNOWN ffff:f::1 ffff:f::2 65535 65535\r\n");
+        assert!(header.unwrap().is_none());
+    }
+
+    #[test]
+    fn v1_malformed() {
+        for header in [
+            &b"PROXY TCP4 192.0.2.1 198.51.100.2 56324 443\n"[..],
+            b"PROXY TCP4 192.0.2.1 198.51.100.2 56324\r\n",
+            b"PROXY TCP4 192.0.2.1 198.51.100.2 56324 65536\r\n",
+            b"PROXY TCP4  192.0.2.1 198.51.100.2 56324 443\r\n",
+            b"PROXY UDP4 192.0.2.1 198.51.100.2 56324 443\r\n",
+            b"PROXY TCP4 192.0.2.1",
+        ] {
+            assert_invalid(header, ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn v1_oversized() {
+        // the longest possible header still fits
+        let longest = b"PROXY UNKNOWN ffff:ffff:ffff:ffff:ffff:ffff:ffff:ffff \
+            ffff:ffff:ffff:ffff:ffff:ffff:ffff:ffff 65535 65535\r\nGET /";
+        assert_eq!(longest.len() as u64, V1_MAX_LENGTH + 5);
+        let (header, rest) = read(longest);
+        assert!(header.unwrap().is_none());
+        assert_eq!(rest, b"GET /");
+
+        let mut endless = b"PROXY UNKNOWN ".to_vec();
+        endless.resize(4096, b'a');
+        endless.extend(b"\r\n");
+        assert_invalid(&endless, ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn v2_proxy_ipv4() {
+        let mut payload = vec![192, 0, 2, 1, 198, 51, 100, 2];
+        payload.extend(56324u16.to_be_bytes());
+        payload.extend(443u16.to_be_bytes());
+        // a type-length-value extension after the addresses is skipped
+        payload.extend([0x04, 0, 1, 0]);
+        let mut bytes = v2(1, 1, &payload);
+        bytes.extend(b"GET /");
+        let (header, rest) = read(&bytes);
+        assert_eq!(
+            addrs(header),
+            ("192.0.2.1:56324".into(), "198.51.100.2:443".into())
+        );
+        assert_eq!(rest, b"GET /");
+    }
+
+    #[test]
+    fn v2_proxy_ipv6() {
+        let source: Ipv6Addr = "2001:db8::1".parse().unwrap();
+        let destination: Ipv6Addr = "2001:db8::2".parse().unwrap();
+        let mut payload = source.octets().to_vec();
+        payload.extend(destination.octets());
+        payload.extend(4000u16.to_be_bytes());
+        payload.extend(80u16.to_be_bytes());
+        let (header, rest) = read(&v2(1, 2, &payload));
+        assert_eq!(
+            addrs(header),
+            ("[2001:db8::1]:4000".into(), "[2001:db8::2]:80".into())
+        );
+        assert!(rest.is_empty());
+    }
+
+    #[test]
+    fn v2_local_and_unspecified() {
+        // LOCAL connections skip whatever addresses they carry
+        let mut bytes = v2(0, 1, &[0; 12]);
+        bytes.extend(b"GET /");
+        let (header, rest) = read(&bytes);
+        assert!(header.unwrap().is_none());
+        assert_eq!(rest, b"GET /");
+
+        let (header, rest) = read(&v2(1, 0, &[]));
+        assert!(header.unwrap().is_none());
+        assert!(rest.is_empty());
+    }
+
+    #[test]
+    fn v2_malformed() {
+        assert_invalid(&v2(2, 1, &[0; 12]), ErrorKind::InvalidData);
+        // too short for the addresses of its family
+        assert_invalid(&v2(1, 1, &[0; 11]), ErrorKind::InvalidData);
+        assert_invalid(&v2(1, 2, &[0; 35]), ErrorKind::InvalidData);
+
+        let mut version_1 = v2(1, 1, &[0; 12]);
+        version_1[12] = 0x11;
+        assert_invalid(&version_1, ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn v2_truncated() {
+        let bytes = v2(1, 1, &[0; 12]);
+        assert_invalid(&bytes[..10], ErrorKind::UnexpectedEof);
+        assert_invalid(&bytes[..20], ErrorKind::UnexpectedEof);
+        assert_invalid(&[], ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn bad_signature() {
+        let mut bytes = v2(1, 1, &[0; 12]);
+        bytes[4] = b'X';
+        assert_invalid(&bytes, ErrorKind::InvalidData);
+        assert_invalid(b"GET / HTTP/1.1\r\n\r\n", ErrorKind::InvalidData);
+        assert_invalid(
+            b"PROXX TCP4 192.0.2.1 198.51.100.2 1 2\r\n",
+            ErrorKind::InvalidData,
+        );
+    }
+}
@@ -0,0 +1,182 @@
+use std::{fmt, str::FromStr};
+
+use crate::{
+    form::QueryParams,
+    http_server::{HTTPRequest, HTTPResponse},
+    problem::HTTPError,
+};
+
+/// types that can be read from the query of a request, usually with the typed getters of
+/// `QueryParams`:
+/// `Ok(Page { number: query.get_or("page", 1)?, sort: query.optional("sort")? })`
+pub trait FromQuery: Sized {
+    fn from_query(query: &QueryParams) -> Result<Self, QueryError>;
+}
+
+/// a query parameter that is missing or doesn't parse
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct QueryError {
+    pub field: String,
+    pub kind: QueryErrorKind,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum QueryErrorKind {
+    Missing,
+    /// the value, which didn't parse
+    Invalid(String),
+}
+
+impl QueryError {
+    pub fn missing(field: &str) -> QueryError {
+        QueryError {
+            field: String::from(field),
+            kind: QueryErrorKind::Missing,
+        }
+    }
+
+    pub fn invalid(field: &str, value: &str) -> QueryError {
+        QueryError {
+            field: String::from(field),
+            kind: QueryErrorKind::Invalid(String::from(value)),
+        }
+    }
+
+    /// a 400 problem document naming the parameter as `field`
+    pub fn response(&self) -> HTTPResponse {
+        HTTPError::from(self.clone()).response()
+    }
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            QueryErrorKind::Missing => write!(f, "missing query parameter `{}`", self.field),
+            QueryErrorKind::Invalid(value) => {
+                write!(
+                    f,
+                    "invalid value `{}` for query parameter `{}`",
+                    value, self.field
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for QueryError {}
+
+impl From<QueryError> for HTTPError {
+    fn from(error: QueryError) -> HTTPError {
+        HTTPError::bad_request(&error.to_string()).extension("field", &error.field)
+    }
+}
+
+impl From<QueryError> for HTTPResponse {
+    fn from(error: QueryError) -> HTTPResponse {
+        error.response()
+    }
+}
+
+impl QueryParams {
+    /// the first value of `name` parsed, failing if it's missing
+    pub fn required<T: FromStr>(&self, name: &str) -> Result<T, QueryError> {
+        self.optional(name)?
+            .ok_or_else(|| QueryError::missing(name))
+    }
+
+    /// the first value of `name` parsed, `None` if it's missing
+    pub fn optional<T: FromStr>(&self, name: &str) -> Result<Option<T>, QueryError> {
+        self.get(name)
+            .map(|value| value.parse().map_err(|_| QueryError::invalid(name, value)))
+            .transpose()
+    }
+
+    /// the first value of `name` parsed, `default` if it's missing
+    pub fn get_or<T: FromStr>(&self, name: &str, default: T) -> Result<T, QueryError> {
+        Ok(self.optional(name)?.unwrap_or(default))
+    }
+
+    /// every value of `name` parsed, as in `?id=1&id=2`
+    pub fn parse_all<T: FromStr>(&self, name: &str) -> Result<Vec<T>, QueryError> {
+        self.get_all(name)
+            .into_iter()
+            .map(|value| value.parse().map_err(|_| QueryError::invalid(name, value)))
+            .collect()
+    }
+}
+
+impl HTTPRequest {
+    /// read the query parameters into `T`. `QueryError::response` makes a fitting 400, and
+    /// listeners returning `Result` can use `?`
+    pub fn query<T: FromQuery>(&self) -> Result<T, QueryError> {
+        T::from_query(&self.query_params)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Page {
+        number: u32,
+        sort: Option<String>,
+    }
+
+    impl FromQuery for Page {
+        fn from_query(query: &QueryParams) -> Result<Page, QueryError> {
+            Ok(Page {
+                number: query.get_or("page", 1)?,
+                sort: query.optional("sort")?,
+            })
+        }
+    }
+
+    #[test]
+    fn typed_getters() {
+        let query = QueryParams::parse("limit=10&id=1&id=2&bad=x");
+        assert_eq!(query.required::<u32>("limit"), Ok(10));
+        assert_eq!(query.optional::<u32>("offset"), Ok(None));
+        assert_eq!(query.get_or("offset", 5u32), Ok(5));
+        assert_eq!(query.parse_all::<u8>("id"), Ok(vec![1, 2]));
+        assert_eq!(
+            query.required::<u32>("offset"),
+            Err(QueryError::missing("offset"))
+        );
+        assert_eq!(
+            query.get_or("bad", 0u32),
+            Err(QueryError::invalid("bad", "x"))
+        );
+        assert_eq!(
+            query.parse_all::<u32>("bad"),
+            Err(QueryError::invalid("bad", "x"))
+        );
+    }
+
+    #[test]
+    fn reads_requests() {
+        let request = HTTPRequest::builder().path("/list?sort=name").build();
+        let page: Page = request.query().unwrap();
+        assert_eq!(page.number, 1);
+        assert_eq!(page.sort.as_deref(), Some("name"));
+
+        let request = HTTPRequest::builder().path("/list?page=two").build();
+        let error = request.query::<Page>().err().unwrap();
+        assert_eq!(error, QueryError::invalid("page", "two"));
+        assert_eq!(
+            error.to_string(),
+            "invalid value `two` for query parameter `page`"
+        );
+        assert_eq!(
+            QueryError::missing("page").to_string(),
+            "missing query parameter `page`"
+        );
+    }
+
+    #[test]
+    fn errors_are_problem_documents() {
+        let response = QueryError::invalid("page", "two").response();
+        assert_eq!(response.status.status, 400);
+        let body = String::from_utf8(response.body.into_bytes().unwrap()).unwrap();
+        assert!(body.contains("\"field\":\"page\""), "{}", body);
+    }
+}
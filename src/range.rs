@@ -0,0 +1,213 @@
+use std::{
+    fmt,
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+};
+
+use crate::http_server::{Body, HTTPRequest, HTTPResponse};
+
+/// answer `request` from a seekable `source`. A satisfiable single byte range in the `Range`
+/// header is answered with 206 Partial Content, an unsatisfiable one with 416 Range Not
+/// Satisfiable, anything else with the whole source. Responses advertise `Accept-Ranges: bytes`
+pub fn range_response(
+    request: &HTTPRequest,
+    source: impl Read + Seek + Send + 'static,
+    content_type: &str,
+) -> std::io::Result<HTTPResponse> {
+    respond(request, source, content_type, Body::sized)
+}
+
+/// `range_response` for a file, which is sent as a `Body::File`
+pub fn file_range_response(
+    request: &HTTPRequest,
+    file: File,
+    content_type: &str,
+) -> std::io::Result<HTTPResponse> {
+    respond(request, file, content_type, Body::file)
+}
+
+fn respond<S: Seek>(
+    request: &HTTPRequest,
+    mut source: S,
+    content_type: &str,
+    body: impl FnOnce(S, u64) -> Body,
+) -> std::io::Result<HTTPResponse> {
+    let length = source.seek(SeekFrom::End(0))?;
+    let builder = HTTPResponse::builder()
+        .header("Accept-Ranges", "bytes")
+        .header("Content-Type", content_type);
+
+    let range = match request.header("Range").and_then(parse_range) {
+        Some(range) => range,
+        None => {
+            source.seek(SeekFrom::Start(0))?;
+            return Ok(builder.body(body(source, length)));
+        }
+    };
+
+    match range.resolve(length) {
+        Some((start, end)) => {
+            source.seek(SeekFrom::Start(start))?;
+            Ok(builder
+                .status(206)
+                .header("Content-Range", &format!("bytes {start}-{end}/{length}"))
+                .body(body(source, end - start + 1)))
+        }
+        None => Ok(builder
+            .status(416)
+            .header("Content-Range", &format!("bytes */{length}"))
+            .empty()),
+    }
+}
+
+/// a single range of the `Range` header
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ByteRange {
+    /// `start-end`, both inclusive
+    FromTo(u64, u64),
+    /// `start-`
+    From(u64),
+    /// `-length`, the last `length` bytes
+    Suffix(u64),
+}
+
+/// as a `Range` header value, like `bytes=0-99`
+impl fmt::Display for ByteRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ByteRange::FromTo(start, end) => write!(f, "bytes={}-{}", start, end),
+            ByteRange::From(start) => write!(f, "bytes={}-", start),
+            ByteRange::Suffix(length) => write!(f, "bytes=-{}", length),
+        }
+    }
+}
+
+impl ByteRange {
+    /// inclusive (start, end) offsets inside a source of `length` bytes, or `None` if unsatisfiable
+    pub fn resolve(self, length: u64) -> Option<(u64, u64)> {
+        let (start, end) = match self {
+            ByteRange::FromTo(start, end) => (start, end.min(length.checked_sub(1)?)),
+            ByteRange::From(start) => (start, length.checked_sub(1)?),
+            ByteRange::Suffix(0) => return None,
+            ByteRange::Suffix(suffix) => (length.saturating_sub(suffix), length.checked_sub(1)?),
+        };
+        (start <= end).then_some((start, end))
+    }
+}
+
+/// parse a `Range: bytes=...` header. Multiple ranges and other units aren't supported and yield `None`,
+/// in which case the whole representation should be sent
+pub fn parse_range(header: &str) -> Option<ByteRange> {
+    let spec = header.trim().strip_prefix("bytes=")?;
+    if spec.contains(',') {
+        return None;
+    }
+    let (start, end) = spec.trim().split_once('-')?;
+    let (start, end) = (start.trim(), end.trim());
+
+    match (start.is_empty(), end.is_empty()) {
+        (true, false) => end.parse().ok().map(ByteRange::Suffix),
+        (false, true) => start.parse().ok().map(ByteRange::From),
+        (false, false) => {
+            let (start, end) = (start.parse().ok()?, end.parse().ok()?);
+            (start <= end).then_some(ByteRange::FromTo(start, end))
+        }
+        (true, true) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn respond(range: Option<&str>) -> HTTPResponse {
+        let mut request = HTTPRequest::builder();
+        if let Some(range) = range {
+            request = request.header("Range", range);
+        }
+        range_response(&request.build(), Cursor::new(b"0123456789"), "text/plain").unwrap()
+    }
+
+    #[test]
+    fn parses_single_ranges() {
+        assert_eq!(parse_range("bytes=0-99"), Some(ByteRange::FromTo(0, 99)));
+        assert_eq!(parse_range(" bytes= 5 - 9 "), Some(ByteRange::FromTo(5, 9)));
+        assert_eq!(parse_range("bytes=100-"), Some(ByteRange::From(100)));
+        assert_eq!(parse_range("bytes=-500"), Some(ByteRange::Suffix(500)));
+        for header in [
+            "bytes=9-5",
+            "bytes=-",
+            "bytes=a-b",
+            "items=0-1",
+            "0-1",
+            "bytes=1-2-3",
+        ] {
+            assert_eq!(parse_range(header), None, "{}", header);
+        }
+    }
+
+    #[test]
+    fn multiple_ranges_get_the_whole_source() {
+        assert_eq!(parse_range("bytes=0-1,4-5"), None);
+        assert_eq!(parse_range("bytes=-1, -2"), None);
+        let response = respond(Some("bytes=0-1,4-5"));
+        assert_eq!(response.status.status, 200);
+        assert_eq!(response.headers.get("Content-Range"), None);
+        assert_eq!(response.body.into_bytes().unwrap(), b"0123456789");
+    }
+
+    #[test]
+    fn resolves_against_the_length() {
+        assert_eq!(ByteRange::FromTo(2, 4).resolve(10), Some((2, 4)));
+        assert_eq!(ByteRange::FromTo(8, 99).resolve(10), Some((8, 9)));
+        assert_eq!(ByteRange::FromTo(10, 12).resolve(10), None);
+        assert_eq!(ByteRange::From(3).resolve(10), Some((3, 9)));
+        assert_eq!(ByteRange::From(0).resolve(0), None);
+    }
+
+    #[test]
+    fn suffix_ranges() {
+        assert_eq!(ByteRange::Suffix(3).resolve(10), Some((7, 9)));
+        // a suffix longer than the source is all of it
+        assert_eq!(ByteRange::Suffix(50).resolve(10), Some((0, 9)));
+        assert_eq!(ByteRange::Suffix(0).resolve(10), None);
+        assert_eq!(ByteRange::Suffix(3).resolve(0), None);
+
+        let response = respond(Some("bytes=-3"));
+        assert_eq!(response.status.status, 206);
+        assert_eq!(response.headers.get("Content-Range"), Some("bytes 7-9/10"));
+        assert_eq!(response.body.into_bytes().unwrap(), b"789");
+    }
+
+    #[test]
+    fn answers_ranges() {
+        let response = respond(Some("bytes=2-4"));
+        assert_eq!(response.status.status, 206);
+        assert_eq!(response.headers.get("Accept-Ranges"), Some("bytes"));
+        assert_eq!(response.headers.get("Content-Range"), Some("bytes 2-4/10"));
+        assert_eq!(response.body.into_bytes().unwrap(), b"234");
+
+        let response = respond(Some("bytes=20-"));
+        assert_eq!(response.status.status, 416);
+        assert_eq!(response.headers.get("Content-Range"), Some("bytes */10"));
+
+        let response = respond(None);
+        assert_eq!(response.status.status, 200);
+        assert_eq!(response.headers.get("Accept-Ranges"), Some("bytes"));
+        assert_eq!(response.body.into_bytes().unwrap(), b"0123456789");
+    }
+
+    #[test]
+    fn displays_as_range_headers() {
+        for range in [
+            ByteRange::FromTo(0, 99),
+            ByteRange::From(100),
+            ByteRange::Suffix(500),
+        ] {
+            assert_eq!(parse_range(&range.to_string()), Some(range));
+        }
+        assert_eq!(ByteRange::Suffix(500).to_string(), "bytes=-500");
+    }
+}
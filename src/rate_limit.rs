@@ -0,0 +1,110 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    http_server::{HTTPRequest, HTTPResponse},
+    middleware::Next,
+};
+
+/// picks the bucket a request is counted against, `None` to let it through unlimited
+pub type KeyFunction = Box<dyn Fn(&HTTPRequest) -> Option<String> + Send + Sync>;
+
+/// a token bucket per client. Every request takes a token, buckets hold up to `burst` tokens
+/// and refill at `rate` tokens per second. Requests finding their bucket empty are answered
+/// with 429
+pub struct RateLimiter {
+    rate: f64,
+    burst: f64,
+    key: KeyFunction,
+    state: Mutex<State>,
+}
+
+struct State {
+    buckets: HashMap<String, Bucket>,
+    last_sweep: Instant,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl RateLimiter {
+    /// allow `rate` requests per second per client ip, and bursts of up to `burst` requests
+    pub fn new(rate: f64, burst: u32) -> RateLimiter {
+        assert!(rate > 0.0, "rate limits need a positive rate");
+        RateLimiter {
+            rate,
+            burst: f64::from(burst.max(1)),
+            key: Box::new(|request| request.client_ip.map(|ip| ip.to_string())),
+            state: Mutex::new(State {
+                buckets: HashMap::new(),
+                last_sweep: Instant::now(),
+            }),
+        }
+    }
+
+    /// count requests by `key` instead of by client ip, e.g. an api token or the user
+    pub fn key(
+        mut self,
+        key: impl Fn(&HTTPRequest) -> Option<String> + Send + Sync + 'static,
+    ) -> Self {
+        self.key = Box::new(key);
+        self
+    }
+
+    /// take a token for `request`. When there are none left, how long until the next one
+    pub fn check(&self, request: &HTTPRequest) -> Result<(), Duration> {
+        let Some(key) = (self.key)(request) else {
+            return Ok(());
+        };
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+
+        // a bucket that had time to fill up again is no different from a new one
+        let full_after = Duration::from_secs_f64(self.burst / self.rate);
+        if now.duration_since(state.last_sweep) >= full_after.max(Duration::from_secs(60)) {
+            state
+                .buckets
+                .retain(|_, bucket| now.duration_since(bucket.updated) < full_after);
+            state.last_sweep = now;
+        }
+
+        let bucket = state.buckets.entry(key).or_insert(Bucket {
+            tokens: self.burst,
+            updated: now,
+        });
+        let refilled = now.duration_since(bucket.updated).as_secs_f64() * self.rate;
+        bucket.tokens = (bucket.tokens + refilled).min(self.burst);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / self.rate))
+        }
+    }
+
+    /// middleware answering requests over the limit with 429 and `Retry-After`
+    pub fn middleware<T>(
+        self,
+    ) -> impl Fn(&mut HTTPRequest, &T, Next<T>) -> HTTPResponse + Send + Sync + 'static {
+        move |request: &mut HTTPRequest, passthrough: &T, next: Next<T>| match self.check(request) {
+            Ok(()) => next.run(request, passthrough),
+            Err(retry_after) => too_many_requests(retry_after),
+        }
+    }
+}
+
+/// 429 telling the client to come back after `retry_after`, in whole seconds
+pub fn too_many_requests(retry_after: Duration) -> HTTPResponse {
+    let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+    HTTPResponse::builder()
+        .status(429)
+        .header("Retry-After", &seconds.max(1).to_string())
+        .text("Too many requests, retry later.")
+}
@@ -0,0 +1,144 @@
+use crate::{
+    http_server::{HTTPMethod, HTTPRequest, HTTPResponse},
+    middleware::Next,
+};
+
+/// permanent redirect, clients may change the method to GET
+pub fn redirect_301(location: &str) -> HTTPResponse {
+    redirect(301, location)
+}
+
+/// temporary redirect, clients may change the method to GET
+pub fn redirect_302(location: &str) -> HTTPResponse {
+    redirect(302, location)
+}
+
+/// temporary redirect keeping the method and body
+pub fn redirect_307(location: &str) -> HTTPResponse {
+    redirect(307, location)
+}
+
+/// permanent redirect keeping the method and body
+pub fn redirect_308(location: &str) -> HTTPResponse {
+    redirect(308, location)
+}
+
+/// a redirect with `status` to `location`, with a short html body linking to it
+pub fn redirect(status: u16, location: &str) -> HTTPResponse {
+    let escaped = escape_html(location);
+    HTTPResponse::builder()
+        .status(status)
+        .header("Location", location)
+        .html(format!(
+            "<html><body>Redirecting to <a href=\"{}\">{}</a></body></html>",
+            escaped, escaped
+        ))
+}
+
+/// middleware redirecting plain http requests to the same url on https. Requests without a
+/// Host header are answered with 400
+pub fn https_only<T>(request: &mut HTTPRequest, passthrough: &T, next: Next<T>) -> HTTPResponse {
+    if request.secure {
+        return next.run(request, passthrough);
+    }
+    let Some(host) = request.header("Host") else {
+        return HTTPResponse::builder()
+            .status(400)
+            .text("A Host header is required.");
+    };
+    // the http port says nothing about the https one
+    let host = match host.rsplit_once(':') {
+        Some((name, port)) if !port.contains(']') => name,
+        _ => host,
+    };
+    permanent(request, &format!("https://{}{}", host, request.target))
+}
+
+/// middleware redirecting paths ending in a slash to the path without it
+pub fn remove_trailing_slash<T>(
+    request: &mut HTTPRequest,
+    passthrough: &T,
+    next: Next<T>,
+) -> HTTPResponse {
+    match canonical_redirect(request) {
+        Some(response) => response,
+        None => next.run(request, passthrough),
+    }
+}
+
+/// a redirect to the request's path without trailing slashes, if it has any
+pub(crate) fn canonical_redirect(request: &HTTPRequest) -> Option<HTTPResponse> {
+    let (path, query) = split_target(&request.target);
+    if path.len() > 1 && path.ends_with('/') {
+        let location = format!("{}{}", local_path(path.trim_end_matches('/')), query);
+        return Some(permanent(request, &location));
+    }
+    None
+}
+
+/// middleware redirecting paths without a trailing slash to the path with it
+pub fn add_trailing_slash<T>(
+    request: &mut HTTPRequest,
+    passthrough: &T,
+    next: Next<T>,
+) -> HTTPResponse {
+    let (path, query) = split_target(&request.target);
+    if path.starts_with('/') && !path.ends_with('/') {
+        return permanent(request, &format!("{}/{}", local_path(path), query));
+    }
+    next.run(request, passthrough)
+}
+
+/// 301 for GET and HEAD, 308 for everything else so the method and body survive
+fn permanent(request: &HTTPRequest, location: &str) -> HTTPResponse {
+    match request.method {
+        HTTPMethod::GET | HTTPMethod::HEAD => redirect_301(location),
+        _ => redirect_308(location),
+    }
+}
+
+/// the path of a request target, and its query including the `?`
+fn split_target(target: &str) -> (&str, &str) {
+    target.split_at(target.find('?').unwrap_or(target.len()))
+}
+
+/// `path` with a single leading slash, clients would read `//host` as another server
+fn local_path(path: &str) -> String {
+    format!("/{}", path.trim_start_matches('/'))
+}
+
+/// `value` safe to put in html text and in attribute values quoted either way
+pub(crate) fn escape_html(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#x27;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_html() {
+        assert_eq!(
+            escape_html("<a href='x' title=\"&amp;\">"),
+            "&lt;a href=&#x27;x&#x27; title=&quot;&amp;amp;&quot;&gt;"
+        );
+        assert_eq!(escape_html("plain text"), "plain text");
+    }
+
+    #[test]
+    fn redirects_link_to_the_escaped_location() {
+        let response = redirect_302("/search?q='<x>'&page=2");
+        assert_eq!(response.status.status, 302);
+        assert_eq!(
+            response.headers.get("Location"),
+            Some("/search?q='<x>'&page=2")
+        );
+        let body = String::from_utf8(response.body.into_bytes().unwrap()).unwrap();
+        assert!(body.contains("<a href=\"/search?q=&#x27;&lt;x&gt;&#x27;&amp;page=2\">"));
+    }
+}
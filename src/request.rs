@@ -0,0 +1,133 @@
+use std::{collections::HashMap, net::SocketAddr};
+
+use crate::{
+    body::RequestBody,
+    extensions::Extensions,
+    form::QueryParams,
+    headers::HeaderMap,
+    http_server::{
+        split_absolute_form, trim_path, HTTPMethod, HTTPRequest, HTTPVersion, TrailingSlash,
+    },
+};
+
+/// builds a `HTTPRequest` as if it had been read from a connection, for tests and middleware
+pub struct HTTPRequestBuilder {
+    method: HTTPMethod,
+    version: HTTPVersion,
+    target: String,
+    headers: HeaderMap,
+    body: Vec<u8>,
+    path_params: HashMap<String, String>,
+    peer_addr: Option<SocketAddr>,
+    secure: bool,
+}
+
+impl HTTPRequest {
+    /// a `GET /` over HTTP/1.1 to start from
+    pub fn builder() -> HTTPRequestBuilder {
+        HTTPRequestBuilder {
+            method: HTTPMethod::GET,
+            version: HTTPVersion::HTTP11,
+            target: String::from("/"),
+            headers: HeaderMap::new(),
+            body: Vec::new(),
+            path_params: HashMap::new(),
+            peer_addr: None,
+            secure: false,
+        }
+    }
+}
+
+impl HTTPRequestBuilder {
+    pub fn method(mut self, method: HTTPMethod) -> Self {
+        self.method = method;
+        self
+    }
+
+    pub fn version(mut self, version: HTTPVersion) -> Self {
+        self.version = version;
+        self
+    }
+
+    /// the request target, a path with an optional query like `/users?page=2`, or an
+    /// absolute url like `http://example.com/users` as sent to proxies
+    pub fn path(mut self, target: &str) -> Self {
+        self.target = String::from(target);
+        self
+    }
+
+    /// set a header, replacing any previous value of it
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.insert(name, value);
+        self
+    }
+
+    /// add another value for a header that may be sent several times
+    pub fn append_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.append(name, value);
+        self
+    }
+
+    /// the body, filling in Content-Length
+    pub fn body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.body = body.into();
+        let length = self.body.len().to_string();
+        self.header("Content-Length", &length)
+    }
+
+    /// a named segment of the route, as the router would have set it
+    pub fn path_param(mut self, name: &str, value: &str) -> Self {
+        self.path_params
+            .insert(String::from(name), String::from(value));
+        self
+    }
+
+    /// the address of the client, also taken as its `client_ip`
+    pub fn peer_addr(mut self, addr: SocketAddr) -> Self {
+        self.peer_addr = Some(addr);
+        self
+    }
+
+    /// whether the request came in over tls
+    pub fn secure(mut self, secure: bool) -> Self {
+        self.secure = secure;
+        self
+    }
+
+    /// the request, with trailing slashes stripped from its path like a default server does
+    pub fn build(self) -> HTTPRequest {
+        self.build_with(TrailingSlash::default())
+    }
+
+    pub(crate) fn build_with(mut self, trailing_slash: TrailingSlash) -> HTTPRequest {
+        let (scheme, authority, target) = match split_absolute_form(&self.target) {
+            Some((scheme, authority, target)) => {
+                self.headers.insert("Host", authority);
+                (Some(scheme), Some(String::from(authority)), target)
+            }
+            None => (None, None, self.target),
+        };
+        let (location, query) = match target.find('?') {
+            Some(index) => target.split_at(index),
+            None => (target.as_str(), ""),
+        };
+        HTTPRequest {
+            method: self.method,
+            version: self.version,
+            path: trim_path(location, trailing_slash),
+            query_params: QueryParams::parse(query),
+            target,
+            scheme,
+            authority,
+            headers: self.headers,
+            path_params: self.path_params,
+            route: None,
+            body: RequestBody::from(self.body),
+            peer_addr: self.peer_addr,
+            local_addr: None,
+            client_ip: self.peer_addr.map(|addr| addr.ip()),
+            secure: self.secure,
+            extensions: Extensions::new(),
+        }
+    }
+}
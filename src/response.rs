@@ -0,0 +1,165 @@
+use std::fmt::Write;
+
+use crate::{
+    headers::HeaderMap,
+    http_server::{Body, HTTPResponse, HTTPStatus},
+};
+
+/// builds a `HTTPResponse`, filling in Content-Length and Content-Type from the body
+pub struct HTTPResponseBuilder {
+    status: HTTPStatus,
+    headers: HeaderMap,
+}
+
+/// what a listener may return: a `HTTPResponse`, anything converting into one like a
+/// `String`, `(404, "no such user")` or a JSON `Value`, or a `Result` of those like
+/// `Result<HTTPResponse, HTTPError>`, so listeners can use `?`. An error type
+/// of the application only needs `From<E> for HTTPResponse` to be returned
+pub trait IntoResponse {
+    fn into_response(self) -> HTTPResponse;
+}
+
+impl HTTPResponse {
+    pub fn builder() -> HTTPResponseBuilder {
+        HTTPResponseBuilder {
+            status: HTTPStatus::new(200),
+            headers: HeaderMap::new(),
+        }
+    }
+}
+
+impl HTTPResponseBuilder {
+    pub fn status(mut self, code: u16) -> Self {
+        self.status = HTTPStatus::new(code);
+        self
+    }
+
+    /// a status with a reason phrase of its own, like `.status_with_reason(599, "Upstream Down")`
+    pub fn status_with_reason(mut self, code: u16, reason: &str) -> Self {
+        self.status = HTTPStatus::with_reason(code, reason);
+        self
+    }
+
+    /// set a header, replacing any previous value of it
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.insert(name, value);
+        self
+    }
+
+    /// add another value for a header that may be sent several times
+    pub fn append_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.append(name, value);
+        self
+    }
+
+    /// `text/plain` body
+    pub fn text(self, body: impl Into<String>) -> HTTPResponse {
+        self.full(body.into().into_bytes(), "text/plain; charset=utf-8")
+    }
+
+    /// `text/html` body
+    pub fn html(self, body: impl Into<String>) -> HTTPResponse {
+        self.full(body.into().into_bytes(), "text/html; charset=utf-8")
+    }
+
+    /// already serialized `application/json` body
+    pub fn json(self, body: impl Into<String>) -> HTTPResponse {
+        self.full(body.into().into_bytes(), "application/json")
+    }
+
+    /// binary body, sent as `application/octet-stream` unless a Content-Type header was set
+    pub fn bytes(self, body: Vec<u8>) -> HTTPResponse {
+        self.full(body, "application/octet-stream")
+    }
+
+    /// any body. Only Content-Length is filled in, and only for `Body::Full`
+    pub fn body(mut self, body: Body) -> HTTPResponse {
+        if let Body::Full(ref bytes) = body {
+            self = self.header("Content-Length", &bytes.len().to_string());
+        }
+        HTTPResponse {
+            status: self.status,
+            headers: self.headers,
+            body,
+        }
+    }
+
+    /// response without a body
+    pub fn empty(self) -> HTTPResponse {
+        self.body(Body::Full(Vec::new()))
+    }
+
+    fn full(mut self, body: Vec<u8>, content_type: &str) -> HTTPResponse {
+        if !self.headers.contains("Content-Type") {
+            self = self.header("Content-Type", content_type);
+        }
+        self.body(Body::Full(body))
+    }
+}
+
+/// a response itself, and errors of the application that convert into one
+impl<T: Into<HTTPResponse>> IntoResponse for T {
+    fn into_response(self) -> HTTPResponse {
+        self.into()
+    }
+}
+
+/// a `text/plain` body
+impl From<String> for HTTPResponse {
+    fn from(body: String) -> HTTPResponse {
+        HTTPResponse::builder().text(body)
+    }
+}
+
+impl From<&str> for HTTPResponse {
+    fn from(body: &str) -> HTTPResponse {
+        HTTPResponse::builder().text(body)
+    }
+}
+
+/// an `application/octet-stream` body
+impl From<Vec<u8>> for HTTPResponse {
+    fn from(body: Vec<u8>) -> HTTPResponse {
+        HTTPResponse::builder().bytes(body)
+    }
+}
+
+/// a status with a `text/plain` body, like `(404, String::from("no such user"))`
+impl From<(u16, String)> for HTTPResponse {
+    fn from((status, body): (u16, String)) -> HTTPResponse {
+        HTTPResponse::builder().status(status).text(body)
+    }
+}
+
+impl From<(u16, &str)> for HTTPResponse {
+    fn from((status, body): (u16, &str)) -> HTTPResponse {
+        HTTPResponse::builder().status(status).text(body)
+    }
+}
+
+/// the response of whichever side, so errors can be returned with `?`
+impl<R: IntoResponse, E: IntoResponse> IntoResponse for Result<R, E> {
+    fn into_response(self) -> HTTPResponse {
+        match self {
+            Ok(response) => response.into_response(),
+            Err(error) => error.into_response(),
+        }
+    }
+}
+
+/// `value` as a quoted JSON string
+pub(crate) fn json_string(value: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(quoted, "\\u{:04x}", c as u32);
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
@@ -0,0 +1,367 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    headers::HeaderMap,
+    http_server::{Body, HTTPMethod, HTTPRequest, HTTPResponse, HTTPStatus},
+    middleware::Next,
+};
+
+/// statuses that are cacheable without explicit freshness, like RFC 9111 allows
+const CACHEABLE_STATUSES: [u16; 6] = [200, 203, 204, 301, 404, 410];
+
+/// responses to GET and HEAD kept in memory, keyed by method, path and query. Meant for
+/// expensive read mostly endpoints, as middleware of the scope holding them. Responses are
+/// kept for their `max-age` or `s-maxage`, or the cache's ttl without one, and are left out
+/// if they're `private`, `no-store`, `no-cache`, set cookies, vary on `*` or stream their
+/// body. `Vary` is honoured by keeping a variant per value of the listed request headers.
+/// Requests with `Authorization` are only answered with, and their responses only kept if,
+/// responses saying they may be shared with `public`, `s-maxage` or `must-revalidate`, so one
+/// user's response doesn't reach another.
+/// Clones share the same entries, so one can be kept around for invalidating
+#[derive(Clone)]
+pub struct ResponseCache {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    ttl: Duration,
+    max_entries: usize,
+    entries: Mutex<HashMap<String, Vec<Entry>>>,
+}
+
+struct Entry {
+    /// the request headers named by `Vary` and their values when it was stored
+    vary: Vec<(String, Option<String>)>,
+    /// whether the response may answer requests with `Authorization`
+    shared: bool,
+    status: u16,
+    reason: String,
+    headers: HeaderMap,
+    body: Vec<u8>,
+    stored: Instant,
+    expires: Instant,
+}
+
+impl ResponseCache {
+    /// keep responses without a max age for `ttl`, and at most `max_entries` of them
+    pub fn new(ttl: Duration, max_entries: usize) -> ResponseCache {
+        ResponseCache {
+            shared: Arc::new(Shared {
+                ttl,
+                max_entries,
+                entries: Mutex::new(HashMap::new()),
+            }),
+        }
+    }
+
+    /// forget every response for `path`, whatever its query
+    pub fn invalidate(&self, path: &str) {
+        self.invalidate_where(|key_path| key_path == path);
+    }
+
+    /// forget every response for paths starting with `prefix`
+    pub fn invalidate_prefix(&self, prefix: &str) {
+        self.invalidate_where(|key_path| key_path.starts_with(prefix));
+    }
+
+    pub fn clear(&self) {
+        self.shared.entries.lock().unwrap().clear();
+    }
+
+    /// the number of responses kept, including expired ones not yet evicted
+    pub fn len(&self) -> usize {
+        let entries = self.shared.entries.lock().unwrap();
+        entries.values().map(Vec::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// middleware answering from the cache, and storing what passes through it. Served
+    /// responses carry their `Age`
+    pub fn middleware<T>(
+        &self,
+    ) -> impl Fn(&mut HTTPRequest, &T, Next<T>) -> HTTPResponse + Send + Sync + 'static {
+        let cache = self.clone();
+        move |request: &mut HTTPRequest, passthrough: &T, next: Next<T>| {
+            if request.method != HTTPMethod::GET && request.method != HTTPMethod::HEAD {
+                return next.run(request, passthrough);
+            }
+            let key = format!("{:?} {}", request.method, request.target);
+            let bypass = request.headers.has_token("Cache-Control", "no-cache")
+                || request.headers.get("Pragma") == Some("no-cache");
+            if !bypass {
+                if let Some(response) = cache.lookup(&key, request) {
+                    return response;
+                }
+            }
+
+            let response = next.run(request, passthrough);
+            if request.headers.has_token("Cache-Control", "no-store") {
+                return response;
+            }
+            cache.store(key, request, response)
+        }
+    }
+
+    fn lookup(&self, key: &str, request: &HTTPRequest) -> Option<HTTPResponse> {
+        let authorized = request.headers.contains("Authorization");
+        let entries = self.shared.entries.lock().unwrap();
+        let now = Instant::now();
+        let entry = entries.get(key)?.iter().find(|entry| {
+            entry.expires > now
+                && (entry.shared || !authorized)
+                && entry
+                    .vary
+                    .iter()
+                    .all(|(name, value)| request.headers.get(name) == value.as_deref())
+        })?;
+
+        let mut headers = entry.headers.clone();
+        headers.insert(
+            "Age",
+            now.duration_since(entry.stored).as_secs().to_string(),
+        );
+        Some(HTTPResponse {
+            status: HTTPStatus::with_reason(entry.status, &entry.reason),
+            headers,
+            body: Body::Full(entry.body.clone()),
+        })
+    }
+
+    /// keep `response` if it's cacheable, handing it on either way
+    fn store(&self, key: String, request: &HTTPRequest, response: HTTPResponse) -> HTTPResponse {
+        let directives = directives(&response.headers);
+        let Some(ttl) = self.freshness(&response, &directives) else {
+            return response;
+        };
+        // RFC 9111 3.5, only responses that say so may be reused for other users
+        let shared = directives.iter().any(|directive| {
+            directive == "public"
+                || directive == "must-revalidate"
+                || directive.starts_with("s-maxage=")
+        });
+        if request.headers.contains("Authorization") && !shared {
+            return response;
+        }
+        let Some(vary) = vary_values(request, &response.headers) else {
+            return response;
+        };
+        let Body::Full(body) = response.body else {
+            return response;
+        };
+
+        let now = Instant::now();
+        let entry = Entry {
+            vary,
+            shared,
+            status: response.status.status,
+            reason: response.status.reason.clone(),
+            headers: response.headers.clone(),
+            body: body.clone(),
+            stored: now,
+            expires: now + ttl,
+        };
+        let mut entries = self.shared.entries.lock().unwrap();
+        let mut count: usize = entries.values().map(Vec::len).sum();
+        if count >= self.shared.max_entries {
+            entries.retain(|_, variants| {
+                variants.retain(|variant| variant.expires > now);
+                !variants.is_empty()
+            });
+            count = entries.values().map(Vec::len).sum();
+        }
+        let variants = entries.entry(key).or_default();
+        if let Some(position) = variants
+            .iter()
+            .position(|variant| variant.vary == entry.vary)
+        {
+            variants.remove(position);
+            count -= 1;
+        }
+        variants.push(entry);
+        if count >= self.shared.max_entries {
+            evict_oldest(&mut entries);
+        }
+        drop(entries);
+
+        HTTPResponse {
+            status: response.status,
+            headers: response.headers,
+            body: Body::Full(body),
+        }
+    }
+
+    /// how long `response` may be kept, `None` if it mustn't be
+    fn freshness(&self, response: &HTTPResponse, directives: &[String]) -> Option<Duration> {
+        if !CACHEABLE_STATUSES.contains(&response.status.status)
+            || response.headers.contains("Set-Cookie")
+        {
+            return None;
+        }
+        let refused = ["no-store", "no-cache", "private"];
+        if directives
+            .iter()
+            .any(|directive| refused.contains(&directive.as_str()))
+        {
+            return None;
+        }
+        let max_age = |name: &str| {
+            directives.iter().find_map(|directive| {
+                let seconds = directive.strip_prefix(name)?.strip_prefix('=')?;
+                seconds
+                    .trim_matches('"')
+                    .parse()
+                    .ok()
+                    .map(Duration::from_secs)
+            })
+        };
+        let ttl = max_age("s-maxage")
+            .or_else(|| max_age("max-age"))
+            .unwrap_or(self.shared.ttl);
+        (!ttl.is_zero() && self.shared.max_entries > 0).then_some(ttl)
+    }
+
+    fn invalidate_where(&self, matches: impl Fn(&str) -> bool) {
+        self.shared.entries.lock().unwrap().retain(|key, _| {
+            let target = key.split_once(' ').map_or("", |(_, target)| target);
+            let path = target.split_once('?').map_or(target, |(path, _)| path);
+            !matches(path)
+        });
+    }
+}
+
+/// the lowercase Cache-Control directives of a response
+fn directives(headers: &HeaderMap) -> Vec<String> {
+    headers
+        .get_all("Cache-Control")
+        .iter()
+        .flat_map(|value| value.split(','))
+        .map(|directive| directive.trim().to_ascii_lowercase())
+        .collect()
+}
+
+/// the values of the request headers the response varies on, `None` for `Vary: *`
+fn vary_values(
+    request: &HTTPRequest,
+    headers: &HeaderMap,
+) -> Option<Vec<(String, Option<String>)>> {
+    let mut vary = Vec::new();
+    for name in headers
+        .get_all("Vary")
+        .iter()
+        .flat_map(|value| value.split(','))
+    {
+        let name = name.trim();
+        match name {
+            "" => {}
+            "*" => return None,
+            name => vary.push((
+                name.to_ascii_lowercase(),
+                request.headers.get(name).map(String::from),
+            )),
+        }
+    }
+    Some(vary)
+}
+
+fn evict_oldest(entries: &mut HashMap<String, Vec<Entry>>) {
+    let oldest = entries
+        .iter()
+        .flat_map(|(key, variants)| {
+            variants
+                .iter()
+                .enumerate()
+                .map(move |(index, variant)| (variant.stored, key, index))
+        })
+        .min_by_key(|(stored, _, _)| *stored)
+        .map(|(_, key, index)| (key.clone(), index));
+    if let Some((key, index)) = oldest {
+        let variants = entries.get_mut(&key).unwrap();
+        variants.remove(index);
+        if variants.is_empty() {
+            entries.remove(&key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::{http_server::HTTPServer, testing::TestClient};
+
+    /// a client for a cached server, whose routes count the requests reaching them
+    fn client(cache: &ResponseCache) -> TestClient<AtomicUsize> {
+        let user = |request: &HTTPRequest| {
+            String::from(request.header("Authorization").unwrap_or("anonymous"))
+        };
+        let server = HTTPServer::builder(AtomicUsize::new(0))
+            .middleware(cache.middleware())
+            .get("/me", move |request: &HTTPRequest, hits: &AtomicUsize| {
+                hits.fetch_add(1, Ordering::SeqCst);
+                HTTPResponse::builder()
+                    .header("Cache-Control", "max-age=60")
+                    .text(user(request))
+            })
+            .get("/news", move |request: &HTTPRequest, hits: &AtomicUsize| {
+                hits.fetch_add(1, Ordering::SeqCst);
+                HTTPResponse::builder()
+                    .header("Cache-Control", "public, max-age=60")
+                    .text(user(request))
+            })
+            .build();
+        TestClient::new(server)
+    }
+
+    fn get(client: &TestClient<AtomicUsize>, target: &str, user: Option<&str>) -> String {
+        let mut request = client.get(target);
+        if let Some(user) = user {
+            request = request.header("Authorization", user);
+        }
+        String::from_utf8(request.send().body.into_bytes().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn answers_repeated_requests() {
+        let cache = ResponseCache::new(Duration::from_secs(60), 10);
+        let client = client(&cache);
+        assert_eq!(get(&client, "/me", None), "anonymous");
+        assert_eq!(get(&client, "/me", None), "anonymous");
+        assert_eq!(cache.len(), 1);
+        assert!(client.get("/me").send().headers.contains("Age"));
+    }
+
+    #[test]
+    fn keeps_authorized_responses_to_their_user() {
+        let cache = ResponseCache::new(Duration::from_secs(60), 10);
+        let client = client(&cache);
+        assert_eq!(get(&client, "/me", Some("alice")), "alice");
+        assert_eq!(get(&client, "/me", Some("bob")), "bob");
+        assert_eq!(get(&client, "/me", None), "anonymous");
+        // only the anonymous response is kept, and it isn't given to users
+        assert_eq!(cache.len(), 1);
+        assert_eq!(get(&client, "/me", Some("alice")), "alice");
+        assert!(!client
+            .get("/me")
+            .header("Authorization", "alice")
+            .send()
+            .headers
+            .contains("Age"));
+    }
+
+    #[test]
+    fn shares_authorized_responses_marked_public() {
+        let cache = ResponseCache::new(Duration::from_secs(60), 10);
+        let client = client(&cache);
+        assert_eq!(get(&client, "/news", Some("alice")), "alice");
+        assert_eq!(get(&client, "/news", None), "alice");
+        assert_eq!(get(&client, "/news", Some("bob")), "alice");
+    }
+}
@@ -0,0 +1,270 @@
+use std::collections::HashMap;
+
+use crate::{
+    http_server::{HTTPMethod, Route},
+    scope::join_paths,
+};
+
+/// maps request paths to routes. Patterns may contain named segments like `/users/:id`,
+/// which are handed to the listener as path params, and may end in a wildcard like
+/// `/static/*path` or `/api/**` matching any suffix. The suffix is passed as the param
+/// named after the wildcard, or `*` for an unnamed one
+pub struct Router<T: std::marker::Sync + std::marker::Send + 'static> {
+    exact: HashMap<String, Vec<Route<T>>>,
+    /// the pattern as registered, its segments and routes
+    patterns: Vec<(String, Vec<Segment>, Vec<Route<T>>)>,
+}
+
+pub enum RouteMatch<'a, T: std::marker::Sync + std::marker::Send + 'static> {
+    /// the route, its path params and the pattern it was registered with
+    Found(&'a Route<T>, HashMap<&'a str, &'a str>, &'a str),
+    /// the path exists, but none of its routes accept the method. Holds the methods it does accept,
+    /// sorted and without duplicates
+    MethodNotAllowed(Vec<&'a HTTPMethod>),
+    NotFound,
+}
+
+/// a registered route as listed by `HTTPServer::routes`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RouteInfo {
+    pub method: HTTPMethod,
+    /// the full pattern, including the prefixes of scopes and mounts
+    pub pattern: String,
+    /// the host the route is served for, `None` for the default routes
+    pub host: Option<String>,
+    pub name: Option<String>,
+    pub description: Option<String>,
+}
+
+#[derive(PartialEq, Eq)]
+enum Segment {
+    Static(String),
+    Param(String),
+    Wildcard(String),
+}
+
+impl<T: std::marker::Sync + std::marker::Send + 'static> Router<T> {
+    pub fn new() -> Router<T> {
+        Router {
+            exact: HashMap::new(),
+            patterns: Vec::new(),
+        }
+    }
+
+    /// register a route. Several routes may share a pattern if they accept different methods
+    pub fn add(&mut self, pattern: &str, route: Route<T>) {
+        let segments = parse_pattern(pattern);
+
+        if segments.iter().all(|s| matches!(s, Segment::Static(_))) {
+            self.exact
+                .entry(String::from(pattern))
+                .or_default()
+                .push(route);
+            return;
+        }
+
+        match self.patterns.iter_mut().find(|(_, s, _)| *s == segments) {
+            Some((_, _, routes)) => routes.push(route),
+            None => self
+                .patterns
+                .push((String::from(pattern), segments, vec![route])),
+        }
+    }
+
+    /// move all routes of `router` into this one, below `prefix`. Lets parts of an application
+    /// define their routes on their own
+    pub fn mount(&mut self, prefix: &str, router: Router<T>) {
+        for (pattern, route) in router.into_routes() {
+            self.add(&join_paths(prefix, &pattern), route);
+        }
+    }
+
+    /// every route with the pattern it was registered with
+    pub(crate) fn into_routes(self) -> Vec<(String, Route<T>)> {
+        let exact = self
+            .exact
+            .into_iter()
+            .flat_map(|(pattern, routes)| routes.into_iter().map(move |r| (pattern.clone(), r)));
+        let patterns = self
+            .patterns
+            .into_iter()
+            .flat_map(|(pattern, _, routes)| routes.into_iter().map(move |r| (pattern.clone(), r)));
+        exact.chain(patterns).collect()
+    }
+
+    /// every route, one entry per method, sorted by pattern and method
+    pub fn routes(&self) -> Vec<RouteInfo> {
+        let exact = self.exact.iter();
+        let patterns = self
+            .patterns
+            .iter()
+            .map(|(pattern, _, routes)| (pattern, routes));
+        let mut routes: Vec<RouteInfo> = exact
+            .chain(patterns)
+            .flat_map(|(pattern, routes)| {
+                routes.iter().flat_map(move |route| {
+                    route.methods.iter().map(move |method| RouteInfo {
+                        method: method.clone(),
+                        pattern: pattern.clone(),
+                        host: None,
+                        name: route.name.clone(),
+                        description: route.description.clone(),
+                    })
+                })
+            })
+            .collect();
+        routes.sort_by(|a, b| (&a.pattern, &a.method).cmp(&(&b.pattern, &b.method)));
+        routes
+    }
+
+    /// every method any route accepts, sorted and without duplicates
+    pub(crate) fn methods(&self) -> Vec<&HTTPMethod> {
+        let mut methods: Vec<&HTTPMethod> = self
+            .exact
+            .values()
+            .chain(self.patterns.iter().map(|(_, _, routes)| routes))
+            .flatten()
+            .flat_map(|route| route.methods.iter())
+            .collect();
+        if methods.contains(&&HTTPMethod::GET) {
+            methods.push(&HTTPMethod::HEAD);
+        }
+        methods.sort();
+        methods.dedup();
+        methods
+    }
+
+    /// find the route for `path` and `method`. Exact paths take precedence over patterns,
+    /// and wildcard patterns are tried last. Otherwise patterns are tried in the order they were added.
+    /// HEAD requests fall back to the GET route of a path without a HEAD route
+    pub fn route<'a>(&'a self, path: &'a str, method: &HTTPMethod) -> RouteMatch<'a, T> {
+        let exact = self
+            .exact
+            .get_key_value(path)
+            .map(|(pattern, routes)| (pattern.as_str(), routes, HashMap::new()));
+        let matching = |wildcard: bool| {
+            self.patterns
+                .iter()
+                .filter(move |(_, segments, _)| has_wildcard(segments) == wildcard)
+                .filter_map(|(pattern, segments, routes)| {
+                    match_segments(segments, path).map(|params| (pattern.as_str(), routes, params))
+                })
+        };
+
+        let mut path_exists = false;
+        let mut allowed = Vec::new();
+        for (pattern, routes, params) in exact
+            .into_iter()
+            .chain(matching(false))
+            .chain(matching(true))
+        {
+            path_exists = true;
+            let route =
+                routes
+                    .iter()
+                    .find(|r| r.methods.contains(method))
+                    .or_else(|| match method {
+                        HTTPMethod::HEAD => {
+                            routes.iter().find(|r| r.methods.contains(&HTTPMethod::GET))
+                        }
+                        _ => None,
+                    });
+            match route {
+                Some(route) => return RouteMatch::Found(route, params, pattern),
+                None => allowed.extend(routes.iter().flat_map(|r| r.methods.iter())),
+            }
+        }
+
+        if !path_exists {
+            return RouteMatch::NotFound;
+        }
+        if allowed.contains(&&HTTPMethod::GET) {
+            allowed.push(&HTTPMethod::HEAD);
+        }
+        allowed.sort();
+        allowed.dedup();
+        RouteMatch::MethodNotAllowed(allowed)
+    }
+}
+
+impl<T: std::marker::Sync + std::marker::Send + 'static> Default for Router<T> {
+    fn default() -> Router<T> {
+        Router::new()
+    }
+}
+
+impl<T: std::marker::Sync + std::marker::Send + 'static> From<HashMap<String, Route<T>>>
+    for Router<T>
+{
+    fn from(routes: HashMap<String, Route<T>>) -> Router<T> {
+        let mut router = Router::new();
+        for (pattern, route) in routes {
+            router.add(&pattern, route);
+        }
+        router
+    }
+}
+
+fn parse_pattern(pattern: &str) -> Vec<Segment> {
+    let segments: Vec<Segment> = pattern
+        .split('/')
+        .map(|segment| {
+            if let Some(name) = segment.strip_prefix(':') {
+                Segment::Param(String::from(name))
+            } else if let Some(name) = segment.strip_prefix('*') {
+                Segment::Wildcard(String::from(match name {
+                    "" | "*" => "*",
+                    name => name,
+                }))
+            } else {
+                Segment::Static(String::from(segment))
+            }
+        })
+        .collect();
+
+    if let Some(i) = segments
+        .iter()
+        .position(|s| matches!(s, Segment::Wildcard(_)))
+    {
+        assert!(
+            i == segments.len() - 1,
+            "wildcards must be the last segment of route `{pattern}`"
+        );
+    }
+    segments
+}
+
+fn has_wildcard(segments: &[Segment]) -> bool {
+    matches!(segments.last(), Some(Segment::Wildcard(_)))
+}
+
+fn match_segments<'a>(segments: &'a [Segment], path: &'a str) -> Option<HashMap<&'a str, &'a str>> {
+    let mut params = HashMap::new();
+    let mut rest = Some(path);
+
+    for segment in segments {
+        if let Segment::Wildcard(name) = segment {
+            params.insert(name.as_str(), rest.unwrap_or(""));
+            return Some(params);
+        }
+
+        let (part, next) = match rest?.split_once('/') {
+            Some((part, next)) => (part, Some(next)),
+            None => (rest?, None),
+        };
+        match segment {
+            Segment::Static(s) if s == part => {}
+            Segment::Param(name) if !part.is_empty() => {
+                params.insert(name.as_str(), part);
+            }
+            _ => return None,
+        }
+        rest = next;
+    }
+
+    // the whole path has to be consumed by the pattern
+    match rest {
+        Some(_) => None,
+        None => Some(params),
+    }
+}
@@ -0,0 +1,385 @@
+use crate::base64;
+
+/// DigestInfo of a SHA-256 hash, which the hash follows in a PKCS #1 v1.5 signature
+const SHA256_DIGEST_INFO: [u8; 19] = [
+    0x30, 0x31, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01, 0x05,
+    0x00, 0x04, 0x20,
+];
+
+/// the object identifier `1.2.840.113549.1.1.1` of rsaEncryption
+const RSA_ENCRYPTION: [u8; 9] = [0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x01];
+
+/// an RSA public key, only good for checking signatures
+#[derive(Clone)]
+pub(crate) struct PublicKey {
+    modulus: BigUint,
+    exponent: BigUint,
+    /// length of the modulus in bytes, which signatures share
+    size: usize,
+}
+
+impl PublicKey {
+    /// a key from its big-endian modulus and exponent
+    pub(crate) fn new(modulus: &[u8], exponent: &[u8]) -> Option<PublicKey> {
+        let modulus = BigUint::from_be_bytes(modulus);
+        let exponent = BigUint::from_be_bytes(exponent);
+        let size = modulus.bits().div_ceil(8);
+        // shorter keys can be broken, and the encoding needs some room
+        if size < 128 || exponent.is_zero() {
+            return None;
+        }
+        Some(PublicKey {
+            modulus,
+            exponent,
+            size,
+        })
+    }
+
+    /// a key from PEM, either `PUBLIC KEY` (SubjectPublicKeyInfo) or `RSA PUBLIC KEY` (PKCS #1)
+    pub(crate) fn from_pem(pem: &str) -> Option<PublicKey> {
+        let pem = pem.trim();
+        let (label, body) = pem.strip_prefix("-----BEGIN ")?.split_once("-----")?;
+        let body = body.strip_suffix(&format!("-----END {}-----", label))?;
+        let body: String = body.split_whitespace().collect();
+        let der = base64::decode(&body, base64::STANDARD)?;
+        match label {
+            "PUBLIC KEY" => PublicKey::from_spki(&der),
+            "RSA PUBLIC KEY" => PublicKey::from_pkcs1(&der),
+            _ => None,
+        }
+    }
+
+    /// SubjectPublicKeyInfo, wrapping the PKCS #1 key with its algorithm
+    fn from_spki(der: &[u8]) -> Option<PublicKey> {
+        let (info, _) = Der::new(der).expect(0x30)?;
+        let mut info = Der::new(info);
+        let (algorithm, rest) = info.expect(0x30)?;
+        info = rest;
+        let (oid, _) = Der::new(algorithm).expect(0x06)?;
+        if oid != RSA_ENCRYPTION {
+            return None;
+        }
+        let (bits, _) = info.expect(0x03)?;
+        // the first byte counts the unused bits, which a key doesn't have
+        match bits.split_first() {
+            Some((0, key)) => PublicKey::from_pkcs1(key),
+            _ => None,
+        }
+    }
+
+    fn from_pkcs1(der: &[u8]) -> Option<PublicKey> {
+        let (key, _) = Der::new(der).expect(0x30)?;
+        let (modulus, rest) = Der::new(key).expect(0x02)?;
+        let (exponent, _) = rest.expect(0x02)?;
+        PublicKey::new(modulus, exponent)
+    }
+
+    /// check a RSASSA-PKCS1-v1_5 signature of the SHA-256 `digest`
+    pub(crate) fn verify_sha256(&self, digest: &[u8; 32], signature: &[u8]) -> bool {
+        if signature.len() != self.size {
+            return false;
+        }
+        let signature = BigUint::from_be_bytes(signature);
+        if signature.cmp(&self.modulus).is_ge() {
+            return false;
+        }
+        let message = signature.pow_mod(&self.exponent, &self.modulus);
+
+        // 00 01 FF .. FF 00 DigestInfo digest
+        let mut expected = vec![0xff; self.size];
+        expected[0] = 0;
+        expected[1] = 1;
+        let info_start = self.size - SHA256_DIGEST_INFO.len() - digest.len();
+        expected[info_start - 1] = 0;
+        expected[info_start..self.size - digest.len()].copy_from_slice(&SHA256_DIGEST_INFO);
+        expected[self.size - digest.len()..].copy_from_slice(digest);
+        message.to_be_bytes(self.size) == expected
+    }
+}
+
+/// reads DER encoded values one after another
+#[derive(Clone, Copy)]
+struct Der<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Der<'a> {
+    fn new(bytes: &'a [u8]) -> Der<'a> {
+        Der { bytes }
+    }
+
+    /// the contents of the next value if it has `tag`, and what follows it
+    fn expect(self, tag: u8) -> Option<(&'a [u8], Der<'a>)> {
+        let (&found, rest) = self.bytes.split_first()?;
+        if found != tag {
+            return None;
+        }
+        let (&first, mut rest) = rest.split_first()?;
+        let length = if first < 0x80 {
+            first as usize
+        } else {
+            let count = (first & 0x7f) as usize;
+            if count == 0 || count > 4 || rest.len() < count {
+                return None;
+            }
+            let (length, after) = rest.split_at(count);
+            rest = after;
+            length
+                .iter()
+                .fold(0, |length, byte| length << 8 | *byte as usize)
+        };
+        if rest.len() < length {
+            return None;
+        }
+        let (contents, rest) = rest.split_at(length);
+        Some((contents, Der::new(rest)))
+    }
+}
+
+/// an unsigned integer of any size, little-endian 32 bit limbs without leading zeros
+#[derive(Clone, PartialEq, Eq, Debug)]
+struct BigUint {
+    limbs: Vec<u32>,
+}
+
+impl BigUint {
+    fn from_be_bytes(bytes: &[u8]) -> BigUint {
+        let mut limbs: Vec<u32> = bytes
+            .rchunks(4)
+            .map(|chunk| chunk.iter().fold(0, |limb, byte| limb << 8 | *byte as u32))
+            .collect();
+        trim(&mut limbs);
+        BigUint { limbs }
+    }
+
+    /// big-endian bytes, left-padded with zeros to `size`
+    fn to_be_bytes(&self, size: usize) -> Vec<u8> {
+        let bytes: Vec<u8> = self
+            .limbs
+            .iter()
+            .rev()
+            .flat_map(|limb| limb.to_be_bytes())
+            .skip_while(|byte| *byte == 0)
+            .collect();
+        let mut padded = vec![0; size.saturating_sub(bytes.len())];
+        padded.extend(bytes);
+        padded
+    }
+
+    fn is_zero(&self) -> bool {
+        self.limbs.is_empty()
+    }
+
+    fn bits(&self) -> usize {
+        match self.limbs.last() {
+            Some(top) => self.limbs.len() * 32 - top.leading_zeros() as usize,
+            None => 0,
+        }
+    }
+
+    fn bit(&self, i: usize) -> bool {
+        self.limbs
+            .get(i / 32)
+            .is_some_and(|limb| limb >> (i % 32) & 1 == 1)
+    }
+
+    fn cmp(&self, other: &BigUint) -> std::cmp::Ordering {
+        self.limbs
+            .len()
+            .cmp(&other.limbs.len())
+            .then_with(|| self.limbs.iter().rev().cmp(other.limbs.iter().rev()))
+    }
+
+    /// `self ^ exponent mod modulus` by square and multiply
+    fn pow_mod(&self, exponent: &BigUint, modulus: &BigUint) -> BigUint {
+        let mut result = BigUint { limbs: vec![1] };
+        for i in (0..exponent.bits()).rev() {
+            result = result.mul_mod(&result, modulus);
+            if exponent.bit(i) {
+                result = result.mul_mod(self, modulus);
+            }
+        }
+        result
+    }
+
+    fn mul_mod(&self, other: &BigUint, modulus: &BigUint) -> BigUint {
+        let mut product = vec![0u32; self.limbs.len() + other.limbs.len()];
+        for (i, a) in self.limbs.iter().enumerate() {
+            let mut carry = 0u64;
+            for (j, b) in other.limbs.iter().enumerate() {
+                let sum = product[i + j] as u64 + *a as u64 * *b as u64 + carry;
+                product[i + j] = sum as u32;
+                carry = sum >> 32;
+            }
+            product[i + other.limbs.len()] = carry as u32;
+        }
+        let product = BigUint { limbs: product };
+
+        // shift the product in bit by bit, subtracting the modulus whenever it fits
+        let mut remainder = BigUint { limbs: Vec::new() };
+        for i in (0..product.bits()).rev() {
+            remainder.shift_left_one(product.bit(i));
+            if remainder.cmp(modulus).is_ge() {
+                remainder.sub_assign(modulus);
+            }
+        }
+        remainder
+    }
+
+    fn shift_left_one(&mut self, low_bit: bool) {
+        let mut carry = low_bit as u32;
+        for limb in &mut self.limbs {
+            let next = *limb >> 31;
+            *limb = *limb << 1 | carry;
+            carry = next;
+        }
+        if carry != 0 {
+            self.limbs.push(carry);
+        }
+    }
+
+    /// `self -= other`, which must not be larger
+    fn sub_assign(&mut self, other: &BigUint) {
+        let mut borrow = 0i64;
+        for (i, limb) in self.limbs.iter_mut().enumerate() {
+            let difference = *limb as i64 - *other.limbs.get(i).unwrap_or(&0) as i64 - borrow;
+            borrow = (difference < 0) as i64;
+            *limb = difference.rem_euclid(1 << 32) as u32;
+        }
+        trim(&mut self.limbs);
+    }
+}
+
+fn trim(limbs: &mut Vec<u32>) {
+    while limbs.last() == Some(&0) {
+        limbs.pop();
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::sha256::sha256;
+
+    /// a 2048 bit key made with `openssl genrsa`
+    pub(crate) const PUBLIC_KEY: &str = "-----BEGIN PUBLIC KEY-----
+MIIBIjANBgkqhkiG9w0BAQEFAAOCAQ8AMIIBCgKCAQEAscHyb0vNAidA78zLAsMQ
+NvJXNLnn5GJjrKjtwY4g59QI5Y13rA0KHe0QJbVb0P3fiEvAYN55NeFEnr9XAecL
+hBMtpc7UMBT16VHeDn6v2bL3Q9canTGi4fEsFBfSd04FfpK9RunjHB4v7m4NgC6K
+AKcFhA1u/kCUGuzzNqeICZ8hROIbsdN4i6UtoEp3Cvv5oNM9G2kaSU64m56vMnP5
+G7M9mnUbghWqdACvqfR9pOuTuzoYwel+fUcDwBG4hRXIgyC1I3+BpAKY49uGRr7B
+jTFRz6tlAFKlYkMtzcitpo584gakg8x8idthlj2oTzZhN8doFMgDpgMRsWRBIKml
+7QIDAQAB
+-----END PUBLIC KEY-----";
+
+    /// the same key as PKCS #1
+    const RSA_PUBLIC_KEY: &str = "-----BEGIN RSA PUBLIC KEY-----
+MIIBCgKCAQEAscHyb0vNAidA78zLAsMQNvJXNLnn5GJjrKjtwY4g59QI5Y13rA0K
+He0QJbVb0P3fiEvAYN55NeFEnr9XAecLhBMtpc7UMBT16VHeDn6v2bL3Q9canTGi
+4fEsFBfSd04FfpK9RunjHB4v7m4NgC6KAKcFhA1u/kCUGuzzNqeICZ8hROIbsdN4
+i6UtoEp3Cvv5oNM9G2kaSU64m56vMnP5G7M9mnUbghWqdACvqfR9pOuTuzoYwel+
+fUcDwBG4hRXIgyC1I3+BpAKY49uGRr7BjTFRz6tlAFKlYkMtzcitpo584gakg8x8
+idthlj2oTzZhN8doFMgDpgMRsWRBIKml7QIDAQAB
+-----END RSA PUBLIC KEY-----";
+
+    pub(crate) const MODULUS: &str = concat!(
+        "b1c1f26f4bcd022740efcccb02c31036f25734b9e7e46263aca8edc18e20e7d408e58d77ac0d0a1ded1025b55bd0fddf",
+        "884bc060de7935e1449ebf5701e70b84132da5ced43014f5e951de0e7eafd9b2f743d71a9d31a2e1f12c1417d2774e05",
+        "7e92bd46e9e31c1e2fee6e0d802e8a00a705840d6efe40941aecf336a788099f2144e21bb1d3788ba52da04a770afbf9",
+        "a0d33d1b691a494eb89b9eaf3273f91bb33d9a751b8215aa7400afa9f47da4eb93bb3a18c1e97e7d4703c011b88515c8",
+        "8320b5237f81a40298e3db8646bec18d3151cfab650052a562432dcdc8ada68e7ce206a483cc7c89db61963da84f3661",
+        "37c76814c803a60311b1644120a9a5ed",
+    );
+
+    /// `openssl dgst -sha256 -sign` of `adhesion`
+    const SIGNATURE: &str = concat!(
+        "af11bff3d22d521ebf6c232cf9ad3cd6bd3285babf9a37c36b72b4967f647b8d7f940326e4a34843cf64ec936542b9ff",
+        "b5ca757092d1a9df733793f6e8a86deaaee09685d23542d0495e846060a32f15ae1ff60fd24a5cc0d626f5465c8ed4a3",
+        "c81c80638e89fae3bf7e1c34d451b87514b6590c541e56b00cfeee0c363c0a785ac2229b7eaccc96c9f1afba64bfb1f8",
+        "56c24fd2e536ed251c41ebc8f5863c2b75645e9c2553d887ea731cbb02e99b47d2e9aa97cb314b3637dd2fd7965e1168",
+        "44f31b7bec6ce2c55e520a334d2d5b50679bd68cdbe2caca28d2c380f70e5a630f3f4c0232baa56990498a4612eb3dcd",
+        "effb25d1645291095bebbdc821d14524",
+    );
+
+    pub(crate) fn unhex(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    fn big(value: u128) -> BigUint {
+        BigUint::from_be_bytes(&value.to_be_bytes())
+    }
+
+    #[test]
+    fn bytes_roundtrip() {
+        let value = BigUint::from_be_bytes(&[0, 0, 1, 2, 3, 4, 5]);
+        assert_eq!(value.limbs, vec![0x02030405, 0x01]);
+        assert_eq!(value.to_be_bytes(3), vec![1, 2, 3, 4, 5]);
+        assert_eq!(value.to_be_bytes(8), vec![0, 0, 0, 1, 2, 3, 4, 5]);
+        assert!(BigUint::from_be_bytes(&[0, 0]).is_zero());
+    }
+
+    #[test]
+    fn modular_arithmetic() {
+        let modulus = 0xfffffffb_u128; // prime
+        for (base, exponent) in [(2u128, 10u128), (0x1234_5678, 0xffff_fffa), (3, 0), (0, 5)] {
+            let mut expected = 1u128;
+            for _ in 0..exponent.min(64) {
+                expected = expected * base % modulus;
+            }
+            let got = big(base).pow_mod(&big(exponent), &big(modulus));
+            if exponent <= 64 {
+                assert_eq!(got, big(expected), "{}^{}", base, exponent);
+            } else {
+                // Fermat: a^(p-1) = 1 mod p
+                assert_eq!(got, big(1));
+            }
+        }
+        let a = 0xdead_beef_cafe_babe_u128;
+        let b = 0x0123_4567_89ab_cdef_u128;
+        let m = 0xffff_ffff_ffff_ffc5_u128;
+        assert_eq!(big(a).mul_mod(&big(b), &big(m)), big((a % m) * (b % m) % m));
+    }
+
+    #[test]
+    fn pem_formats_read_the_same_key() {
+        let spki = PublicKey::from_pem(PUBLIC_KEY).unwrap();
+        let pkcs1 = PublicKey::from_pem(RSA_PUBLIC_KEY).unwrap();
+        let components = PublicKey::new(&unhex(MODULUS), &[1, 0, 1]).unwrap();
+        assert_eq!(spki.modulus, components.modulus);
+        assert_eq!(pkcs1.modulus, components.modulus);
+        assert_eq!(spki.exponent, big(65537));
+        assert_eq!(spki.size, 256);
+    }
+
+    #[test]
+    fn malformed_and_short_keys_are_rejected() {
+        let mismatched = PUBLIC_KEY.replace("END PUBLIC KEY", "END RSA PUBLIC KEY");
+        assert!(PublicKey::from_pem(&mismatched).is_none());
+        assert!(PublicKey::from_pem(&PUBLIC_KEY.replace("MIIB", "MIIC")).is_none());
+        let short = "-----BEGIN RSA PUBLIC KEY-----
+MEgCQQDIGQStjbrC2jDdqo2S1RRheO6jPGq/bUGOtes741kGelora+c1k2hP7Cle
+21XwETXLyQXaB6VkL2ZTmuN2MFdNAgMBAAE=
+-----END RSA PUBLIC KEY-----";
+        assert!(PublicKey::from_pem(short).is_none());
+        assert!(PublicKey::new(&unhex(MODULUS), &[0]).is_none());
+    }
+
+    #[test]
+    fn pkcs1_v1_5_signature() {
+        let key = PublicKey::from_pem(PUBLIC_KEY).unwrap();
+        let signature = unhex(SIGNATURE);
+        assert!(key.verify_sha256(&sha256(b"adhesion"), &signature));
+        assert!(!key.verify_sha256(&sha256(b"adhesioN"), &signature));
+
+        let mut tampered = signature.clone();
+        tampered[100] ^= 0x01;
+        assert!(!key.verify_sha256(&sha256(b"adhesion"), &tampered));
+        assert!(!key.verify_sha256(&sha256(b"adhesion"), &signature[1..]));
+        // at or above the modulus
+        assert!(!key.verify_sha256(&sha256(b"adhesion"), &unhex(MODULUS)));
+        assert!(!key.verify_sha256(&sha256(b"adhesion"), &[0xff; 256]));
+    }
+}
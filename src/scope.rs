@@ -0,0 +1,156 @@
+use std::sync::Arc;
+
+use crate::{
+    http_server::{HTTPMethod, HTTPRequest, HTTPResponse, Route},
+    middleware::{Middleware, Next},
+    response::IntoResponse,
+    router::Router,
+};
+
+/// a group of routes sharing a path prefix and middleware, registered with
+/// `HTTPServerBuilder::scope`. The scope's middleware runs after the server's, and only for
+/// the scope's routes
+pub struct Scope<T: std::marker::Sync + std::marker::Send + 'static> {
+    prefix: String,
+    middleware: Vec<Middleware<T>>,
+    routes: Vec<(String, Route<T>)>,
+}
+
+impl<T: std::marker::Sync + std::marker::Send + 'static> Scope<T> {
+    pub fn new(prefix: &str) -> Scope<T> {
+        Scope {
+            prefix: String::from(prefix.trim_end_matches('/')),
+            middleware: Vec::new(),
+            routes: Vec::new(),
+        }
+    }
+
+    /// append `middleware` to the chain of this scope. It also wraps routes added before
+    pub fn middleware(
+        mut self,
+        middleware: impl Fn(&mut HTTPRequest, &T, Next<T>) -> HTTPResponse + Send + Sync + 'static,
+    ) -> Self {
+        self.middleware.push(Arc::new(middleware));
+        self
+    }
+
+    /// register `listener` for all `methods` on `pattern`, relative to the prefix
+    pub fn route<R: IntoResponse + 'static>(
+        mut self,
+        pattern: &str,
+        methods: Vec<HTTPMethod>,
+        listener: impl Fn(&HTTPRequest, &T) -> R + Send + Sync + 'static,
+    ) -> Self {
+        self.routes.push((
+            join_paths(&self.prefix, pattern),
+            Route::new(methods, listener),
+        ));
+        self
+    }
+
+    /// register a prepared route relative to the prefix, e.g. one with a name
+    pub fn add(mut self, pattern: &str, route: Route<T>) -> Self {
+        self.routes.push((join_paths(&self.prefix, pattern), route));
+        self
+    }
+
+    pub fn get<R: IntoResponse + 'static>(
+        self,
+        pattern: &str,
+        listener: impl Fn(&HTTPRequest, &T) -> R + Send + Sync + 'static,
+    ) -> Self {
+        self.route(pattern, vec![HTTPMethod::GET], listener)
+    }
+
+    pub fn head<R: IntoResponse + 'static>(
+        self,
+        pattern: &str,
+        listener: impl Fn(&HTTPRequest, &T) -> R + Send + Sync + 'static,
+    ) -> Self {
+        self.route(pattern, vec![HTTPMethod::HEAD], listener)
+    }
+
+    pub fn post<R: IntoResponse + 'static>(
+        self,
+        pattern: &str,
+        listener: impl Fn(&HTTPRequest, &T) -> R + Send + Sync + 'static,
+    ) -> Self {
+        self.route(pattern, vec![HTTPMethod::POST], listener)
+    }
+
+    pub fn put<R: IntoResponse + 'static>(
+        self,
+        pattern: &str,
+        listener: impl Fn(&HTTPRequest, &T) -> R + Send + Sync + 'static,
+    ) -> Self {
+        self.route(pattern, vec![HTTPMethod::PUT], listener)
+    }
+
+    pub fn delete<R: IntoResponse + 'static>(
+        self,
+        pattern: &str,
+        listener: impl Fn(&HTTPRequest, &T) -> R + Send + Sync + 'static,
+    ) -> Self {
+        self.route(pattern, vec![HTTPMethod::DELETE], listener)
+    }
+
+    pub fn patch<R: IntoResponse + 'static>(
+        self,
+        pattern: &str,
+        listener: impl Fn(&HTTPRequest, &T) -> R + Send + Sync + 'static,
+    ) -> Self {
+        self.route(pattern, vec![HTTPMethod::PATCH], listener)
+    }
+
+    /// answer OPTIONS for `pattern` instead of the server's automatic 204 with an Allow header
+    pub fn options<R: IntoResponse + 'static>(
+        self,
+        pattern: &str,
+        listener: impl Fn(&HTTPRequest, &T) -> R + Send + Sync + 'static,
+    ) -> Self {
+        self.route(pattern, vec![HTTPMethod::OPTIONS], listener)
+    }
+
+    /// register all routes of a separately built `router` below the prefix. They run this
+    /// scope's middleware too
+    pub fn mount(mut self, prefix: &str, router: Router<T>) -> Self {
+        let prefix = join_paths(&self.prefix, prefix);
+        self.routes.extend(
+            router
+                .into_routes()
+                .into_iter()
+                .map(|(pattern, route)| (join_paths(&prefix, &pattern), route)),
+        );
+        self
+    }
+
+    /// a nested scope below this one. Its routes run this scope's middleware first
+    pub fn scope(mut self, prefix: &str, build: impl FnOnce(Scope<T>) -> Scope<T>) -> Self {
+        let nested = build(Scope::new(&join_paths(&self.prefix, prefix)));
+        self.routes.extend(nested.into_routes());
+        self
+    }
+
+    /// the routes with full patterns, each wrapped in the scope's middleware
+    pub(crate) fn into_routes(self) -> Vec<(String, Route<T>)> {
+        let middleware = self.middleware;
+        self.routes
+            .into_iter()
+            .map(|(pattern, mut route)| {
+                route.middleware.splice(0..0, middleware.iter().cloned());
+                (pattern, route)
+            })
+            .collect()
+    }
+}
+
+/// `prefix` and `pattern` joined by a single slash. The prefix alone for an empty or `/` pattern
+pub(crate) fn join_paths(prefix: &str, pattern: &str) -> String {
+    let prefix = prefix.trim_end_matches('/');
+    let pattern = pattern.trim_start_matches('/');
+    match (prefix.is_empty(), pattern.is_empty()) {
+        (true, _) => format!("/{}", pattern),
+        (false, true) => String::from(prefix),
+        (false, false) => format!("{}/{}", prefix, pattern),
+    }
+}
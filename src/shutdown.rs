@@ -0,0 +1,288 @@
+use std::{
+    collections::HashMap,
+    net::{Shutdown, SocketAddr},
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Condvar, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    lifecycle::run_hooks,
+    signals,
+    socket::{Address, Socket},
+};
+
+/// called once a server has shut down and its requests are finished, e.g. to flush logs
+pub type ShutdownHook = Arc<dyn Fn() + Send + Sync>;
+
+/// a signal that stops servers set to `shutdown_on_signals`
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Signal {
+    /// SIGINT, as sent by Ctrl-C
+    Interrupt,
+    /// SIGTERM, as sent by service managers
+    Terminate,
+}
+
+impl Signal {
+    /// the status of a process killed by the signal, e.g. for `std::process::exit` once
+    /// `listen` returned
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Signal::Interrupt => 130,
+            Signal::Terminate => 143,
+        }
+    }
+}
+
+/// the signal last caught by a server set to `shutdown_on_signals`
+pub fn received_signal() -> Option<Signal> {
+    signals::last()
+}
+
+/// stops a server started with `HTTPServer::listen_with_shutdown`
+pub struct ShutdownHandle {
+    connections: Arc<Connections>,
+    /// every listener of the server, the first one being the main address
+    addresses: Vec<Address>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl ShutdownHandle {
+    pub(crate) fn new(
+        connections: Arc<Connections>,
+        address: Address,
+        thread: thread::JoinHandle<()>,
+    ) -> ShutdownHandle {
+        ShutdownHandle::with_addresses(connections, vec![address], thread)
+    }
+
+    pub(crate) fn with_addresses(
+        connections: Arc<Connections>,
+        addresses: Vec<Address>,
+        thread: thread::JoinHandle<()>,
+    ) -> ShutdownHandle {
+        ShutdownHandle {
+            connections,
+            addresses,
+            thread: Some(thread),
+        }
+    }
+
+    /// the first tcp address the server is listening on, `None` for a unix socket
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.local_addrs().first().copied()
+    }
+
+    /// every tcp address the server is listening on
+    pub fn local_addrs(&self) -> Vec<SocketAddr> {
+        self.addresses
+            .iter()
+            .filter_map(|address| match address {
+                Address::Tcp(addr) => Some(*addr),
+                Address::Unix(_) => None,
+            })
+            .collect()
+    }
+
+    /// the path of the unix socket the server is listening on
+    pub fn unix_path(&self) -> Option<&Path> {
+        self.addresses.iter().find_map(|address| match address {
+            Address::Tcp(_) => None,
+            Address::Unix(path) => Some(path.as_path()),
+        })
+    }
+
+    /// connections accepted and not yet closed
+    pub fn open_connections(&self) -> usize {
+        self.connections.gauge.get()
+    }
+
+    /// stop accepting connections, let in-flight requests finish until the server's
+    /// `drain_timeout` elapses and join the thread pool
+    pub fn shutdown(mut self) {
+        self.connections.stop(&self.addresses);
+        self.wait();
+    }
+
+    /// block until the server has shut down
+    pub fn wait(&mut self) {
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                println!("accept loop panicked during shutdown");
+            }
+        }
+    }
+}
+
+/// counts the connections of a server from being accepted until they are closed, including
+/// those still waiting for a worker. Clones share the count
+#[derive(Clone, Default)]
+pub struct ConnectionGauge {
+    inner: Arc<(Mutex<usize>, Condvar)>,
+}
+
+impl ConnectionGauge {
+    pub fn new() -> ConnectionGauge {
+        ConnectionGauge::default()
+    }
+
+    pub fn get(&self) -> usize {
+        *self.inner.0.lock().unwrap()
+    }
+
+    /// count another connection unless there already are `max`
+    pub(crate) fn try_acquire(&self, max: Option<usize>) -> Option<ConnectionSlot> {
+        let mut count = self.inner.0.lock().unwrap();
+        if max.is_some_and(|max| *count >= max) {
+            return None;
+        }
+        *count += 1;
+        Some(ConnectionSlot {
+            gauge: self.clone(),
+        })
+    }
+
+    /// block until there are fewer than `max` connections or `timeout` elapsed
+    pub(crate) fn wait_below(&self, max: usize, timeout: Duration) {
+        let (count, freed) = &*self.inner;
+        let count = count.lock().unwrap();
+        let _ = freed
+            .wait_timeout_while(count, timeout, |count| *count >= max)
+            .unwrap();
+    }
+}
+
+/// a counted connection, released when dropped
+pub(crate) struct ConnectionSlot {
+    gauge: ConnectionGauge,
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        let (count, freed) = &*self.gauge.inner;
+        *count.lock().unwrap() -= 1;
+        freed.notify_all();
+    }
+}
+
+/// the open connections of a server, so they can be drained on shutdown
+pub(crate) struct Connections {
+    /// every accepted connection, `open` only has those a worker picked up
+    pub(crate) gauge: ConnectionGauge,
+    next_id: AtomicUsize,
+    shutting_down: AtomicBool,
+    open: Mutex<HashMap<usize, Tracked>>,
+    /// run when the shutdown begins
+    begin_hooks: Arc<Vec<ShutdownHook>>,
+}
+
+struct Tracked {
+    socket: Socket,
+    /// waiting for the next request on a persistent connection
+    idle: bool,
+}
+
+impl Connections {
+    pub(crate) fn new(gauge: ConnectionGauge, begin_hooks: Arc<Vec<ShutdownHook>>) -> Connections {
+        Connections {
+            gauge,
+            next_id: AtomicUsize::new(0),
+            shutting_down: AtomicBool::new(false),
+            open: Mutex::new(HashMap::new()),
+            begin_hooks,
+        }
+    }
+
+    pub(crate) fn is_shutting_down(&self) -> bool {
+        self.shutting_down.load(Ordering::SeqCst)
+    }
+
+    /// start tracking `socket`. Returns `None` if the server is already shutting down
+    pub(crate) fn register(&self, socket: &Socket) -> Option<usize> {
+        let socket = socket.try_clone().ok()?;
+        let mut open = self.open.lock().unwrap();
+        if self.is_shutting_down() {
+            return None;
+        }
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        open.insert(id, Tracked { socket, idle: true });
+        Some(id)
+    }
+
+    /// mark a connection as idle or busy. Returns `false` if the server is shutting down
+    /// and the connection should be closed instead
+    pub(crate) fn set_idle(&self, id: usize, idle: bool) -> bool {
+        let mut open = self.open.lock().unwrap();
+        if self.is_shutting_down() {
+            return false;
+        }
+        if let Some(tracked) = open.get_mut(&id) {
+            tracked.idle = idle;
+        }
+        true
+    }
+
+    pub(crate) fn remove(&self, id: usize) {
+        self.open.lock().unwrap().remove(&id);
+    }
+
+    /// stop accepting new requests and wake connections waiting for one
+    pub(crate) fn begin_shutdown(&self) {
+        let open = self.open.lock().unwrap();
+        let already = self.shutting_down.swap(true, Ordering::SeqCst);
+        for tracked in open.values().filter(|t| t.idle) {
+            let _ = tracked.socket.shutdown(Shutdown::Read);
+        }
+        drop(open);
+        if !already {
+            run_hooks(&self.begin_hooks, |hook| hook());
+        }
+    }
+
+    /// begin the shutdown and wake up the accept loops blocked on `addresses`
+    pub(crate) fn stop(&self, addresses: &[Address]) {
+        self.begin_shutdown();
+        for address in addresses {
+            let _ = address.connect();
+        }
+    }
+
+    /// wait for busy connections to finish, closing whatever is left after `timeout`
+    pub(crate) fn drain(&self, timeout: Duration) {
+        let deadline = Instant::now() + timeout;
+        while Instant::now() < deadline {
+            if self.open.lock().unwrap().is_empty() {
+                return;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        for tracked in self.open.lock().unwrap().values() {
+            let _ = tracked.socket.shutdown(Shutdown::Both);
+        }
+    }
+}
+
+/// shut the server down gracefully once SIGINT or SIGTERM arrives. The watching thread ends
+/// with the server
+pub(crate) fn stop_on_signals(connections: Arc<Connections>, addresses: Vec<Address>) {
+    signals::install();
+    let seen = signals::count();
+    thread::spawn(move || {
+        while !connections.is_shutting_down() {
+            if signals::count() != seen {
+                if let Some(signal) = signals::last() {
+                    println!("received {:?}, shutting down", signal);
+                }
+                connections.stop(&addresses);
+                return;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+    });
+}
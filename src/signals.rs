@@ -0,0 +1,52 @@
+use std::sync::{
+    atomic::{AtomicI32, AtomicUsize, Ordering},
+    Once,
+};
+
+use crate::shutdown::Signal;
+
+/// the same numbers on every unix
+const SIGINT: i32 = 2;
+const SIGTERM: i32 = 15;
+
+/// the last signal caught, and how many were caught so far
+static LAST: AtomicI32 = AtomicI32::new(0);
+static COUNT: AtomicUsize = AtomicUsize::new(0);
+static INSTALL: Once = Once::new();
+
+#[cfg(unix)]
+extern "C" {
+    fn signal(signum: i32, handler: usize) -> usize;
+}
+
+/// only stores the signal, atomics being about all that's safe inside a handler
+#[cfg(unix)]
+extern "C" fn record(signum: i32) {
+    LAST.store(signum, Ordering::SeqCst);
+    COUNT.fetch_add(1, Ordering::SeqCst);
+}
+
+/// catch SIGINT and SIGTERM from now on instead of being killed by them. Does nothing on
+/// other platforms
+pub(crate) fn install() {
+    INSTALL.call_once(|| {
+        #[cfg(unix)]
+        unsafe {
+            signal(SIGINT, record as extern "C" fn(i32) as usize);
+            signal(SIGTERM, record as extern "C" fn(i32) as usize);
+        }
+    });
+}
+
+/// how many signals were caught so far, to tell new ones from old
+pub(crate) fn count() -> usize {
+    COUNT.load(Ordering::SeqCst)
+}
+
+pub(crate) fn last() -> Option<Signal> {
+    match LAST.load(Ordering::SeqCst) {
+        SIGINT => Some(Signal::Interrupt),
+        SIGTERM => Some(Signal::Terminate),
+        _ => None,
+    }
+}
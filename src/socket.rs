@@ -0,0 +1,203 @@
+use std::{
+    fs::File,
+    io::{self, Read, Write},
+    net::{Shutdown, SocketAddr, TcpListener, TcpStream},
+    path::PathBuf,
+    time::Duration,
+};
+
+#[cfg(unix)]
+use std::os::unix::{
+    io::{AsRawFd, RawFd},
+    net::{UnixListener, UnixStream},
+};
+
+/// an accepted connection, before any TLS is layered on top
+pub(crate) enum Socket {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl Socket {
+    pub(crate) fn try_clone(&self) -> io::Result<Socket> {
+        match self {
+            Socket::Tcp(stream) => stream.try_clone().map(Socket::Tcp),
+            #[cfg(unix)]
+            Socket::Unix(stream) => stream.try_clone().map(Socket::Unix),
+        }
+    }
+
+    pub(crate) fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        match self {
+            Socket::Tcp(stream) => stream.set_read_timeout(timeout),
+            #[cfg(unix)]
+            Socket::Unix(stream) => stream.set_read_timeout(timeout),
+        }
+    }
+
+    pub(crate) fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        match self {
+            Socket::Tcp(stream) => stream.set_write_timeout(timeout),
+            #[cfg(unix)]
+            Socket::Unix(stream) => stream.set_write_timeout(timeout),
+        }
+    }
+
+    /// the client address. Unix socket peers don't have one
+    pub(crate) fn peer_addr(&self) -> Option<SocketAddr> {
+        match self {
+            Socket::Tcp(stream) => stream.peer_addr().ok(),
+            #[cfg(unix)]
+            Socket::Unix(_) => None,
+        }
+    }
+
+    /// the address the connection was accepted on, `None` for unix sockets
+    pub(crate) fn local_addr(&self) -> Option<SocketAddr> {
+        match self {
+            Socket::Tcp(stream) => stream.local_addr().ok(),
+            #[cfg(unix)]
+            Socket::Unix(_) => None,
+        }
+    }
+
+    pub(crate) fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        match self {
+            Socket::Tcp(stream) => stream.shutdown(how),
+            #[cfg(unix)]
+            Socket::Unix(stream) => stream.shutdown(how),
+        }
+    }
+
+    /// copy `file` to the socket. std has the kernel do it where it can, e.g. with sendfile on
+    /// linux, and reads and writes it otherwise
+    pub(crate) fn send_file(&self, file: &mut io::Take<File>) -> io::Result<u64> {
+        match self {
+            Socket::Tcp(stream) => io::copy(file, &mut &*stream),
+            #[cfg(unix)]
+            Socket::Unix(stream) => io::copy(file, &mut &*stream),
+        }
+    }
+
+    /// the tcp stream, for acceptors that only handle tcp
+    pub(crate) fn into_tcp(self) -> io::Result<TcpStream> {
+        match self {
+            Socket::Tcp(stream) => Ok(stream),
+            #[cfg(unix)]
+            Socket::Unix(_) => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "expected a tcp connection",
+            )),
+        }
+    }
+}
+
+impl Read for Socket {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Socket::Tcp(stream) => stream.read(buf),
+            #[cfg(unix)]
+            Socket::Unix(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Socket {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Socket::Tcp(stream) => stream.write(buf),
+            #[cfg(unix)]
+            Socket::Unix(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Socket::Tcp(stream) => stream.flush(),
+            #[cfg(unix)]
+            Socket::Unix(stream) => stream.flush(),
+        }
+    }
+}
+
+#[cfg(unix)]
+impl AsRawFd for Socket {
+    fn as_raw_fd(&self) -> RawFd {
+        match self {
+            Socket::Tcp(stream) => stream.as_raw_fd(),
+            Socket::Unix(stream) => stream.as_raw_fd(),
+        }
+    }
+}
+
+pub(crate) enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener),
+}
+
+impl Listener {
+    pub(crate) fn accept(&self) -> io::Result<Socket> {
+        match self {
+            Listener::Tcp(listener) => listener.accept().map(|(stream, _)| Socket::Tcp(stream)),
+            #[cfg(unix)]
+            Listener::Unix(listener) => listener.accept().map(|(stream, _)| Socket::Unix(stream)),
+        }
+    }
+}
+
+/// where a listener accepts connections
+#[derive(Clone)]
+pub(crate) enum Address {
+    Tcp(SocketAddr),
+    #[cfg_attr(not(unix), allow(dead_code))]
+    Unix(PathBuf),
+}
+
+impl Address {
+    /// how the address is shown in logs
+    pub(crate) fn url(&self, scheme: &str) -> String {
+        match self {
+            Address::Tcp(addr) => format!("{}://{}", scheme, addr),
+            Address::Unix(path) => format!("unix:{}", path.display()),
+        }
+    }
+
+    /// open a connection to the listener, e.g. to wake up its accept loop
+    pub(crate) fn connect(&self) -> io::Result<()> {
+        match self {
+            Address::Tcp(addr) => {
+                let mut addr = *addr;
+                if addr.ip().is_unspecified() {
+                    addr.set_ip(match addr {
+                        SocketAddr::V4(_) => [127, 0, 0, 1].into(),
+                        SocketAddr::V6(_) => std::net::Ipv6Addr::LOCALHOST.into(),
+                    });
+                }
+                TcpStream::connect_timeout(&addr, Duration::from_secs(1)).map(drop)
+            }
+            #[cfg(unix)]
+            Address::Unix(path) => UnixStream::connect(path).map(drop),
+            #[cfg(not(unix))]
+            Address::Unix(_) => Ok(()),
+        }
+    }
+}
+
+/// remove a socket file left behind by a server that is no longer running
+#[cfg(unix)]
+pub(crate) fn remove_stale_socket(path: &std::path::Path) {
+    use std::os::unix::fs::FileTypeExt;
+
+    let is_socket = std::fs::symlink_metadata(path).is_ok_and(|meta| meta.file_type().is_socket());
+    if !is_socket {
+        return;
+    }
+    // nobody answering means nobody is listening, a live server keeps its socket
+    if let Err(error) = UnixStream::connect(path) {
+        if error.kind() == io::ErrorKind::ConnectionRefused {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
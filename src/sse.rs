@@ -0,0 +1,317 @@
+use std::{
+    collections::VecDeque,
+    fmt,
+    sync::{Arc, Condvar, Mutex},
+    time::Duration,
+};
+
+use crate::http_server::{Body, HTTPResponse};
+
+/// a single server-sent event
+#[derive(Clone, Debug, Default)]
+pub struct Event {
+    pub event: Option<String>,
+    pub id: Option<String>,
+    pub data: String,
+    /// how long the client should wait before reconnecting
+    pub retry: Option<Duration>,
+}
+
+impl Event {
+    pub fn new(data: impl Into<String>) -> Event {
+        Event {
+            data: data.into(),
+            ..Event::default()
+        }
+    }
+
+    pub fn event(mut self, event: impl Into<String>) -> Event {
+        self.event = Some(event.into());
+        self
+    }
+
+    pub fn id(mut self, id: impl Into<String>) -> Event {
+        self.id = Some(id.into());
+        self
+    }
+
+    pub fn retry(mut self, retry: Duration) -> Event {
+        self.retry = Some(retry);
+        self
+    }
+}
+
+/// the wire format of the event, terminated by an empty line
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // line breaks would end a field early, so they are dropped from single line fields
+        let single_line = |value: &str| value.replace(['\r', '\n'], "");
+        if let Some(ref event) = self.event {
+            writeln!(f, "event: {}", single_line(event))?;
+        }
+        if let Some(ref id) = self.id {
+            writeln!(f, "id: {}", single_line(id))?;
+        }
+        if let Some(retry) = self.retry {
+            writeln!(f, "retry: {}", retry.as_millis())?;
+        }
+        for line in self.data.split('\n') {
+            writeln!(f, "data: {}", line.strip_suffix('\r').unwrap_or(line))?;
+        }
+        writeln!(f)
+    }
+}
+
+/// returned once the client has gone away and nothing more can be sent
+#[derive(Debug)]
+pub struct Disconnected;
+
+impl fmt::Display for Disconnected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "the event stream was closed")
+    }
+}
+
+impl std::error::Error for Disconnected {}
+
+/// pushes events into a response created by `event_stream`. Can be cloned and moved to other threads
+pub struct EventSender {
+    channel: Arc<Channel>,
+}
+
+/// the events sent but not yet taken by the stream
+struct Channel {
+    state: Mutex<ChannelState>,
+    /// signalled when an event arrives or the last sender is dropped
+    available: Condvar,
+}
+
+struct ChannelState {
+    events: VecDeque<Vec<u8>>,
+    senders: usize,
+    /// the stream was dropped, nothing reaches the client anymore
+    disconnected: bool,
+    /// called along with `available`, for streams waited on without a thread
+    wake: Option<Box<dyn Fn() + Send>>,
+}
+
+impl EventSender {
+    pub fn send(&self, event: &Event) -> Result<(), Disconnected> {
+        self.push(event.to_string())
+    }
+
+    /// send a comment line, which clients ignore
+    pub fn comment(&self, comment: &str) -> Result<(), Disconnected> {
+        let mut lines = String::new();
+        for line in comment.lines() {
+            lines.push_str(": ");
+            lines.push_str(line);
+            lines.push('\n');
+        }
+        lines.push('\n');
+        self.push(lines)
+    }
+
+    fn push(&self, text: String) -> Result<(), Disconnected> {
+        let mut state = self.channel.state.lock().unwrap();
+        if state.disconnected {
+            return Err(Disconnected);
+        }
+        state.events.push_back(text.into_bytes());
+        self.channel.notify(&state);
+        Ok(())
+    }
+}
+
+impl Clone for EventSender {
+    fn clone(&self) -> EventSender {
+        self.channel.state.lock().unwrap().senders += 1;
+        EventSender {
+            channel: Arc::clone(&self.channel),
+        }
+    }
+}
+
+impl Drop for EventSender {
+    fn drop(&mut self) {
+        let mut state = self.channel.state.lock().unwrap();
+        state.senders -= 1;
+        if state.senders == 0 {
+            self.channel.notify(&state);
+        }
+    }
+}
+
+impl Channel {
+    fn notify(&self, state: &ChannelState) {
+        self.available.notify_one();
+        if let Some(ref wake) = state.wake {
+            wake();
+        }
+    }
+}
+
+/// create a `text/event-stream` response to return from a listener, and the sender to push events
+/// into it from another thread. A comment is sent after `keep_alive` without events, so proxies
+/// keep the connection open and a disconnected client is noticed. Once it is, every send fails
+/// with `Disconnected`. The stream ends when all senders are dropped.
+pub fn event_stream(keep_alive: Duration) -> (EventSender, HTTPResponse) {
+    let channel = Arc::new(Channel {
+        state: Mutex::new(ChannelState {
+            events: VecDeque::new(),
+            senders: 1,
+            disconnected: false,
+            wake: None,
+        }),
+        available: Condvar::new(),
+    });
+    let response = HTTPResponse::builder()
+        .header("Content-Type", "text/event-stream")
+        .header("Cache-Control", "no-cache")
+        .body(Body::Events(EventStream {
+            channel: Arc::clone(&channel),
+            keep_alive,
+        }));
+    (EventSender { channel }, response)
+}
+
+/// the body of an event stream, yielding events as they're sent. Dropped when writing to the
+/// client fails, which disconnects the senders
+pub struct EventStream {
+    channel: Arc<Channel>,
+    keep_alive: Duration,
+}
+
+/// what an event stream has for a connection that doesn't wait on it
+#[cfg(feature = "evented")]
+pub(crate) enum Pending {
+    Event(Vec<u8>),
+    /// nothing yet, more may follow
+    Empty,
+    /// all senders are gone and every event was taken
+    Ended,
+}
+
+/// sent after `keep_alive` without events
+pub(crate) const KEEP_ALIVE: &[u8] = b": keep-alive\n\n";
+
+impl EventStream {
+    /// how long the stream may go without sending anything
+    #[cfg(feature = "evented")]
+    pub(crate) fn keep_alive(&self) -> Duration {
+        self.keep_alive
+    }
+
+    /// the next event if there is one, without waiting for it
+    #[cfg(feature = "evented")]
+    pub(crate) fn try_next(&mut self) -> Pending {
+        let mut state = self.channel.state.lock().unwrap();
+        match state.events.pop_front() {
+            Some(event) => Pending::Event(event),
+            None if state.senders == 0 => Pending::Ended,
+            None => Pending::Empty,
+        }
+    }
+
+    /// call `wake` whenever an event arrives or the stream ends, instead of waiting on it
+    #[cfg(feature = "evented")]
+    pub(crate) fn on_wake(&self, wake: impl Fn() + Send + 'static) {
+        self.channel.state.lock().unwrap().wake = Some(Box::new(wake));
+    }
+}
+
+impl Iterator for EventStream {
+    type Item = Vec<u8>;
+
+    fn next(&mut self) -> Option<Vec<u8>> {
+        let state = self.channel.state.lock().unwrap();
+        let (mut state, _) = self
+            .channel
+            .available
+            .wait_timeout_while(state, self.keep_alive, |state| {
+                state.events.is_empty() && state.senders > 0
+            })
+            .unwrap();
+        match state.events.pop_front() {
+            Some(event) => Some(event),
+            None if state.senders == 0 => None,
+            None => Some(KEEP_ALIVE.to_vec()),
+        }
+    }
+}
+
+impl Drop for EventStream {
+    fn drop(&mut self) {
+        let mut state = self.channel.state.lock().unwrap();
+        state.disconnected = true;
+        state.events.clear();
+        state.wake = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    fn stream(keep_alive: Duration) -> (EventSender, EventStream) {
+        let (sender, response) = event_stream(keep_alive);
+        match response.body {
+            Body::Events(events) => (sender, events),
+            _ => unreachable!("event_stream returns an event stream"),
+        }
+    }
+
+    #[test]
+    fn yields_events_in_order_until_the_senders_are_gone() {
+        let (sender, mut events) = stream(Duration::from_secs(5));
+        let other = sender.clone();
+        sender.send(&Event::new("one")).unwrap();
+        other.comment("two").unwrap();
+        drop(sender);
+        assert_eq!(events.next(), Some(b"data: one\n\n".to_vec()));
+        assert_eq!(events.next(), Some(b": two\n\n".to_vec()));
+
+        let waiting = thread::spawn(move || events.next());
+        thread::sleep(Duration::from_millis(20));
+        drop(other);
+        assert_eq!(waiting.join().unwrap(), None);
+    }
+
+    #[test]
+    fn keeps_quiet_streams_alive() {
+        let (_sender, mut events) = stream(Duration::from_millis(10));
+        assert_eq!(events.next(), Some(KEEP_ALIVE.to_vec()));
+    }
+
+    #[test]
+    fn sending_fails_once_the_stream_is_dropped() {
+        let (sender, events) = stream(Duration::from_secs(5));
+        sender.send(&Event::new("one")).unwrap();
+        drop(events);
+        assert!(sender.send(&Event::new("two")).is_err());
+        assert!(sender.comment("three").is_err());
+    }
+
+    #[cfg(feature = "evented")]
+    #[test]
+    fn wakes_streams_nobody_waits_on() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let (sender, mut events) = stream(Duration::from_secs(5));
+        let woken = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&woken);
+        events.on_wake(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+        assert!(matches!(events.try_next(), Pending::Empty));
+        sender.send(&Event::new("one")).unwrap();
+        assert_eq!(woken.load(Ordering::SeqCst), 1);
+        assert!(matches!(events.try_next(), Pending::Event(event) if event == b"data: one\n\n"));
+
+        drop(sender);
+        assert_eq!(woken.load(Ordering::SeqCst), 2);
+        assert!(matches!(events.try_next(), Pending::Ended));
+    }
+}
@@ -0,0 +1,245 @@
+use std::{
+    fs::File,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    compression::accepts_encoding,
+    conditional::{check_preconditions, weak_etag},
+    form::percent_decode,
+    http_date::format_http_date,
+    http_server::{get_404_default_response, HTTPRequest, HTTPResponse},
+    listing::list_directory,
+    mime,
+    range::file_range_response,
+};
+
+/// how `serve_dir_with` treats a mounted directory
+#[derive(Clone, Debug, Default)]
+pub struct DirOptions {
+    /// list directories without an `index.html`, as HTML or JSON with `?format=json`
+    pub listing: bool,
+    /// include dotfiles in listings
+    pub show_hidden: bool,
+}
+
+/// encodings of compressed siblings like `app.js.br`, in order of preference
+pub(crate) const PRECOMPRESSED: [(&str, &str); 2] = [("br", "br"), ("gzip", "gz")];
+
+/// serve the file at `path` with the Content-Type of its extension, answering conditional and
+/// range requests. A sibling compressed ahead of time, like `app.js.br` or `app.js.gz`, is
+/// sent instead with its Content-Encoding if the client accepts it
+pub fn serve_file(request: &HTTPRequest, path: impl AsRef<Path>) -> HTTPResponse {
+    let path = path.as_ref();
+    let mut varies = false;
+    for (encoding, extension) in PRECOMPRESSED {
+        let mut sibling = path.as_os_str().to_owned();
+        sibling.push(".");
+        sibling.push(extension);
+        let sibling = PathBuf::from(sibling);
+        if !sibling.is_file() {
+            continue;
+        }
+        varies = true;
+        if accepts_encoding(request, encoding) {
+            return send_file(request, &sibling, path, Some(encoding), true);
+        }
+    }
+    send_file(request, path, path, None, varies)
+}
+
+/// answer with the file at `path`, typed after `served_as`. `varies` if other encodings of it
+/// exist, so caches keep them apart
+fn send_file(
+    request: &HTTPRequest,
+    path: &Path,
+    served_as: &Path,
+    encoding: Option<&str>,
+    varies: bool,
+) -> HTTPResponse {
+    let opened = File::open(path).and_then(|file| {
+        let metadata = file.metadata()?;
+        Ok((file, metadata))
+    });
+    let (file, metadata) = match opened {
+        Ok((_, metadata)) if metadata.is_dir() => return get_404_default_response(),
+        Ok(opened) => opened,
+        Err(error) => return error_response(&error),
+    };
+
+    let modified = metadata.modified().ok();
+    let etag = modified.map(|modified| weak_etag(metadata.len(), modified));
+    if let Some(mut response) = check_preconditions(request, etag.as_deref(), modified) {
+        if varies {
+            response.headers.insert("Vary", "Accept-Encoding");
+        }
+        return response;
+    }
+
+    let mut response = match file_range_response(request, file, &mime::from_path(served_as)) {
+        Ok(response) => response,
+        Err(error) => return error_response(&error),
+    };
+    if let Some(etag) = etag {
+        response.headers.insert("ETag", etag);
+    }
+    if let Some(modified) = modified {
+        response
+            .headers
+            .insert("Last-Modified", format_http_date(modified));
+    }
+    if let Some(encoding) = encoding {
+        response.headers.insert("Content-Encoding", encoding);
+    }
+    if varies {
+        response.headers.insert("Vary", "Accept-Encoding");
+    }
+    response
+}
+
+/// serve `relative` from the directory `root`, e.g. the remainder of a `/static/*path` route.
+/// Paths escaping `root` are answered with 404, directories with their `index.html`
+pub fn serve_dir(request: &HTTPRequest, root: impl AsRef<Path>, relative: &str) -> HTTPResponse {
+    serve_dir_with(request, root, relative, &DirOptions::default())
+}
+
+/// `serve_dir` with `options` for this mount, like listing directories without an index
+pub fn serve_dir_with(
+    request: &HTTPRequest,
+    root: impl AsRef<Path>,
+    relative: &str,
+    options: &DirOptions,
+) -> HTTPResponse {
+    let root = root.as_ref();
+    let Some(mut path) = resolve(root, relative) else {
+        return get_404_default_response();
+    };
+    if path.is_dir() {
+        let index = path.join("index.html");
+        if options.listing && !index.is_file() {
+            return list_directory(request, &path, path == root, options.show_hidden)
+                .unwrap_or_else(|error| error_response(&error));
+        }
+        path = index;
+    }
+    serve_file(request, path)
+}
+
+/// `serve_dir` for single page applications. Unknown paths get the `index.html` of `root`,
+/// leaving them to the client side routing, but missing files with an extension like
+/// `app.js` are still answered with 404
+pub fn serve_spa(request: &HTTPRequest, root: impl AsRef<Path>, relative: &str) -> HTTPResponse {
+    let root = root.as_ref();
+    let Some(path) = resolve(root, relative) else {
+        return get_404_default_response();
+    };
+    if path.is_file() || path.join("index.html").is_file() {
+        return serve_dir(request, root, relative);
+    }
+    match Path::new(relative).extension() {
+        Some(_) => get_404_default_response(),
+        None => serve_file(request, root.join("index.html")),
+    }
+}
+
+/// join `relative`, a path as sent with its `%XX` escapes, onto `root`, refusing anything that
+/// could leave it. Segments are checked once decoded, so `%2e%2e` is caught like `..` and an
+/// escaped `/` can't add a segment
+fn resolve(root: &Path, relative: &str) -> Option<PathBuf> {
+    let mut path = root.to_path_buf();
+    for segment in relative.split('/') {
+        let segment = percent_decode(segment);
+        match segment.as_str() {
+            "" | "." => {}
+            ".." => return None,
+            segment if segment.contains(['/', '\\', '\0']) => return None,
+            segment => path.push(segment),
+        }
+    }
+    Some(path)
+}
+
+fn error_response(error: &std::io::Error) -> HTTPResponse {
+    match error.kind() {
+        ErrorKind::NotFound => get_404_default_response(),
+        ErrorKind::PermissionDenied => HTTPResponse::builder().status(403).text("Forbidden"),
+        _ => {
+            println!("failed serving file: {}", error);
+            HTTPResponse::builder()
+                .status(500)
+                .text("Internal Server Error")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::{http_server::HTTPServer, testing::TestClient};
+
+    fn text(response: HTTPResponse) -> String {
+        String::from_utf8(response.body.into_bytes().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn resolves_escaped_segments() {
+        let root = Path::new("/srv");
+        assert_eq!(resolve(root, "a%20b%25.txt"), Some(root.join("a b%.txt")));
+        assert_eq!(
+            resolve(root, "docs/%C3%BC.txt"),
+            Some(root.join("docs/ü.txt"))
+        );
+        assert_eq!(resolve(root, "./a//b"), Some(root.join("a/b")));
+        for escaping in [
+            "..",
+            "a/../..",
+            "%2e%2e/etc",
+            "a%2F..%2F..",
+            "a%5Cb",
+            "a%00",
+        ] {
+            assert_eq!(resolve(root, escaping), None, "{}", escaping);
+        }
+    }
+
+    #[test]
+    fn listed_names_link_to_their_files() {
+        let root = std::env::temp_dir().join(format!("adhesion-listing-{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        let names = [
+            ("a b%.txt", "/files/a%20b%25.txt"),
+            ("ü.txt", "/files/%C3%BC.txt"),
+            ("plain.txt", "/files/plain.txt"),
+        ];
+        for (name, _) in names {
+            fs::write(root.join(name), name).unwrap();
+        }
+
+        let served = root.clone();
+        let options = DirOptions {
+            listing: true,
+            show_hidden: false,
+        };
+        let server = HTTPServer::builder(())
+            .get("/files/*path", move |request: &HTTPRequest, _: &()| {
+                serve_dir_with(request, &served, &request.path_params["path"], &options)
+            })
+            .build();
+        let client = TestClient::new(server);
+        let listing = text(client.get("/files/").send());
+        for (name, href) in names {
+            assert!(
+                listing.contains(&format!("<a href=\"{}\">", href)),
+                "{}",
+                listing
+            );
+            let response = client.get(href).send();
+            assert_eq!(response.status.status, 200, "{}", href);
+            assert_eq!(text(response), name);
+        }
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,460 @@
+use std::{collections::HashMap, fmt, fs, path::Path};
+
+use crate::{
+    http_server::HTTPResponse,
+    json::{ToJson, Value},
+    redirect::escape_html,
+};
+
+/// includes nested deeper than this are taken for a cycle
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// a template engine handlers can render pages with, like the built-in `Templates` or a
+/// wrapper around another engine
+pub trait Render {
+    /// the template `name` filled in with `context`
+    fn render_to_string(&self, name: &str, context: &Value) -> Result<String, TemplateError>;
+
+    /// the template as a `text/html` response, or 500 if it couldn't be rendered
+    fn render(&self, name: &str, context: &impl ToJson) -> HTTPResponse
+    where
+        Self: Sized,
+    {
+        match self.render_to_string(name, &context.to_json()) {
+            Ok(page) => HTTPResponse::builder().html(page),
+            Err(error) => {
+                println!("failed rendering template {}: {}", name, error);
+                HTTPResponse::builder()
+                    .status(500)
+                    .text("Internal Server Error")
+            }
+        }
+    }
+}
+
+/// why a template couldn't be loaded or rendered
+#[derive(Debug)]
+pub struct TemplateError {
+    pub message: String,
+}
+
+/// a minimal template engine. `{{ user.name }}` inserts a value of the context HTML escaped,
+/// `{{ body|raw }}` as is, and missing values as nothing. `{% if x %}`, `{% else %}` and
+/// `{% endif %}` test a value for being present, non empty, non zero and not false,
+/// `{% for item in items %}` to `{% endfor %}` repeats for every element of an array,
+/// `{% include "nav.html" %}` inserts another template and `{# ... #}` is a comment
+#[derive(Default)]
+pub struct Templates {
+    templates: HashMap<String, Vec<Node>>,
+}
+
+enum Node {
+    Text(String),
+    Value { path: Vec<String>, raw: bool },
+    If(Vec<String>, Vec<Node>, Vec<Node>),
+    For(String, Vec<String>, Vec<Node>),
+    Include(String),
+}
+
+enum Token<'a> {
+    Text(&'a str),
+    Value(&'a str),
+    Tag(&'a str),
+}
+
+impl TemplateError {
+    pub fn new(message: impl Into<String>) -> TemplateError {
+        TemplateError {
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for TemplateError {}
+
+impl Templates {
+    pub fn new() -> Templates {
+        Templates::default()
+    }
+
+    /// every file below `dir`, named by its path relative to it like `partials/nav.html`
+    pub fn from_dir(dir: impl AsRef<Path>) -> Result<Templates, TemplateError> {
+        let mut templates = Templates::new();
+        templates.add_dir(dir.as_ref(), "")?;
+        Ok(templates)
+    }
+
+    /// parse `source` as the template `name`, replacing any previous one
+    pub fn add(&mut self, name: &str, source: &str) -> Result<(), TemplateError> {
+        let tokens = tokenize(source)
+            .map_err(|message| TemplateError::new(format!("{}: {}", name, message)))?;
+        let (nodes, _) = parse(&mut tokens.into_iter(), &[])
+            .map_err(|message| TemplateError::new(format!("{}: {}", name, message)))?;
+        self.templates.insert(String::from(name), nodes);
+        Ok(())
+    }
+
+    fn add_dir(&mut self, dir: &Path, prefix: &str) -> Result<(), TemplateError> {
+        let read_error = |error: std::io::Error| {
+            TemplateError::new(format!("reading {}: {}", dir.display(), error))
+        };
+        for entry in fs::read_dir(dir).map_err(read_error)? {
+            let entry = entry.map_err(read_error)?;
+            let name = format!("{}{}", prefix, entry.file_name().to_string_lossy());
+            let path = entry.path();
+            if path.is_dir() {
+                self.add_dir(&path, &format!("{}/", name))?;
+            } else {
+                let source = fs::read_to_string(&path).map_err(read_error)?;
+                self.add(&name, &source)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn render_nodes(
+        &self,
+        nodes: &[Node],
+        scope: &mut Vec<(String, Value)>,
+        context: &Value,
+        depth: usize,
+        out: &mut String,
+    ) -> Result<(), TemplateError> {
+        for node in nodes {
+            match node {
+                Node::Text(text) => out.push_str(text),
+                Node::Value { path, raw } => {
+                    let text = lookup(path, scope, context)
+                        .map(display)
+                        .unwrap_or_default();
+                    match raw {
+                        true => out.push_str(&text),
+                        false => out.push_str(&escape_html(&text)),
+                    }
+                }
+                Node::If(path, then, otherwise) => {
+                    let branch = match lookup(path, scope, context).is_some_and(truthy) {
+                        true => then,
+                        false => otherwise,
+                    };
+                    self.render_nodes(branch, scope, context, depth, out)?;
+                }
+                Node::For(name, path, body) => {
+                    let Some(Value::Array(items)) = lookup(path, scope, context).cloned() else {
+                        continue;
+                    };
+                    for item in items {
+                        scope.push((name.clone(), item));
+                        let rendered = self.render_nodes(body, scope, context, depth, out);
+                        scope.pop();
+                        rendered?;
+                    }
+                }
+                Node::Include(name) => {
+                    if depth >= MAX_INCLUDE_DEPTH {
+                        return Err(TemplateError::new(format!(
+                            "including {} nests too deep",
+                            name
+                        )));
+                    }
+                    let nodes = self.template(name)?;
+                    self.render_nodes(nodes, scope, context, depth + 1, out)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn template(&self, name: &str) -> Result<&[Node], TemplateError> {
+        self.templates
+            .get(name)
+            .map(Vec::as_slice)
+            .ok_or_else(|| TemplateError::new(format!("no template named {}", name)))
+    }
+}
+
+impl Render for Templates {
+    fn render_to_string(&self, name: &str, context: &Value) -> Result<String, TemplateError> {
+        let mut out = String::new();
+        self.render_nodes(self.template(name)?, &mut Vec::new(), context, 0, &mut out)?;
+        Ok(out)
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token<'_>>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = source;
+    while let Some(start) = rest.find('{') {
+        let open = rest[start..].get(..2).unwrap_or("");
+        let close = match open {
+            "{{" => "}}",
+            "{%" => "%}",
+            "{#" => "#}",
+            _ => {
+                tokens.push(Token::Text(&rest[..start + 1]));
+                rest = &rest[start + 1..];
+                continue;
+            }
+        };
+        tokens.push(Token::Text(&rest[..start]));
+        let inner = &rest[start + 2..];
+        let end = inner
+            .find(close)
+            .ok_or_else(|| format!("unclosed {}", open))?;
+        match open {
+            "{{" => tokens.push(Token::Value(inner[..end].trim())),
+            "{%" => tokens.push(Token::Tag(inner[..end].trim())),
+            _ => {}
+        }
+        rest = &inner[end + 2..];
+    }
+    tokens.push(Token::Text(rest));
+    Ok(tokens)
+}
+
+/// nodes up to one of the tags in `ends`, returning that tag, or up to the end for `None`
+fn parse<'a>(
+    tokens: &mut impl Iterator<Item = Token<'a>>,
+    ends: &[&str],
+) -> Result<(Vec<Node>, Option<&'a str>), String> {
+    let mut nodes = Vec::new();
+    while let Some(token) = tokens.next() {
+        let tag = match token {
+            Token::Text("") => continue,
+            Token::Text(text) => {
+                nodes.push(Node::Text(String::from(text)));
+                continue;
+            }
+            Token::Value(value) => {
+                let (value, raw) = match value.strip_suffix("raw") {
+                    Some(value) if value.trim_end().ends_with('|') => {
+                        (value.trim_end().trim_end_matches('|').trim_end(), true)
+                    }
+                    _ => (value, false),
+                };
+                nodes.push(Node::Value {
+                    path: parse_path(value)?,
+                    raw,
+                });
+                continue;
+            }
+            Token::Tag(tag) => tag,
+        };
+
+        let (keyword, argument) = tag.split_once(char::is_whitespace).unwrap_or((tag, ""));
+        let argument = argument.trim();
+        match keyword {
+            _ if ends.contains(&keyword) => return Ok((nodes, Some(keyword))),
+            "if" => {
+                let path = parse_path(argument)?;
+                let (then, end) = parse(tokens, &["else", "endif"])?;
+                let otherwise = match end {
+                    Some("else") => expect(parse(tokens, &["endif"])?, "endif")?,
+                    Some(_) => Vec::new(),
+                    None => return Err(String::from("{% if %} without {% endif %}")),
+                };
+                nodes.push(Node::If(path, then, otherwise));
+            }
+            "for" => {
+                let (name, path) = argument.split_once(" in ").ok_or_else(|| {
+                    format!("expected {{% for item in items %}}, got {{% {} %}}", tag)
+                })?;
+                let body = expect(parse(tokens, &["endfor"])?, "endfor")?;
+                nodes.push(Node::For(
+                    String::from(name.trim()),
+                    parse_path(path)?,
+                    body,
+                ));
+            }
+            "include" => {
+                let name = argument
+                    .strip_prefix('"')
+                    .and_then(|name| name.strip_suffix('"'))
+                    .ok_or_else(|| format!("expected a quoted name in {{% {} %}}", tag))?;
+                nodes.push(Node::Include(String::from(name)));
+            }
+            _ => return Err(format!("unexpected {{% {} %}}", tag)),
+        }
+    }
+    Ok((nodes, None))
+}
+
+fn expect((nodes, end): (Vec<Node>, Option<&str>), tag: &str) -> Result<Vec<Node>, String> {
+    match end {
+        Some(_) => Ok(nodes),
+        None => Err(format!("missing {{% {} %}}", tag)),
+    }
+}
+
+fn parse_path(path: &str) -> Result<Vec<String>, String> {
+    let segments: Vec<String> = path.trim().split('.').map(String::from).collect();
+    let valid = |segment: &String| {
+        !segment.is_empty()
+            && segment
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+    };
+    match segments.iter().all(valid) {
+        true => Ok(segments),
+        false => Err(format!("invalid value name `{}`", path.trim())),
+    }
+}
+
+/// the value at `path`, starting from the innermost loop variable of that name or the context
+fn lookup<'a>(
+    path: &[String],
+    scope: &'a [(String, Value)],
+    context: &'a Value,
+) -> Option<&'a Value> {
+    let (first, rest) = path.split_first()?;
+    let mut value = scope
+        .iter()
+        .rev()
+        .find(|(name, _)| name == first)
+        .map(|(_, value)| value)
+        .or_else(|| context.get(first))?;
+    for segment in rest {
+        value = match value {
+            Value::Array(items) => items.get(segment.parse::<usize>().ok()?)?,
+            value => value.get(segment)?,
+        };
+    }
+    Some(value)
+}
+
+fn display(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(text) => text.clone(),
+        value => value.to_string(),
+    }
+}
+
+fn truthy(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Bool(b) => *b,
+        Value::Number(n) => *n != 0.0,
+        Value::String(text) => !text.is_empty(),
+        Value::Array(items) => !items.is_empty(),
+        Value::Object(fields) => !fields.is_empty(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(source: &str, context: &str) -> Result<String, TemplateError> {
+        let mut templates = Templates::new();
+        templates.add("page", source)?;
+        templates.render_to_string("page", &Value::parse(context).unwrap())
+    }
+
+    #[test]
+    fn escapes_values() {
+        let context = r#"{"name": "<script>alert('x') & \"y\"</script>"}"#;
+        assert_eq!(
+            render("<p title='{{ name }}'>{{name}}</p>", context).unwrap(),
+            "<p title='&lt;script&gt;alert(&#x27;x&#x27;) &amp; &quot;y&quot;&lt;/script&gt;'>\
+            &lt;script&gt;alert(&#x27;x&#x27;) &amp; &quot;y&quot;&lt;/script&gt;</p>"
+        );
+        assert_eq!(
+            render("{{ name | raw }}", context).unwrap(),
+            "<script>alert('x') & \"y\"</script>"
+        );
+        // values other than strings are escaped as they're displayed
+        assert_eq!(
+            render("{{ tags }}", r#"{"tags": ["<b>"]}"#).unwrap(),
+            "[&quot;&lt;b&gt;&quot;]"
+        );
+    }
+
+    #[test]
+    fn looks_up_values() {
+        let context = r#"{"user": {"name": "Ada", "langs": ["en", "fr"]}, "n": 3, "none": null}"#;
+        assert_eq!(
+            render(
+                "{{ user.name }} {{ user.langs.1 }} {{ n }} [{{ none }}] [{{ missing.x }}]",
+                context
+            )
+            .unwrap(),
+            "Ada fr 3 [] []"
+        );
+    }
+
+    #[test]
+    fn conditions_and_loops() {
+        let source = "{% for item in items %}{% if item.done %}x{% else %}o{% endif %}\
+            {{ item.name }};{% endfor %}{% if empty %}!{% endif %}{# a comment #}";
+        let context =
+            r#"{"items": [{"name": "a", "done": true}, {"name": "b", "done": 0}], "empty": ""}"#;
+        assert_eq!(render(source, context).unwrap(), "xa;ob;");
+        // a loop variable shadows the context while it's in scope
+        assert_eq!(
+            render(
+                "{% for x in xs %}{{ x }}{% endfor %}{{ x }}",
+                r#"{"xs": [1, 2], "x": "c"}"#
+            )
+            .unwrap(),
+            "12c"
+        );
+    }
+
+    #[test]
+    fn includes_other_templates() {
+        let mut templates = Templates::new();
+        templates.add("nav", "<nav>{{ title }}</nav>").unwrap();
+        templates
+            .add("page", "{% include \"nav\" %}<main></main>")
+            .unwrap();
+        templates.add("loop", "{% include \"loop\" %}").unwrap();
+        let context = Value::parse(r#"{"title": "Home & away"}"#).unwrap();
+        assert_eq!(
+            templates.render_to_string("page", &context).unwrap(),
+            "<nav>Home &amp; away</nav><main></main>"
+        );
+        assert!(templates.render_to_string("loop", &context).is_err());
+        assert!(templates.render_to_string("missing", &context).is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_templates() {
+        for source in [
+            "{{ name",
+            "{% if x %}",
+            "{% if x %}{% else %}",
+            "{% for x %}{% endfor %}",
+            "{% include nav %}",
+            "{% endif %}",
+            "{{ a..b }}",
+            "{{ a b }}",
+        ] {
+            assert!(Templates::new().add("page", source).is_err(), "{}", source);
+        }
+        // a lone brace is text
+        assert_eq!(render("{ {x} }", "{}").unwrap(), "{ {x} }");
+    }
+
+    #[test]
+    fn renders_responses() {
+        let mut templates = Templates::new();
+        templates.add("page", "<h1>{{ title }}</h1>").unwrap();
+        let context = Value::object([("title", Value::String(String::from("<Hi>")))]);
+        let response = templates.render("page", &context);
+        assert_eq!(response.status.status, 200);
+        assert!(response
+            .headers
+            .get("Content-Type")
+            .unwrap()
+            .starts_with("text/html"));
+        assert_eq!(response.body.into_bytes().unwrap(), b"<h1>&lt;Hi&gt;</h1>");
+        assert_eq!(templates.render("missing", &context).status.status, 500);
+    }
+}
@@ -0,0 +1,158 @@
+use std::{net::SocketAddr, sync::Arc};
+
+use crate::{
+    error::Error,
+    http_server::{HTTPMethod, HTTPRequest, HTTPResponse, HTTPServer, HTTPVersion, Shared},
+    request::HTTPRequestBuilder,
+    shutdown::ShutdownHandle,
+};
+
+/// sends requests through a server's middleware and routes without opening a socket, for
+/// testing handlers in plain `#[test]` functions
+pub struct TestClient<T: std::marker::Sync + std::marker::Send + 'static> {
+    shared: Arc<Shared<T>>,
+    passthrough: Arc<T>,
+}
+
+/// a server listening on a random port of localhost, for end-to-end tests over real
+/// connections. Dropping it shuts the server down
+pub struct TestServer {
+    addr: SocketAddr,
+    handle: Option<ShutdownHandle>,
+}
+
+/// a request about to be sent by a `TestClient`
+pub struct TestRequest<'a, T: std::marker::Sync + std::marker::Send + 'static> {
+    client: &'a TestClient<T>,
+    request: HTTPRequestBuilder,
+}
+
+impl<T: std::marker::Sync + std::marker::Send + 'static> TestClient<T> {
+    pub fn new(server: HTTPServer<T>) -> TestClient<T> {
+        TestClient {
+            shared: server.shared(),
+            passthrough: Arc::clone(&server.passthrough),
+        }
+    }
+
+    /// a request for `target`, a path with an optional query like `/users?page=2`
+    pub fn request(&self, method: HTTPMethod, target: &str) -> TestRequest<'_, T> {
+        TestRequest {
+            client: self,
+            request: HTTPRequest::builder().method(method).path(target),
+        }
+    }
+
+    pub fn get(&self, target: &str) -> TestRequest<'_, T> {
+        self.request(HTTPMethod::GET, target)
+    }
+
+    pub fn post(&self, target: &str) -> TestRequest<'_, T> {
+        self.request(HTTPMethod::POST, target)
+    }
+
+    pub fn put(&self, target: &str) -> TestRequest<'_, T> {
+        self.request(HTTPMethod::PUT, target)
+    }
+
+    pub fn patch(&self, target: &str) -> TestRequest<'_, T> {
+        self.request(HTTPMethod::PATCH, target)
+    }
+
+    pub fn delete(&self, target: &str) -> TestRequest<'_, T> {
+        self.request(HTTPMethod::DELETE, target)
+    }
+
+    /// send a request put together with `HTTPRequest::builder`
+    pub fn send(&self, request: HTTPRequestBuilder) -> HTTPResponse {
+        let mut request = request.build_with(self.shared.trailing_slash);
+        request.client_ip = self
+            .shared
+            .trusted_proxies
+            .client_ip(request.peer_addr, &request.headers);
+        HTTPServer::<T>::respond(&self.shared, &mut request, &self.passthrough)
+    }
+}
+
+impl<T: std::marker::Sync + std::marker::Send + 'static> TestRequest<'_, T> {
+    /// set a header, replacing any previous value of it
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.request = self.request.header(name, value);
+        self
+    }
+
+    /// the body, filling in Content-Length
+    pub fn body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.request = self.request.body(body);
+        self
+    }
+
+    pub fn version(mut self, version: HTTPVersion) -> Self {
+        self.request = self.request.version(version);
+        self
+    }
+
+    /// pretend the request came in from `addr`, which decides `client_ip` together with the
+    /// server's trusted proxies
+    pub fn peer_addr(mut self, addr: SocketAddr) -> Self {
+        self.request = self.request.peer_addr(addr);
+        self
+    }
+
+    /// pretend the request came in over tls
+    pub fn secure(mut self, secure: bool) -> Self {
+        self.request = self.request.secure(secure);
+        self
+    }
+
+    /// the response as returned by the middleware and the route. Headers the connection adds,
+    /// like `Date` or `Connection`, are missing, and HEAD requests get their body too
+    pub fn send(self) -> HTTPResponse {
+        self.client.send(self.request)
+    }
+}
+
+impl TestServer {
+    /// serve `server` on `127.0.0.1` with a port picked by the OS, in the background
+    pub fn spawn<T: std::marker::Sync + std::marker::Send + 'static>(
+        mut server: HTTPServer<T>,
+    ) -> Result<TestServer, Error> {
+        server.address = String::from("127.0.0.1");
+        server.port = 0;
+        let bound = server.bind()?;
+        let addr = bound.local_addr().expect("tcp sockets have an address");
+        Ok(TestServer {
+            addr,
+            handle: Some(bound.listen_with_shutdown()?),
+        })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// like `http://127.0.0.1:49152`, without a trailing slash
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// the url of `path` on the server
+    pub fn url_for(&self, path: &str) -> String {
+        format!("{}{}", self.url(), path)
+    }
+
+    /// stop the server, waiting for in-flight requests like `ShutdownHandle::shutdown`
+    pub fn shutdown(mut self) {
+        if let Some(handle) = self.handle.take() {
+            handle.shutdown();
+        }
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            handle.shutdown();
+        }
+    }
+}
@@ -1,71 +1,297 @@
 use std::{
-    sync::{mpsc, Arc, Mutex},
+    any::Any,
+    collections::{HashMap, VecDeque},
+    panic::{self, AssertUnwindSafe},
+    sync::{Arc, Condvar, Mutex, MutexGuard},
     thread,
+    time::Duration,
 };
 
+/// runs jobs on worker threads. The pool keeps `min` workers alive and spawns more up to `max`
+/// while jobs are waiting, which exit again after being idle for `idle_timeout`
 pub struct ThreadPool {
-    workers: Vec<Worker>,
-    sender: Option<mpsc::Sender<Job>>,
+    shared: Arc<Shared>,
 }
 
-struct Worker {
-    id: usize,
-    thread: Option<thread::JoinHandle<()>>,
+struct Shared {
+    state: Mutex<State>,
+    /// signalled when a job is queued or the pool shuts down
+    available: Condvar,
+    min: usize,
+    max: usize,
+    /// `None` for a fixed size pool
+    idle_timeout: Option<Duration>,
+}
+
+struct State {
+    jobs: VecDeque<Job>,
+    /// `Priority::High` jobs, taken before any in `jobs`
+    urgent: VecDeque<Job>,
+    workers: HashMap<usize, thread::JoinHandle<()>>,
+    next_id: usize,
+    /// most jobs waiting for a worker, `None` for no limit
+    queue_limit: Option<usize>,
+    /// workers running a job
+    busy: usize,
+    shutting_down: bool,
+    on_panic: Option<PanicHook>,
 }
 
 type Job = Box<dyn FnOnce() + Send + 'static>;
 
+/// the order waiting jobs are taken in. Jobs of the same priority run in the order they came in
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub enum Priority {
+    /// ahead of every `Normal` job still waiting, like health checks that shouldn't queue
+    /// behind long uploads
+    High,
+    #[default]
+    Normal,
+}
+
+type PanicHook = Arc<dyn Fn() + Send + Sync>;
+
 impl Drop for ThreadPool {
     fn drop(&mut self) {
-        drop(self.sender.take());
+        let workers: Vec<(usize, thread::JoinHandle<()>)> = {
+            let mut state = self.shared.state.lock().unwrap();
+            state.shutting_down = true;
+            state.workers.drain().collect()
+        };
+        self.shared.available.notify_all();
 
-        for worker in &mut self.workers {
-            println!("Shutting down worker {}", worker.id);
-
-            if let Some(thread) = worker.thread.take() {
-                thread.join().unwrap();
-            }
+        for (id, thread) in workers {
+            println!("Shutting down worker {}", id);
+            thread.join().unwrap();
         }
     }
 }
 
 impl ThreadPool {
+    /// a pool of `size` workers
     pub fn new(size: usize) -> ThreadPool {
-        let mut workers = Vec::with_capacity(size);
+        ThreadPool::with_limits(size, size, None)
+    }
 
-        let (sender, receiver) = mpsc::channel();
-        let receiver = Arc::new(Mutex::new(receiver));
+    /// a pool growing from `min` up to `max` workers under load. Workers beyond `min` exit
+    /// after `idle_timeout` without a job
+    pub fn dynamic(min: usize, max: usize, idle_timeout: Duration) -> ThreadPool {
+        ThreadPool::with_limits(min, max, Some(idle_timeout))
+    }
 
-        for i in 0..size {
-            workers.push(Worker::new(i, Arc::clone(&receiver)));
-        }
+    fn with_limits(min: usize, max: usize, idle_timeout: Option<Duration>) -> ThreadPool {
+        let pool = ThreadPool {
+            shared: Arc::new(Shared {
+                state: Mutex::new(State {
+                    jobs: VecDeque::new(),
+                    urgent: VecDeque::new(),
+                    workers: HashMap::new(),
+                    next_id: 0,
+                    queue_limit: None,
+                    busy: 0,
+                    shutting_down: false,
+                    on_panic: None,
+                }),
+                available: Condvar::new(),
+                min,
+                max: max.max(min).max(1),
+                idle_timeout,
+            }),
+        };
 
-        ThreadPool {
-            workers,
-            sender: Some(sender),
+        let mut state = pool.shared.state.lock().unwrap();
+        for _ in 0..min {
+            spawn_worker(&pool.shared, &mut state);
         }
+        drop(state);
+        pool
+    }
+
+    /// reject jobs with `try_execute` once `limit` jobs are waiting with all workers busy
+    pub fn limit_queue(self, limit: Option<usize>) -> ThreadPool {
+        self.shared.state.lock().unwrap().queue_limit = limit;
+        self
+    }
+
+    /// call `hook` whenever a job panics. The worker survives the panic either way
+    pub fn on_panic(self, hook: impl Fn() + Send + Sync + 'static) -> ThreadPool {
+        self.shared.state.lock().unwrap().on_panic = Some(Arc::new(hook));
+        self
     }
 
     pub fn execute<F>(&self, f: F)
     where
         F: FnOnce() + Send + 'static,
     {
-        let job = Box::new(f);
+        self.execute_with_priority(Priority::Normal, f);
+    }
 
-        self.sender.as_ref().unwrap().send(job).unwrap();
+    pub fn execute_with_priority<F>(&self, priority: Priority, f: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let state = self.shared.state.lock().unwrap();
+        push(&self.shared, state, priority, Box::new(f));
+    }
+
+    /// run `f` unless the pool is saturated, in which case it is handed back
+    pub fn try_execute<F>(&self, f: F) -> Result<(), F>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.try_execute_with_priority(Priority::Normal, f)
+    }
+
+    /// like `try_execute`, with jobs of either priority counting toward the queue limit
+    pub fn try_execute_with_priority<F>(&self, priority: Priority, f: F) -> Result<(), F>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let state = self.shared.state.lock().unwrap();
+        let free = state.workers.len() - state.busy;
+        let waiting = state.queued().saturating_sub(free);
+        let saturated = state.workers.len() >= self.shared.max
+            && state.queue_limit.is_some_and(|limit| waiting >= limit);
+        if saturated {
+            return Err(f);
+        }
+        push(&self.shared, state, priority, Box::new(f));
+        Ok(())
     }
+
+    /// run `f` if a worker is free or can be added, handing it back instead of queueing it
+    pub fn try_execute_now<F>(&self, f: F) -> Result<(), F>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let state = self.shared.state.lock().unwrap();
+        let free = state.workers.len() - state.busy;
+        if state.queued() >= free && state.workers.len() >= self.shared.max {
+            return Err(f);
+        }
+        push(&self.shared, state, Priority::Normal, Box::new(f));
+        Ok(())
+    }
+
+    /// the number of live workers
+    pub fn workers(&self) -> usize {
+        self.shared.state.lock().unwrap().workers.len()
+    }
+
+    /// a handle queueing jobs on the pool, for threads that can't borrow it
+    #[cfg(feature = "evented")]
+    pub(crate) fn spawner(&self) -> Spawner {
+        Spawner {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+/// queues jobs like `ThreadPool::execute`, without owning the workers. Only the pool joins them
+#[cfg(feature = "evented")]
+pub(crate) struct Spawner {
+    shared: Arc<Shared>,
+}
+
+#[cfg(feature = "evented")]
+impl Spawner {
+    pub(crate) fn execute(&self, f: impl FnOnce() + Send + 'static) {
+        let state = self.shared.state.lock().unwrap();
+        push(&self.shared, state, Priority::Normal, Box::new(f));
+    }
+}
+
+impl State {
+    /// jobs waiting for a worker
+    fn queued(&self) -> usize {
+        self.urgent.len() + self.jobs.len()
+    }
+
+    fn next_job(&mut self) -> Option<Job> {
+        self.urgent.pop_front().or_else(|| self.jobs.pop_front())
+    }
+}
+
+fn push(shared: &Arc<Shared>, mut state: MutexGuard<State>, priority: Priority, job: Job) {
+    match priority {
+        Priority::High => state.urgent.push_back(job),
+        Priority::Normal => state.jobs.push_back(job),
+    }
+    // every free worker will pick up one of the queued jobs, the rest need new workers
+    let free = state.workers.len() - state.busy;
+    if state.queued() > free && state.workers.len() < shared.max {
+        spawn_worker(shared, &mut state);
+    }
+    drop(state);
+    shared.available.notify_one();
+}
+
+fn spawn_worker(shared: &Arc<Shared>, state: &mut State) {
+    let id = state.next_id;
+    state.next_id += 1;
+    let shared = Arc::clone(shared);
+    let thread = thread::spawn(move || work(&shared, id));
+    state.workers.insert(id, thread);
 }
 
-impl Worker {
-    fn new(id: usize, receiver: Arc<Mutex<mpsc::Receiver<Job>>>) -> Worker {
-        let thread = thread::spawn(move || loop {
-            let job = receiver.lock().unwrap().recv().unwrap();
-            job();
-        });
+fn work(shared: &Shared, id: usize) {
+    let mut state = shared.state.lock().unwrap();
+    loop {
+        if let Some(job) = state.next_job() {
+            state.busy += 1;
+            drop(state);
+            // a panicking job must not shrink the pool
+            let panicked = panic::catch_unwind(AssertUnwindSafe(job)).err();
+            state = shared.state.lock().unwrap();
+            state.busy -= 1;
+            if let Some(payload) = panicked {
+                println!(
+                    "worker {} recovered from a panic: {}",
+                    id,
+                    panic_message(&*payload)
+                );
+                if let Some(hook) = state.on_panic.clone() {
+                    drop(state);
+                    hook();
+                    state = shared.state.lock().unwrap();
+                }
+            }
+            continue;
+        }
+        if state.shutting_down {
+            return;
+        }
+
+        let timed_out = match shared.idle_timeout {
+            Some(idle_timeout) => {
+                let (next, timeout) = shared.available.wait_timeout(state, idle_timeout).unwrap();
+                state = next;
+                timeout.timed_out()
+            }
+            None => {
+                state = shared.available.wait(state).unwrap();
+                false
+            }
+        };
 
-        Worker {
-            id,
-            thread: Some(thread),
+        if timed_out
+            && state.queued() == 0
+            && !state.shutting_down
+            && state.workers.len() > shared.min
+        {
+            // detach the handle, nobody needs to join a worker that stopped on its own
+            state.workers.remove(&id);
+            return;
         }
     }
 }
+
+/// the message a panic was raised with, if it's text
+pub(crate) fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    match payload.downcast_ref::<&str>() {
+        Some(message) => message,
+        None => payload
+            .downcast_ref::<String>()
+            .map_or("<non-text payload>", String::as_str),
+    }
+}
@@ -0,0 +1,194 @@
+use std::{
+    io::{self, BufReader, ErrorKind, Read, Write},
+    net::{Shutdown, TcpStream, ToSocketAddrs},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    http_server::{HTTPRequest, HTTPResponse},
+    socket::Socket,
+};
+
+/// which CONNECT requests a server turns into tunnels, set with `HTTPServerBuilder::tunnel`.
+/// An accepted request is answered with 200, after which the connection carries raw bytes to
+/// and from the requested `host:port` until either side closes it or both stay quiet for the
+/// idle timeout. A tunnel keeps its worker thread busy while open, and only works on plaintext
+/// connections
+#[derive(Clone, Debug)]
+pub struct Tunnel {
+    allowed: Vec<(String, String)>,
+    connect_timeout: Duration,
+    idle_timeout: Duration,
+}
+
+/// the connection to the upstream of an accepted CONNECT, waiting for the response to be sent
+pub(crate) struct Upstream(pub(crate) TcpStream);
+
+impl Default for Tunnel {
+    fn default() -> Tunnel {
+        Tunnel {
+            allowed: Vec::new(),
+            connect_timeout: Duration::from_secs(10),
+            idle_timeout: Duration::from_secs(300),
+        }
+    }
+}
+
+impl Tunnel {
+    /// a tunnel refusing every destination until some are allowed
+    pub fn new() -> Tunnel {
+        Tunnel::default()
+    }
+
+    /// allow destinations matching `pattern`, like `example.com:443`, `*.example.com:443` for
+    /// its subdomains or `*:443` for any host. A port of `*` allows every port
+    pub fn allow(mut self, pattern: &str) -> Self {
+        if let Some((host, port)) = pattern.rsplit_once(':') {
+            self.allowed
+                .push((host.to_ascii_lowercase(), String::from(port)));
+        }
+        self
+    }
+
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    pub fn idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = timeout;
+        self
+    }
+
+    /// whether `host` and `port` match one of the allowed patterns
+    fn allows(&self, host: &str, port: &str) -> bool {
+        let host = host.to_ascii_lowercase();
+        self.allowed.iter().any(|(allowed_host, allowed_port)| {
+            let host_matches = match allowed_host.strip_prefix('*') {
+                Some("") => true,
+                Some(suffix) => host.ends_with(suffix),
+                None => *allowed_host == host,
+            };
+            host_matches && (allowed_port == "*" || allowed_port == port)
+        })
+    }
+
+    /// check the destination of a CONNECT request and connect to it, leaving the connection
+    /// in the request's extensions. 400 for a target not in `host:port` form, 403 for one that
+    /// isn't allowed, 502 if it can't be reached and 504 if connecting takes too long
+    pub(crate) fn open(&self, request: &mut HTTPRequest) -> HTTPResponse {
+        if request.secure {
+            return error(501, "Tunnels need a plaintext connection");
+        }
+        let Some((host, port)) = request.target.rsplit_once(':') else {
+            return error(400, "Expected a host:port target");
+        };
+        if host.is_empty() || port.parse::<u16>().is_err() {
+            return error(400, "Expected a host:port target");
+        }
+        if !self.allows(host.trim_start_matches('[').trim_end_matches(']'), port) {
+            return error(403, "Forbidden");
+        }
+        match self.connect(&request.target) {
+            Ok(upstream) => {
+                request.extensions.insert(Upstream(upstream));
+                HTTPResponse::builder().empty()
+            }
+            Err(error) if matches!(error.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                println!("tunnel to {} timed out: {}", request.target, error);
+                self::error(504, "Gateway Timeout")
+            }
+            Err(error) => {
+                println!("failed opening tunnel to {}: {}", request.target, error);
+                self::error(502, "Bad Gateway")
+            }
+        }
+    }
+
+    fn connect(&self, authority: &str) -> io::Result<TcpStream> {
+        let mut last_error = io::Error::new(ErrorKind::NotFound, "no address for the host");
+        for address in authority.to_socket_addrs()? {
+            match TcpStream::connect_timeout(&address, self.connect_timeout) {
+                Ok(stream) => return Ok(stream),
+                Err(error) => last_error = error,
+            }
+        }
+        Err(last_error)
+    }
+
+    /// copy bytes between the client and `upstream` until both directions are closed. Bytes the
+    /// client sent after the CONNECT head are still in `client`'s buffer and go out first.
+    /// Returns how many bytes were sent to the client
+    pub(crate) fn pipe<S: Read + Write>(
+        &self,
+        client: &mut BufReader<S>,
+        socket: &Socket,
+        upstream: TcpStream,
+    ) -> io::Result<u64> {
+        let activity = Arc::new(Mutex::new(Instant::now()));
+        socket.set_read_timeout(Some(self.idle_timeout))?;
+        upstream.set_read_timeout(Some(self.idle_timeout))?;
+
+        let mut to_client = socket.try_clone()?;
+        let mut from_upstream = upstream.try_clone()?;
+        let downstream_activity = Arc::clone(&activity);
+        let idle_timeout = self.idle_timeout;
+        let downstream = thread::spawn(move || {
+            let copied = copy(
+                &mut from_upstream,
+                &mut to_client,
+                &downstream_activity,
+                idle_timeout,
+            );
+            let _ = to_client.shutdown(Shutdown::Write);
+            copied
+        });
+
+        let copied = copy(client, &mut &upstream, &activity, self.idle_timeout);
+        let _ = upstream.shutdown(Shutdown::Write);
+        if copied.is_err() {
+            // the client is gone, there's no one left to send to
+            let _ = upstream.shutdown(Shutdown::Both);
+        }
+        let sent = downstream
+            .join()
+            .unwrap_or_else(|_| Err(io::Error::other("tunnel thread panicked")));
+        copied.and(sent)
+    }
+}
+
+/// copy `from` to `to` until `from` ends. A read timing out only ends the copy if the other
+/// direction has been quiet as long
+fn copy(
+    from: &mut impl Read,
+    to: &mut impl Write,
+    activity: &Mutex<Instant>,
+    idle_timeout: Duration,
+) -> io::Result<u64> {
+    let mut buffer = [0; 16 * 1024];
+    let mut copied = 0;
+    loop {
+        match from.read(&mut buffer) {
+            Ok(0) => return Ok(copied),
+            Ok(read) => {
+                to.write_all(&buffer[..read])?;
+                to.flush()?;
+                copied += read as u64;
+                *activity.lock().unwrap() = Instant::now();
+            }
+            Err(error) if matches!(error.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                if activity.lock().unwrap().elapsed() >= idle_timeout {
+                    return Ok(copied);
+                }
+            }
+            Err(error) if error.kind() == ErrorKind::Interrupted => {}
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+fn error(status: u16, message: &str) -> HTTPResponse {
+    HTTPResponse::builder().status(status).text(message)
+}
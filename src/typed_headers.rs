@@ -0,0 +1,253 @@
+use std::{collections::HashMap, fmt, time::SystemTime};
+
+use crate::{
+    cookies::parse_cookies,
+    headers::HeaderMap,
+    http_date::{format_http_date, parse_http_date},
+    multipart::header_params,
+    range::{parse_range, ByteRange},
+};
+
+/// a `Content-Type` value like `text/html; charset=utf-8`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ContentType {
+    /// the lowercase media type, like `multipart/form-data`
+    pub media_type: String,
+    pub charset: Option<String>,
+    pub boundary: Option<String>,
+}
+
+/// an `Authorization` value like `Bearer <token>`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Authorization {
+    pub scheme: String,
+    /// everything after the scheme, still encoded as sent
+    pub credentials: String,
+}
+
+impl ContentType {
+    pub fn new(media_type: &str) -> ContentType {
+        ContentType {
+            media_type: media_type.to_ascii_lowercase(),
+            charset: None,
+            boundary: None,
+        }
+    }
+
+    pub fn charset(mut self, charset: &str) -> Self {
+        self.charset = Some(String::from(charset));
+        self
+    }
+
+    pub fn boundary(mut self, boundary: &str) -> Self {
+        self.boundary = Some(String::from(boundary));
+        self
+    }
+
+    /// parse a `Content-Type` value, `None` if it lacks a `type/subtype`
+    pub fn parse(value: &str) -> Option<ContentType> {
+        let mut params = value.split(';');
+        let media_type = params.next()?.trim();
+        if !media_type.contains('/') {
+            return None;
+        }
+        let mut content_type = ContentType::new(media_type);
+        for (name, value) in header_params(params) {
+            if name.eq_ignore_ascii_case("charset") {
+                content_type.charset = Some(value);
+            } else if name.eq_ignore_ascii_case("boundary") {
+                content_type.boundary = Some(value);
+            }
+        }
+        Some(content_type)
+    }
+}
+
+/// as a `Content-Type` value
+impl fmt::Display for ContentType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.media_type)?;
+        if let Some(charset) = &self.charset {
+            write!(f, "; charset={}", charset)?;
+        }
+        if let Some(boundary) = &self.boundary {
+            write!(f, "; boundary=\"{}\"", boundary)?;
+        }
+        Ok(())
+    }
+}
+
+impl Authorization {
+    pub fn new(scheme: &str, credentials: &str) -> Authorization {
+        Authorization {
+            scheme: String::from(scheme),
+            credentials: String::from(credentials),
+        }
+    }
+
+    /// parse an `Authorization` value, `None` without credentials after the scheme
+    pub fn parse(value: &str) -> Option<Authorization> {
+        let (scheme, credentials) = value.trim().split_once(' ')?;
+        let credentials = credentials.trim();
+        (!credentials.is_empty()).then(|| Authorization::new(scheme, credentials))
+    }
+
+    /// check the scheme ignoring case, like `is("Bearer")`
+    pub fn is(&self, scheme: &str) -> bool {
+        self.scheme.eq_ignore_ascii_case(scheme)
+    }
+}
+
+/// as an `Authorization` value
+impl fmt::Display for Authorization {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.scheme, self.credentials)
+    }
+}
+
+/// typed views of common headers, for requests and responses alike. Getters return `None`
+/// for missing and malformed values
+impl HeaderMap {
+    pub fn content_type(&self) -> Option<ContentType> {
+        self.get("Content-Type").and_then(ContentType::parse)
+    }
+
+    pub fn set_content_type(&mut self, content_type: &ContentType) {
+        self.insert("Content-Type", content_type.to_string());
+    }
+
+    pub fn content_length(&self) -> Option<u64> {
+        self.get("Content-Length")?.trim().parse().ok()
+    }
+
+    pub fn set_content_length(&mut self, length: u64) {
+        self.insert("Content-Length", length.to_string());
+    }
+
+    pub fn authorization(&self) -> Option<Authorization> {
+        self.get("Authorization").and_then(Authorization::parse)
+    }
+
+    pub fn set_authorization(&mut self, authorization: &Authorization) {
+        self.insert("Authorization", authorization.to_string());
+    }
+
+    /// the cookies of every `Cookie` header
+    pub fn cookies(&self) -> HashMap<&str, &str> {
+        self.get_all("Cookie")
+            .into_iter()
+            .flat_map(parse_cookies)
+            .collect()
+    }
+
+    /// send `cookies` as a single `Cookie` header
+    pub fn set_cookies(&mut self, cookies: &[(&str, &str)]) {
+        let cookies: Vec<String> = cookies
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect();
+        self.insert("Cookie", cookies.join("; "));
+    }
+
+    pub fn range(&self) -> Option<ByteRange> {
+        self.get("Range").and_then(parse_range)
+    }
+
+    pub fn set_range(&mut self, range: ByteRange) {
+        self.insert("Range", range.to_string());
+    }
+
+    pub fn if_modified_since(&self) -> Option<SystemTime> {
+        self.get("If-Modified-Since").and_then(parse_http_date)
+    }
+
+    pub fn set_if_modified_since(&mut self, since: SystemTime) {
+        self.insert("If-Modified-Since", format_http_date(since));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::*;
+
+    #[test]
+    fn parses_content_types() {
+        let content_type =
+            ContentType::parse("Multipart/Form-Data; boundary=\"a b\"; Charset=utf-8").unwrap();
+        assert_eq!(content_type.media_type, "multipart/form-data");
+        assert_eq!(content_type.charset.as_deref(), Some("utf-8"));
+        assert_eq!(content_type.boundary.as_deref(), Some("a b"));
+        assert_eq!(
+            ContentType::parse("text/html"),
+            Some(ContentType::new("text/html"))
+        );
+        assert_eq!(ContentType::parse("html"), None);
+        assert_eq!(ContentType::parse(""), None);
+
+        let content_type = ContentType::new("text/plain")
+            .charset("utf-8")
+            .boundary("x");
+        assert_eq!(
+            content_type.to_string(),
+            "text/plain; charset=utf-8; boundary=\"x\""
+        );
+        assert_eq!(
+            ContentType::parse(&content_type.to_string()),
+            Some(content_type)
+        );
+    }
+
+    #[test]
+    fn parses_authorization() {
+        let authorization = Authorization::parse("  Bearer   abc.def ").unwrap();
+        assert_eq!(authorization, Authorization::new("Bearer", "abc.def"));
+        assert!(authorization.is("bearer"));
+        assert!(!authorization.is("Basic"));
+        assert_eq!(authorization.to_string(), "Bearer abc.def");
+        assert_eq!(Authorization::parse("Bearer"), None);
+        assert_eq!(Authorization::parse("Bearer  "), None);
+    }
+
+    #[test]
+    fn typed_getters_and_setters() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(headers.content_type(), None);
+        headers.set_content_type(&ContentType::new("application/json"));
+        assert_eq!(headers.get("Content-Type"), Some("application/json"));
+        headers.set_content_length(42);
+        assert_eq!(headers.content_length(), Some(42));
+        headers.insert("Content-Length", "-1");
+        assert_eq!(headers.content_length(), None);
+
+        headers.set_authorization(&Authorization::new("Basic", "YTpi"));
+        assert_eq!(headers.get("Authorization"), Some("Basic YTpi"));
+        assert!(headers.authorization().unwrap().is("basic"));
+
+        headers.set_range(ByteRange::Suffix(10));
+        assert_eq!(headers.get("Range"), Some("bytes=-10"));
+        assert_eq!(headers.range(), Some(ByteRange::Suffix(10)));
+
+        let since = UNIX_EPOCH + Duration::from_secs(784111777);
+        headers.set_if_modified_since(since);
+        assert_eq!(
+            headers.get("If-Modified-Since"),
+            Some("Sun, 06 Nov 1994 08:49:37 GMT")
+        );
+        assert_eq!(headers.if_modified_since(), Some(since));
+        headers.insert("If-Modified-Since", "yesterday");
+        assert_eq!(headers.if_modified_since(), None);
+    }
+
+    #[test]
+    fn cookies_of_every_header() {
+        let mut headers = HeaderMap::new();
+        headers.set_cookies(&[("a", "1"), ("b", "2")]);
+        assert_eq!(headers.get("Cookie"), Some("a=1; b=2"));
+        headers.append("Cookie", "c=3");
+        let cookies = headers.cookies();
+        assert_eq!(cookies.len(), 3);
+        assert_eq!(cookies["c"], "3");
+    }
+}
@@ -0,0 +1,122 @@
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    net::SocketAddr,
+    time::Duration,
+};
+
+use crate::{
+    http_server::{Body, HTTPRequest, HTTPResponse},
+    socket::Socket,
+};
+
+/// takes over a connection once its `101 Switching Protocols` response is sent
+pub type UpgradeHandler = Box<dyn for<'a> FnOnce(Upgraded<'a>) + Send>;
+
+/// a connection after a `101 Switching Protocols`, speaking the protocol the response switched
+/// to. Reads start with any bytes the client sent right after its request. The connection
+/// closes once the handler returns
+pub struct Upgraded<'a> {
+    stream: &'a mut dyn Stream,
+    socket: &'a Socket,
+}
+
+/// the buffered stream of a connection, readable through its buffer and writable beneath it
+trait Stream {
+    fn reader(&mut self) -> &mut dyn BufRead;
+    fn writer(&mut self) -> &mut dyn Write;
+}
+
+impl<S: Read + Write> Stream for BufReader<S> {
+    fn reader(&mut self) -> &mut dyn BufRead {
+        self
+    }
+
+    fn writer(&mut self) -> &mut dyn Write {
+        self.get_mut()
+    }
+}
+
+impl Upgraded<'_> {
+    /// how long reads wait for the client, `None` to wait without limit. The server's read
+    /// timeout applies until this is called
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.socket.set_read_timeout(timeout)
+    }
+
+    pub fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.socket.set_write_timeout(timeout)
+    }
+
+    /// address of the client, `None` for unix sockets
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        self.socket.peer_addr()
+    }
+}
+
+impl Read for Upgraded<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.stream.reader().read(buf)
+    }
+}
+
+impl BufRead for Upgraded<'_> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.stream.reader().fill_buf()
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.stream.reader().consume(amount)
+    }
+}
+
+impl Write for Upgraded<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stream.writer().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.writer().flush()
+    }
+}
+
+impl HTTPRequest {
+    /// whether the client asks to switch to `protocol`, like `websocket`, with `Upgrade` and
+    /// `Connection: upgrade` headers
+    pub fn wants_upgrade(&self, protocol: &str) -> bool {
+        self.headers.has_token("Connection", "upgrade")
+            && self
+                .headers
+                .get_all("Upgrade")
+                .iter()
+                .flat_map(|value| value.split(','))
+                .any(|offered| {
+                    let name = offered.trim().split('/').next().unwrap_or("");
+                    name.eq_ignore_ascii_case(protocol)
+                })
+    }
+}
+
+impl HTTPResponse {
+    /// a `101 Switching Protocols` to `protocol`, after which `handler` gets the connection to
+    /// speak it, e.g. `HTTPResponse::upgrade("echo", |mut stream| { io::copy(..) })`. The
+    /// handler runs on the worker thread, which stays busy until it returns
+    pub fn upgrade(
+        protocol: &str,
+        handler: impl FnOnce(Upgraded<'_>) + Send + 'static,
+    ) -> HTTPResponse {
+        HTTPResponse::builder()
+            .status(101)
+            .header("Connection", "Upgrade")
+            .header("Upgrade", protocol)
+            .body(Body::Upgrade(Box::new(handler)))
+    }
+}
+
+/// hand the connection to `handler`, once the 101 is sent
+pub(crate) fn run<S: Read + Write>(
+    handler: UpgradeHandler,
+    stream: &mut BufReader<S>,
+    socket: &Socket,
+) {
+    handler(Upgraded { stream, socket });
+}
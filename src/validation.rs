@@ -0,0 +1,135 @@
+use std::sync::Arc;
+
+use crate::{
+    http_server::{HTTPRequest, HTTPResponse, Route},
+    middleware::Next,
+    problem::HTTPError,
+};
+
+type Check = Arc<dyn Fn(&HTTPRequest) -> bool + Send + Sync>;
+
+/// checks a route's requests pass before reaching its listener, answering those that don't
+/// with a problem document: 400 for a missing header or failed check, 415 for a body of
+/// another type and 413 for one that's too large. Added with `Route::validate`:
+/// `Route::new(..).validate(Validate::new().content_type("application/json").max_body_size(4096))`
+#[derive(Clone, Default)]
+pub struct Validate {
+    headers: Vec<String>,
+    content_types: Vec<String>,
+    max_body_size: Option<usize>,
+    checks: Vec<(Check, String)>,
+}
+
+impl Validate {
+    pub fn new() -> Validate {
+        Validate::default()
+    }
+
+    /// require the header `name`
+    pub fn header(mut self, name: &str) -> Self {
+        self.headers.push(String::from(name));
+        self
+    }
+
+    /// accept bodies of `media_type`, which may be a range like `text/*`. Once any is given,
+    /// a body of another or no type is refused. Requests without a body are always accepted
+    pub fn content_type(mut self, media_type: &str) -> Self {
+        self.content_types.push(media_type.to_ascii_lowercase());
+        self
+    }
+
+    /// refuse bodies larger than `size` bytes, as sent or once decoded. Chunked bodies are cut
+    /// off once they grow beyond it, as are encoded ones while decoding, so reading them fails
+    /// with `BodyError::TooLarge`
+    pub fn max_body_size(mut self, size: usize) -> Self {
+        self.max_body_size = Some(size);
+        self
+    }
+
+    /// refuse requests `check` returns false for, with `message` as the detail of the 400
+    pub fn check(
+        mut self,
+        check: impl Fn(&HTTPRequest) -> bool + Send + Sync + 'static,
+        message: &str,
+    ) -> Self {
+        self.checks.push((Arc::new(check), String::from(message)));
+        self
+    }
+
+    /// the response for the first rule `request` breaks, in the order headers, body size,
+    /// content type, checks
+    pub fn validate(&self, request: &HTTPRequest) -> Result<(), HTTPResponse> {
+        if let Some(name) = self
+            .headers
+            .iter()
+            .find(|name| !request.headers.contains(name))
+        {
+            return Err(HTTPError::bad_request(&format!("missing header {}", name))
+                .extension("header", name)
+                .response());
+        }
+
+        // the body knows how it was sent, even for encoded requests that lost their
+        // Content-Length
+        let has_body = request.body.is_present();
+        if let (Some(max), Some(length)) = (self.max_body_size, request.body.sent_length()) {
+            if length > max as u64 {
+                return Err(HTTPError::new(413)
+                    .detail(&format!("the body may be at most {} bytes", max))
+                    .response());
+            }
+        }
+        if has_body && !self.content_types.is_empty() {
+            let media_type = request.headers.content_type().map(|c| c.media_type);
+            let accepted = media_type.as_deref().is_some_and(|media_type| {
+                self.content_types
+                    .iter()
+                    .any(|accepted| media_type_matches(accepted, media_type))
+            });
+            if !accepted {
+                return Err(HTTPError::new(415)
+                    .detail(&format!(
+                        "expected a body of type {}",
+                        self.content_types.join(", ")
+                    ))
+                    .response());
+            }
+        }
+
+        match self.checks.iter().find(|(check, _)| !check(request)) {
+            Some((_, message)) => Err(HTTPError::bad_request(message).response()),
+            None => Ok(()),
+        }
+    }
+
+    /// the rules as middleware, e.g. for `Scope::middleware` to validate a group of routes
+    pub fn middleware<T>(
+        self,
+    ) -> impl Fn(&mut HTTPRequest, &T, Next<T>) -> HTTPResponse + Send + Sync + 'static {
+        move |request: &mut HTTPRequest, context: &T, next: Next<T>| {
+            if let Err(response) = self.validate(request) {
+                return response;
+            }
+            if let Some(max) = self.max_body_size {
+                request.body.limit(max);
+            }
+            next.run(request, context)
+        }
+    }
+}
+
+impl<T: std::marker::Sync + std::marker::Send + 'static> Route<T> {
+    /// validate requests before the listener and the route's other middleware see them
+    pub fn validate(mut self, validate: Validate) -> Self {
+        self.middleware.insert(0, Arc::new(validate.middleware()));
+        self
+    }
+}
+
+fn media_type_matches(accepted: &str, media_type: &str) -> bool {
+    match accepted.strip_suffix("/*") {
+        Some("*") => true,
+        Some(kind) => media_type.split('/').next() == Some(kind),
+        None => accepted == media_type,
+    }
+}
@@ -0,0 +1,183 @@
+#![cfg(feature = "evented")]
+
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpStream,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use adhesion::{
+    http_server::{Dispatch, HTTPRequest, HTTPServer},
+    sse::{self, Event, EventSender},
+    testing::TestServer,
+};
+
+/// far more connections than the server has workers
+const CONNECTIONS: usize = 50;
+
+/// the senders of every open event stream
+type Streams = Arc<Mutex<Vec<EventSender>>>;
+
+fn server(streams: &Streams, keep_alive: Duration) -> TestServer {
+    let server = HTTPServer::builder(Arc::clone(streams))
+        .dispatch(Dispatch::Evented)
+        .threads(2)
+        .max_threads(2)
+        .get("/hello", |_: &HTTPRequest, _: &Streams| "hello")
+        .get("/events", move |_: &HTTPRequest, streams: &Streams| {
+            let (sender, response) = sse::event_stream(keep_alive);
+            streams.lock().unwrap().push(sender);
+            response
+        })
+        .keep_alive_timeout(Some(Duration::from_secs(5)))
+        .build();
+    TestServer::spawn(server).unwrap()
+}
+
+fn connect(server: &TestServer) -> BufReader<TcpStream> {
+    let stream = TcpStream::connect(server.addr()).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    BufReader::new(stream)
+}
+
+/// the status and headers of a response, lowercased
+fn read_head(reader: &mut impl BufRead) -> (u16, Vec<String>) {
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+    let status = line.split(' ').nth(1).unwrap().parse().unwrap();
+    let mut headers = Vec::new();
+    loop {
+        line.clear();
+        reader.read_line(&mut line).unwrap();
+        if line.trim_end().is_empty() {
+            return (status, headers);
+        }
+        headers.push(line.trim_end().to_ascii_lowercase());
+    }
+}
+
+/// the next chunk of a chunked body, empty for the last one
+fn read_chunk(reader: &mut impl BufRead) -> String {
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+    let size = usize::from_str_radix(line.trim_end(), 16).unwrap();
+    let mut chunk = vec![0; size + 2];
+    reader.read_exact(&mut chunk).unwrap();
+    chunk.truncate(size);
+    String::from_utf8(chunk).unwrap()
+}
+
+fn get_hello(connection: &mut BufReader<TcpStream>) {
+    connection
+        .get_mut()
+        .write_all(b"GET /hello HTTP/1.1\r\nHost: test\r\n\r\n")
+        .unwrap();
+    let (status, headers) = read_head(connection);
+    assert_eq!(status, 200);
+    assert!(headers.contains(&String::from("content-length: 5")));
+    let mut body = [0; 5];
+    connection.read_exact(&mut body).unwrap();
+    assert_eq!(&body, b"hello");
+}
+
+fn open_streams(server: &TestServer, streams: &Streams) -> Vec<BufReader<TcpStream>> {
+    let connections: Vec<_> = (0..CONNECTIONS)
+        .map(|_| {
+            let mut connection = connect(server);
+            connection
+                .get_mut()
+                .write_all(b"GET /events HTTP/1.1\r\nHost: test\r\n\r\n")
+                .unwrap();
+            let (status, headers) = read_head(&mut connection);
+            assert_eq!(status, 200);
+            assert!(headers.contains(&String::from("transfer-encoding: chunked")));
+            connection
+        })
+        .collect();
+    assert_eq!(streams.lock().unwrap().len(), CONNECTIONS);
+    connections
+}
+
+#[test]
+fn idle_connections_dont_hold_workers() {
+    let streams = Streams::default();
+    let server = server(&streams, Duration::from_secs(5));
+    let mut connections: Vec<_> = (0..CONNECTIONS).map(|_| connect(&server)).collect();
+    // every connection is answered while all the others stay open
+    for connection in &mut connections {
+        get_hello(connection);
+    }
+    for connection in connections.iter_mut().rev() {
+        get_hello(connection);
+    }
+}
+
+#[test]
+fn event_streams_dont_hold_workers() {
+    let streams = Streams::default();
+    let server = server(&streams, Duration::from_secs(5));
+    let mut connections = open_streams(&server, &streams);
+    // the server still answers other requests while all streams are open
+    get_hello(&mut connect(&server));
+
+    for sender in streams.lock().unwrap().iter() {
+        sender.send(&Event::new("one")).unwrap();
+        sender.send(&Event::new("two")).unwrap();
+    }
+    for connection in &mut connections {
+        assert_eq!(read_chunk(connection), "data: one\n\n");
+        assert_eq!(read_chunk(connection), "data: two\n\n");
+    }
+
+    // the streams end with their senders, and their connections take the next request
+    streams.lock().unwrap().clear();
+    for connection in &mut connections {
+        assert_eq!(read_chunk(connection), "");
+        get_hello(connection);
+    }
+}
+
+#[test]
+fn quiet_event_streams_get_keep_alive_comments() {
+    let streams = Streams::default();
+    let server = server(&streams, Duration::from_millis(50));
+    let mut connections = open_streams(&server, &streams);
+    for connection in &mut connections {
+        assert_eq!(read_chunk(connection), ": keep-alive\n\n");
+    }
+}
+
+#[test]
+fn disconnected_event_streams_fail_sending() {
+    let streams = Streams::default();
+    let server = server(&streams, Duration::from_millis(50));
+    drop(open_streams(&server, &streams));
+    // the client is noticed gone once a keep-alive comment can't be written
+    for sender in streams.lock().unwrap().iter() {
+        let mut attempts = 0;
+        while sender.comment("ping").is_ok() {
+            attempts += 1;
+            assert!(attempts < 100, "the stream wasn't disconnected");
+            std::thread::sleep(Duration::from_millis(50));
+        }
+    }
+}
+
+#[test]
+fn shutdown_closes_parked_connections() {
+    let streams = Streams::default();
+    let server = server(&streams, Duration::from_secs(5));
+    let mut connections: Vec<_> = (0..CONNECTIONS).map(|_| connect(&server)).collect();
+    for connection in &mut connections {
+        get_hello(connection);
+    }
+    server.shutdown();
+    for connection in &mut connections {
+        let mut rest = Vec::new();
+        connection.read_to_end(&mut rest).unwrap();
+        assert!(rest.is_empty());
+    }
+}
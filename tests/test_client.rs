@@ -0,0 +1,149 @@
+use std::{
+    net::SocketAddr,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use adhesion::{
+    error_pages::ErrorPage,
+    forwarded::TrustedProxies,
+    http_server::{HTTPMethod, HTTPRequest, HTTPResponse, HTTPServer},
+    testing::TestClient,
+};
+
+fn text(response: HTTPResponse) -> String {
+    String::from_utf8(response.body.into_bytes().unwrap()).unwrap()
+}
+
+fn client() -> TestClient<AtomicUsize> {
+    let server = HTTPServer::builder(AtomicUsize::new(0))
+        .get("/", |_: &HTTPRequest, _: &AtomicUsize| "index")
+        .get("/users/:id", |request: &HTTPRequest, _: &AtomicUsize| {
+            format!("user {}", request.path_params["id"])
+        })
+        .post("/users", |request: &HTTPRequest, created: &AtomicUsize| {
+            created.fetch_add(1, Ordering::SeqCst);
+            HTTPResponse::builder()
+                .status(201)
+                .text(request.body.text().unwrap().to_uppercase())
+        })
+        .get("/search", |request: &HTTPRequest, _: &AtomicUsize| {
+            let term = request.query_params.get("q").unwrap_or("nothing");
+            format!("searching {}", term)
+        })
+        .get("/whoami", |request: &HTTPRequest, _: &AtomicUsize| {
+            format!(
+                "{} {}",
+                request
+                    .client_ip
+                    .map_or(String::from("-"), |ip| ip.to_string()),
+                request.secure
+            )
+        })
+        .get("/boom", |_: &HTTPRequest, _: &AtomicUsize| -> String {
+            panic!("handler failed")
+        })
+        .scope("/admin", |scope| {
+            scope
+                .middleware(
+                    |request, passthrough, next| match request.header("Authorization") {
+                        Some("letmein") => next.run(request, passthrough),
+                        _ => HTTPResponse::builder().status(401).text("no"),
+                    },
+                )
+                .get("/stats", |_: &HTTPRequest, created: &AtomicUsize| {
+                    format!("{} created", created.load(Ordering::SeqCst))
+                })
+        })
+        .trusted_proxies(TrustedProxies::new().trust("10.0.0.0/8"))
+        .error_page(404, ErrorPage::text("nothing at {method} {path}"))
+        .build();
+    TestClient::new(server)
+}
+
+#[test]
+fn routes_by_method_and_path() {
+    let client = client();
+    assert_eq!(text(client.get("/").send()), "index");
+    assert_eq!(text(client.get("/users/42").send()), "user 42");
+    assert_eq!(text(client.get("/users/42/").send()), "user 42");
+    assert_eq!(client.delete("/users/42").send().status.status, 405);
+}
+
+#[test]
+fn sends_bodies_and_queries() {
+    let client = client();
+    let response = client.post("/users").body("alice").send();
+    assert_eq!(response.status.status, 201);
+    assert_eq!(text(response), "ALICE");
+    assert_eq!(
+        text(client.get("/search?q=rust&page=2").send()),
+        "searching rust"
+    );
+    assert_eq!(text(client.get("/search").send()), "searching nothing");
+}
+
+#[test]
+fn passthrough_is_shared_between_requests() {
+    let client = client();
+    for name in ["a", "b", "c"] {
+        client.post("/users").body(name).send();
+    }
+    let stats = client
+        .get("/admin/stats")
+        .header("Authorization", "letmein")
+        .send();
+    assert_eq!(text(stats), "3 created");
+}
+
+#[test]
+fn scoped_middleware_guards_its_routes() {
+    let client = client();
+    assert_eq!(client.get("/admin/stats").send().status.status, 401);
+    let wrong = client
+        .get("/admin/stats")
+        .header("Authorization", "guess")
+        .send();
+    assert_eq!(wrong.status.status, 401);
+    assert_eq!(client.get("/").send().status.status, 200);
+}
+
+#[test]
+fn error_pages_replace_built_in_responses() {
+    let client = client();
+    let response = client.get("/missing").send();
+    assert_eq!(response.status.status, 404);
+    assert_eq!(text(response), "nothing at GET /missing");
+    assert_eq!(client.get("/boom").send().status.status, 500);
+}
+
+#[test]
+fn client_ip_honors_trusted_proxies() {
+    let client = client();
+    let proxy: SocketAddr = "10.1.2.3:4000".parse().unwrap();
+    let forwarded = client
+        .get("/whoami")
+        .peer_addr(proxy)
+        .header("X-Forwarded-For", "203.0.113.7")
+        .secure(true)
+        .send();
+    assert_eq!(text(forwarded), "203.0.113.7 true");
+
+    let untrusted: SocketAddr = "198.51.100.1:4000".parse().unwrap();
+    let spoofed = client
+        .request(HTTPMethod::GET, "/whoami")
+        .peer_addr(untrusted)
+        .header("X-Forwarded-For", "203.0.113.7")
+        .send();
+    assert_eq!(text(spoofed), "198.51.100.1 false");
+}
+
+#[test]
+fn requests_built_separately_can_be_sent() {
+    let client = client();
+    let request = HTTPRequest::builder()
+        .method(HTTPMethod::POST)
+        .path("/users")
+        .header("Content-Type", "text/plain")
+        .body("bob");
+    assert_eq!(text(client.send(request)), "BOB");
+}
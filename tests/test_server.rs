@@ -0,0 +1,335 @@
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpStream,
+    time::Duration,
+};
+
+use adhesion::{
+    error_pages::ErrorPage,
+    headers::HeaderMap,
+    http_server::{Body, HTTPRequest, HTTPResponse, HTTPServer},
+    multipart::MultipartConfig,
+    testing::TestServer,
+};
+
+/// a response read off a connection
+struct Response {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn text(&self) -> &str {
+        std::str::from_utf8(&self.body).unwrap()
+    }
+}
+
+fn read_response(reader: &mut impl BufRead) -> Response {
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+    let status = line.split(' ').nth(1).unwrap().parse().unwrap();
+    let mut headers = Vec::new();
+    loop {
+        line.clear();
+        reader.read_line(&mut line).unwrap();
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        let (name, value) = header.split_once(':').unwrap();
+        headers.push((name.to_owned(), value.trim().to_owned()));
+    }
+    let mut response = Response {
+        status,
+        headers,
+        body: Vec::new(),
+    };
+    if let Some(length) = response.header("Content-Length") {
+        let mut body = vec![0; length.parse().unwrap()];
+        reader.read_exact(&mut body).unwrap();
+        response.body = body;
+    } else if response.header("Transfer-Encoding") == Some("chunked") {
+        loop {
+            line.clear();
+            reader.read_line(&mut line).unwrap();
+            let size = usize::from_str_radix(line.trim_end(), 16).unwrap();
+            let mut chunk = vec![0; size + 2];
+            reader.read_exact(&mut chunk).unwrap();
+            if size == 0 {
+                break;
+            }
+            response.body.extend_from_slice(&chunk[..size]);
+        }
+    }
+    response
+}
+
+fn server() -> TestServer {
+    let server = HTTPServer::builder(())
+        .get("/hello", |_: &HTTPRequest, _: &()| "hello")
+        .post("/echo", |request: &HTTPRequest, _: &()| {
+            HTTPResponse::builder().bytes(request.body.bytes().unwrap().to_vec())
+        })
+        .get("/stream", |_: &HTTPRequest, _: &()| {
+            let chunks = ["one ", "two ", "three"].map(|chunk| chunk.as_bytes().to_vec());
+            HTTPResponse::builder().body(Body::chunks(chunks.into_iter()))
+        })
+        .get("/trailed", |_: &HTTPRequest, _: &()| {
+            let chunks = std::iter::once(b"body".to_vec());
+            let body = Body::trailed(chunks, || {
+                let mut trailers = HeaderMap::new();
+                trailers.append("Checksum", "abc");
+                trailers.append("X-Split", "a\r\nX-Admin: 1");
+                trailers.append("Bad Name", "x");
+                trailers
+            });
+            HTTPResponse::builder()
+                .header("Trailer", "Checksum")
+                .body(body)
+        })
+        .post("/upload", |request: &HTTPRequest, _: &()| {
+            let form = request.multipart(&MultipartConfig::default()).unwrap();
+            let upload = form.file("upload").unwrap().text().unwrap();
+            format!("{}: {}", form.field("title").unwrap(), upload)
+        })
+        .error_page(404, ErrorPage::text("no {path} here"))
+        .keep_alive_timeout(Some(Duration::from_secs(5)))
+        .build();
+    TestServer::spawn(server).unwrap()
+}
+
+fn connect(server: &TestServer) -> BufReader<TcpStream> {
+    let stream = TcpStream::connect(server.addr()).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    BufReader::new(stream)
+}
+
+#[test]
+fn keeps_connections_alive() {
+    let server = server();
+    let mut connection = connect(&server);
+    for _ in 0..3 {
+        connection
+            .get_mut()
+            .write_all(b"GET /hello HTTP/1.1\r\nHost: test\r\n\r\n")
+            .unwrap();
+        let response = read_response(&mut connection);
+        assert_eq!(response.status, 200);
+        assert_eq!(response.text(), "hello");
+        assert_ne!(response.header("Connection"), Some("close"));
+    }
+
+    connection
+        .get_mut()
+        .write_all(b"GET /hello HTTP/1.1\r\nHost: test\r\nConnection: close\r\n\r\n")
+        .unwrap();
+    assert_eq!(
+        read_response(&mut connection).header("Connection"),
+        Some("close")
+    );
+    let mut rest = Vec::new();
+    connection.read_to_end(&mut rest).unwrap();
+    assert!(rest.is_empty());
+}
+
+#[test]
+fn http_1_0_closes_after_the_response() {
+    let server = server();
+    let mut connection = connect(&server);
+    connection
+        .get_mut()
+        .write_all(b"GET /hello HTTP/1.0\r\n\r\n")
+        .unwrap();
+    assert_eq!(read_response(&mut connection).text(), "hello");
+    let mut rest = Vec::new();
+    connection.read_to_end(&mut rest).unwrap();
+    assert!(rest.is_empty());
+}
+
+#[test]
+fn chunked_bodies_both_ways() {
+    let server = server();
+    let mut connection = connect(&server);
+    connection
+        .get_mut()
+        .write_all(b"GET /stream HTTP/1.1\r\nHost: test\r\n\r\n")
+        .unwrap();
+    let response = read_response(&mut connection);
+    assert_eq!(response.header("Transfer-Encoding"), Some("chunked"));
+    assert_eq!(response.text(), "one two three");
+
+    connection
+        .get_mut()
+        .write_all(b"POST /echo HTTP/1.1\r\nHost: test\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabc\r\n2\r\nde\r\n0\r\n\r\n")
+        .unwrap();
+    assert_eq!(read_response(&mut connection).text(), "abcde");
+}
+
+#[test]
+fn uploads_are_read_off_the_connection() {
+    const FORM: &str = "--XyZ\r\n\
+        Content-Disposition: form-data; name=\"title\"\r\n\r\n\
+        notes\r\n\
+        --XyZ\r\n\
+        Content-Disposition: form-data; name=\"upload\"; filename=\"a.txt\"\r\n\r\n\
+        line one\r\nline two\r\n\
+        --XyZ--\r\n\
+        the epilogue";
+    let server = server();
+    let mut connection = connect(&server);
+    let head = "POST /upload HTTP/1.1\r\nHost: test\r\n\
+        Content-Type: multipart/form-data; boundary=XyZ\r\n";
+    write!(
+        connection.get_mut(),
+        "{}Content-Length: {}\r\n\r\n{}",
+        head,
+        FORM.len(),
+        FORM
+    )
+    .unwrap();
+    assert_eq!(
+        read_response(&mut connection).text(),
+        "notes: line one\r\nline two"
+    );
+
+    let (first, second) = FORM.split_at(70);
+    write!(
+        connection.get_mut(),
+        "{}Transfer-Encoding: chunked\r\n\r\n{:x}\r\n{}\r\n{:x}\r\n{}\r\n0\r\n\r\n",
+        head,
+        first.len(),
+        first,
+        second.len(),
+        second
+    )
+    .unwrap();
+    assert_eq!(
+        read_response(&mut connection).text(),
+        "notes: line one\r\nline two"
+    );
+
+    // the epilogues were skipped, leaving the connection at the next request
+    connection
+        .get_mut()
+        .write_all(b"GET /hello HTTP/1.1\r\nHost: test\r\n\r\n")
+        .unwrap();
+    assert_eq!(read_response(&mut connection).text(), "hello");
+}
+
+#[test]
+fn trailers_that_would_split_the_response_are_dropped() {
+    let server = server();
+    let mut connection = connect(&server);
+    connection
+        .get_mut()
+        .write_all(b"GET /trailed HTTP/1.1\r\nHost: test\r\nConnection: close\r\n\r\n")
+        .unwrap();
+    let mut response = String::new();
+    connection.read_to_string(&mut response).unwrap();
+    assert!(
+        response.ends_with("\r\n\r\n4\r\nbody\r\n0\r\nChecksum: abc\r\n\r\n"),
+        "{:?}",
+        response
+    );
+}
+
+#[test]
+fn error_pages_go_over_the_wire() {
+    let server = server();
+    let mut connection = connect(&server);
+    connection
+        .get_mut()
+        .write_all(b"GET /missing HTTP/1.1\r\nHost: test\r\n\r\n")
+        .unwrap();
+    let response = read_response(&mut connection);
+    assert_eq!(response.status, 404);
+    assert_eq!(response.text(), "no /missing here");
+    assert!(response.header("Date").is_some());
+}
+
+#[test]
+fn urls_point_at_the_server() {
+    let server = server();
+    assert_eq!(
+        server.url(),
+        format!("http://127.0.0.1:{}", server.addr().port())
+    );
+    assert_eq!(server.url_for("/hello"), format!("{}/hello", server.url()));
+}
+
+#[test]
+fn shutdown_stops_accepting() {
+    let server = server();
+    let addr = server.addr();
+    server.shutdown();
+    let refused = TcpStream::connect(addr).and_then(|mut stream| {
+        stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+        stream.write_all(b"GET /hello HTTP/1.1\r\nHost: test\r\n\r\n")?;
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response)?;
+        Ok(response)
+    });
+    assert!(refused.map_or(true, |response| response.is_empty()));
+}
+
+#[test]
+fn pipelined_requests_are_answered_in_order() {
+    let server = server();
+    let mut connection = connect(&server);
+    connection
+        .get_mut()
+        .write_all(concat!(
+            "GET /hello HTTP/1.1\r\nHost: test\r\n\r\n",
+            "POST /echo HTTP/1.1\r\nHost: test\r\nContent-Length: 5\r\n\r\nfirst",
+            "GET /missing HTTP/1.1\r\nHost: test\r\n\r\n",
+            "POST /echo HTTP/1.1\r\nHost: test\r\nTransfer-Encoding: chunked\r\n\r\n6\r\nsecond\r\n0\r\n\r\n",
+            "GET /stream HTTP/1.1\r\nHost: test\r\n\r\n",
+            "GET /hello HTTP/1.1\r\nHost: test\r\nConnection: close\r\n\r\n",
+        ).as_bytes())
+        .unwrap();
+
+    let answers: Vec<(u16, String)> = (0..6)
+        .map(|_| {
+            let response = read_response(&mut connection);
+            (response.status, response.text().to_owned())
+        })
+        .collect();
+    assert_eq!(
+        answers,
+        [
+            (200, "hello"),
+            (200, "first"),
+            (404, "no /missing here"),
+            (200, "second"),
+            (200, "one two three"),
+            (200, "hello"),
+        ]
+        .map(|(status, text)| (status, text.to_owned()))
+    );
+    let mut rest = Vec::new();
+    connection.read_to_end(&mut rest).unwrap();
+    assert!(rest.is_empty());
+}
+
+#[test]
+fn pipelined_requests_split_across_writes() {
+    let server = server();
+    let mut connection = connect(&server);
+    let requests = "GET /hello HTTP/1.1\r\nHost: test\r\n\r\n".repeat(4);
+    for piece in requests.as_bytes().chunks(7) {
+        connection.get_mut().write_all(piece).unwrap();
+    }
+    for _ in 0..4 {
+        assert_eq!(read_response(&mut connection).text(), "hello");
+    }
+}
@@ -0,0 +1,103 @@
+use std::{
+    io::{Read, Write},
+    net::TcpStream,
+    time::Duration,
+};
+
+use adhesion::{
+    http_server::{HTTPMethod, HTTPRequest, HTTPResponse, HTTPServer, Route},
+    testing::{TestClient, TestServer},
+    validation::Validate,
+};
+
+/// `b"x"` 1000 times, gzipped by python
+const GZIPPED_XS: &[u8] = b"\x1f\x8b\x08\x00\x00\x00\x00\x00\x02\x03\xab\xa8\x18\x05\xa3\x60\x14\x0c\x77\x00\x00\xe6\xc9\x41\x3b\xe8\x03\x00\x00";
+
+fn server() -> HTTPServer<()> {
+    let echo = |request: &HTTPRequest, _: &()| match request.body.bytes() {
+        Ok(body) => HTTPResponse::builder().text(format!("read {}", body.len())),
+        Err(error) => error.response(),
+    };
+    let validate = Validate::new()
+        .content_type("application/json")
+        .max_body_size(64);
+    HTTPServer::builder(())
+        .add(
+            "/upload",
+            Route::new(vec![HTTPMethod::POST], echo).validate(validate),
+        )
+        .build()
+}
+
+/// the status and body of the response to `head` followed by `body`
+fn post(server: &TestServer, head: &str, body: &[u8]) -> (u16, String) {
+    let mut stream = TcpStream::connect(server.addr()).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    let request = format!(
+        "POST /upload HTTP/1.1\r\nHost: test\r\nConnection: close\r\nContent-Length: {}\r\n{}\r\n",
+        body.len(),
+        head
+    );
+    stream.write_all(request.as_bytes()).unwrap();
+    stream.write_all(body).unwrap();
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).unwrap();
+    let response = String::from_utf8_lossy(&response);
+    let status = response[9..12].parse().unwrap();
+    let (_, body) = response.split_once("\r\n\r\n").unwrap();
+    (status, body.to_owned())
+}
+
+#[test]
+fn checks_bodies_as_sent() {
+    let server = TestServer::spawn(server()).unwrap();
+    let json = "Content-Type: application/json\r\n";
+    assert_eq!(post(&server, json, b"{}").0, 200);
+    assert_eq!(post(&server, json, &[b' '; 65]).0, 413);
+    assert_eq!(
+        post(&server, "Content-Type: text/plain\r\n", b"0123456789").0,
+        415
+    );
+    assert_eq!(post(&server, "", b"0123456789").0, 415);
+    assert_eq!(post(&server, "", b"").0, 200);
+}
+
+#[test]
+fn content_encoding_does_not_skip_the_checks() {
+    let server = TestServer::spawn(server()).unwrap();
+    let identity = "Content-Encoding: identity\r\n";
+    let plain = "Content-Type: text/plain\r\nContent-Encoding: identity\r\n";
+    assert_eq!(post(&server, plain, b"0123456789").0, 415);
+    assert_eq!(post(&server, identity, b"0123456789").0, 415);
+    let json = "Content-Type: application/json\r\nContent-Encoding: identity\r\n";
+    assert_eq!(post(&server, json, &[b' '; 65]).0, 413);
+    assert_eq!(post(&server, json, b"{}"), (200, String::from("read 2")));
+}
+
+#[test]
+fn decoded_bodies_are_limited_too() {
+    let server = TestServer::spawn(server()).unwrap();
+    let gzip = "Content-Type: application/json\r\nContent-Encoding: gzip\r\n";
+    // small enough as sent, far too large once decoded
+    let (status, _) = post(&server, gzip, GZIPPED_XS);
+    assert_eq!(status, 413);
+}
+
+#[test]
+fn requests_built_in_tests_are_checked_the_same() {
+    let client = TestClient::new(server());
+    let response = client
+        .post("/upload")
+        .header("Content-Type", "text/plain")
+        .body("0123456789")
+        .send();
+    assert_eq!(response.status.status, 415);
+    let response = client
+        .post("/upload")
+        .header("Content-Type", "application/json")
+        .body(vec![b' '; 65])
+        .send();
+    assert_eq!(response.status.status, 413);
+}